@@ -1,7 +1,9 @@
 #![no_std]
 #![no_main]
 
-use bmvm_guest::{ForeignBuf, SharedBuf, alloc_buf, upcall};
+use bmvm_guest::{
+    ForeignBuf, HOST_MEMCPY, HostError, SharedBuf, Transport, alloc_buf, bmvm_interface, upcall,
+};
 
 #[upcall]
 fn noop() {
@@ -17,7 +19,7 @@ fn reverse(foreign: ForeignBuf) -> SharedBuf {
     let mut owned = unsafe { alloc_buf(foreign.len()).ok().unwrap() };
     let buf = owned.as_mut();
 
-    buf.copy_from_slice(foreign.as_ref());
+    foreign.try_copy_to(buf).ok().unwrap();
     let half_len = buf.len() / 2;
     let core::ops::Range { start, end } = buf.as_mut_ptr_range();
     let (front_half, back_half) = unsafe {
@@ -43,3 +45,43 @@ fn reverse(foreign: ForeignBuf) -> SharedBuf {
 
     owned.into_shared()
 }
+
+/// Two scalar parameters, which are passed in the transport registers
+#[upcall]
+fn add2(a: u64, b: u64) -> u64 {
+    a.wrapping_add(b)
+}
+
+/// Three scalar parameters, which are packed into the shared memory
+#[upcall]
+fn add3(a: u64, b: u64, c: u64) -> u64 {
+    a.wrapping_add(b).wrapping_add(c)
+}
+
+include!("../../interface.rs");
+
+/// Issue `n` empty hypercalls, which isolates the cost of the hypercall transport.
+#[upcall]
+fn ping(n: u64) -> u64 {
+    (0..n).filter(|&i| pong(i).is_ok()).count() as u64
+}
+
+/// Copy `data` into a new buffer `rounds` times, via the `HOST_MEMCPY` hypercall if `host` is set
+/// or inline otherwise. Unlike `host_memcpy`, the host is used regardless of `HOST_MEMOP_THRESHOLD`.
+#[upcall]
+fn copy_rounds(data: ForeignBuf, rounds: u64, host: bool) -> SharedBuf {
+    let mut out = unsafe { alloc_buf(data.len()).ok().unwrap() };
+    let (src, dst) = (data.as_ref().as_ptr(), out.as_mut().as_mut_ptr());
+    let request = [dst as u64, src as u64, data.len() as u64];
+    for _ in 0..rounds {
+        match host {
+            true => {
+                let transport = Transport::new(request.as_ptr() as u64, 0);
+                let result = unsafe { bmvm_guest::hypercall(HOST_MEMCPY, transport) };
+                assert_eq!(result.primary(), 0, "host declined the copy");
+            }
+            false => unsafe { core::ptr::copy(src, dst, data.len()) },
+        }
+    }
+    out.into_shared()
+}
@@ -0,0 +1,8 @@
+// Hypercalls of the echo guest, included by the guest and the host benchmarks alike, so both sides
+// are generated from this single definition.
+bmvm_interface! {
+    /// Echo the argument, which isolates the cost of the hypercall transport
+    fn pong(n: u64) -> Result<u64, HostError> {
+        Ok(n)
+    }
+}
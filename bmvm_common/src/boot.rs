@@ -0,0 +1,28 @@
+/// Progress of the guest setup, reported to the host by writing the phase byte to the boot port,
+/// if the host enabled the markers. The host records the time of every marker, see
+/// `Module::boot_phases`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[repr(u8)]
+pub enum Phase {
+    /// The memory layout table is parsed
+    LayoutParsed = 1,
+    /// The shared memory allocator, ports, arguments etc. are initialized
+    ArenasInitialized = 2,
+    /// The user provided `setup` function returned
+    CustomSetupDone = 3,
+    /// The guest signaled `ready()`. Not written to the port, the host records it on the exit.
+    Ready = 4,
+}
+
+impl Phase {
+    /// Decode the byte written to the boot port
+    pub const fn from_u8(value: u8) -> Option<Self> {
+        match value {
+            1 => Some(Phase::LayoutParsed),
+            2 => Some(Phase::ArenasInitialized),
+            3 => Some(Phase::CustomSetupDone),
+            4 => Some(Phase::Ready),
+            _ => None,
+        }
+    }
+}
@@ -1,6 +1,10 @@
+use crate::TypeSignature;
 use crate::mem::{RawOffsetPtr, VirtAddr};
 use crate::vmi::Signature;
 
+/// Maximum number of detail bytes the guest may write to the exit port after the exit code byte
+pub const EXIT_DETAIL_MAX_LEN: usize = size_of::<u64>();
+
 #[cfg_attr(
     feature = "vmi-consume",
     derive(Debug, Clone, Copy, PartialEq, Eq, thiserror::Error)
@@ -68,6 +72,32 @@ pub enum ExitCode {
     /// The provided buffer capacity is Zero
     #[cfg_attr(feature = "vmi-consume", error("Buffer capacity is ZERO"))]
     ZeroCapacity,
+    /// A transported value is not a valid instance of its type, e.g. a `bool` other than 0 or 1
+    #[cfg_attr(
+        feature = "vmi-consume",
+        error("Invalid value for the transported type")
+    )]
+    InvalidValue,
+    /// The guest aborted without further information, see `bmvm_guest::abort`
+    #[cfg_attr(feature = "vmi-consume", error("Aborted"))]
+    Aborted,
+    /// Program exited with a value in the transport registers, see `bmvm_guest::exit_with_value`
+    #[cfg_attr(feature = "vmi-consume", error("Exit with value"))]
+    Value,
+    /// A transported buffer exceeds the configured maximum or the shared memory
+    #[cfg_attr(feature = "vmi-consume", error("Transported buffer too large"))]
+    TransportTooLarge,
+    /// A VMI buffer or shared value was used, but the shared memory allocator is not initialized,
+    /// e.g. in a guest built with the `no-arena` feature
+    #[cfg_attr(
+        feature = "vmi-consume",
+        error("Shared memory allocator is not initialized")
+    )]
+    AllocatorUninitialized,
+    /// The guest wrote to data owned by the peer, which is mapped read-only. Reported by the host
+    /// with the fault address as detail.
+    #[cfg_attr(feature = "vmi-consume", error("Write to foreign data"))]
+    ForeignWriteViolation,
     /// The given exit code is not mapped to an enum variant.
     #[cfg_attr(feature = "vmi-consume", error("Panic"))]
     Panic(VirtAddr),
@@ -94,6 +124,12 @@ impl ExitCode {
             ExitCode::PageAlreadyMapped => 12,
             ExitCode::UnknownUpcall(_) => 13,
             ExitCode::ZeroCapacity => 14,
+            ExitCode::InvalidValue => 15,
+            ExitCode::Aborted => 16,
+            ExitCode::Value => 17,
+            ExitCode::TransportTooLarge => 18,
+            ExitCode::AllocatorUninitialized => 19,
+            ExitCode::ForeignWriteViolation => 20,
             ExitCode::Panic(_) => 254,
             ExitCode::Unmapped(value) => value,
         }
@@ -103,11 +139,11 @@ impl ExitCode {
 #[cfg(feature = "vmi-execute")]
 impl ExitCode {
     /// Write additional values to registers before VM exit.
-    pub fn write_values(self) {
+    pub fn write_values(&self) {
         unsafe {
             match self {
-                ExitCode::UnknownUpcall(sig) => core::arch::asm!("mov rbx, {}", in(reg) sig),
-                ExitCode::Unmapped(code) => core::arch::asm!("mov bl, {}", in(reg_byte) code),
+                ExitCode::UnknownUpcall(sig) => core::arch::asm!("mov rbx, {}", in(reg) *sig),
+                ExitCode::Unmapped(code) => core::arch::asm!("mov bl, {}", in(reg_byte) *code),
                 ExitCode::Ptr(ptr) => core::arch::asm!("mov ebx, {0:e}", in(reg) ptr.as_u32()),
                 ExitCode::Panic(addr) => core::arch::asm!("mov rbx, {0}", in(reg) addr.as_u64()),
                 _ => {}
@@ -118,6 +154,63 @@ impl ExitCode {
 
 #[cfg(feature = "vmi-consume")]
 impl ExitCode {
+    /// First process exit status reserved for guest-origin exit codes. Statuses below are used by
+    /// the host runtime, see `bmvm_host::Error::exit_code`.
+    pub const HOST_EXIT_GUEST_BASE: i32 = 64;
+
+    /// Highest guest exit code with a status of its own, see `as_host_exit_code`
+    pub const HOST_EXIT_GUEST_MAX: u8 = 126;
+
+    /// Map the exit code to a process exit status, so a host binary can forward the guest outcome
+    /// to the shell:
+    ///
+    /// | Status     | Origin                                                        |
+    /// |------------|---------------------------------------------------------------|
+    /// | `0`        | `Normal`, `Ready`, `Return` and `Value`                       |
+    /// | `1..=63`   | host runtime errors                                           |
+    /// | `64..=190` | guest exit codes `0..=126` as `64 + code`                     |
+    /// | `191`      | `Panic`                                                       |
+    /// | `192`      | any other guest exit code above 126, which has no own status  |
+    ///
+    /// Every status below 192 identifies a single exit code.
+    pub fn as_host_exit_code(&self) -> i32 {
+        const PANIC: i32 =
+            ExitCode::HOST_EXIT_GUEST_BASE + ExitCode::HOST_EXIT_GUEST_MAX as i32 + 1;
+
+        match self {
+            ExitCode::Normal | ExitCode::Ready | ExitCode::Return | ExitCode::Value => 0,
+            ExitCode::Panic(_) => PANIC,
+            code if code.as_u8() <= Self::HOST_EXIT_GUEST_MAX => {
+                Self::HOST_EXIT_GUEST_BASE + code.as_u8() as i32
+            }
+            _ => PANIC + 1,
+        }
+    }
+
+    /// Decode the data written to the exit port by the guest. The first byte is the exit code,
+    /// variants carrying a value receive it from `rbx`, where the guest placed it via
+    /// `write_values` before the exit (e.g. the `PanicInfo` address of `Panic`). Returns `None` if
+    /// no byte was written.
+    pub fn decode(data: &[u8], regs: &kvm_bindings::kvm_regs) -> Option<Self> {
+        data.first().map(|code| Self::from(*code).read_values(regs))
+    }
+
+    /// Decode the optional detail the guest wrote behind the exit code byte, see
+    /// `bmvm_guest::exit_with_detail`. Up to `EXIT_DETAIL_MAX_LEN` little endian bytes are
+    /// interpreted as `u64`, shorter payloads are zero extended. Returns `None` if only the exit code
+    /// was written.
+    pub fn decode_detail(data: &[u8]) -> Option<u64> {
+        let detail = data.get(1..)?;
+        if detail.is_empty() {
+            return None;
+        }
+
+        let mut bytes = [0u8; EXIT_DETAIL_MAX_LEN];
+        let len = detail.len().min(EXIT_DETAIL_MAX_LEN);
+        bytes[..len].copy_from_slice(&detail[..len]);
+        Some(u64::from_le_bytes(bytes))
+    }
+
     /// Read additional values from registers after VM exit.
     pub fn read_values(self, regs: &kvm_bindings::kvm_regs) -> Self {
         match self {
@@ -160,6 +253,12 @@ impl From<u8> for ExitCode {
             12 => ExitCode::PageAlreadyMapped,
             13 => ExitCode::UnknownUpcall(Signature::from(value)),
             14 => ExitCode::ZeroCapacity,
+            15 => ExitCode::InvalidValue,
+            16 => ExitCode::Aborted,
+            17 => ExitCode::Value,
+            18 => ExitCode::TransportTooLarge,
+            19 => ExitCode::AllocatorUninitialized,
+            20 => ExitCode::ForeignWriteViolation,
             254 => ExitCode::Panic(VirtAddr::new_unchecked(value as u64)),
             v => ExitCode::Unmapped(v),
         }
@@ -184,8 +283,152 @@ impl From<ExitCode> for u8 {
             ExitCode::PageAlreadyMapped => 12,
             ExitCode::UnknownUpcall(_) => 13,
             ExitCode::ZeroCapacity => 14,
+            ExitCode::InvalidValue => 15,
+            ExitCode::Aborted => 16,
+            ExitCode::Value => 17,
+            ExitCode::TransportTooLarge => 18,
+            ExitCode::AllocatorUninitialized => 19,
+            ExitCode::ForeignWriteViolation => 20,
             ExitCode::Panic(_) => 254,
             ExitCode::Unmapped(value) => value,
         }
     }
 }
+
+/// Error returned by a host function to signal a recoverable failure to the guest.
+/// The error is transported as its discriminant, see `Result<T, HostError>` transport handling.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "vmi-consume", derive(thiserror::Error))]
+pub enum HostError {
+    /// The operation failed without further details
+    #[cfg_attr(feature = "vmi-consume", error("Operation failed"))]
+    Failed,
+    /// The requested resource could not be found
+    #[cfg_attr(feature = "vmi-consume", error("Not found"))]
+    NotFound,
+    /// The host denied access to the requested resource
+    #[cfg_attr(feature = "vmi-consume", error("Permission denied"))]
+    PermissionDenied,
+    /// The provided arguments are invalid
+    #[cfg_attr(feature = "vmi-consume", error("Invalid input"))]
+    InvalidInput,
+    /// The requested operation is not supported by the host
+    #[cfg_attr(feature = "vmi-consume", error("Unsupported"))]
+    Unsupported,
+    /// The resource is temporarily unavailable
+    #[cfg_attr(feature = "vmi-consume", error("Unavailable"))]
+    Unavailable,
+    /// The given error code is not mapped to an enum variant.
+    #[cfg_attr(feature = "vmi-consume", error("Unmapped host error: {0}"))]
+    Unmapped(u8),
+}
+
+impl HostError {
+    pub const fn as_u8(self) -> u8 {
+        match self {
+            HostError::Failed => 0,
+            HostError::NotFound => 1,
+            HostError::PermissionDenied => 2,
+            HostError::InvalidInput => 3,
+            HostError::Unsupported => 4,
+            HostError::Unavailable => 5,
+            HostError::Unmapped(value) => value,
+        }
+    }
+}
+
+impl From<u8> for HostError {
+    fn from(value: u8) -> Self {
+        match value {
+            0 => HostError::Failed,
+            1 => HostError::NotFound,
+            2 => HostError::PermissionDenied,
+            3 => HostError::InvalidInput,
+            4 => HostError::Unsupported,
+            5 => HostError::Unavailable,
+            v => HostError::Unmapped(v),
+        }
+    }
+}
+
+impl From<HostError> for u8 {
+    fn from(err: HostError) -> u8 {
+        err.as_u8()
+    }
+}
+
+impl TypeSignature for HostError {
+    const SIGNATURE: u64 = {
+        let mut h = crate::hash::SignatureHasher::new();
+        h.write(0u64.to_le_bytes().as_slice());
+        h.write(b"HostError");
+        h.write(u8::SIGNATURE.to_le_bytes().as_slice());
+        h.finish()
+    };
+    const IS_PRIMITIVE: bool = true;
+    #[cfg(feature = "vmi-consume")]
+    fn name() -> String {
+        String::from("HostError")
+    }
+}
+
+#[cfg(all(test, feature = "vmi-consume"))]
+mod test {
+    use super::*;
+
+    #[test]
+    fn decode_exit_port() {
+        let regs = kvm_bindings::kvm_regs {
+            rbx: 0xdead_beef,
+            ..Default::default()
+        };
+
+        assert_eq!(ExitCode::decode(&[], &regs), None);
+        assert_eq!(ExitCode::decode(&[2], &regs), Some(ExitCode::Return));
+        assert_eq!(ExitCode::decode(&[16], &regs), Some(ExitCode::Aborted));
+        assert_eq!(ExitCode::decode(&[17], &regs), Some(ExitCode::Value));
+        assert_eq!(
+            ExitCode::decode(&[19], &regs),
+            Some(ExitCode::AllocatorUninitialized)
+        );
+        assert_eq!(
+            ExitCode::decode(&[254], &regs),
+            Some(ExitCode::Panic(VirtAddr::new(0xdead_beef)))
+        );
+        assert_eq!(
+            ExitCode::decode(&[13], &regs),
+            Some(ExitCode::UnknownUpcall(0xdead_beef))
+        );
+    }
+
+    #[test]
+    fn host_exit_code() {
+        assert_eq!(ExitCode::Normal.as_host_exit_code(), 0);
+        assert_eq!(ExitCode::Value.as_host_exit_code(), 0);
+        assert_eq!(ExitCode::ForeignWriteViolation.as_host_exit_code(), 64 + 20);
+        assert_eq!(ExitCode::Unmapped(126).as_host_exit_code(), 190);
+        assert_eq!(
+            ExitCode::Panic(VirtAddr::new(0x1000)).as_host_exit_code(),
+            191
+        );
+        // codes without an own status must not collide with a lower one
+        assert_eq!(ExitCode::Unmapped(127).as_host_exit_code(), 192);
+        assert_eq!(ExitCode::Unmapped(200).as_host_exit_code(), 192);
+    }
+
+    #[test]
+    fn decode_exit_detail() {
+        assert_eq!(ExitCode::decode_detail(&[]), None);
+        assert_eq!(ExitCode::decode_detail(&[6]), None);
+        assert_eq!(ExitCode::decode_detail(&[6, 0x10, 0x02]), Some(0x210));
+        assert_eq!(
+            ExitCode::decode_detail(&[6, 1, 2, 3, 4, 5, 6, 7, 8]),
+            Some(0x0807_0605_0403_0201)
+        );
+        // surplus bytes are ignored
+        assert_eq!(
+            ExitCode::decode_detail(&[6, 0xff, 0, 0, 0, 0, 0, 0, 0, 0xff]),
+            Some(0xff)
+        );
+    }
+}
@@ -68,7 +68,7 @@ pub trait Interpret: Sized {
 }
 
 /// Check if the buffer is aligned to be properly interpreted as T
-fn is_aligned<T>(buf: &[u8]) -> Result<(), InterpretError> {
+pub(crate) fn is_aligned<T>(buf: &[u8]) -> Result<(), InterpretError> {
     if !(buf.as_ptr() as usize).is_multiple_of(align_of::<T>()) {
         Err(InterpretError::Misaligned(
             align_of::<T>(),
@@ -80,7 +80,7 @@ fn is_aligned<T>(buf: &[u8]) -> Result<(), InterpretError> {
 }
 
 /// Check if T can even fit into the provided slice
-fn fits<T>(buf: &[u8]) -> Result<(), InterpretError> {
+pub(crate) fn fits<T>(buf: &[u8]) -> Result<(), InterpretError> {
     if buf.len() < size_of::<T>() {
         Err(InterpretError::TooSmall(size_of::<T>(), buf.len()))
     } else {
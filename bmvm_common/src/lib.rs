@@ -1,6 +1,7 @@
 #![cfg_attr(not(any(feature = "vmi-consume", feature = "vmi-macro")), no_std)]
 #![feature(allocator_api)]
 #![feature(macro_metavar_expr_concat)]
+#![cfg_attr(feature = "serde-transport", feature(const_type_name))]
 #[cfg(feature = "vmi-consume")]
 extern crate alloc;
 extern crate core;
@@ -8,10 +9,12 @@ extern crate core;
 #[cfg(all(feature = "vmi-consume", feature = "vmi-execute"))]
 compile_error!("Features `vmi-consume` and `vmi-execute` cannot be enabled at the same time.");
 
+pub mod boot;
 pub mod error;
 pub mod hash;
 pub mod interprete;
 pub mod mem;
+pub mod metrics;
 #[cfg(feature = "vmi-consume")]
 pub mod registry;
 mod typesignature;
@@ -20,10 +23,18 @@ pub mod vmi;
 use crate::mem::PhysAddr;
 pub use crate::typesignature::TypeSignature;
 
-/// The IO Port used for triggering hypercalls to host from the guest.
+/// The default IO Port used for triggering hypercalls to host from the guest.
 pub const HYPERCALL_IO_PORT: u16 = 0x0434;
-/// The IO Port used for exiting from the guest to host with an ExitCode.
+/// The default IO Port used for exiting from the guest to host with an ExitCode.
 pub const EXIT_IO_PORT: u16 = 0x0433;
+/// The IO Port used by the guest to write diagnostic output, which is logged by the host (COM1).
+pub const SERIAL_IO_PORT: u16 = 0x03f8;
+/// The default IO Port used by the guest to mark the progress of its setup, if the host enables the
+/// markers, see `boot::Phase`.
+pub const BOOT_IO_PORT: u16 = 0x0435;
+/// Value the guest places in `rax` before a `hlt` to wait for the host instead of terminating,
+/// given the host allows resuming from it.
+pub const HLT_WAIT_MARKER: u64 = u64::from_le_bytes(*b"bmvmwait");
 
 /// The ELF section name for the metadata containing the call guest required function information.
 pub const BMVM_META_SECTION_HOST: &str = ".bmvm.vpc.hypercall";
@@ -33,5 +44,45 @@ pub const BMVM_META_SECTION_EXPOSE: &str = ".bmvm.vpc.upcall";
 pub const BMVM_META_SECTION_EXPOSE_CALLS: &str = ".bmvm.vpc.upcall.calls";
 /// The ELF section name for the debug metadata.
 pub const BMVM_META_SECTION_DEBUG: &str = ".bmvm.vpc.debug";
-/// The memory layout table will be places at this address for the guest to access.
+/// The ELF section name for the ABI version the guest was built against.
+pub const BMVM_META_SECTION_ABI: &str = ".bmvm.vpc.abi";
+/// Version of the binary interface between host and guest, stored as little-endian `u32` in
+/// `BMVM_META_SECTION_ABI`. Bump it whenever the layout of `Transport`, `FnCall` or `UpcallFn`
+/// or the registers passed to the guest entry point change, or the handling of the values
+/// passed with them, or the ports the guest writes during its setup, so the host rejects guests
+/// built against an incompatible version.
+pub const BMVM_ABI_VERSION: u32 = 6;
+/// Version of the record layout within the VMI metadata sections. Every `FnCall` and `UpcallFn`
+/// record is prefixed with a header carrying this version, see `vmi::record_header`. Bump it
+/// whenever the layout of a record changes.
+pub const BMVM_META_FORMAT_VERSION: u8 = 1;
+/// The memory layout table will be places at this address for the guest to access, unless the host
+/// is configured otherwise. The guest receives the actual address in `rdi` at entry.
 pub const BMVM_MEM_LAYOUT_TABLE: PhysAddr = PhysAddr::new_unchecked(0x1000);
+/// The optional guest arguments blob will be placed at this address, right after the default
+/// location of the layout table. It stays here if the table is placed elsewhere by the host.
+/// The guest receives the address past the length prefix in `rsi` and the length in `rdx` at entry.
+/// The blob is prefixed with its length as native-endian `u64`.
+pub const BMVM_GUEST_ARGS: PhysAddr = PhysAddr::new_unchecked(0x2000);
+/// The maximum size of the guest arguments region including the length prefix (64KiB).
+pub const BMVM_GUEST_ARGS_MAX_SIZE: usize = 0x10000;
+/// The doorbell page of the MMIO hypercall transport, placed right after the guest arguments. The
+/// page is mapped in the guest but not backed by memory, so every write exits to the host.
+pub const BMVM_HYPERCALL_MMIO: PhysAddr =
+    PhysAddr::new_unchecked(BMVM_GUEST_ARGS.as_u64() + BMVM_GUEST_ARGS_MAX_SIZE as u64);
+/// The guest panic handler records the most recent panic message at this address, right after the
+/// hypercall doorbell. The message is prefixed with its length as native-endian `u64`.
+pub const BMVM_PANIC_MESSAGE: PhysAddr =
+    PhysAddr::new_unchecked(BMVM_HYPERCALL_MMIO.as_u64() + 0x1000);
+/// The size of the panic message region including the length prefix (4KiB).
+pub const BMVM_PANIC_MESSAGE_SIZE: usize = 0x1000;
+/// If the host is configured with non-default IO ports or boot markers, it places the hypercall,
+/// exit and boot port as consecutive native-endian `u16` at this address, right after the panic
+/// message region. A boot port of zero disables the boot markers.
+pub const BMVM_IO_PORTS: PhysAddr =
+    PhysAddr::new_unchecked(BMVM_PANIC_MESSAGE.as_u64() + BMVM_PANIC_MESSAGE_SIZE as u64);
+/// The guest counts its metrics in a table of `metrics::MetricSlot` at this address, right after
+/// the IO ports region. The host reads the table after a call.
+pub const BMVM_METRICS: PhysAddr = PhysAddr::new_unchecked(BMVM_IO_PORTS.as_u64() + 0x1000);
+/// The size of the metrics table (4KiB), see `metrics::METRICS_CAPACITY`.
+pub const BMVM_METRICS_SIZE: usize = 0x1000;
@@ -71,6 +71,19 @@ impl<B: AddrSpace> PhysAddr<B> {
         self.inner as *mut T
     }
 
+    /// Add `rhs` to the address. Returns `None` if the result overflows or exceeds the address
+    /// space.
+    #[inline]
+    pub fn checked_add(self, rhs: u64) -> Option<Self> {
+        Self::try_from(self.inner.checked_add(rhs)?).ok()
+    }
+
+    /// Subtract `rhs` from the address. Returns `None` if the result underflows.
+    #[inline]
+    pub fn checked_sub(self, rhs: u64) -> Option<Self> {
+        self.inner.checked_sub(rhs).map(Self::new_unchecked)
+    }
+
     /// Convert the physical address to a virtual address.
     /// The system uses two address mapping modes:
     /// For physical addresses in the lower half of the the address space, an identity mapping is
@@ -246,6 +259,23 @@ impl VirtAddr {
         self.0
     }
 
+    /// Add `rhs` to the address. Returns `None` if the result overflows or is not canonical.
+    #[inline]
+    pub fn checked_add(self, rhs: u64) -> Option<Self> {
+        let raw = self.0.checked_add(rhs)?;
+        let addr = Self::new_truncate(raw);
+        (addr.0 == raw).then_some(addr)
+    }
+
+    /// Subtract `rhs` from the address. Returns `None` if the result underflows or is not
+    /// canonical.
+    #[inline]
+    pub fn checked_sub(self, rhs: u64) -> Option<Self> {
+        let raw = self.0.checked_sub(rhs)?;
+        let addr = Self::new_truncate(raw);
+        (addr.0 == raw).then_some(addr)
+    }
+
     /// Aligns the virtual address upwards to the given alignment.
     #[inline]
     pub fn align_ceil<A>(self) -> Self
@@ -444,4 +474,28 @@ mod tests {
         let virt = unsafe { VirtAddr::new_unchecked(0x3000000123) };
         assert_eq!(PhysAddr::<AddrSpace39>::from(virt).as_u64(), 0x3000000123);
     }
+
+    #[test]
+    fn checked_arithmetic() {
+        let phys: PhysAddr<AddrSpace39> = PhysAddr::new(0x7ffffff000);
+        assert_eq!(
+            phys.checked_add(0xfff).map(|a| a.as_u64()),
+            Some(0x7fffffffff)
+        );
+        assert!(phys.checked_add(0x1000).is_none());
+        assert!(phys.checked_add(u64::MAX).is_none());
+        assert!(
+            PhysAddr::<AddrSpace39>::new(0x10)
+                .checked_sub(0x11)
+                .is_none()
+        );
+
+        let virt = VirtAddr::new(0x7fff_ffff_f000);
+        assert!(virt.checked_add(0x1000).is_none());
+        assert_eq!(VirtAddr::new(0xffff_8000_0000_0000).checked_sub(1), None);
+        assert_eq!(
+            virt.checked_sub(0x1000),
+            Some(VirtAddr::new(0x7fff_ffff_e000))
+        );
+    }
 }
@@ -1,10 +1,15 @@
+use crate::error::ExitCode;
+use crate::interprete::{InterpretError, fits, is_aligned};
 use crate::mem::{AlignedNonZeroUsize, VirtAddr};
 use crate::typesignature::TypeSignature;
+use crate::vmi::{ForeignShareable, OwnedShareable};
 use core::alloc::{Allocator, Layout};
 use core::fmt::{LowerHex, UpperHex};
 use core::mem::ManuallyDrop;
 use core::num::NonZeroUsize;
 use core::ptr::NonNull;
+use core::str::FromStr;
+use core::sync::atomic::{AtomicUsize, Ordering};
 use spin::once::Once;
 use talc::{ErrOnOom, Span, Talck};
 
@@ -36,82 +41,301 @@ pub enum Error {
         error("Invalid offset pointer (out of bounds)")
     )]
     InvalidOffsetPtr,
+    #[cfg_attr(
+        feature = "vmi-consume",
+        error("Transported buffer exceeds the maximum size or the shared memory")
+    )]
+    TransportTooLarge,
+    #[cfg_attr(feature = "vmi-consume", error("Buffer must not be empty"))]
+    EmptyBuffer,
+    #[cfg_attr(
+        feature = "vmi-consume",
+        error("Buffer exceeds the size of the shared memory")
+    )]
+    ExceedsArena,
+}
+
+/// Size mismatch between a VMI buffer and the memory it is copied from or to.
+#[cfg_attr(
+    feature = "vmi-consume",
+    derive(Debug, Clone, Copy, PartialEq, Eq, thiserror::Error)
+)]
+pub enum BufError {
+    /// The destination is smaller than the buffer (required, available)
+    #[cfg_attr(
+        feature = "vmi-consume",
+        error("Destination too small: requires {0} bytes, but only {1} available")
+    )]
+    DestinationTooSmall(usize, usize),
+    /// The buffer length exceeds the accepted maximum (length, maximum)
+    #[cfg_attr(
+        feature = "vmi-consume",
+        error("Buffer of {0} bytes exceeds the maximum of {1} bytes")
+    )]
+    TooLarge(usize, usize),
+}
+
+/// Failure to interpret a buffer as a typed value, see `ForeignBuf::as_foreign`.
+#[cfg_attr(
+    feature = "vmi-consume",
+    derive(Debug, Clone, Copy, PartialEq, Eq, thiserror::Error)
+)]
+pub enum ConversionError {
+    /// The buffer is smaller than the value (required, available)
+    #[cfg_attr(
+        feature = "vmi-consume",
+        error("Buffer too small: requires {0} bytes, but only {1} available")
+    )]
+    TooSmall(usize, usize),
+    /// The buffer is not aligned for the value (alignment, address)
+    #[cfg_attr(
+        feature = "vmi-consume",
+        error("Buffer at {1:#x} is not aligned to {0} bytes")
+    )]
+    Misaligned(usize, usize),
+    /// The buffer does not contain a valid value
+    #[cfg_attr(feature = "vmi-consume", error("Invalid value: {0}"))]
+    InvalidValue(ExitCode),
+    /// The value could not be allocated
+    #[cfg_attr(feature = "vmi-consume", error("Allocation failed: {0}"))]
+    Alloc(Error),
+}
+
+impl From<InterpretError> for ConversionError {
+    fn from(err: InterpretError) -> Self {
+        match err {
+            InterpretError::TooSmall(want, got) => ConversionError::TooSmall(want, got),
+            InterpretError::Misaligned(want, got) => ConversionError::Misaligned(want, got),
+        }
+    }
+}
+
+impl From<ExitCode> for ConversionError {
+    fn from(code: ExitCode) -> Self {
+        ConversionError::InvalidValue(code)
+    }
+}
+
+impl From<Error> for ConversionError {
+    fn from(err: Error) -> Self {
+        ConversionError::Alloc(err)
+    }
+}
+
+/// Copy `src` to the beginning of `dst`, returning the number of copied bytes.
+fn copy_checked(src: &[u8], dst: &mut [u8]) -> Result<usize, BufError> {
+    let available = dst.len();
+    let dst = dst
+        .get_mut(..src.len())
+        .ok_or(BufError::DestinationTooSmall(src.len(), available))?;
+    dst.copy_from_slice(src);
+    Ok(src.len())
+}
+
+/// Check the buffer length against the accepted maximum
+fn len_checked(len: usize, max: usize) -> Result<usize, BufError> {
+    if len > max {
+        return Err(BufError::TooLarge(len, max));
+    }
+    Ok(len)
+}
+
+fn buf_within(offset: u32, len: usize, capacity: usize, max: usize) -> Result<(), Error> {
+    let end = (offset as usize).checked_add(len);
+    if len > max || end.is_none_or(|e| e > capacity) {
+        return Err(Error::TransportTooLarge);
+    }
+    Ok(())
+}
+
+/// Bytes reserved at the start of the shared memory for the `ArenaHeader`, keeping the allocator
+/// metadata behind it aligned
+const ARENA_HEADER_SIZE: usize = 64;
+
+/// Bookkeeping shared by both peers, placed ahead of the allocator in the shared memory
+#[repr(C)]
+struct ArenaHeader {
+    /// Bytes currently allocated by either peer, excluding the allocator overhead
+    allocated: AtomicUsize,
+}
+
+/// Split the header off the arena, returning it together with the span left for the allocator
+fn split_header<'a>(arena: Arena) -> Result<(&'a ArenaHeader, Span), Error> {
+    let capacity = arena
+        .capacity
+        .get()
+        .checked_sub(ARENA_HEADER_SIZE)
+        .ok_or(Error::InitNotEnoughSpace)?;
+    let header = unsafe { arena.ptr.cast::<ArenaHeader>().as_ref() };
+    let base = unsafe { arena.ptr.as_ptr().add(ARENA_HEADER_SIZE) };
+    Ok((header, Span::from_base_size(base, capacity)))
 }
 
 struct AllocImpl<'a, M: lock_api::RawMutex, O: talc::OomHandler> {
     talck: &'a Talck<M, O>,
+    header: &'a ArenaHeader,
     base: VirtAddr,
+    /// Base of the read-only view data owned by the peer is read through, `base` if there is none
+    foreign: VirtAddr,
     capacity: usize,
+    /// Maximum length of a buffer received from the peer
+    max_transport: usize,
 }
 
 impl<'a, M: lock_api::RawMutex, O: talc::OomHandler> AllocImpl<'a, M, O> {
     #[allow(dead_code)]
-    fn new(oom: O, arena: Arena) -> Result<Self, Error> {
-        let (talck, span) = unsafe {
-            Talck::<M, O>::new_in_place(oom, arena.into()).map_err(|_| Error::InitSharedFailed)?
-        };
+    fn new(oom: O, arena: Arena, max_transport: usize) -> Result<Self, Error> {
+        let (header, span) = split_header(arena)?;
+        header.allocated.store(0, Ordering::Relaxed);
+        let (talck, span) =
+            unsafe { Talck::<M, O>::new_in_place(oom, span).map_err(|_| Error::InitSharedFailed)? };
 
         let (b, _) = span.get_base_acme().unwrap();
         let base = VirtAddr::from_ptr(b);
         let capacity = span.size();
         Ok(Self {
             talck,
+            header,
             base,
+            foreign: base,
             capacity,
+            max_transport,
         })
     }
 
+    /// Attach to the allocator the peer initialized in the arena. If given, data owned by the peer
+    /// is read through the read-only view `foreign` of the arena, so writing to it faults.
     #[allow(dead_code)]
-    fn new_shared(arena: Arena) -> Result<Self, Error> {
+    fn new_shared(arena: Arena, foreign: Option<NonNull<u8>>) -> Result<Self, Error> {
+        let start = VirtAddr::from_ptr(arena.ptr.as_ptr());
+        let (header, span) = split_header(arena)?;
         let (talck, span) =
-            unsafe { Talck::<M, O>::get_from(arena.into()).map_err(|_| Error::InitSharedFailed)? };
+            unsafe { Talck::<M, O>::get_from(span).map_err(|_| Error::InitSharedFailed)? };
 
         let (b, _) = span.get_base_acme().unwrap();
         let base = VirtAddr::from_ptr(b);
+        let foreign = match foreign {
+            Some(view) => VirtAddr::from_ptr(view.as_ptr()) + (base.as_u64() - start.as_u64()),
+            None => base,
+        };
         let capacity = span.size();
         Ok(Self {
             talck,
+            header,
             base,
+            foreign,
             capacity,
+            max_transport: usize::MAX,
         })
     }
 
+    /// Allocate via talc, keeping track of the allocated bytes
+    fn allocate(&self, layout: Layout) -> Result<NonNull<u8>, Error> {
+        let ptr = self
+            .talck
+            .allocate(layout)
+            .map_err(|_| Error::OutOfMemory)?;
+        self.header
+            .allocated
+            .fetch_add(layout.size(), Ordering::Relaxed);
+        Ok(ptr.cast::<u8>())
+    }
+
+    /// Deallocate via talc, keeping track of the allocated bytes
+    fn deallocate(&self, ptr: NonNull<u8>, layout: Layout) {
+        unsafe { self.talck.deallocate(ptr, layout) }
+        self.header
+            .allocated
+            .fetch_sub(layout.size(), Ordering::Relaxed);
+    }
+
+    /// Bytes not allocated by either peer
+    fn remaining(&self) -> usize {
+        self.capacity
+            .saturating_sub(self.header.allocated.load(Ordering::Relaxed))
+    }
+
     /// Allocate memory for a type in the owned shared memory.
     /// # Safety
     /// This is a wrapper or the `core::alloc::Allocator` trait. Reference their documentation
     /// regarding further safety guarantees.
     unsafe fn alloc<T: TypeSignature>(&self) -> Result<Owned<T>, Error> {
         let layout = Layout::new::<T>();
-        self.talck
-            .allocate(layout)
-            .map(|ptr| Owned {
-                inner: ptr.cast::<T>(),
-            })
-            .map_err(|_| Error::OutOfMemory)
+        self.allocate(layout).map(|ptr| Owned {
+            inner: ptr.cast::<T>(),
+        })
     }
 
     unsafe fn alloc_buf(&self, size: usize) -> Result<OwnedBuf, Error> {
         let align = align_of::<u8>();
         let layout = Layout::from_size_align(size, align).unwrap();
 
-        let ptr = self
-            .talck
-            .allocate(layout)
-            .map(|ptr| ptr.cast::<u8>())
-            .map_err(|_| Error::OutOfMemory)?;
-
+        let ptr = self.allocate(layout)?;
         Ok(OwnedBuf::new(ptr, NonZeroUsize::new(size).unwrap()))
     }
 
+    /// Resize the buffer, in place if the memory behind it is free (e.g. it is the most recent
+    /// allocation) or the buffer shrinks. Otherwise, the content is moved to a new allocation. The
+    /// alignment of the buffer is preserved and a grown tail is zeroed. On failure, the buffer is
+    /// returned unchanged.
+    unsafe fn realloc_buf(
+        &self,
+        buf: OwnedBuf,
+        new_len: usize,
+    ) -> Result<OwnedBuf, (OwnedBuf, Error)> {
+        let Some(capacity) = NonZeroUsize::new(new_len) else {
+            return Err((buf, Error::EmptyBuffer));
+        };
+        let old_len = buf.capacity.get();
+        let layout = Layout::from_size_align(old_len, align_of::<u8>()).unwrap();
+
+        let in_place = match new_len.cmp(&old_len) {
+            core::cmp::Ordering::Equal => return Ok(buf),
+            core::cmp::Ordering::Less => {
+                unsafe { self.talck.lock().shrink(buf.ptr, layout, new_len) };
+                self.header
+                    .allocated
+                    .fetch_sub(old_len - new_len, Ordering::Relaxed);
+                true
+            }
+            core::cmp::Ordering::Greater => {
+                let grown = unsafe { self.talck.lock().grow_in_place(buf.ptr, layout, new_len) };
+                if grown.is_ok() {
+                    self.header
+                        .allocated
+                        .fetch_add(new_len - old_len, Ordering::Relaxed);
+                }
+                grown.is_ok()
+            }
+        };
+        if in_place {
+            let mut resized = OwnedBuf::new(buf.ptr, capacity);
+            if let Some(tail) = resized.as_mut().get_mut(old_len..) {
+                tail.fill(0);
+            }
+            return Ok(resized);
+        }
+
+        let mut moved = match unsafe { self.alloc_buf(new_len) } {
+            Ok(moved) => moved,
+            Err(e) => return Err((buf, e)),
+        };
+        let (head, tail) = moved.as_mut().split_at_mut(old_len);
+        head.copy_from_slice(buf.as_ref());
+        tail.fill(0);
+        self.dealloc_buf(buf.ptr, buf.capacity);
+        Ok(moved)
+    }
+
     fn dealloc<T: TypeSignature>(&self, ptr: NonNull<T>) {
         let layout = Layout::new::<T>();
-        unsafe { self.talck.deallocate(ptr.cast::<u8>(), layout) }
+        self.deallocate(ptr.cast::<u8>(), layout)
     }
 
     fn dealloc_buf(&self, ptr: NonNull<u8>, capacity: NonZeroUsize) {
         let align = align_of::<u8>();
         let layout = Layout::from_size_align(capacity.get(), align).unwrap();
-        unsafe { self.talck.deallocate(ptr, layout) }
+        self.deallocate(ptr, layout)
     }
 
     /// Check the offset pointer for validity (fits in the arena) and return a readable reference
@@ -132,14 +356,43 @@ impl<'a, M: lock_api::RawMutex, O: talc::OomHandler> AllocImpl<'a, M, O> {
         Ok(Foreign { ptr: offset })
     }
 
+    /// Check if a buffer of `len` bytes at the offset pointer lies within the arena and does not
+    /// exceed the configured transport limit.
+    fn check_buf(&self, ptr: &OffsetPtr<u8>, len: usize) -> Result<(), Error> {
+        buf_within(ptr.offset, len, self.capacity, self.max_transport)
+    }
+
+    /// Allocate a reference counter for the parts of a split buffer, returning its offset
+    fn alloc_refs(&self, count: usize) -> Result<u32, Error> {
+        let ptr = self
+            .allocate(Layout::new::<AtomicUsize>())?
+            .cast::<AtomicUsize>();
+        unsafe { ptr.write(AtomicUsize::new(count)) };
+        Ok(self.ptr_offset(ptr.cast::<u8>()).offset)
+    }
+
+    fn refs(&self, offset: u32) -> &AtomicUsize {
+        let addr = self.base + offset as u64;
+        unsafe { addr.as_ptr::<AtomicUsize>().as_ref().unwrap() }
+    }
+
+    fn dealloc_refs(&self, offset: u32) {
+        let addr = self.base + offset as u64;
+        let ptr = unsafe { NonNull::new_unchecked(addr.as_ptr::<u8>().cast_mut()) };
+        self.deallocate(ptr, Layout::new::<AtomicUsize>())
+    }
+
     fn get<T: TypeSignature>(&self, ptr: &OffsetPtr<T>) -> &T {
-        let addr = self.base + ptr.offset as u64;
-        let value_ptr: *const T = addr.as_ptr::<T>();
-        unsafe { value_ptr.as_ref().unwrap() }
+        unsafe { self.get_readonly(ptr).as_ref().unwrap() }
     }
 
     fn get_ptr<T: Unpackable>(&self, ptr: &OffsetPtr<T>) -> *const T {
-        let addr = self.base + ptr.offset as u64;
+        self.get_readonly(ptr)
+    }
+
+    /// Pointer to data owned by the peer within the read-only view of the arena
+    fn get_readonly<T: TypeSignature>(&self, ptr: &OffsetPtr<T>) -> *const T {
+        let addr = self.foreign + ptr.offset as u64;
         addr.as_ptr::<T>()
     }
 
@@ -176,26 +429,50 @@ impl From<Arena> for Span {
     }
 }
 
+/// Initialize the allocator in `arena`. Buffers received from the peer are limited to
+/// `max_transport` bytes, buffers reaching beyond the shared memory are rejected regardless of the
+/// limit, see `check_foreign_buf`.
 #[cfg(feature = "vmi-consume")]
-pub fn init(arena: Option<Arena>) {
+pub fn init(arena: Option<Arena>, max_transport: usize) {
     if let Some(arena) = arena {
-        ALLOC.call_once(|| match AllocImpl::new(ErrOnOom, arena) {
+        ALLOC.call_once(|| match AllocImpl::new(ErrOnOom, arena, max_transport) {
             Ok(alloc) => alloc,
             Err(_) => panic!("Failed to initialize allocator"),
         });
     }
 }
 
+/// Attach to the allocator initialized by the host. Data owned by the host is read through
+/// `foreign`, a read-only view of the arena, if provided.
 #[cfg(feature = "vmi-execute")]
-pub fn init(arena: Option<Arena>) {
+pub fn init(arena: Option<Arena>, foreign: Option<NonNull<u8>>) {
     if let Some(arena) = arena {
-        ALLOC.call_once(|| match AllocImpl::new_shared(arena) {
+        ALLOC.call_once(|| match AllocImpl::new_shared(arena, foreign) {
             Ok(alloc) => alloc,
             Err(_) => panic!("Failed to initialize allocator"),
         });
     }
 }
 
+/// Check a buffer of `len` bytes at `ptr` received from the peer before it is accessed against
+/// the bounds of the shared memory and the transport limit of the allocator, see `init`.
+pub fn check_foreign_buf(ptr: &OffsetPtr<u8>, len: usize) -> Result<(), Error> {
+    match ALLOC.get() {
+        Some(alloc) => alloc.check_buf(ptr, len),
+        None => Err(Error::UninitializedAllocator),
+    }
+}
+
+/// Number of bytes in the shared memory, which are not allocated by either peer. The figure is an
+/// upper bound: the allocator metadata and fragmentation caused by deallocations are not accounted
+/// for, so an allocation of the returned size may still fail.
+pub fn remaining() -> Result<usize, Error> {
+    match ALLOC.get() {
+        Some(alloc) => Ok(alloc.remaining()),
+        None => Err(Error::UninitializedAllocator),
+    }
+}
+
 /// Allocate type T on the shared memory. This should only be used for data destined for the
 /// remote peer. The peer will free the allocated memory if the data is dropped. The original
 /// allocator can also drop it, but should only be done if one can ensure that the peer will not
@@ -224,6 +501,19 @@ pub unsafe fn alloc_buf(size: usize) -> Result<OwnedBuf, Error> {
     }
 }
 
+/// Resize a buffer allocated by `alloc_buf` to `new_len` bytes, preserving its content up to the
+/// smaller of both lengths. The buffer grows in place if the memory behind it is free, which is
+/// the case for the most recent allocation, e.g. when appending to a buffer of unknown final
+/// size. Otherwise, it is moved to a new allocation. Grown bytes are zeroed. On failure, e.g. if
+/// the shared memory is exhausted or `new_len` is zero, the buffer is returned unchanged along
+/// with the error.
+pub fn realloc_buf(buf: OwnedBuf, new_len: usize) -> Result<OwnedBuf, (OwnedBuf, Error)> {
+    match ALLOC.get() {
+        Some(alloc) => unsafe { alloc.realloc_buf(buf, new_len) },
+        None => Err((buf, Error::UninitializedAllocator)),
+    }
+}
+
 /// Deallocate a type allocated by `alloc`. Make sure to only call this if one can ensure that the
 /// peer will not use the memory anymore.
 pub fn dealloc<T: TypeSignature>(ptr: NonNull<T>) {
@@ -328,10 +618,7 @@ pub struct Owned<T: TypeSignature> {
 
 impl<T: TypeSignature> Owned<T> {
     pub fn into_shared(self) -> Shared<T> {
-        let alloc = ALLOC.get().unwrap();
-        Shared {
-            inner: alloc.ptr_offset(self.inner),
-        }
+        Shared::from(self)
     }
 }
 
@@ -359,8 +646,18 @@ pub struct Shared<T: TypeSignature> {
     pub(crate) inner: OffsetPtr<T>,
 }
 
+impl<T: TypeSignature> Shared<T> {
+    /// Deallocate the value instead of passing it to the peer.
+    pub(crate) fn deallocate(self) {
+        let alloc = ALLOC.get().unwrap();
+        alloc.dealloc(alloc.get_non_null(&self.inner));
+    }
+}
+
 impl<T: TypeSignature> From<Owned<T>> for Shared<T> {
     fn from(owned: Owned<T>) -> Self {
+        // ownership is passed on to the peer, which deallocates the value
+        let owned = ManuallyDrop::new(owned);
         let alloc = ALLOC.get().unwrap();
         Shared {
             inner: alloc.ptr_offset(owned.inner),
@@ -386,12 +683,19 @@ impl OwnedBuf {
         self.capacity.get()
     }
 
+    /// Copy `src` to the beginning of the buffer, failing instead of panicking if it does not fit.
+    /// Returns the number of copied bytes.
+    pub fn try_copy_from(&mut self, src: &[u8]) -> Result<usize, BufError> {
+        copy_checked(src, self.as_mut())
+    }
+
     pub fn into_shared(self) -> SharedBuf {
         let alloc = ALLOC.get().unwrap();
         let offset = alloc.ptr_offset(self.ptr);
 
         SharedBuf {
             ptr: offset,
+            refs: 0,
             capacity: self.capacity,
         }
     }
@@ -413,14 +717,79 @@ impl AsMut<[u8]> for OwnedBuf {
 #[repr(C)]
 pub struct SharedBuf {
     pub(crate) ptr: OffsetPtr<u8>,
+    /// Offset of the reference counter of a retained buffer, zero otherwise. It occupies the
+    /// padding behind `ptr`, see `RetainedBuf`.
+    pub(crate) refs: u32,
     pub(crate) capacity: NonZeroUsize,
 }
 
 impl SharedBuf {
+    /// Allocate a buffer and copy `bytes` into it. Shorthand for `alloc_buf`, writing the data
+    /// and `into_shared`.
+    pub fn from_bytes(bytes: &[u8]) -> Result<SharedBuf, Error> {
+        let mut owned = unsafe { alloc_buf(bytes.len()) }?;
+        owned.as_mut().copy_from_slice(bytes);
+        Ok(owned.into_shared())
+    }
+
+    /// Allocate a single buffer containing the contents of `bufs` one after another. The inputs
+    /// are left untouched. Fails if the total length is zero or exceeds the shared memory.
+    pub fn concat(bufs: &[SharedBuf]) -> Result<SharedBuf, Error> {
+        let alloc = ALLOC.get().ok_or(Error::UninitializedAllocator)?;
+        let total = bufs
+            .iter()
+            .try_fold(0usize, |total, buf| total.checked_add(buf.capacity.get()))
+            .filter(|total| *total <= alloc.capacity)
+            .ok_or(Error::ExceedsArena)?;
+        if total == 0 {
+            return Err(Error::EmptyBuffer);
+        }
+
+        let mut owned = unsafe { alloc.alloc_buf(total) }?;
+        let mut offset = 0;
+        for buf in bufs {
+            let bytes = buf.as_bytes();
+            owned.as_mut()[offset..offset + bytes.len()].copy_from_slice(bytes);
+            offset += bytes.len();
+        }
+        Ok(owned.into_shared())
+    }
+
+    /// Length of the buffer, or an error if it exceeds `max` bytes.
+    pub fn len_checked(&self, max: usize) -> Result<usize, BufError> {
+        len_checked(self.capacity.get(), max)
+    }
+
+    /// Copy the buffer to the beginning of `dst`, failing instead of panicking if `dst` is too
+    /// small. Returns the number of copied bytes.
+    pub fn try_copy_to(&self, dst: &mut [u8]) -> Result<usize, BufError> {
+        copy_checked(self.as_bytes(), dst)
+    }
+
+    /// Copy `src` to the beginning of the buffer, failing instead of panicking if it does not fit.
+    /// Returns the number of copied bytes.
+    pub fn try_copy_from(&mut self, src: &[u8]) -> Result<usize, BufError> {
+        let alloc = ALLOC.get().unwrap();
+        let ptr = alloc.get_non_null(&self.ptr);
+        let dst = unsafe { core::slice::from_raw_parts_mut(ptr.as_ptr(), self.capacity.get()) };
+        copy_checked(src, dst)
+    }
+
+    /// Read access to the underlying buffer, e.g. to decode previously written data.
+    pub(crate) fn as_bytes(&self) -> &[u8] {
+        let alloc = ALLOC.get().unwrap();
+        let ptr = alloc.get_non_null(&self.ptr);
+        unsafe { core::slice::from_raw_parts(ptr.as_ptr(), self.capacity.get()) }
+    }
+
     /// This function deallocates the buffer.
     /// SAFETY: using the value after this function call triggers undefined behavior! This extends
     /// to usage by the VMI peer!
     pub fn deallocate(self) {
+        // a retained buffer only drops its reference, see `Drop`
+        if self.refs != 0 {
+            return;
+        }
         // unwrap is safe because the allocator is needed to even construct the foreign pointer
         let alloc = ALLOC.get().unwrap();
         let ptr = alloc.get_non_null(&self.ptr);
@@ -428,6 +797,94 @@ impl SharedBuf {
     }
 }
 
+impl Drop for SharedBuf {
+    /// Drop the reference of a retained buffer, returning it to its owner, e.g. a buffer pool of
+    /// the host. The last reference deallocates it. Other buffers are meant for the VMI peer and
+    /// have to be passed on or deallocated explicitly.
+    fn drop(&mut self) {
+        if self.refs == 0 {
+            return;
+        }
+        let alloc = ALLOC.get().unwrap();
+        if alloc.refs(self.refs).fetch_sub(1, Ordering::AcqRel) == 1 {
+            let ptr = alloc.get_non_null(&self.ptr);
+            alloc.dealloc_buf(ptr, self.capacity);
+            alloc.dealloc_refs(self.refs);
+        }
+    }
+}
+
+/// Buffer in the shared memory, which is kept alive by a reference counter instead of being
+/// deallocated by the VMI peer. Every `SharedBuf` created via `share` holds a reference, which the
+/// receiver drops once it is done with the buffer, so the allocation can be shared again.
+pub struct RetainedBuf {
+    ptr: OffsetPtr<u8>,
+    refs: u32,
+    capacity: NonZeroUsize,
+}
+
+impl RetainedBuf {
+    /// Allocate a buffer of `size` bytes along with its reference counter.
+    pub fn new(size: usize) -> Result<Self, Error> {
+        let alloc = ALLOC.get().ok_or(Error::UninitializedAllocator)?;
+        let buf = unsafe { alloc.alloc_buf(size) }?;
+        let refs = match alloc.alloc_refs(1) {
+            Ok(refs) => refs,
+            Err(e) => {
+                alloc.dealloc_buf(buf.ptr, buf.capacity);
+                return Err(e);
+            }
+        };
+        Ok(Self {
+            ptr: alloc.ptr_offset(buf.ptr),
+            refs,
+            capacity: buf.capacity,
+        })
+    }
+
+    pub fn len(&self) -> usize {
+        self.capacity.get()
+    }
+
+    /// Check if no `SharedBuf` of this buffer is alive anymore.
+    pub fn is_idle(&self) -> bool {
+        let alloc = ALLOC.get().unwrap();
+        alloc.refs(self.refs).load(Ordering::Acquire) == 1
+    }
+
+    /// Share the buffer with the VMI peer, which drops the reference instead of deallocating it.
+    pub fn share(&self) -> SharedBuf {
+        let alloc = ALLOC.get().unwrap();
+        alloc.refs(self.refs).fetch_add(1, Ordering::Relaxed);
+        SharedBuf {
+            ptr: OffsetPtr::from(self.ptr.offset),
+            refs: self.refs,
+            capacity: self.capacity,
+        }
+    }
+}
+
+impl Drop for RetainedBuf {
+    fn drop(&mut self) {
+        // the last reference deallocates the buffer, even if held by the peer
+        let alloc = ALLOC.get().unwrap();
+        if alloc.refs(self.refs).fetch_sub(1, Ordering::AcqRel) == 1 {
+            let ptr = alloc.get_non_null(&self.ptr);
+            alloc.dealloc_buf(ptr, self.capacity);
+            alloc.dealloc_refs(self.refs);
+        }
+    }
+}
+
+impl FromStr for SharedBuf {
+    type Err = Error;
+
+    /// Allocate a buffer containing the UTF-8 bytes of `s`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Self::from_bytes(s.as_bytes())
+    }
+}
+
 /// Foreign memory allocated by the VMI peer.
 /// This wraps a raw pointer and manages deallocation on drop.
 #[repr(transparent)]
@@ -448,6 +905,12 @@ impl<T: TypeSignature> Foreign<T> {
         let ptr = alloc.get_non_null(&self.ptr);
         Owned { inner: ptr }
     }
+
+    /// Raw pointer to the value, which is not necessarily a valid `T` yet
+    pub(crate) fn as_ptr(&self) -> *const T {
+        let alloc = ALLOC.get().unwrap();
+        alloc.get_readonly(&self.ptr)
+    }
 }
 
 impl<T: Unpackable> Foreign<T> {
@@ -493,6 +956,17 @@ impl<T: TypeSignature> Drop for Foreign<T> {
 pub struct ForeignBuf {
     pub(crate) ptr: OffsetPtr<u8>,
     pub(crate) capacity: NonZeroUsize,
+    /// Allocation shared with the other parts, if the buffer was split
+    pub(crate) origin: Option<Origin>,
+}
+
+/// Allocation backing the parts of a split `ForeignBuf` or a `RetainedBuf`. It is deallocated with the
+/// reference counter, once the last part is dropped.
+#[derive(Clone, Copy)]
+pub(crate) struct Origin {
+    pub(crate) offset: u32,
+    pub(crate) capacity: NonZeroUsize,
+    pub(crate) refs: u32,
 }
 
 impl ForeignBuf {
@@ -500,13 +974,124 @@ impl ForeignBuf {
         self.capacity.get()
     }
 
-    /// Own the pointer
+    /// Split the buffer into the bytes `[0, mid)` and `[mid, len)`. Both parts are views of the
+    /// original allocation, which is deallocated once both of them are dropped. Splitting the
+    /// first time allocates a reference counter in the shared memory, while splitting a part again
+    /// reuses it. Fails with `EmptyBuffer` if `mid` is zero or not less than the length, as both
+    /// parts must not be empty, releasing the buffer like any other error.
+    pub fn split_at(self, mid: usize) -> Result<(ForeignBuf, ForeignBuf), Error> {
+        if mid == 0 || mid >= self.len() {
+            return Err(Error::EmptyBuffer);
+        }
+
+        let alloc = ALLOC.get().unwrap();
+        let origin = match self.origin {
+            Some(origin) => {
+                alloc.refs(origin.refs).fetch_add(1, Ordering::Relaxed);
+                origin
+            }
+            None => Origin {
+                offset: self.ptr.offset,
+                capacity: self.capacity,
+                refs: alloc.alloc_refs(2)?,
+            },
+        };
+        // the reference of `self` is passed on to the parts
+        let this = ManuallyDrop::new(self);
+
+        let part = |offset: u32, len: usize| ForeignBuf {
+            ptr: OffsetPtr::from(offset),
+            capacity: NonZeroUsize::new(len).unwrap(),
+            origin: Some(origin),
+        };
+        Ok((
+            part(this.ptr.offset, mid),
+            part(this.ptr.offset + mid as u32, this.len() - mid),
+        ))
+    }
+
+    /// Length of the buffer, or an error if it exceeds `max` bytes. The length is chosen by the
+    /// VMI peer, so it should be checked before sizing any local memory after it.
+    pub fn len_checked(&self, max: usize) -> Result<usize, BufError> {
+        len_checked(self.capacity.get(), max)
+    }
+
+    /// Copy the buffer to the beginning of `dst`, failing instead of panicking if `dst` is too
+    /// small. Returns the number of copied bytes.
+    pub fn try_copy_to(&self, dst: &mut [u8]) -> Result<usize, BufError> {
+        copy_checked(self.as_ref(), dst)
+    }
+
+    /// Copy the buffer contents into a `Vec`.
+    #[cfg(feature = "vmi-consume")]
+    pub fn to_vec(&self) -> Vec<u8> {
+        self.as_ref().to_vec()
+    }
+
+    /// Copy the buffer contents into a `String`, replacing invalid UTF-8 sequences.
+    #[cfg(feature = "vmi-consume")]
+    pub fn to_string_lossy(&self) -> String {
+        String::from_utf8_lossy(self.as_ref()).into_owned()
+    }
+
+    /// Iterate over the buffer in views of `size` bytes without copying, see `slice::chunks`. The
+    /// last chunk is shorter, if the length is not a multiple of `size`.
+    ///
+    /// # Panics
+    /// If `size` is zero.
+    pub fn chunks(&self, size: usize) -> core::slice::Chunks<'_, u8> {
+        self.as_ref().chunks(size)
+    }
+
+    /// Iterate over the buffer in views of exactly `size` bytes without copying, see
+    /// `slice::chunks_exact`. The trailing bytes not filling a chunk are available via
+    /// `remainder()` of the iterator.
+    ///
+    /// # Panics
+    /// If `size` is zero.
+    pub fn chunks_exact(&self, size: usize) -> core::slice::ChunksExact<'_, u8> {
+        self.as_ref().chunks_exact(size)
+    }
+
+    /// Interpret the beginning of the buffer as `T` after checking size, alignment and the value
+    /// itself. The buffer stays responsible for its memory, which the allocator releases based on
+    /// the buffer length, therefore the value is copied into an allocation of its own.
+    pub fn as_foreign<T: TypeSignature + Unpackable>(&self) -> Result<Foreign<T>, ConversionError> {
+        let buf = self.as_ref();
+        is_aligned::<T>(buf)?;
+        fits::<T>(buf)?;
+
+        let alloc = ALLOC.get().ok_or(Error::UninitializedAllocator)?;
+        let owned = unsafe { alloc.alloc::<T>() }?;
+        unsafe {
+            core::ptr::copy_nonoverlapping(
+                buf.as_ptr(),
+                owned.inner.as_ptr().cast::<u8>(),
+                size_of::<T>(),
+            )
+        };
+        // checks the validity of the copied value
+        let t = owned.into_shared().into_transport();
+        Ok(Foreign::<T>::from_transport(t)?)
+    }
+
+    /// Own the pointer. A part of a split buffer does not cover its allocation, therefore it is
+    /// copied into a new buffer instead, panicking if the shared memory is exhausted.
     pub fn owned(self) -> OwnedBuf {
         let alloc = ALLOC.get().unwrap();
-        let ptr = alloc.get_non_null(&self.ptr);
+        if self.origin.is_some() {
+            let Ok(mut owned) = (unsafe { alloc.alloc_buf(self.len()) }) else {
+                panic!("shared memory exhausted while copying a split buffer");
+            };
+            owned.as_mut().copy_from_slice(self.as_ref());
+            return owned;
+        }
+
+        let this = ManuallyDrop::new(self);
+        let ptr = alloc.get_non_null(&this.ptr);
         OwnedBuf {
             ptr,
-            capacity: self.capacity,
+            capacity: this.capacity,
         }
     }
 }
@@ -514,8 +1099,8 @@ impl ForeignBuf {
 impl AsRef<[u8]> for ForeignBuf {
     fn as_ref(&self) -> &[u8] {
         let alloc = ALLOC.get().unwrap();
-        let ptr = alloc.get_non_null(&self.ptr);
-        unsafe { core::slice::from_raw_parts(ptr.as_ptr(), self.capacity.get()) }
+        let ptr = alloc.get_readonly(&self.ptr);
+        unsafe { core::slice::from_raw_parts(ptr, self.capacity.get()) }
     }
 }
 
@@ -523,8 +1108,19 @@ impl Drop for ForeignBuf {
     fn drop(&mut self) {
         // unwrap is safe because the allocator is needed to even construct the foreign pointer
         let alloc = ALLOC.get().unwrap();
-        let ptr = alloc.get_non_null(&self.ptr);
-        alloc.dealloc_buf(ptr, self.capacity);
+        match self.origin {
+            Some(origin) => {
+                if alloc.refs(origin.refs).fetch_sub(1, Ordering::AcqRel) == 1 {
+                    let ptr = alloc.get_non_null(&OffsetPtr::<u8>::from(origin.offset));
+                    alloc.dealloc_buf(ptr, origin.capacity);
+                    alloc.dealloc_refs(origin.refs);
+                }
+            }
+            None => {
+                let ptr = alloc.get_non_null(&self.ptr);
+                alloc.dealloc_buf(ptr, self.capacity);
+            }
+        }
     }
 }
 
@@ -549,6 +1145,23 @@ pub unsafe trait Unpackable: TypeSignature {
     unsafe fn unpack(this: *const Self) -> Self::Output;
 }
 
+macro_rules! impl_unpackable_for_primitive {
+    ($($prim:ty),* $(,)?) => {
+        $(
+        unsafe impl Unpackable for $prim {
+            type Output = $prim;
+            unsafe fn unpack(this: *const Self) -> Self::Output {
+                unsafe { core::ptr::read(this) }
+            }
+        }
+        )*
+    };
+}
+
+impl_unpackable_for_primitive!(
+    u8, u16, u32, u64, u128, i8, i16, i32, i64, i128, f32, f64, usize,
+);
+
 macro_rules! impl_type_signature_for_shareable {
     ($($t:ident),*) => {
         $(
@@ -604,3 +1217,255 @@ macro_rules! impl_type_signature_for_buf {
 }
 
 impl_type_signature_for_buf!(ForeignBuf, SharedBuf);
+
+#[cfg(test)]
+pub(crate) mod test {
+    use super::*;
+
+    /// Values exceeding the transport registers are passed via the shared memory, which requires
+    /// the global allocator. It is initialized once with a leaked arena for all tests.
+    #[cfg(feature = "vmi-consume")]
+    pub(crate) fn setup() {
+        const ARENA_SIZE: usize = 1 << 20;
+        static INIT: std::sync::Once = std::sync::Once::new();
+        INIT.call_once(|| {
+            let layout = std::alloc::Layout::from_size_align(ARENA_SIZE, 4096).unwrap();
+            let ptr = NonNull::new(unsafe { std::alloc::alloc_zeroed(layout) }).unwrap();
+            let capacity = AlignedNonZeroUsize::new_aligned(ARENA_SIZE).unwrap();
+            init(Some(Arena::new(ptr, capacity)), usize::MAX);
+        });
+    }
+
+    #[test]
+    #[cfg(feature = "vmi-consume")]
+    fn foreign_buf_as_foreign() {
+        setup();
+        let foreign = |bytes: &[u8]| {
+            let t = SharedBuf::from_bytes(bytes).unwrap().into_transport();
+            ForeignBuf::from_transport(t).unwrap()
+        };
+
+        let buf = foreign(&0x1122_3344_5566_7788u64.to_ne_bytes());
+        let value = buf.as_foreign::<u64>().unwrap();
+        assert_eq!(*value.get(), 0x1122_3344_5566_7788);
+        // the value is a copy, dropping it leaves the buffer intact
+        drop(value);
+        assert_eq!(buf.as_ref(), 0x1122_3344_5566_7788u64.to_ne_bytes());
+
+        assert!(matches!(
+            foreign(&[0; 4]).as_foreign::<u64>(),
+            Err(ConversionError::TooSmall(8, 4))
+        ));
+        let (_, tail) = foreign(&[0; 16]).split_at(1).unwrap();
+        assert!(matches!(
+            tail.as_foreign::<u64>(),
+            Err(ConversionError::Misaligned(8, _))
+        ));
+    }
+
+    #[test]
+    #[cfg(feature = "vmi-consume")]
+    fn split_at_refcount() {
+        setup();
+        let alloc = ALLOC.get().unwrap();
+        let count = |origin: Option<Origin>| {
+            let refs = origin.unwrap().refs;
+            alloc.refs(refs).load(Ordering::Acquire)
+        };
+        let t = SharedBuf::from_bytes(b"abcdef").unwrap().into_transport();
+        let buf = ForeignBuf::from_transport(t).unwrap();
+        assert!(buf.origin.is_none());
+
+        // the first split allocates a counter for both parts
+        let (head, tail) = buf.split_at(2).unwrap();
+        assert_eq!((head.as_ref(), tail.as_ref()), (&b"ab"[..], &b"cdef"[..]));
+        assert_eq!(count(head.origin), 2);
+
+        // splitting a part again passes its reference on and shares the counter
+        let (c, def) = tail.split_at(1).unwrap();
+        assert_eq!(def.origin.unwrap().refs, head.origin.unwrap().refs);
+        assert_eq!(count(def.origin), 3);
+        drop(head);
+        drop(c);
+        assert_eq!(count(def.origin), 1);
+        assert_eq!(def.as_ref(), b"def");
+
+        assert!(matches!(def.split_at(0), Err(Error::EmptyBuffer)));
+        let t = SharedBuf::from_bytes(b"ab").unwrap().into_transport();
+        let buf = ForeignBuf::from_transport(t).unwrap();
+        assert!(matches!(buf.split_at(2), Err(Error::EmptyBuffer)));
+    }
+
+    #[test]
+    #[cfg(feature = "vmi-consume")]
+    fn retained_buf_origin() {
+        setup();
+        let alloc = ALLOC.get().unwrap();
+        let retained = RetainedBuf::new(4).unwrap();
+        let mut shared = retained.share();
+        assert!(!retained.is_idle());
+        shared.try_copy_from(b"wxyz").unwrap();
+
+        // the received buffer holds the reference of the shared one
+        let foreign = ForeignBuf::from_transport(shared.into_transport()).unwrap();
+        let origin = foreign.origin.unwrap();
+        assert_eq!(origin.refs, retained.refs);
+
+        // splitting reuses the counter of the retained buffer
+        let (head, tail) = foreign.split_at(1).unwrap();
+        assert_eq!(alloc.refs(retained.refs).load(Ordering::Acquire), 3);
+        assert_eq!(tail.as_ref(), b"xyz");
+        drop(head);
+        assert!(!retained.is_idle());
+        drop(tail);
+        assert!(retained.is_idle());
+
+        // concatenating copies the content without taking a reference
+        let parts = [SharedBuf::from_bytes(b"ab").unwrap(), retained.share()];
+        let joined = SharedBuf::concat(&parts).unwrap();
+        assert_eq!(alloc.refs(retained.refs).load(Ordering::Acquire), 2);
+        assert_eq!(joined.as_bytes(), b"abwxyz");
+        assert_eq!(joined.refs, 0);
+        joined.deallocate();
+        for part in parts {
+            part.deallocate();
+        }
+        assert!(retained.is_idle());
+    }
+
+    #[test]
+    #[cfg(feature = "vmi-consume")]
+    fn retained_buf_dropped_share() {
+        setup();
+        let retained = RetainedBuf::new(4).unwrap();
+
+        // a share which is never passed to the peer returns the buffer when dropped
+        drop(retained.share());
+        assert!(retained.is_idle());
+
+        // the reference counter of a received buffer has to be aligned
+        let t = retained.share().into_transport();
+        let t = crate::vmi::Transport::new(t.primary() + (1 << 32), t.secondary());
+        assert!(matches!(
+            ForeignBuf::from_transport(t),
+            Err(ExitCode::Ptr(_))
+        ));
+    }
+
+    #[test]
+    #[cfg(feature = "vmi-consume")]
+    fn foreign_buf_chunks() {
+        setup();
+        let data = (0..100u8).collect::<Vec<_>>();
+        let t = SharedBuf::from_bytes(&data).unwrap().into_transport();
+        let buf = ForeignBuf::from_transport(t).unwrap();
+
+        // six full blocks followed by the remaining four bytes
+        let lens = buf.chunks(16).map(<[u8]>::len).collect::<Vec<_>>();
+        assert_eq!(lens, [16, 16, 16, 16, 16, 16, 4]);
+        assert!(buf.chunks(16).flatten().eq(data.iter()));
+
+        let mut exact = buf.chunks_exact(16);
+        assert_eq!(exact.by_ref().count(), 6);
+        assert_eq!(exact.remainder(), &data[96..]);
+        // views into the arena instead of copies
+        assert_eq!(
+            buf.chunks(16).next().unwrap().as_ptr(),
+            buf.as_ref().as_ptr()
+        );
+    }
+
+    #[test]
+    fn checked_copy() {
+        let src = [1u8, 2, 3];
+        let mut dst = [0u8; 4];
+        assert!(matches!(copy_checked(&src, &mut dst), Ok(3)));
+        assert_eq!(dst, [1, 2, 3, 0]);
+
+        let mut small = [0u8; 2];
+        assert!(matches!(
+            copy_checked(&src, &mut small),
+            Err(BufError::DestinationTooSmall(3, 2))
+        ));
+        assert_eq!(small, [0, 0]);
+
+        assert!(matches!(len_checked(16, 16), Ok(16)));
+        assert!(matches!(
+            len_checked(17, 16),
+            Err(BufError::TooLarge(17, 16))
+        ));
+    }
+
+    #[test]
+    fn transported_buf_bounds() {
+        assert!(matches!(buf_within(0, 4096, 4096, usize::MAX), Ok(())));
+        assert!(matches!(buf_within(4000, 96, 4096, usize::MAX), Ok(())));
+        assert!(matches!(
+            buf_within(4000, 97, 4096, usize::MAX),
+            Err(Error::TransportTooLarge)
+        ));
+        assert!(matches!(
+            buf_within(0, usize::MAX, 4096, usize::MAX),
+            Err(Error::TransportTooLarge)
+        ));
+        assert!(matches!(buf_within(0, 64, 4096, 64), Ok(())));
+        assert!(matches!(
+            buf_within(0, 65, 4096, 64),
+            Err(Error::TransportTooLarge)
+        ));
+    }
+
+    #[test]
+    fn realloc_buf() {
+        #[repr(C, align(4096))]
+        struct Backing([u8; 0x4000]);
+        let mut backing = Backing([0; 0x4000]);
+        let arena = Arena::new(
+            NonNull::new(backing.0.as_mut_ptr()).unwrap(),
+            AlignedNonZeroUsize::new_aligned(backing.0.len()).unwrap(),
+        );
+        let alloc =
+            AllocImpl::<spin::Mutex<()>, ErrOnOom>::new(ErrOnOom, arena, usize::MAX).unwrap();
+
+        let mut buf = unsafe { alloc.alloc_buf(16) }.unwrap();
+        buf.as_mut().fill(7);
+        let addr = buf.ptr;
+
+        // the most recent allocation grows in place, the grown tail is zeroed
+        let mut grown = unsafe { alloc.realloc_buf(buf, 256) }.ok().unwrap();
+        assert_eq!(grown.ptr, addr);
+        assert_eq!(grown.len(), 256);
+        assert_eq!(&grown.as_ref()[..16], &[7; 16]);
+        assert!(grown.as_ref()[16..].iter().all(|b| *b == 0));
+        grown.as_mut().fill(9);
+
+        // blocked by a subsequent allocation, the content is moved
+        let blocker = unsafe { alloc.alloc_buf(16) }.unwrap();
+        let moved = unsafe { alloc.realloc_buf(grown, 512) }.ok().unwrap();
+        assert_ne!(moved.ptr, addr);
+        assert_eq!(moved.len(), 512);
+        assert_eq!(&moved.as_ref()[..256], &[9; 256]);
+        assert!(moved.as_ref()[256..].iter().all(|b| *b == 0));
+
+        // shrinking is always done in place
+        let addr = moved.ptr;
+        let shrunk = unsafe { alloc.realloc_buf(moved, 8) }.ok().unwrap();
+        assert_eq!(shrunk.ptr, addr);
+        assert_eq!(shrunk.as_ref(), &[9; 8]);
+
+        // the buffer survives a failed resize
+        let Err((shrunk, Error::OutOfMemory)) = (unsafe { alloc.realloc_buf(shrunk, 0x8000) })
+        else {
+            panic!("expected out of memory");
+        };
+        assert_eq!(shrunk.ptr, addr);
+        assert_eq!(shrunk.as_ref(), &[9; 8]);
+        let Err((shrunk, Error::EmptyBuffer)) = (unsafe { alloc.realloc_buf(shrunk, 0) }) else {
+            panic!("expected empty buffer");
+        };
+        assert_eq!(shrunk.as_ref(), &[9; 8]);
+
+        alloc.dealloc_buf(shrunk.ptr, shrunk.capacity);
+        alloc.dealloc_buf(blocker.ptr, blocker.capacity);
+    }
+}
@@ -0,0 +1,92 @@
+use crate::TypeSignature;
+use crate::mem::{Error, ForeignBuf, OffsetPtr, SharedBuf};
+use core::ffi::CStr;
+use core::num::NonZeroUsize;
+
+/// NUL-terminated string allocated for sharing with the VMI peer, which receives it as
+/// `ForeignCStr`. It is transported like a `SharedBuf` covering the string including the NUL.
+#[repr(transparent)]
+pub struct OwnedCStr {
+    pub(crate) buf: SharedBuf,
+}
+
+impl OwnedCStr {
+    /// Allocate a buffer in the shared memory and copy `s` including its NUL terminator into it.
+    pub fn new(s: &CStr) -> Result<Self, Error> {
+        SharedBuf::from_bytes(s.to_bytes_with_nul()).map(|buf| Self { buf })
+    }
+
+    pub fn as_cstr(&self) -> &CStr {
+        // the buffer was filled from a `CStr` and is not modified afterward
+        CStr::from_bytes_with_nul(self.buf.as_bytes()).unwrap()
+    }
+}
+
+/// NUL-terminated string allocated by the VMI peer. The terminator is verified when the string is
+/// received, a buffer without any NUL byte is rejected with `ExitCode::InvalidValue`.
+pub struct ForeignCStr {
+    pub(crate) buf: ForeignBuf,
+}
+
+impl ForeignCStr {
+    /// Wrap the received buffer, if it contains a NUL byte
+    pub(crate) fn new(buf: ForeignBuf) -> Option<Self> {
+        CStr::from_bytes_until_nul(buf.as_ref()).ok()?;
+        Some(Self { buf })
+    }
+
+    /// The string up to the first NUL byte of the buffer. Any bytes behind it are ignored.
+    pub fn as_cstr(&self) -> &CStr {
+        // the terminator was verified on reception
+        CStr::from_bytes_until_nul(self.buf.as_ref()).unwrap()
+    }
+
+    /// Take the underlying buffer including the terminator and any bytes behind it.
+    pub fn into_buf(self) -> ForeignBuf {
+        self.buf
+    }
+}
+
+macro_rules! impl_type_signature_for_cstr {
+    ($($t:ident),*) => {
+        $(
+        impl TypeSignature for $t {
+            const SIGNATURE: u64 = {
+                let mut h = crate::hash::SignatureHasher::new();
+                h.write(0u64.to_le_bytes().as_slice());
+                h.write(b"ShareableCStr");
+                h.write(
+                    <OffsetPtr<u8> as TypeSignature>::SIGNATURE
+                        .to_le_bytes()
+                        .as_slice(),
+                );
+                h.write(1u64.to_le_bytes().as_slice());
+                h.write(
+                    <NonZeroUsize as TypeSignature>::SIGNATURE
+                        .to_le_bytes()
+                        .as_slice(),
+                );
+                h.finish()
+            };
+            const IS_PRIMITIVE: bool = false;
+            #[cfg(feature = "vmi-consume")]
+            fn name() -> String {
+                String::from(stringify!($t))
+            }
+        }
+        )*
+    };
+}
+
+impl_type_signature_for_cstr!(OwnedCStr, ForeignCStr);
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn signature() {
+        assert_eq!(OwnedCStr::SIGNATURE, ForeignCStr::SIGNATURE);
+        assert_ne!(OwnedCStr::SIGNATURE, SharedBuf::SIGNATURE);
+    }
+}
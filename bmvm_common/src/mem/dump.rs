@@ -0,0 +1,108 @@
+use crate::mem::Flags;
+use std::io::{Error, ErrorKind, Read, Result, Write};
+
+/// Magic bytes at the start of every memory dump.
+pub const DUMP_MAGIC: [u8; 8] = *b"BMVMDUMP";
+
+/// Header preceding the contents of each region in a memory dump. All fields are encoded as
+/// little-endian, followed by `len` bytes of region data:
+///
+/// | Offset | Size | Field   |
+/// |--------|------|---------|
+/// | 0      | 8    | `paddr` |
+/// | 8      | 8    | `vaddr` |
+/// | 16     | 8    | `len`   |
+/// | 24     | 1    | `flags` |
+/// | 25     | 7    | padding |
+///
+/// Regions without a layout table entry (e.g. the paging structures) use a `vaddr` of zero and
+/// empty flags.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DumpHeader {
+    pub paddr: u64,
+    pub vaddr: u64,
+    pub len: u64,
+    pub flags: Flags,
+}
+
+impl DumpHeader {
+    pub const SIZE: usize = 32;
+
+    pub fn write_to<W: Write>(&self, w: &mut W) -> Result<()> {
+        let mut buf = [0u8; Self::SIZE];
+        buf[0..8].copy_from_slice(&self.paddr.to_le_bytes());
+        buf[8..16].copy_from_slice(&self.vaddr.to_le_bytes());
+        buf[16..24].copy_from_slice(&self.len.to_le_bytes());
+        buf[24] = self.flags.bits();
+        w.write_all(&buf)
+    }
+
+    /// Read the next header. Returns `None` if the reader is exhausted.
+    pub fn read_from<R: Read>(r: &mut R) -> Result<Option<Self>> {
+        let mut buf = [0u8; Self::SIZE];
+        let mut filled = 0;
+        while filled < Self::SIZE {
+            match r.read(&mut buf[filled..])? {
+                0 if filled == 0 => return Ok(None),
+                0 => return Err(ErrorKind::UnexpectedEof.into()),
+                n => filled += n,
+            }
+        }
+
+        let u64_at =
+            |offset: usize| u64::from_le_bytes(buf[offset..offset + 8].try_into().unwrap());
+        Ok(Some(Self {
+            paddr: u64_at(0),
+            vaddr: u64_at(8),
+            len: u64_at(16),
+            flags: Flags::from_bits_retain(buf[24]),
+        }))
+    }
+}
+
+/// Write the dump magic, which has to precede the first region.
+pub fn write_dump_magic<W: Write>(w: &mut W) -> Result<()> {
+    w.write_all(&DUMP_MAGIC)
+}
+
+/// Read a complete memory dump as written by `Module::dump_memory`.
+pub fn read_dump<R: Read>(r: &mut R) -> Result<Vec<(DumpHeader, Vec<u8>)>> {
+    let mut magic = [0u8; DUMP_MAGIC.len()];
+    r.read_exact(&mut magic)?;
+    if magic != DUMP_MAGIC {
+        return Err(Error::new(ErrorKind::InvalidData, "not a bmvm memory dump"));
+    }
+
+    let mut regions = Vec::new();
+    while let Some(header) = DumpHeader::read_from(r)? {
+        let mut data = vec![0u8; header.len as usize];
+        r.read_exact(&mut data)?;
+        regions.push((header, data));
+    }
+
+    Ok(regions)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn round_trip() {
+        let header = DumpHeader {
+            paddr: 0x1000,
+            vaddr: 0xffff_8000_0000_1000,
+            len: 3,
+            flags: Flags::PRESENT | Flags::DATA_WRITE,
+        };
+
+        let mut buf = Vec::new();
+        write_dump_magic(&mut buf).unwrap();
+        header.write_to(&mut buf).unwrap();
+        buf.extend_from_slice(&[1, 2, 3]);
+
+        let regions = read_dump(&mut buf.as_slice()).unwrap();
+        assert_eq!(regions, vec![(header, vec![1, 2, 3])]);
+        assert!(read_dump(&mut &buf[1..]).is_err());
+    }
+}
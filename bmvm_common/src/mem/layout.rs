@@ -49,6 +49,86 @@ impl LayoutTable {
             .find(|(_, e)| e.flags().intersects(flag))
             .map(|(i, e)| (i, *e))
     }
+
+    /// Check if both tables describe the same regions in the same order, i.e. the number of
+    /// present entries as well as their flags and sizes match. The addresses may differ, e.g. to
+    /// validate a snapshot of one guest before restoring it into another.
+    pub fn is_compatible_with(&self, other: &LayoutTable) -> bool {
+        let mut this = self.entries.iter().filter(|e| e.is_present());
+        let mut other = other.entries.iter().filter(|e| e.is_present());
+        loop {
+            match (this.next(), other.next()) {
+                (None, None) => return true,
+                (Some(a), Some(b)) if a.flags() == b.flags() && a.size() == b.size() => continue,
+                _ => return false,
+            }
+        }
+    }
+
+    /// All differences preventing `is_compatible_with`, empty if the tables are compatible. Entries
+    /// are compared by their position among the present entries.
+    #[cfg(feature = "vmi-consume")]
+    pub fn compatibility_report(&self, other: &LayoutTable) -> Vec<LayoutMismatch> {
+        let (this, other) = (self.as_vec_present(), other.as_vec_present());
+        let mut report = Vec::new();
+        if this.len() != other.len() {
+            report.push(LayoutMismatch::RegionCount {
+                this: this.len(),
+                other: other.len(),
+            });
+        }
+
+        for (index, (a, b)) in this.iter().zip(other.iter()).enumerate() {
+            if a.flags() != b.flags() {
+                report.push(LayoutMismatch::Flags {
+                    index,
+                    this: a.flags(),
+                    other: b.flags(),
+                });
+            }
+            if a.size() != b.size() {
+                report.push(LayoutMismatch::Size {
+                    index,
+                    this: a.size(),
+                    other: b.size(),
+                });
+            }
+        }
+        report
+    }
+}
+
+/// Difference between two layout tables, see `LayoutTable::compatibility_report`
+#[cfg(feature = "vmi-consume")]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LayoutMismatch {
+    /// The tables contain a different number of present entries
+    RegionCount { this: usize, other: usize },
+    /// The present entries at `index` carry different flags
+    Flags {
+        index: usize,
+        this: Flags,
+        other: Flags,
+    },
+    /// The present entries at `index` differ in size (bytes)
+    Size { index: usize, this: u64, other: u64 },
+}
+
+#[cfg(feature = "vmi-consume")]
+impl core::fmt::Display for LayoutMismatch {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            LayoutMismatch::RegionCount { this, other } => {
+                write!(f, "{} regions, but the other table has {}", this, other)
+            }
+            LayoutMismatch::Flags { index, this, other } => {
+                write!(f, "region {} has flags {:?}, but {:?}", index, this, other)
+            }
+            LayoutMismatch::Size { index, this, other } => {
+                write!(f, "region {} has size {:#x}, but {:#x}", index, this, other)
+            }
+        }
+    }
 }
 
 pub struct LayoutTableIter<'a> {
@@ -110,6 +190,7 @@ bitflags! {
     ///         10 -> Shared Foreign
     ///         11 -> Shared Owned
     ///     Else: Unused
+    /// - 6: Read-only mapping of a host file, see `LayoutTableEntry::tail_padding`
     #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
     pub struct Flags: u8 {
         /// Present bit - if set, the entry is valid
@@ -124,10 +205,15 @@ bitflags! {
         // Data-specific access flags (bits 4-5)
         const DATA_READ = 0b00 << 4;
         const DATA_WRITE = 0b01 << 4;
+        /// Read-only alias of the shared memory, through which data owned by the peer is read
+        const DATA_FOREIGN = 0b10 << 4;
         const DATA_SHARED = 0b11 << 4;
 
         // Mask for data access bits
         const DATA_ACCESS_MASK = 0b11 << 4;
+
+        /// Read-only mapping of a host file
+        const MAPPED_FILE = 1 << 6;
     }
 }
 
@@ -186,8 +272,9 @@ impl Flags {
         match self.bits() >> 4 & 0b11 {
             0b00 => Some(DataAccessMode::Read),
             0b01 => Some(DataAccessMode::Write),
+            0b10 => Some(DataAccessMode::Foreign),
             0b11 => Some(DataAccessMode::Shared),
-            _ => panic!("Invalid data access mode"),
+            _ => unreachable!(),
         }
     }
 
@@ -202,6 +289,7 @@ impl Flags {
         self.data_access_mode().is_some_and(|m| match m {
             DataAccessMode::Read => false,
             DataAccessMode::Write => true,
+            DataAccessMode::Foreign => false,
             DataAccessMode::Shared => true,
         })
     }
@@ -219,6 +307,7 @@ impl Flags {
         *self |= match mode {
             DataAccessMode::Read => Flags::DATA_READ,
             DataAccessMode::Write => Flags::DATA_WRITE,
+            DataAccessMode::Foreign => Flags::DATA_FOREIGN,
             DataAccessMode::Shared => Flags::DATA_SHARED,
         };
 
@@ -231,6 +320,7 @@ impl Flags {
 pub enum DataAccessMode {
     Read,
     Write,
+    Foreign,
     Shared,
 }
 
@@ -264,15 +354,18 @@ pub struct LayoutTableEntry(u128);
 ///         10 -> Shared Foreign
 ///         11 -> Shared Owned
 ///     Else: Unsued
+/// 6: Mapped host file
 /// 8-27: multiplicator of pages
 /// 28-63: physical starting address
 /// 64-99: virtual starting address
-/// 100-127: padding
+/// 100-111: bytes at the end of the last page not covered by the content (mapped files only)
+/// 112-127: padding
 impl LayoutTableEntry {
     const MASK_RETRIEVE_FLAGS: u128 = 0xff;
     const MASK_RETRIEVE_SIZE: u128 = 0xf_ffff << 8;
     const MASK_RETRIEVE_PADDR: u128 = 0xf_ffff_ffff << 28;
     const MASK_RETRIEVE_VADDR: u128 = 0xf_ffff_ffff << 64;
+    const MASK_RETRIEVE_TAIL: u128 = 0xfff << 100;
 
     /// Creates a new LayoutTableEntry with the given parameters.
     ///
@@ -349,6 +442,23 @@ impl LayoutTableEntry {
         self
     }
 
+    /// Set the number of bytes at the end of the last page, which are not covered by the content
+    /// of the region, e.g. the zero padding behind a mapped file. Limited to a page.
+    pub const fn set_tail_padding(mut self, bytes: u16) -> Self {
+        assert!(
+            (bytes as u64) < DefaultAlign::ALIGNMENT,
+            "tail padding must be smaller than a page"
+        );
+        self.0 &= !Self::MASK_RETRIEVE_TAIL;
+        self.0 |= (bytes as u128) << 100;
+        self
+    }
+
+    /// Number of bytes at the end of the last page, which are not covered by the region content
+    pub const fn tail_padding(&self) -> u16 {
+        ((self.0 & Self::MASK_RETRIEVE_TAIL) >> 100) as u16
+    }
+
     /// Checks if the entry is present
     pub const fn is_present(&self) -> bool {
         self.flags().contains(Flags::PRESENT)
@@ -508,6 +618,70 @@ mod test {
         assert_eq!(want, entry.0, "wnat {:x} but got {:x}", want, entry.0);
     }
 
+    #[test]
+    fn layout_table_entry_tail_padding() {
+        let entry = LayoutTableEntry::empty()
+            .set_len(3)
+            .set_vaddr(VirtAddr::new_unchecked(0x0000_7fff_ffff_f000))
+            .set_flags(Flags::PRESENT | Flags::MAPPED_FILE)
+            .set_tail_padding(0xfff);
+        assert_eq!(entry.tail_padding(), 0xfff);
+        assert_eq!(entry.vaddr_raw(), 0x0000_7fff_ffff_f000);
+        assert_eq!(entry.pages(), 3);
+        assert_eq!(entry.set_tail_padding(1).tail_padding(), 1);
+        assert!(entry.flags().contains(Flags::MAPPED_FILE));
+    }
+
+    #[cfg(feature = "vmi-consume")]
+    #[test]
+    fn layout_table_compatibility() {
+        fn table(entries: &[(u64, u32, Flags)]) -> LayoutTable {
+            let entries = entries
+                .iter()
+                .map(|&(addr, pages, flags)| {
+                    LayoutTableEntry::empty()
+                        .set_paddr(PhysAddr::new_unchecked(addr))
+                        .set_vaddr(VirtAddr::new_truncate(addr))
+                        .set_len(pages)
+                        .set_flags(flags | Flags::PRESENT)
+                })
+                .collect::<Vec<_>>();
+            LayoutTable::from_vec(&entries).unwrap()
+        }
+
+        let base = table(&[(0x1000, 1, Flags::DATA_READ), (0x10000, 4, Flags::CODE)]);
+        // the same regions at different addresses
+        let moved = table(&[(0x2000, 1, Flags::DATA_READ), (0x40000, 4, Flags::CODE)]);
+        assert!(base.is_compatible_with(&moved));
+        assert!(base.compatibility_report(&moved).is_empty());
+
+        let changed = table(&[(0x1000, 1, Flags::DATA_WRITE), (0x10000, 8, Flags::CODE)]);
+        assert!(!base.is_compatible_with(&changed));
+        assert_eq!(
+            base.compatibility_report(&changed),
+            [
+                LayoutMismatch::Flags {
+                    index: 0,
+                    this: Flags::DATA_READ | Flags::PRESENT,
+                    other: Flags::DATA_WRITE | Flags::PRESENT,
+                },
+                LayoutMismatch::Size {
+                    index: 1,
+                    this: 0x4000,
+                    other: 0x8000,
+                },
+            ]
+        );
+
+        let shorter = table(&[(0x1000, 1, Flags::DATA_READ)]);
+        assert!(!base.is_compatible_with(&shorter));
+        assert!(!shorter.is_compatible_with(&base));
+        assert_eq!(
+            base.compatibility_report(&shorter),
+            [LayoutMismatch::RegionCount { this: 2, other: 1 }]
+        );
+    }
+
     #[test]
     fn flag_build() {
         assert_eq!(Flags::empty().bits(), 0);
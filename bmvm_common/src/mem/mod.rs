@@ -2,13 +2,26 @@ mod addr;
 mod align;
 mod alloc;
 mod bits;
+mod cstr;
+#[cfg(feature = "vmi-consume")]
+mod dump;
 mod layout;
+#[cfg(feature = "vmi-consume")]
+mod stats;
 
 pub use addr::*;
 pub use align::*;
 pub use alloc::*;
 pub use bits::*;
+pub use cstr::*;
+#[cfg(feature = "vmi-consume")]
+pub use dump::*;
 pub use layout::*;
+#[cfg(feature = "vmi-consume")]
+pub use stats::*;
+
+#[cfg(all(test, feature = "vmi-consume"))]
+pub(crate) use alloc::test::setup as setup_test_alloc;
 
 #[inline]
 pub fn aligned_and_fits<A: Align>(from: u64, to: u64) -> bool {
@@ -0,0 +1,125 @@
+use crate::mem::{Align, Flags, LayoutTable, Page4KiB, VirtAddr};
+
+const PAGE_FLAG_PRESENT: u64 = 1;
+const PAGE_FLAG_ACCESSED: u64 = 1 << 5;
+const PAGE_FLAG_DIRTY: u64 = 1 << 6;
+const PAGE_FLAG_HUGE: u64 = 1 << 7;
+
+// 52-bit physical address mask (bits 51:12) in entries
+const ADDR_MASK: u64 = 0x000F_FFFF_FFFF_F000;
+
+/// Number of 4KiB pages of a layout table region the guest accessed or wrote to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RegionStat {
+    pub paddr: u64,
+    pub vaddr: u64,
+    pub flags: Flags,
+    pub pages: u64,
+    pub accessed: u64,
+    pub dirty: u64,
+}
+
+/// Collect the access statistics for every present layout table entry by walking the paging
+/// structure rooted at `pml4` and evaluating the accessed/dirty bits of the leaf entries.
+///
+/// `read` returns the page table entry at the given guest physical address, or `None` if the
+/// address is not backed. Pages mapped by a huge leaf entry share its bits, therefore the result is
+/// only exact if all regions are mapped via 4KiB pages.
+pub fn region_stats(
+    table: &LayoutTable,
+    pml4: u64,
+    read: impl Fn(u64) -> Option<u64>,
+) -> Vec<RegionStat> {
+    let mut stats = Vec::new();
+    for entry in table.into_iter() {
+        let mut stat = RegionStat {
+            paddr: entry.paddr_raw(),
+            vaddr: entry.vaddr_raw(),
+            flags: entry.flags(),
+            pages: entry.pages() as u64,
+            accessed: 0,
+            dirty: 0,
+        };
+
+        for page in 0..stat.pages {
+            let vaddr = VirtAddr::new(stat.vaddr + page * Page4KiB::ALIGNMENT);
+            let Some(leaf) = leaf_entry(vaddr, pml4, &read) else {
+                continue;
+            };
+            if leaf & PAGE_FLAG_ACCESSED != 0 {
+                stat.accessed += 1;
+            }
+            if leaf & PAGE_FLAG_DIRTY != 0 {
+                stat.dirty += 1;
+            }
+        }
+
+        stats.push(stat);
+    }
+
+    stats
+}
+
+/// Walk the paging structure and return the leaf entry mapping `vaddr`.
+fn leaf_entry(vaddr: VirtAddr, pml4: u64, read: &impl Fn(u64) -> Option<u64>) -> Option<u64> {
+    let indices = [
+        vaddr.p4_index(),
+        vaddr.p3_index(),
+        vaddr.p2_index(),
+        vaddr.p1_index(),
+    ];
+
+    let mut table = pml4;
+    for (level, idx) in indices.into_iter().enumerate() {
+        let entry = read(table + (idx * size_of::<u64>()) as u64)?;
+        if entry & PAGE_FLAG_PRESENT == 0 {
+            return None;
+        }
+
+        // the PML4 can not contain huge entries, the PT only contains leaf entries
+        if level == indices.len() - 1 || (level > 0 && entry & PAGE_FLAG_HUGE != 0) {
+            return Some(entry);
+        }
+        table = entry & ADDR_MASK;
+    }
+
+    None
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::mem::LayoutTableEntry;
+    use std::collections::HashMap;
+
+    #[test]
+    fn accessed_and_dirty() {
+        let (pml4, pdpt, pd, pt) = (0x1000u64, 0x2000u64, 0x3000u64, 0x4000u64);
+        let vaddr = VirtAddr::new(0x400000);
+
+        let mut mem = HashMap::new();
+        mem.insert(pml4 + vaddr.p4_index() as u64 * 8, pdpt | PAGE_FLAG_PRESENT);
+        mem.insert(pdpt + vaddr.p3_index() as u64 * 8, pd | PAGE_FLAG_PRESENT);
+        mem.insert(pd + vaddr.p2_index() as u64 * 8, pt | PAGE_FLAG_PRESENT);
+        let base = pt + vaddr.p1_index() as u64 * 8;
+        mem.insert(
+            base,
+            PAGE_FLAG_PRESENT | PAGE_FLAG_ACCESSED | PAGE_FLAG_DIRTY,
+        );
+        mem.insert(base + 8, PAGE_FLAG_PRESENT | PAGE_FLAG_ACCESSED);
+        mem.insert(base + 16, PAGE_FLAG_PRESENT);
+
+        let mut table = LayoutTable::new();
+        table.entries[0] = LayoutTableEntry::empty()
+            .set_paddr(crate::mem::PhysAddr::new(0x400000))
+            .set_vaddr(vaddr)
+            .set_len(4)
+            .set_flags(Flags::PRESENT | Flags::DATA_WRITE);
+
+        let stats = region_stats(&table, pml4, |addr| mem.get(&addr).copied());
+        assert_eq!(stats.len(), 1);
+        assert_eq!(stats[0].pages, 4);
+        assert_eq!(stats[0].accessed, 2);
+        assert_eq!(stats[0].dirty, 1);
+    }
+}
@@ -0,0 +1,18 @@
+use crate::BMVM_METRICS_SIZE;
+
+/// Maximum length of a metric name in bytes
+pub const METRIC_NAME_MAX_LEN: usize = 55;
+
+/// Number of distinct metrics the table at `BMVM_METRICS` can hold. Further names are rejected.
+pub const METRICS_CAPACITY: usize = BMVM_METRICS_SIZE / size_of::<MetricSlot>();
+
+/// Entry of the metrics table. A slot is in use once `name_len` is non-zero, the guest writes the
+/// name before publishing its length. `value` is updated atomically.
+#[repr(C)]
+pub struct MetricSlot {
+    pub name_len: u8,
+    pub name: [u8; METRIC_NAME_MAX_LEN],
+    pub value: u64,
+}
+
+const _: () = assert!(size_of::<MetricSlot>() == 64);
@@ -3,7 +3,7 @@ use crate::hash::SignatureHasher;
 use crate::mem::{Error as MemError, Shared, alloc};
 #[allow(unused_imports)]
 use crate::typesignature::TypeSignature;
-use crate::vmi::{OwnedShareable, Transport};
+use crate::vmi::{OwnedShareable, Signature, Transport, is_scalar, scalar_to_raw};
 use alloc::string::String;
 use alloc::vec;
 use alloc::vec::Vec;
@@ -15,13 +15,30 @@ use alloc::vec::Vec;
 /// * be `repr(C)` or `repr(transparent)` (where the single field must implement `Msg`)
 #[sealed::sealed]
 pub trait Params: TypeSignature {
+    /// Type signature of every parameter in order. The signature of `Self` is combined from these,
+    /// so two parameter lists can be compared per parameter instead of only as a whole.
+    #[doc(hidden)]
+    const PARAM_SIGNATURES: &'static [Signature];
+
     // TODO: could this be a const field to improve startup time?
     fn strings() -> Vec<String>;
     fn into_transport(self) -> Result<Transport, MemError>;
+
+    /// Type signature of every parameter in order, see `vmi::function_signature`.
+    fn param_signatures() -> &'static [Signature] {
+        Self::PARAM_SIGNATURES
+    }
+
+    /// Number of parameters
+    fn count() -> usize {
+        Self::PARAM_SIGNATURES.len()
+    }
 }
 
 #[sealed::sealed]
 impl Params for () {
+    const PARAM_SIGNATURES: &'static [Signature] = &[];
+
     fn strings() -> Vec<String> {
         vec![]
     }
@@ -32,6 +49,8 @@ impl Params for () {
 
 #[sealed::sealed]
 impl<T: OwnedShareable> Params for (T,) {
+    const PARAM_SIGNATURES: &'static [Signature] = &[T::SIGNATURE];
+
     fn strings() -> Vec<String> {
         vec![T::name()]
     }
@@ -54,31 +73,34 @@ impl<T: OwnedShareable> TypeSignature for (T,) {
     fn name() -> String {
         T::name()
     }
+    unsafe fn is_valid(ptr: *const u8) -> bool {
+        unsafe { T::is_valid(ptr.add(core::mem::offset_of!(Self, 0))) }
+    }
 }
 
 macro_rules! for_each_function_signature {
     ($mac:ident) => {
-        $mac!("2" T1 T2);
-        $mac!("3" T1 T2 T3);
-        $mac!("4" T1 T2 T3 T4);
-        $mac!("5" T1 T2 T3 T4 T5);
-        $mac!("6" T1 T2 T3 T4 T5 T6);
-        $mac!("7" T1 T2 T3 T4 T5 T6 T7);
-        $mac!("8" T1 T2 T3 T4 T5 T6 T7 T8);
-        $mac!("9" T1 T2 T3 T4 T5 T6 T7 T8 T9);
-        $mac!("10" T1 T2 T3 T4 T5 T6 T7 T8 T9 T10);
-        $mac!("11" T1 T2 T3 T4 T5 T6 T7 T8 T9 T10 T11);
-        $mac!("12" T1 T2 T3 T4 T5 T6 T7 T8 T9 T10 T11 T12);
-        $mac!("13" T1 T2 T3 T4 T5 T6 T7 T8 T9 T10 T11 T12 T13);
-        $mac!("14" T1 T2 T3 T4 T5 T6 T7 T8 T9 T10 T11 T12 T13 T14);
-        $mac!("15" T1 T2 T3 T4 T5 T6 T7 T8 T9 T10 T11 T12 T13 T14 T15);
-        $mac!("16" T1 T2 T3 T4 T5 T6 T7 T8 T9 T10 T11 T12 T13 T14 T15 T16);
-        $mac!("17" T1 T2 T3 T4 T5 T6 T7 T8 T9 T10 T11 T12 T13 T14 T15 T16 T17);
+        $mac!("2" T1 0 T2 1);
+        $mac!("3" T1 0 T2 1 T3 2);
+        $mac!("4" T1 0 T2 1 T3 2 T4 3);
+        $mac!("5" T1 0 T2 1 T3 2 T4 3 T5 4);
+        $mac!("6" T1 0 T2 1 T3 2 T4 3 T5 4 T6 5);
+        $mac!("7" T1 0 T2 1 T3 2 T4 3 T5 4 T6 5 T7 6);
+        $mac!("8" T1 0 T2 1 T3 2 T4 3 T5 4 T6 5 T7 6 T8 7);
+        $mac!("9" T1 0 T2 1 T3 2 T4 3 T5 4 T6 5 T7 6 T8 7 T9 8);
+        $mac!("10" T1 0 T2 1 T3 2 T4 3 T5 4 T6 5 T7 6 T8 7 T9 8 T10 9);
+        $mac!("11" T1 0 T2 1 T3 2 T4 3 T5 4 T6 5 T7 6 T8 7 T9 8 T10 9 T11 10);
+        $mac!("12" T1 0 T2 1 T3 2 T4 3 T5 4 T6 5 T7 6 T8 7 T9 8 T10 9 T11 10 T12 11);
+        $mac!("13" T1 0 T2 1 T3 2 T4 3 T5 4 T6 5 T7 6 T8 7 T9 8 T10 9 T11 10 T12 11 T13 12);
+        $mac!("14" T1 0 T2 1 T3 2 T4 3 T5 4 T6 5 T7 6 T8 7 T9 8 T10 9 T11 10 T12 11 T13 12 T14 13);
+        $mac!("15" T1 0 T2 1 T3 2 T4 3 T5 4 T6 5 T7 6 T8 7 T9 8 T10 9 T11 10 T12 11 T13 12 T14 13 T15 14);
+        $mac!("16" T1 0 T2 1 T3 2 T4 3 T5 4 T6 5 T7 6 T8 7 T9 8 T10 9 T11 10 T12 11 T13 12 T14 13 T15 14 T16 15);
+        $mac!("17" T1 0 T2 1 T3 2 T4 3 T5 4 T6 5 T7 6 T8 7 T9 8 T10 9 T11 10 T12 11 T13 12 T14 13 T15 14 T16 15 T17 16);
     };
 }
 
 macro_rules! impl_params_and_typesignature {
-    ($n:literal $($t:ident)*) => (
+    ($n:literal $($t:ident $idx:tt)*) => (
         #[repr(C)]
         pub struct ${concat(Tuple, $n)} <$($t),*>
         where
@@ -124,19 +146,30 @@ macro_rules! impl_params_and_typesignature {
             fn name() -> String {
                 String::default()
             }
+            unsafe fn is_valid(ptr: *const u8) -> bool {
+                true $(&& unsafe { $t::is_valid(ptr.add(core::mem::offset_of!(Self, $t))) })*
+            }
         }
 
         #[allow(unused_parens)]
         #[sealed::sealed]
         impl<$($t),*> Params for ($($t),*) where $($t: TypeSignature,)* {
+            const PARAM_SIGNATURES: &'static [Signature] = &[$($t::SIGNATURE),*];
+
             fn strings() -> Vec<String> {
                 vec![$($t::name()),*]
             }
 
             fn into_transport(self) -> Result<Transport, MemError> {
+                let ($($t,)+) = self;
+                // two scalars are passed directly via the transport registers
+                if [$(is_scalar::<$t>()),*].as_slice() == [true, true] {
+                    let raw = [$(scalar_to_raw($t)),*];
+                    return Ok(Transport::new(raw[0], raw[1]));
+                }
+
                 let mut owned = unsafe { alloc::<${concat(Tuple, $n)}<$($t),*>>() }?;
                 let this = owned.as_mut();
-                let ($($t,)+) = self;
                 $(
                     this.$t = $t;
                 )*
@@ -168,8 +201,52 @@ macro_rules! impl_params_and_typesignature {
             fn name() -> String {
                 String::default()
             }
+            unsafe fn is_valid(ptr: *const u8) -> bool {
+                true $(&& unsafe { $t::is_valid(ptr.add(core::mem::offset_of!(Self, $idx))) })*
+            }
         }
     );
 }
 
 for_each_function_signature!(impl_params_and_typesignature);
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use core::mem::offset_of;
+
+    /// Bytes of a zeroed `T`, which is valid for all types used below
+    fn zeroed<T>() -> Vec<u8> {
+        vec![0u8; size_of::<T>()]
+    }
+
+    #[test]
+    fn tuple_fields_validated() {
+        type Pair = (u32, bool);
+        let mut bytes = zeroed::<Pair>();
+        assert!(unsafe { Pair::is_valid(bytes.as_ptr()) });
+        bytes[offset_of!(Pair, 1)] = 2;
+        assert!(!unsafe { Pair::is_valid(bytes.as_ptr()) });
+
+        type Triple = (u8, char, bool);
+        let mut bytes = zeroed::<Triple>();
+        assert!(unsafe { Triple::is_valid(bytes.as_ptr()) });
+        let offset = offset_of!(Triple, 1);
+        bytes[offset..offset + 4].copy_from_slice(&0xD800u32.to_ne_bytes());
+        assert!(!unsafe { Triple::is_valid(bytes.as_ptr()) });
+
+        type Single = (bool,);
+        assert!(!unsafe { Single::is_valid([2u8].as_ptr()) });
+    }
+
+    #[test]
+    fn packed_tuple_fields_validated() {
+        type Packed = Tuple3<u64, bool, u16>;
+        let mut bytes = zeroed::<Packed>();
+        assert!(unsafe { Packed::is_valid(bytes.as_ptr()) });
+        bytes[offset_of!(Packed, T2)] = 1;
+        assert!(unsafe { Packed::is_valid(bytes.as_ptr()) });
+        bytes[offset_of!(Packed, T2)] = 0xff;
+        assert!(!unsafe { Packed::is_valid(bytes.as_ptr()) });
+    }
+}
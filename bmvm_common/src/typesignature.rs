@@ -1,3 +1,4 @@
+use crate::error::HostError;
 use core::num::NonZeroUsize;
 
 pub trait TypeSignature: Send + Sync {
@@ -5,10 +6,20 @@ pub trait TypeSignature: Send + Sync {
     const IS_PRIMITIVE: bool;
     #[cfg(feature = "vmi-consume")]
     fn name() -> String;
+
+    /// Check if the bytes written by the peer form a valid value of `Self`. Only types with
+    /// invalid bit patterns, e.g. `bool` or `char`, have to override this.
+    ///
+    /// # Safety
+    /// `ptr` must be valid for reads of `size_of::<Self>()` bytes.
+    #[doc(hidden)]
+    unsafe fn is_valid(_ptr: *const u8) -> bool {
+        true
+    }
 }
 
 macro_rules! impl_type_hash_for_primitive {
-    ($($prim:ty),* $(,)?) => {
+    ($($prim:ty $(=> $valid:expr)?),* $(,)?) => {
         $(
             impl TypeSignature for $prim {
                 const SIGNATURE: u64 = {
@@ -22,6 +33,11 @@ macro_rules! impl_type_hash_for_primitive {
                 fn name() -> String {
                     String::from(stringify!($prim))
                 }
+                $(
+                unsafe fn is_valid(ptr: *const u8) -> bool {
+                    ($valid)(ptr)
+                }
+                )?
             }
         )*
     };
@@ -39,6 +55,47 @@ impl TypeSignature for NonZeroUsize {
     fn name() -> String {
         String::from("NonZeroUsize")
     }
+
+    unsafe fn is_valid(ptr: *const u8) -> bool {
+        unsafe { ptr.cast::<usize>().read_unaligned() != 0 }
+    }
+}
+
+impl<T: TypeSignature> TypeSignature for Result<T, HostError> {
+    const SIGNATURE: u64 = {
+        let mut h = crate::hash::SignatureHasher::new();
+        h.write(0u64.to_le_bytes().as_slice());
+        h.write(b"Result");
+        h.write(T::SIGNATURE.to_le_bytes().as_slice());
+        h.write(1u64.to_le_bytes().as_slice());
+        h.write(HostError::SIGNATURE.to_le_bytes().as_slice());
+        h.finish()
+    };
+    const IS_PRIMITIVE: bool = false;
+    #[cfg(feature = "vmi-consume")]
+    fn name() -> String {
+        format!("Result<{}, HostError>", T::name())
+    }
+}
+
+impl<T: TypeSignature, const N: usize> TypeSignature for [T; N] {
+    const SIGNATURE: u64 = {
+        let mut h = crate::hash::SignatureHasher::new();
+        h.write(0u64.to_le_bytes().as_slice());
+        h.write(b"Array");
+        h.write(T::SIGNATURE.to_le_bytes().as_slice());
+        h.write((N as u64).to_le_bytes().as_slice());
+        h.finish()
+    };
+    const IS_PRIMITIVE: bool = false;
+    #[cfg(feature = "vmi-consume")]
+    fn name() -> String {
+        format!("[{}; {}]", T::name(), N)
+    }
+
+    unsafe fn is_valid(ptr: *const u8) -> bool {
+        (0..N).all(|i| unsafe { T::is_valid(ptr.add(i * size_of::<T>())) })
+    }
 }
 
 impl_type_hash_for_primitive!(
@@ -54,7 +111,8 @@ impl_type_hash_for_primitive!(
     i128,
     f32,
     f64,
-    bool,
+    bool => |ptr: *const u8| unsafe { ptr.read() <= 1 },
+    char => |ptr: *const u8| char::from_u32(unsafe { ptr.cast::<u32>().read_unaligned() }).is_some(),
     usize,
     (),
 );
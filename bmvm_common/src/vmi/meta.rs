@@ -1,4 +1,5 @@
-use crate::vmi::{Function, Signature};
+use crate::BMVM_META_FORMAT_VERSION;
+use crate::vmi::{Function, RECORD_HEADER_SIZE, Signature, record_header};
 use core::array::TryFromSliceError;
 use core::cmp::Ordering;
 use std::ffi::{CStr, CString, FromVecWithNulError, NulError};
@@ -30,6 +31,44 @@ pub enum Error {
     TooManyParameters { max: usize, actual: usize },
     #[error("too few parameters: expected {expected}, got {actual}")]
     TooFewParameters { expected: usize, actual: usize },
+    #[error(
+        "unsupported metadata format version {0}, expected {BMVM_META_FORMAT_VERSION}: the binary was built against an incompatible bmvm version"
+    )]
+    UnsupportedFormatVersion(u8),
+    #[error("record length mismatch: expected {expected} bytes, got {actual}")]
+    RecordLength { expected: usize, actual: usize },
+}
+
+/// Format version of the first record in `buf`, without checking if it is supported. `None` if
+/// `buf` does not contain a complete record header.
+#[cfg(feature = "vmi-consume")]
+pub fn record_version(buf: &[u8]) -> Option<u8> {
+    buf.get(..RECORD_HEADER_SIZE).map(|header| header[0])
+}
+
+/// Parse the header of the record at the start of `buf` and return its payload. Fails if the
+/// format version is not supported or the payload exceeds `buf`.
+#[cfg(feature = "vmi-consume")]
+fn read_record(buf: &[u8]) -> Result<&[u8]> {
+    if buf.len() < RECORD_HEADER_SIZE {
+        return Err(Error::TooShort {
+            expected: RECORD_HEADER_SIZE,
+            actual: buf.len(),
+        });
+    }
+
+    let header = read_u64(buf)?.to_le();
+    let version = header as u8;
+    if version != BMVM_META_FORMAT_VERSION {
+        return Err(Error::UnsupportedFormatVersion(version));
+    }
+
+    let len = (header >> 32) as usize;
+    buf.get(RECORD_HEADER_SIZE..RECORD_HEADER_SIZE + len)
+        .ok_or(Error::TooShort {
+            expected: RECORD_HEADER_SIZE + len,
+            actual: buf.len(),
+        })
 }
 
 #[cfg(feature = "vmi-consume")]
@@ -112,23 +151,34 @@ impl Ord for UpcallFn {
 
 #[cfg(feature = "vmi-consume")]
 impl UpcallFn {
+    /// Size of an encoded upcall pointer including the record header
+    pub const RECORD_SIZE: usize = RECORD_HEADER_SIZE + size_of::<Self>();
+
     #[allow(dead_code)]
     pub fn try_from_bytes_consumed(buf: &[u8]) -> Result<(Self, usize)> {
-        if buf.len() < size_of::<Self>() {
+        if buf.len() < Self::RECORD_SIZE {
             return Err(Error::TooShort {
-                expected: size_of::<Self>(),
+                expected: Self::RECORD_SIZE,
                 actual: buf.len(),
             });
         }
 
+        let payload = read_record(buf)?;
+        if payload.len() != size_of::<Self>() {
+            return Err(Error::RecordLength {
+                expected: size_of::<Self>(),
+                actual: payload.len(),
+            });
+        }
+
         let mut offset = 0;
-        let sig: Signature = read_u64(&buf[offset..])?;
+        let sig: Signature = read_u64(&payload[offset..])?;
         offset += size_of::<Signature>();
 
-        let func: FnPtr = FnPtr::try_from(read_u64(&buf[offset..])?)?;
+        let func: FnPtr = FnPtr::try_from(read_u64(&payload[offset..])?)?;
         offset += size_of::<FnPtr>();
 
-        Ok((Self { sig, func }, offset))
+        Ok((Self { sig, func }, RECORD_HEADER_SIZE + offset))
     }
 
     /// Try parsing a vector of `UpcallFn` from a byte buffer
@@ -169,9 +219,9 @@ impl FnCall {
     /// Serialize the `FnCall` to a byte vector, including debug information if either build in
     /// debug mode, or one of the following features are enabled: `vmi-debug`, `vmi-consume`.
     /// The `vmi-no-debug` feature overwrites the other features and enforces the omission of the
-    /// debug information.
+    /// debug information. The record is prefixed with its header, see `record_header`.
     pub fn to_bytes(&self) -> Vec<u8> {
-        let mut buf = Vec::new();
+        let mut buf = vec![0u8; RECORD_HEADER_SIZE];
         buf.extend(&self.sig.to_ne_bytes());
         buf.extend(self.name.as_bytes_with_nul());
 
@@ -196,6 +246,8 @@ impl FnCall {
             }
         }
 
+        let header = record_header(buf.len() - RECORD_HEADER_SIZE);
+        buf[..RECORD_HEADER_SIZE].copy_from_slice(&header.to_le_bytes());
         buf
     }
 
@@ -296,8 +348,8 @@ impl FnCall {
 /// Parsing implementation
 impl FnCall {
     const MIN_SIZE: usize = {
-        // signature u64
-        size_of::<u64>()
+        // record header and signature u64
+        RECORD_HEADER_SIZE + size_of::<u64>()
                 // Fn Name: min len 1 + null terminator
                 + size_of::<u8>() + size_of::<u8>()
     };
@@ -322,7 +374,14 @@ impl FnCall {
             });
         }
 
-        let sig = read_u64(&buf[0..8])?;
+        let buf = read_record(buf)?;
+        if buf.len() < Self::MIN_SIZE - RECORD_HEADER_SIZE {
+            return Err(Error::TooShort {
+                expected: Self::MIN_SIZE,
+                actual: RECORD_HEADER_SIZE + buf.len(),
+            });
+        }
+        let sig = read_u64(buf)?;
         if sig == 0 {
             return Err(Error::ZeroSignature);
         }
@@ -333,7 +392,10 @@ impl FnCall {
         offset += o;
 
         let (params, output) = if debug {
-            let param_count = buf[offset] as usize;
+            let param_count = *buf.get(offset).ok_or(Error::TooShort {
+                expected: RECORD_HEADER_SIZE + offset + 1,
+                actual: RECORD_HEADER_SIZE + buf.len(),
+            })? as usize;
             offset += 1;
 
             let mut params = Vec::with_capacity(param_count);
@@ -354,8 +416,16 @@ impl FnCall {
             offset += o;
             let output = if ret.is_empty() { None } else { Some(ret) };
 
+            if offset != buf.len() {
+                return Err(Error::RecordLength {
+                    expected: buf.len(),
+                    actual: offset,
+                });
+            }
             (params, output)
         } else {
+            // the length is known from the header, skip the debug information if present
+            offset = buf.len();
             (Vec::new(), None)
         };
 
@@ -366,7 +436,7 @@ impl FnCall {
                 debug_param_types: params,
                 debug_return_type: output,
             },
-            offset,
+            RECORD_HEADER_SIZE + offset,
         ))
     }
 
@@ -434,6 +504,13 @@ mod test {
 
     use super::*;
 
+    /// Prefix `payload` with a record header
+    fn record(payload: Vec<u8>) -> Vec<u8> {
+        let mut buf = record_header(payload.len()).to_le_bytes().to_vec();
+        buf.extend(payload);
+        buf
+    }
+
     #[cfg(any(
         all(debug_assertions, not(feature = "vmi-no-debug")),
         all(feature = "vmi-debug", not(feature = "vmi-no-debug")),
@@ -456,7 +533,7 @@ mod test {
         expect.extend(b"baz\0");
         expect.extend(b"qux\0");
 
-        assert_eq!(expect.as_slice(), meta.to_bytes().as_slice());
+        assert_eq!(record(expect).as_slice(), meta.to_bytes().as_slice());
     }
 
     #[cfg(not(any(
@@ -475,7 +552,7 @@ mod test {
         expect.extend(0x1234567890abcdefu64.to_le_bytes());
         expect.extend(b"foo\0");
 
-        assert_eq!(expect.as_slice(), meta.to_bytes().as_slice());
+        assert_eq!(record(expect).as_slice(), meta.to_bytes().as_slice());
     }
 
     #[cfg(feature = "vmi-consume")]
@@ -496,7 +573,7 @@ mod test {
 
         assert_eq!(
             expect,
-            FnCall::try_from_bytes(buf.as_slice(), true).unwrap()
+            FnCall::try_from_bytes(record(buf).as_slice(), true).unwrap()
         );
     }
 
@@ -516,7 +593,7 @@ mod test {
 
         assert_eq!(
             expect,
-            FnCall::try_from_bytes(buf.as_slice(), false).unwrap()
+            FnCall::try_from_bytes(record(buf).as_slice(), false).unwrap()
         );
     }
 
@@ -540,7 +617,7 @@ mod test {
 
         assert_eq!(
             expect,
-            FnCall::try_from_bytes(buf.as_slice(), true).unwrap()
+            FnCall::try_from_bytes(record(buf).as_slice(), true).unwrap()
         );
     }
 
@@ -554,7 +631,7 @@ mod test {
         buf.push(0);
 
         let expect = Error::ZeroSignature;
-        let result = FnCall::try_from_bytes(buf.as_slice(), true);
+        let result = FnCall::try_from_bytes(record(buf).as_slice(), true);
         assert!(matches!(result, Err(Error::ZeroSignature)));
     }
 
@@ -597,7 +674,7 @@ mod test {
         buf.push(2);
         buf.extend(b"bar\0");
 
-        let result = FnCall::try_from_bytes(buf.as_slice(), true);
+        let result = FnCall::try_from_bytes(record(buf).as_slice(), true);
         assert!(matches!(
             result,
             Err(Error::TooFewParameters {
@@ -658,4 +735,80 @@ mod test {
         let result = FnCall::try_from_bytes_vec(buf.as_slice(), true);
         assert!(matches!(result, Err(_)));
     }
+
+    #[cfg(feature = "vmi-consume")]
+    #[test]
+    fn from_bytes_unsupported_version() {
+        let mut buf = FnCall {
+            sig: 0x1234567890abcdef,
+            name: CString::new("foo").unwrap(),
+            debug_param_types: Vec::new(),
+            debug_return_type: None,
+        }
+        .to_bytes();
+        assert_eq!(record_version(&buf), Some(BMVM_META_FORMAT_VERSION));
+
+        buf[0] = BMVM_META_FORMAT_VERSION + 1;
+        assert!(matches!(
+            FnCall::try_from_bytes_vec(buf.as_slice(), true),
+            Err(Error::UnsupportedFormatVersion(v)) if v == BMVM_META_FORMAT_VERSION + 1
+        ));
+    }
+
+    #[cfg(feature = "vmi-consume")]
+    #[test]
+    fn from_bytes_vec_skips_debug() {
+        let calls = [("foo", 0x1234567890abcdef), ("another", 0xabcdef1234567890)];
+        let buf = calls
+            .iter()
+            .flat_map(|(name, sig)| {
+                FnCall {
+                    sig: *sig,
+                    name: CString::new(*name).unwrap(),
+                    debug_param_types: vec![CString::new("u64").unwrap()],
+                    debug_return_type: Some(CString::new("u32").unwrap()),
+                }
+                .to_bytes()
+            })
+            .collect::<Vec<_>>();
+
+        // the record length allows ignoring the debug information
+        let result = FnCall::try_from_bytes_vec(buf.as_slice(), false).unwrap();
+        assert_eq!(result.len(), 2);
+        assert_eq!(result[1].name.to_str(), Ok("another"));
+        assert!(result[1].debug_param_types.is_empty());
+    }
+
+    #[cfg(feature = "vmi-consume")]
+    #[test]
+    fn upcall_from_bytes() {
+        let mut buf = Vec::new();
+        for (sig, func) in [(0x1234u64, 0x4000u64), (0x5678, 0x4100)] {
+            buf.extend(record_header(2 * size_of::<u64>()).to_le_bytes());
+            buf.extend(sig.to_ne_bytes());
+            buf.extend(func.to_ne_bytes());
+        }
+        assert_eq!(buf.len(), 2 * UpcallFn::RECORD_SIZE);
+
+        let calls = UpcallFn::try_from_bytes_vec(&buf).unwrap();
+        assert_eq!(calls[1].sig, 0x5678);
+        assert_eq!(calls[1].func.as_u64(), 0x4100);
+
+        buf[UpcallFn::RECORD_SIZE] = 0;
+        assert!(matches!(
+            UpcallFn::try_from_bytes_vec(&buf),
+            Err(Error::UnsupportedFormatVersion(0))
+        ));
+
+        // a record announcing a longer payload than an upcall pointer
+        let mut buf = record_header(3 * size_of::<u64>()).to_le_bytes().to_vec();
+        buf.extend([0u8; 3 * size_of::<u64>()]);
+        assert!(matches!(
+            UpcallFn::try_from_bytes_consumed(&buf),
+            Err(Error::RecordLength {
+                expected: 16,
+                actual: 24
+            })
+        ));
+    }
 }
@@ -1,19 +1,155 @@
+use crate::hash::SignatureHasher;
+use crate::{BMVM_META_FORMAT_VERSION, TypeSignature};
+
 #[cfg(any(feature = "vmi-consume", feature = "vmi-macro"))]
 mod meta;
+#[cfg(all(test, feature = "vmi-consume"))]
+mod roundtrip;
+#[cfg(feature = "serde-transport")]
+mod serialize;
+#[cfg(feature = "vmi-consume")]
+mod sidecar;
 pub mod transport;
 
 #[cfg(any(feature = "vmi-consume", feature = "vmi-macro"))]
 pub use meta::*;
 
+#[cfg(feature = "serde-transport")]
+pub use serialize::*;
+#[cfg(feature = "vmi-consume")]
+pub use sidecar::*;
 pub use transport::*;
 
 pub type Signature = u64;
 
+/// Size of the header prefixing every record of the VMI metadata sections. The header consists of
+/// the format version, three reserved bytes and the payload length as little-endian `u32`. It is
+/// padded to 8 bytes to keep the `UpcallFn` records aligned.
+pub const RECORD_HEADER_SIZE: usize = 8;
+
+/// Encode the header of a VMI metadata record with a payload of `len` bytes. The bytes of the
+/// header are the little-endian representation of the returned value.
+pub const fn record_header(len: usize) -> u64 {
+    BMVM_META_FORMAT_VERSION as u64 | (len as u64) << 32
+}
+
+/// Compute the signature of the function `name` from the signatures of its parameter types and
+/// its return type. The result is identical to the signatures generated by the `host`/`expose`
+/// macros and `bmvm_host::signature_of`.
+pub const fn function_signature(name: &str, params: &[u64], ret: u64) -> Signature {
+    let params = if params.is_empty() {
+        <() as TypeSignature>::SIGNATURE
+    } else {
+        let mut hasher = SignatureHasher::new();
+        let mut idx = 0;
+        while idx < params.len() {
+            hasher.write((idx as u64).to_le_bytes().as_slice());
+            hasher.write(params[idx].to_le_bytes().as_slice());
+            idx += 1;
+        }
+        hasher.finish()
+    };
+
+    let mut hasher = SignatureHasher::new();
+    hasher.write(name.as_bytes());
+    hasher.write(params.to_le_bytes().as_slice());
+    hasher.write(ret.to_le_bytes().as_slice());
+    hasher.finish()
+}
+
+/// Signature of the built-in hypercall requesting additional pages from the host. The single `u64`
+/// parameter is the number of 4KiB pages, the result the guest virtual base address or zero.
+pub const REQUEST_PAGES: Signature =
+    function_signature("__bmvm_request_pages", &[u64::SIGNATURE], u64::SIGNATURE);
+
+/// Signature of the built-in hypercall forwarding guest log records to the host. The `primary`
+/// transport field contains the guest virtual address of the record, which consists of the target
+/// followed by the message. The `secondary` field packs the level into bits 56..64, the target
+/// length into bits 32..48 and the total record length into bits 0..32.
+pub const GUEST_LOG: Signature = function_signature(
+    "__bmvm_log",
+    &[u64::SIGNATURE, u64::SIGNATURE],
+    <()>::SIGNATURE,
+);
+
+/// Signature of the built-in hypercall passing a chunk of a streaming upcall to the host. The
+/// transport contains the guest virtual address and length of the chunk, the result the stream
+/// control word, see `STREAM_CANCELLED`.
+pub const STREAM_CHUNK: Signature = function_signature(
+    "__bmvm_stream_chunk",
+    &[u64::SIGNATURE, u64::SIGNATURE],
+    u64::SIGNATURE,
+);
+
+/// Bit of the stream control word signaling the guest to stop producing chunks, as the consumer on
+/// the host dropped the stream.
+pub const STREAM_CANCELLED: u64 = 1;
+
+/// Signature of the built-in hypercall suspending the guest until the host resumes it. Neither the
+/// transport nor the result carry a value.
+pub const YIELD: Signature = function_signature("__bmvm_yield", &[], <()>::SIGNATURE);
+
+/// Signature of the built-in hypercall copying guest memory on behalf of the guest. The `primary`
+/// transport field contains the guest virtual address of the request `[dst, src, len]`, the
+/// result is zero if the host performed the copy and non-zero if it declined the request.
+pub const HOST_MEMCPY: Signature = function_signature(
+    "__bmvm_memcpy",
+    &[u64::SIGNATURE, u64::SIGNATURE, u64::SIGNATURE],
+    u64::SIGNATURE,
+);
+
+/// Signature of the built-in hypercall filling guest memory on behalf of the guest, like
+/// `HOST_MEMCPY` with the request `[dst, value, len]`.
+pub const HOST_MEMSET: Signature = function_signature(
+    "__bmvm_memset",
+    &[u64::SIGNATURE, u64::SIGNATURE, u64::SIGNATURE],
+    u64::SIGNATURE,
+);
+
+/// Length in bytes from which the guest passes copies and fills to the host. The hypercall costs a
+/// VM exit and re-entry, which only pays off for large copies. The `memop` bench of bmvm-host
+/// reports the size from which the host is faster on the machine at hand.
+pub const HOST_MEMOP_THRESHOLD: usize = 64 * 1024;
+
+/// The maximum size of a guest log record including the target. Longer messages are truncated.
+pub const GUEST_LOG_MAX_SIZE: usize = 256;
+
+/// Severity of a guest log record, matching the levels of the `log` crate.
+#[repr(u8)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum LogLevel {
+    Error = 1,
+    Warn,
+    Info,
+    Debug,
+    Trace,
+}
+
+impl LogLevel {
+    pub fn from_u8(value: u8) -> Option<Self> {
+        match value {
+            1 => Some(LogLevel::Error),
+            2 => Some(LogLevel::Warn),
+            3 => Some(LogLevel::Info),
+            4 => Some(LogLevel::Debug),
+            5 => Some(LogLevel::Trace),
+            _ => None,
+        }
+    }
+}
+
 pub type Function = extern "C" fn() -> ();
 
 #[cfg(any(feature = "vmi-execute", feature = "vmi-macro"))]
 #[repr(C)]
 pub struct UpcallFn {
+    pub header: u64,
     pub sig: Signature,
     pub func: Function,
 }
+
+#[cfg(any(feature = "vmi-execute", feature = "vmi-macro"))]
+impl UpcallFn {
+    /// Record header of every upcall pointer
+    pub const HEADER: u64 = record_header(size_of::<Signature>() + size_of::<Function>());
+}
@@ -0,0 +1,314 @@
+//! Property based round trips through the transport: values are marshalled like the generated
+//! wrappers do, unmarshalled again and compared to the input.
+use crate::TypeSignature;
+use crate::error::ExitCode;
+use crate::error::HostError;
+use crate::mem::{
+    BufError, Foreign, ForeignBuf, ForeignCStr, OwnedCStr, Shared, SharedBuf, Unpackable, alloc,
+    setup_test_alloc as setup,
+};
+use crate::registry::{Params as RegistryParams, Tuple3};
+use crate::vmi::{ForeignShareable, OwnedShareable, Transport, scalar_from_raw, scalar_to_raw};
+use core::ffi::CStr;
+use core::mem::offset_of;
+use proptest::prelude::*;
+
+/// Pass `value` through the transport and back
+fn round_trip<T: OwnedShareable + ForeignShareable>(value: T) -> T {
+    let t: Transport = value.into_transport();
+    match T::from_transport(t) {
+        Ok(value) => value,
+        Err(code) => panic!("round trip failed with {:?} for {}", code, t),
+    }
+}
+
+/// Pass `value` through a single register like a parameter of a scalar pair
+fn raw_round_trip<T: TypeSignature>(value: T) -> T {
+    let raw = scalar_to_raw(value);
+    unsafe { scalar_from_raw::<T>(raw) }.expect("scalar rejected")
+}
+
+macro_rules! scalar_round_trips {
+    ($($name:ident: $ty:ty),* $(,)?) => {
+        proptest! {
+            $(
+                #[test]
+                fn $name(value in any::<$ty>()) {
+                    prop_assert_eq!(round_trip(value), value);
+                    prop_assert_eq!(raw_round_trip(value), value);
+                }
+            )*
+        }
+
+        #[test]
+        fn scalar_bounds() {
+            $(
+                for value in [<$ty>::MIN, <$ty>::MAX, <$ty>::default()] {
+                    assert_eq!(round_trip(value), value);
+                    assert_eq!(raw_round_trip(value), value);
+                }
+            )*
+        }
+    };
+}
+
+scalar_round_trips!(
+    round_trip_u8: u8,
+    round_trip_u16: u16,
+    round_trip_u32: u32,
+    round_trip_u64: u64,
+    round_trip_usize: usize,
+    round_trip_i8: i8,
+    round_trip_i16: i16,
+    round_trip_i32: i32,
+    round_trip_i64: i64,
+);
+
+proptest! {
+    #[test]
+    fn round_trip_bool(value in any::<bool>()) {
+        prop_assert_eq!(round_trip(value), value);
+        prop_assert_eq!(raw_round_trip(value), value);
+    }
+
+    #[test]
+    fn round_trip_char(value in any::<char>()) {
+        prop_assert_eq!(round_trip(value), value);
+        prop_assert_eq!(raw_round_trip(value), value);
+    }
+
+    #[test]
+    fn round_trip_u128(value in any::<u128>()) {
+        prop_assert_eq!(round_trip(value), value);
+    }
+
+    #[test]
+    fn round_trip_i128(value in any::<i128>()) {
+        prop_assert_eq!(round_trip(value), value);
+    }
+
+    // compared by bit pattern, so NaN payloads and signed zeros have to survive as well
+    #[test]
+    fn round_trip_f32(value in any::<u32>().prop_map(f32::from_bits)) {
+        prop_assert_eq!(round_trip(value).to_bits(), value.to_bits());
+        prop_assert_eq!(raw_round_trip(value).to_bits(), value.to_bits());
+    }
+
+    #[test]
+    fn round_trip_f64(value in any::<u64>().prop_map(f64::from_bits)) {
+        prop_assert_eq!(round_trip(value).to_bits(), value.to_bits());
+        prop_assert_eq!(raw_round_trip(value).to_bits(), value.to_bits());
+    }
+
+    #[test]
+    fn round_trip_result(value in any::<u32>(), err in any::<u8>(), ok in any::<bool>()) {
+        let value = match ok {
+            true => Ok(value),
+            false => Err(HostError::from(err)),
+        };
+        prop_assert_eq!(round_trip(value), value);
+    }
+
+    #[test]
+    fn round_trip_inline_array(value in any::<[u8; 16]>()) {
+        prop_assert_eq!(round_trip(value), value);
+    }
+
+    #[test]
+    fn round_trip_inline_u16_array(value in any::<[u16; 3]>()) {
+        prop_assert_eq!(round_trip(value), value);
+    }
+}
+
+#[test]
+fn char_bounds() {
+    // around the surrogate range, which is not a valid `char`
+    for value in ['\0', '\u{D7FF}', '\u{E000}', char::MAX] {
+        assert_eq!(round_trip(value), value);
+        assert_eq!(raw_round_trip(value), value);
+    }
+}
+
+#[test]
+fn wide_bounds() {
+    for value in [u128::MIN, u128::MAX, u64::MAX as u128, u64::MAX as u128 + 1] {
+        assert_eq!(round_trip(value), value);
+    }
+    for value in [i128::MIN, i128::MAX, -1, 0] {
+        assert_eq!(round_trip(value), value);
+    }
+}
+
+#[test]
+fn float_bounds() {
+    for value in [
+        f32::MIN,
+        f32::MAX,
+        f32::MIN_POSITIVE,
+        -0.0,
+        f32::INFINITY,
+        1.5,
+    ] {
+        assert_eq!(round_trip(value).to_bits(), value.to_bits());
+    }
+    for value in [
+        f64::MIN,
+        f64::MAX,
+        f64::MIN_POSITIVE,
+        -0.0,
+        f64::NEG_INFINITY,
+        1.5,
+    ] {
+        assert_eq!(round_trip(value).to_bits(), value.to_bits());
+    }
+    assert!(round_trip(f64::NAN).is_nan());
+}
+
+/// Mirrors the parameter struct generated for a function with multiple parameters, the field
+/// order forces padding between the fields.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Params {
+    a: u8,
+    b: u64,
+    c: u16,
+    d: i32,
+    e: bool,
+    f: char,
+    g: f64,
+}
+
+impl TypeSignature for Params {
+    const SIGNATURE: u64 = 0x5041_5241_4d53;
+    const IS_PRIMITIVE: bool = false;
+    fn name() -> String {
+        String::from("Params")
+    }
+}
+
+unsafe impl Unpackable for Params {
+    type Output = (u8, u64, u16, i32, bool, char, f64);
+    unsafe fn unpack(this: *const Self) -> Self::Output {
+        let this = unsafe { core::ptr::read(this) };
+        (this.a, this.b, this.c, this.d, this.e, this.f, this.g)
+    }
+}
+
+fn params() -> impl Strategy<Value = Params> {
+    (
+        any::<u8>(),
+        any::<u64>(),
+        any::<u16>(),
+        any::<i32>(),
+        any::<bool>(),
+        any::<char>(),
+        any::<u64>().prop_map(f64::from_bits),
+    )
+        .prop_map(|(a, b, c, d, e, f, g)| Params {
+            a,
+            b,
+            c,
+            d,
+            e,
+            f,
+            g,
+        })
+}
+
+proptest! {
+    #[test]
+    fn round_trip_struct(value in params()) {
+        setup();
+        let mut owned = unsafe { alloc::<Params>() }.unwrap();
+        unsafe { core::ptr::write(owned.as_mut(), value) };
+        let t = owned.into_shared().into_transport();
+
+        let foreign = Foreign::<Params>::from_transport(t).unwrap();
+        let (a, b, c, d, e, f, g) = unsafe { foreign.unpack() };
+        prop_assert_eq!((a, b, c, d, e, f), (value.a, value.b, value.c, value.d, value.e, value.f));
+        prop_assert_eq!(g.to_bits(), value.g.to_bits());
+    }
+
+    #[test]
+    fn round_trip_buf(value in proptest::collection::vec(any::<u8>(), 1..4096)) {
+        setup();
+        let t = SharedBuf::from_bytes(&value).unwrap().into_transport();
+        let foreign = ForeignBuf::from_transport(t).unwrap();
+        prop_assert_eq!(foreign.as_ref(), value.as_slice());
+    }
+
+    #[test]
+    fn round_trip_arena_array(value in any::<[u32; 8]>()) {
+        setup();
+        prop_assert_eq!(round_trip(value), value);
+    }
+}
+
+#[test]
+fn shared_value_bounds() {
+    setup();
+    for value in [u64::MIN, u64::MAX] {
+        let mut owned = unsafe { alloc::<u64>() }.unwrap();
+        *owned.as_mut() = value;
+        let shared: Shared<u64> = owned.into_shared();
+        let foreign = Foreign::<u64>::from_transport(shared.into_transport()).unwrap();
+        assert_eq!(*foreign.get(), value);
+    }
+}
+
+#[test]
+fn cstr() {
+    setup();
+    let owned = OwnedCStr::new(c"hello").unwrap();
+    assert_eq!(owned.as_cstr(), c"hello");
+    let foreign = ForeignCStr::from_transport(owned.into_transport()).unwrap();
+    assert_eq!(foreign.as_cstr(), c"hello");
+
+    // bytes behind the first terminator are ignored
+    let t = SharedBuf::from_bytes(b"ab\0cd").unwrap().into_transport();
+    let foreign = ForeignCStr::from_transport(t).unwrap();
+    assert_eq!(
+        foreign.as_cstr(),
+        CStr::from_bytes_with_nul(b"ab\0").unwrap()
+    );
+
+    let t = SharedBuf::from_bytes(b"abc").unwrap().into_transport();
+    assert!(matches!(
+        ForeignCStr::from_transport(t),
+        Err(ExitCode::InvalidValue)
+    ));
+}
+
+#[test]
+fn shared_buf_read_back() {
+    setup();
+    let mut buf = SharedBuf::from_bytes(b"abc").unwrap();
+    let mut dst = [0u8; 4];
+    assert!(matches!(buf.try_copy_to(&mut dst), Ok(3)));
+    assert_eq!(&dst, b"abc\0");
+    assert!(matches!(
+        buf.try_copy_to(&mut dst[..2]),
+        Err(BufError::DestinationTooSmall(3, 2))
+    ));
+
+    // writes through the shared handle are visible when reading it back
+    assert!(matches!(buf.try_copy_from(b"xy"), Ok(2)));
+    let tail = SharedBuf::from_bytes(b"de").unwrap();
+    let joined = SharedBuf::concat(&[buf, tail]).unwrap();
+    let foreign = ForeignBuf::from_transport(joined.into_transport()).unwrap();
+    assert_eq!(foreign.as_ref(), b"xycde");
+}
+
+#[test]
+fn little_endian_in_arena() {
+    setup();
+    type Packed = Tuple3<u8, u32, u16>;
+    let t = RegistryParams::into_transport((0xffu8, 0x01020304u32, 0x0506u16)).unwrap();
+    let packed = Foreign::<Packed>::from_transport(t).unwrap();
+    let bytes =
+        unsafe { core::slice::from_raw_parts(packed.as_ptr().cast::<u8>(), size_of::<Packed>()) };
+
+    assert_eq!(bytes[offset_of!(Packed, T1)], 0xff);
+    assert_eq!(bytes[offset_of!(Packed, T2)..][..4], [4, 3, 2, 1]);
+    assert_eq!(bytes[offset_of!(Packed, T3)..][..2], [6, 5]);
+}
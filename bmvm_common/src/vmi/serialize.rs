@@ -0,0 +1,131 @@
+use crate::TypeSignature;
+use crate::hash::SignatureHasher;
+use crate::mem::{Error as MemError, ForeignBuf, SharedBuf, alloc_buf};
+use bincode::enc::write::SizeWriter;
+use core::marker::PhantomData;
+use serde::Serialize;
+use serde::de::DeserializeOwned;
+
+/// Configuration used for both encoding and decoding
+const CONFIG: bincode::config::Configuration = bincode::config::standard();
+
+#[cfg_attr(feature = "vmi-consume", derive(Debug, thiserror::Error))]
+pub enum SerdeError {
+    #[cfg_attr(feature = "vmi-consume", error("Unable to allocate buffer: {0}"))]
+    Alloc(MemError),
+    #[cfg_attr(feature = "vmi-consume", error("Unable to encode value: {0}"))]
+    Encode(bincode::error::EncodeError),
+    #[cfg_attr(feature = "vmi-consume", error("Unable to decode value: {0}"))]
+    Decode(bincode::error::DecodeError),
+}
+
+pub(crate) enum Inner {
+    Shared(SharedBuf),
+    Foreign(ForeignBuf),
+}
+
+/// Transport any serde (de-)serializable type between host and guest. The value is encoded via
+/// bincode into a shared buffer, so neither `TypeSignature` nor a `repr(C)` layout is required.
+pub struct SerdeArg<T> {
+    pub(crate) inner: Inner,
+    pub(crate) _marker: PhantomData<T>,
+}
+
+impl<T: Serialize> SerdeArg<T> {
+    /// Encode the value into a newly allocated shared buffer.
+    pub fn new(value: &T) -> Result<Self, SerdeError> {
+        let mut size = SizeWriter::default();
+        bincode::serde::encode_into_writer(value, &mut size, CONFIG).map_err(SerdeError::Encode)?;
+
+        // zero sized buffers are not supported by the allocator
+        let mut owned =
+            unsafe { alloc_buf(size.bytes_written.max(1)) }.map_err(SerdeError::Alloc)?;
+        bincode::serde::encode_into_slice(value, owned.as_mut(), CONFIG)
+            .map_err(SerdeError::Encode)?;
+
+        Ok(Self {
+            inner: Inner::Shared(owned.into_shared()),
+            _marker: PhantomData,
+        })
+    }
+}
+
+impl<T: DeserializeOwned> SerdeArg<T> {
+    /// Decode the value from the underlying buffer.
+    pub fn decode(&self) -> Result<T, SerdeError> {
+        let bytes = match &self.inner {
+            Inner::Shared(buf) => buf.as_bytes(),
+            Inner::Foreign(buf) => buf.as_ref(),
+        };
+
+        bincode::serde::decode_from_slice(bytes, CONFIG)
+            .map(|(value, _)| value)
+            .map_err(SerdeError::Decode)
+    }
+
+    /// Decode the value and release the underlying buffer.
+    pub fn into_inner(self) -> Result<T, SerdeError> {
+        let value = self.decode();
+        match self.inner {
+            Inner::Shared(buf) => buf.deallocate(),
+            Inner::Foreign(buf) => drop(buf),
+        }
+        value
+    }
+}
+
+impl<T: Send + Sync> TypeSignature for SerdeArg<T> {
+    const SIGNATURE: u64 = {
+        let mut h = SignatureHasher::new();
+        h.write(0u64.to_le_bytes().as_slice());
+        h.write(b"SerdeArg");
+        h.write(
+            type_name_signature(core::any::type_name::<T>())
+                .to_le_bytes()
+                .as_slice(),
+        );
+        h.finish()
+    };
+    const IS_PRIMITIVE: bool = false;
+    #[cfg(feature = "vmi-consume")]
+    fn name() -> String {
+        format!("SerdeArg<{}>", core::any::type_name::<T>())
+    }
+}
+
+/// Hash the type name while skipping module paths, as these differ between host and guest
+/// crates for otherwise identical types.
+const fn type_name_signature(name: &str) -> u64 {
+    const fn is_ident(c: u8) -> bool {
+        c.is_ascii_alphanumeric() || c == b'_'
+    }
+
+    let bytes = name.as_bytes();
+    let mut h = SignatureHasher::new();
+    let mut i = 0;
+    while i < bytes.len() {
+        if !is_ident(bytes[i]) {
+            h.write(&[bytes[i]]);
+            i += 1;
+            continue;
+        }
+
+        let mut end = i;
+        while end < bytes.len() && is_ident(bytes[end]) {
+            end += 1;
+        }
+
+        // identifier followed by `::` is a path segment -> skip it
+        if end + 1 < bytes.len() && bytes[end] == b':' && bytes[end + 1] == b':' {
+            i = end + 2;
+            continue;
+        }
+
+        while i < end {
+            h.write(&[bytes[i]]);
+            i += 1;
+        }
+    }
+
+    h.finish()
+}
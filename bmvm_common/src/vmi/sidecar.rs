@@ -0,0 +1,115 @@
+use std::io::{Error, ErrorKind, Read, Result, Write};
+use std::path::{Path, PathBuf};
+
+/// Magic bytes at the start of every sidecar file.
+pub const SIDECAR_MAGIC: [u8; 8] = *b"BMVMVMI\0";
+
+/// File extension appended to the executable path to locate its sidecar, e.g. `guest.bmvm`.
+pub const SIDECAR_EXTENSION: &str = "bmvm";
+
+/// Raw contents of the VMI metadata sections of a guest executable, stored in a separate file to
+/// preserve the interface description of binaries without these sections. The file starts with
+/// `SIDECAR_MAGIC`, followed by the debug flag as a single byte and the `host`, `expose`,
+/// `expose_calls` and `abi` sections, each prefixed with its length as little-endian `u64`.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Sidecar {
+    /// Set if the call data includes debug information, i.e. parameter and return types
+    pub debug: bool,
+    /// Content of the `BMVM_META_SECTION_HOST` section
+    pub host: Vec<u8>,
+    /// Content of the `BMVM_META_SECTION_EXPOSE` section
+    pub expose: Vec<u8>,
+    /// Content of the `BMVM_META_SECTION_EXPOSE_CALLS` section
+    pub expose_calls: Vec<u8>,
+    /// Content of the `BMVM_META_SECTION_ABI` section
+    pub abi: Vec<u8>,
+}
+
+impl Sidecar {
+    /// Path of the sidecar belonging to the executable at `exec`.
+    pub fn path_for<P: AsRef<Path>>(exec: P) -> PathBuf {
+        let mut path = exec.as_ref().as_os_str().to_owned();
+        path.push(".");
+        path.push(SIDECAR_EXTENSION);
+        PathBuf::from(path)
+    }
+
+    pub fn write_to<W: Write>(&self, w: &mut W) -> Result<()> {
+        w.write_all(&SIDECAR_MAGIC)?;
+        w.write_all(&[self.debug as u8])?;
+        for section in [&self.host, &self.expose, &self.expose_calls, &self.abi] {
+            w.write_all(&(section.len() as u64).to_le_bytes())?;
+            w.write_all(section)?;
+        }
+        Ok(())
+    }
+
+    pub fn read_from<R: Read>(r: &mut R) -> Result<Self> {
+        let mut magic = [0u8; SIDECAR_MAGIC.len()];
+        r.read_exact(&mut magic)?;
+        if magic != SIDECAR_MAGIC {
+            return Err(Error::new(ErrorKind::InvalidData, "not a bmvm sidecar"));
+        }
+
+        let mut debug = [0u8; 1];
+        r.read_exact(&mut debug)?;
+
+        let mut section = || -> Result<Vec<u8>> {
+            let mut len = [0u8; size_of::<u64>()];
+            r.read_exact(&mut len)?;
+            let len = u64::from_le_bytes(len);
+            // the length may be corrupt, only allocate for the bytes actually present
+            let mut data = Vec::new();
+            r.by_ref().take(len).read_to_end(&mut data)?;
+            if data.len() as u64 != len {
+                return Err(Error::new(
+                    ErrorKind::UnexpectedEof,
+                    "truncated sidecar section",
+                ));
+            }
+            Ok(data)
+        };
+
+        Ok(Self {
+            debug: debug[0] != 0,
+            host: section()?,
+            expose: section()?,
+            expose_calls: section()?,
+            abi: section()?,
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn round_trip() {
+        let sidecar = Sidecar {
+            debug: true,
+            host: vec![1, 2, 3],
+            expose: Vec::new(),
+            expose_calls: vec![4; 16],
+            abi: 1u32.to_le_bytes().to_vec(),
+        };
+
+        let mut buf = Vec::new();
+        sidecar.write_to(&mut buf).unwrap();
+        assert_eq!(Sidecar::read_from(&mut buf.as_slice()).unwrap(), sidecar);
+        assert!(Sidecar::read_from(&mut &buf[1..]).is_err());
+        assert!(Sidecar::read_from(&mut &buf[..buf.len() - 1]).is_err());
+
+        // a corrupt length must not allocate beyond the content of the file
+        let mut corrupt = buf[..SIDECAR_MAGIC.len() + 1].to_vec();
+        corrupt.extend(u64::MAX.to_le_bytes());
+        corrupt.extend([0u8; 16]);
+        let err = Sidecar::read_from(&mut corrupt.as_slice()).unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::UnexpectedEof);
+
+        assert_eq!(
+            Sidecar::path_for("target/guest"),
+            PathBuf::from("target/guest.bmvm")
+        );
+    }
+}
@@ -1,10 +1,40 @@
 use crate::TypeSignature;
-use crate::error::ExitCode;
+use crate::error::{ExitCode, HostError};
 use crate::mem::{
-    Error as MemError, Foreign, ForeignBuf, OffsetPtr, RawOffsetPtr, Shared, SharedBuf, get_foreign,
+    Error as MemError, Foreign, ForeignBuf, ForeignCStr, OffsetPtr, Origin, OwnedCStr,
+    RawOffsetPtr, Shared, SharedBuf, alloc, check_foreign_buf, get_foreign,
 };
+use core::mem::ManuallyDrop;
 use core::num::NonZeroUsize;
+use core::sync::atomic::AtomicUsize;
 
+#[cfg(feature = "serde-transport")]
+use crate::vmi::{SerdeArg, serialize::Inner as SerdeInner};
+
+// Scalars are transported in little endian: within the registers as well as in the shared memory,
+// where values are stored in their native representation. Host and guest only agree on the latter
+// if both are little endian targets.
+const _: () = assert!(
+    cfg!(target_endian = "little"),
+    "the VMI transport requires a little endian target"
+);
+
+/// Value of `Transport.secondary` marking the transport of a `HostError`. No shareable type can
+/// produce this value, as it would require a buffer spanning the whole address space.
+const HOST_ERROR_MARKER: u64 = u64::MAX;
+
+/// Register pair passed between host and guest. Scalars are always encoded little endian, a value
+/// narrower than 64 bit occupies the low bytes of its field.
+///
+/// The layout is part of the ABI between the host wrappers and the guest `hypercall::execute` and
+/// checked at compile time:
+///
+/// | Offset | Size | Field       | Register |
+/// |--------|------|-------------|----------|
+/// | `0`    | `8`  | `primary`   | `r8`     |
+/// | `8`    | `8`  | `secondary` | `r9`     |
+///
+/// The struct is 16 bytes in size and 8 byte aligned, without padding.
 #[derive(Debug, Copy, Clone, PartialEq, Eq)]
 #[repr(C)]
 pub struct Transport {
@@ -15,11 +45,44 @@ pub struct Transport {
     secondary: u64,
 }
 
+const TRANSPORT_SIZE: usize = 16;
+const TRANSPORT_ALIGN: usize = 8;
+
+const _: () = assert!(size_of::<Transport>() == TRANSPORT_SIZE);
+const _: () = assert!(align_of::<Transport>() == TRANSPORT_ALIGN);
+const _: () = assert!(core::mem::offset_of!(Transport, primary) == 0);
+const _: () = assert!(core::mem::offset_of!(Transport, secondary) == size_of::<u64>());
+
 impl Transport {
     pub fn new(primary: u64, secondary: u64) -> Self {
         Self { primary, secondary }
     }
 
+    /// Verify the layout and encoding the peers rely on at runtime, e.g. from an integration test
+    /// of a crate built with a different toolchain or target. Returns the violated property.
+    pub fn abi_selfcheck() -> Result<(), &'static str> {
+        if size_of::<Self>() != TRANSPORT_SIZE {
+            return Err("unexpected transport size");
+        }
+        if align_of::<Self>() != TRANSPORT_ALIGN {
+            return Err("unexpected transport alignment");
+        }
+
+        // the fields are laid out in register order without padding
+        let t = Self::new(0x0807_0605_0403_0201, 0x100f_0e0d_0c0b_0a09);
+        let bytes: [u8; TRANSPORT_SIZE] = unsafe { core::mem::transmute(t) };
+        if bytes != core::array::from_fn(|i| i as u8 + 1) {
+            return Err("unexpected transport field layout");
+        }
+
+        // narrow scalars occupy the low bytes of a register
+        if scalar_to_raw(0x0201u16).to_le_bytes() != [1, 2, 0, 0, 0, 0, 0, 0] {
+            return Err("unexpected scalar encoding");
+        }
+
+        Ok(())
+    }
+
     pub fn primary(&self) -> u64 {
         self.primary
     }
@@ -29,6 +92,47 @@ impl Transport {
     }
 }
 
+/// Check if values of `T` fit into a single transport register.
+pub const fn is_scalar<T: TypeSignature>() -> bool {
+    T::IS_PRIMITIVE && size_of::<T>() <= size_of::<u64>()
+}
+
+/// Parameter lists of exactly two scalars bypass the shared memory, as each value is passed
+/// directly in one of the transport registers. Host and guest both evaluate this at compile time.
+pub const fn is_scalar_pair<A: TypeSignature, B: TypeSignature>() -> bool {
+    is_scalar::<A>() && is_scalar::<B>()
+}
+
+/// Copy the little endian bytes of a scalar into the low bytes of a register value.
+pub fn scalar_to_raw<T: TypeSignature>(value: T) -> u64 {
+    assert!(is_scalar::<T>());
+    let mut raw = [0u8; size_of::<u64>()];
+    unsafe {
+        core::ptr::copy_nonoverlapping(
+            (&value as *const T).cast::<u8>(),
+            raw.as_mut_ptr(),
+            size_of::<T>(),
+        )
+    };
+    // ownership is passed on to the other side
+    core::mem::forget(value);
+    u64::from_le_bytes(raw)
+}
+
+/// Read a scalar from the low bytes of a register value. Bit patterns, which are not a valid `T`,
+/// are rejected with `ExitCode::InvalidValue`.
+///
+/// # Safety
+/// `raw` must have been produced by `scalar_to_raw` for the same type `T`.
+pub unsafe fn scalar_from_raw<T: TypeSignature>(raw: u64) -> Result<T, ExitCode> {
+    assert!(is_scalar::<T>());
+    let bytes = raw.to_le_bytes();
+    if !unsafe { T::is_valid(bytes.as_ptr()) } {
+        return Err(ExitCode::InvalidValue);
+    }
+    Ok(unsafe { bytes.as_ptr().cast::<T>().read_unaligned() })
+}
+
 #[cfg(feature = "vmi-consume")]
 impl core::fmt::Display for Transport {
     fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
@@ -38,11 +142,26 @@ impl core::fmt::Display for Transport {
 
 #[sealed::sealed(pub(crate))]
 pub trait OwnedShareable: TypeSignature {
+    /// Set if the value occupies both transport fields, leaving no room for the `HostError` marker.
+    #[doc(hidden)]
+    const WIDE: bool = false;
     fn into_transport(self) -> Transport;
+
+    /// Release the shared memory of a value, which is not passed to the peer after all, e.g. as
+    /// the table of the enclosing tuple could not be allocated. Other values are simply dropped.
+    #[doc(hidden)]
+    fn release(self)
+    where
+        Self: Sized,
+    {
+    }
 }
 
 #[sealed::sealed(pub(crate))]
 pub trait ForeignShareable: TypeSignature {
+    /// Set if the value occupies both transport fields, leaving no room for the `HostError` marker.
+    #[doc(hidden)]
+    const WIDE: bool = false;
     fn from_transport(t: Transport) -> Result<Self, ExitCode>
     where
         Self: Sized;
@@ -60,8 +179,45 @@ impl ForeignShareable for ForeignBuf {
 
         let raw = RawOffsetPtr::from(t.primary as u32);
         let ptr = OffsetPtr::from(raw);
+        let to_exit_code = |e| match e {
+            MemError::UninitializedAllocator => ExitCode::AllocatorUninitialized,
+            _ => ExitCode::TransportTooLarge,
+        };
 
-        Ok(ForeignBuf { ptr, capacity })
+        // the length is chosen by the peer, never read beyond the shared memory
+        check_foreign_buf(&ptr, capacity.get()).map_err(to_exit_code)?;
+
+        // a retained buffer only drops its reference, see `RetainedBuf`
+        let refs = (t.primary >> 32) as u32;
+        let origin = if refs != 0 {
+            // the counter is accessed atomically, which requires its natural alignment
+            if refs as usize % align_of::<AtomicUsize>() != 0 {
+                return Err(ExitCode::Ptr(RawOffsetPtr::from(refs)));
+            }
+            check_foreign_buf(&OffsetPtr::from(refs), size_of::<AtomicUsize>())
+                .map_err(to_exit_code)?;
+            Some(Origin {
+                offset: ptr.offset,
+                capacity,
+                refs,
+            })
+        } else {
+            None
+        };
+
+        Ok(ForeignBuf {
+            ptr,
+            capacity,
+            origin,
+        })
+    }
+}
+
+#[sealed::sealed]
+impl ForeignShareable for ForeignCStr {
+    fn from_transport(t: Transport) -> Result<Self, ExitCode> {
+        // dropping the buffer deallocates it, if the terminator is missing
+        ForeignCStr::new(ForeignBuf::from_transport(t)?).ok_or(ExitCode::InvalidValue)
     }
 }
 
@@ -70,13 +226,19 @@ impl<T: TypeSignature> ForeignShareable for Foreign<T> {
     fn from_transport(t: Transport) -> Result<Self, ExitCode> {
         let raw = RawOffsetPtr::from(t.primary as u32);
         let ptr = OffsetPtr::from(raw);
-        unsafe {
-            get_foreign(ptr).map_err(|e| match e {
-                MemError::UninitializedAllocator => ExitCode::NullPtr,
+        let foreign = unsafe {
+            get_foreign::<T>(ptr).map_err(|e| match e {
+                MemError::UninitializedAllocator => ExitCode::AllocatorUninitialized,
                 MemError::NullPointer => ExitCode::NullPtr,
                 _ => ExitCode::Ptr(raw),
             })
+        }?;
+
+        // dropping the foreign value only deallocates it, the invalid `T` is never accessed
+        if !unsafe { T::is_valid(foreign.as_ptr().cast()) } {
+            return Err(ExitCode::InvalidValue);
         }
+        Ok(foreign)
     }
 }
 
@@ -88,16 +250,37 @@ impl<T: TypeSignature> OwnedShareable for Shared<T> {
             secondary: 0,
         }
     }
+
+    fn release(self) {
+        self.deallocate()
+    }
 }
 
 #[sealed::sealed]
 impl OwnedShareable for SharedBuf {
     fn into_transport(self) -> Transport {
+        // the reference of a retained buffer is passed on to the peer
+        let this = ManuallyDrop::new(self);
         Transport {
-            primary: self.ptr.offset as u64,
-            secondary: self.capacity.get() as u64,
+            primary: this.ptr.offset as u64 | (this.refs as u64) << 32,
+            secondary: this.capacity.get() as u64,
         }
     }
+
+    fn release(self) {
+        self.deallocate()
+    }
+}
+
+#[sealed::sealed]
+impl OwnedShareable for OwnedCStr {
+    fn into_transport(self) -> Transport {
+        self.buf.into_transport()
+    }
+
+    fn release(self) {
+        self.buf.deallocate()
+    }
 }
 
 macro_rules! impl_owned_shareable_for_primitives {
@@ -130,12 +313,62 @@ macro_rules! impl_foreign_shareable_for_primitives {
     };
 }
 
-impl_owned_shareable_for_primitives!(
-    u8, u16, u32, u64, u128, i8, i16, i32, i64, i128, f32, f64, usize, bool
-);
-impl_foreign_shareable_for_primitives!(
-    u8, u16, u32, u64, u128, i8, i16, i32, i64, i128, f32, f64, usize
-);
+/// Floats are transported by their bit pattern, a numeric conversion would truncate them.
+macro_rules! impl_shareable_for_floats {
+    ($($prim:ty => $bits:ty),* $(,)?) => {
+        $(
+            #[sealed::sealed]
+            impl OwnedShareable for $prim {
+                #[inline(always)]
+                fn into_transport(self) -> Transport {
+                    Transport {
+                        primary: self.to_bits() as u64,
+                        secondary: 0,
+                    }
+                }
+            }
+
+            #[sealed::sealed]
+            impl ForeignShareable for $prim {
+                fn from_transport(t: Transport) -> Result<Self, ExitCode> {
+                    Ok(<$prim>::from_bits(t.primary as $bits))
+                }
+            }
+        )*
+    };
+}
+
+/// 128-bit integers span both fields: `primary` holds the lower and `secondary` the upper half.
+macro_rules! impl_shareable_for_wide_primitives {
+    ($($prim:ty),* $(,)?) => {
+        $(
+            #[sealed::sealed]
+            impl OwnedShareable for $prim {
+                const WIDE: bool = true;
+                #[inline(always)]
+                fn into_transport(self) -> Transport {
+                    Transport {
+                        primary: self as u64,
+                        secondary: (self >> 64) as u64,
+                    }
+                }
+            }
+
+            #[sealed::sealed]
+            impl ForeignShareable for $prim {
+                const WIDE: bool = true;
+                fn from_transport(t: Transport) -> Result<Self, ExitCode> {
+                    Ok(((t.secondary as u128) << 64 | t.primary as u128) as $prim)
+                }
+            }
+        )*
+    };
+}
+
+impl_owned_shareable_for_primitives!(u8, u16, u32, u64, i8, i16, i32, i64, usize, bool, char);
+impl_foreign_shareable_for_primitives!(u8, u16, u32, u64, i8, i16, i32, i64, usize);
+impl_shareable_for_floats!(f32 => u32, f64 => u64);
+impl_shareable_for_wide_primitives!(u128, i128);
 
 #[sealed::sealed]
 impl OwnedShareable for () {
@@ -157,6 +390,354 @@ impl ForeignShareable for () {
 #[sealed::sealed]
 impl ForeignShareable for bool {
     fn from_transport(t: Transport) -> Result<Self, ExitCode> {
-        Ok(t.primary != 0)
+        match t.primary {
+            0 => Ok(false),
+            1 => Ok(true),
+            _ => Err(ExitCode::InvalidValue),
+        }
+    }
+}
+
+#[sealed::sealed]
+impl ForeignShareable for char {
+    fn from_transport(t: Transport) -> Result<Self, ExitCode> {
+        u32::try_from(t.primary)
+            .ok()
+            .and_then(char::from_u32)
+            .ok_or(ExitCode::InvalidValue)
+    }
+}
+
+#[sealed::sealed]
+impl<T: OwnedShareable> OwnedShareable for Result<T, HostError> {
+    fn into_transport(self) -> Transport {
+        const { assert!(!T::WIDE, "128-bit values cannot be combined with HostError") };
+        match self {
+            Ok(value) => value.into_transport(),
+            Err(err) => Transport {
+                primary: err.as_u8() as u64,
+                secondary: HOST_ERROR_MARKER,
+            },
+        }
+    }
+
+    fn release(self) {
+        if let Ok(value) = self {
+            value.release()
+        }
+    }
+}
+
+#[sealed::sealed]
+impl<T: ForeignShareable> ForeignShareable for Result<T, HostError> {
+    fn from_transport(t: Transport) -> Result<Self, ExitCode> {
+        const { assert!(!T::WIDE, "128-bit values cannot be combined with HostError") };
+        if t.secondary == HOST_ERROR_MARKER {
+            return Ok(Err(HostError::from(t.primary as u8)));
+        }
+
+        T::from_transport(t).map(Ok)
+    }
+}
+
+/// Arrays of up to 16 bytes are passed directly in the transport, the bytes are split across
+/// `primary` and `secondary` like a 128-bit integer. Larger arrays are copied into the shared
+/// memory and passed as offset and size, the receiver copies them out and deallocates the memory.
+///
+/// Only arrays of primitives are supported, as other types may contain padding.
+const fn array_inline<T: TypeSignature, const N: usize>() -> bool {
+    assert!(
+        T::IS_PRIMITIVE,
+        "arrays are only shareable if the element is a primitive"
+    );
+    size_of::<[T; N]>() <= TRANSPORT_SIZE
+}
+
+#[sealed::sealed]
+impl<T: TypeSignature + Copy, const N: usize> OwnedShareable for [T; N] {
+    const WIDE: bool = size_of::<[T; N]>() > size_of::<u64>() && array_inline::<T, N>();
+    fn into_transport(self) -> Transport {
+        if const { array_inline::<T, N>() } {
+            let mut raw = [0u8; TRANSPORT_SIZE];
+            unsafe {
+                core::ptr::copy_nonoverlapping(
+                    self.as_ptr().cast::<u8>(),
+                    raw.as_mut_ptr(),
+                    size_of::<[T; N]>(),
+                )
+            };
+            let (primary, secondary) = raw.split_at(size_of::<u64>());
+            return Transport {
+                primary: u64::from_le_bytes(primary.try_into().unwrap()),
+                secondary: u64::from_le_bytes(secondary.try_into().unwrap()),
+            };
+        }
+
+        // a failed allocation is reported to the receiver via a zero size
+        match unsafe { alloc::<[T; N]>() } {
+            Ok(mut owned) => {
+                *owned.as_mut() = self;
+                Transport {
+                    primary: owned.into_shared().inner.offset as u64,
+                    secondary: size_of::<[T; N]>() as u64,
+                }
+            }
+            Err(_) => Transport {
+                primary: 0,
+                secondary: 0,
+            },
+        }
+    }
+}
+
+#[sealed::sealed]
+impl<T: TypeSignature + Copy, const N: usize> ForeignShareable for [T; N] {
+    const WIDE: bool = size_of::<[T; N]>() > size_of::<u64>() && array_inline::<T, N>();
+    fn from_transport(t: Transport) -> Result<Self, ExitCode> {
+        if const { array_inline::<T, N>() } {
+            let mut raw = [0u8; TRANSPORT_SIZE];
+            raw[..size_of::<u64>()].copy_from_slice(&t.primary.to_le_bytes());
+            raw[size_of::<u64>()..].copy_from_slice(&t.secondary.to_le_bytes());
+            if !unsafe { Self::is_valid(raw.as_ptr()) } {
+                return Err(ExitCode::InvalidValue);
+            }
+            return Ok(unsafe { raw.as_ptr().cast::<Self>().read_unaligned() });
+        }
+
+        if t.secondary != size_of::<[T; N]>() as u64 {
+            return Err(ExitCode::AllocationFailed);
+        }
+        // dropping the foreign value deallocates it after the copy
+        Foreign::<[T; N]>::from_transport(t).map(|foreign| *foreign.get())
+    }
+}
+
+/// Tuples are passed as a table of the transports of their elements in the shared memory, the
+/// transport contains the offset of the table and the number of elements. Each element keeps its
+/// own representation, e.g. the buffers of a `(SharedBuf, SharedBuf)` stay separate allocations and
+/// are received as `ForeignBuf`s without copying them. The receiver deallocates the table.
+macro_rules! impl_shareable_for_tuples {
+    ($($n:literal => ($($t:ident $i:tt),+)),* $(,)?) => {
+        $(
+            #[sealed::sealed]
+            impl<$($t: OwnedShareable),+> OwnedShareable for ($($t,)+) {
+                fn into_transport(self) -> Transport {
+                    // allocate the table first, the elements are released if that fails. The
+                    // failed allocation is reported to the receiver via a zero element count.
+                    let Ok(mut owned) = (unsafe { alloc::<[u64; 2 * $n]>() }) else {
+                        self.release();
+                        return Transport {
+                            primary: 0,
+                            secondary: 0,
+                        };
+                    };
+
+                    let table = owned.as_mut();
+                    $(
+                        let t = self.$i.into_transport();
+                        table[2 * $i] = t.primary;
+                        table[2 * $i + 1] = t.secondary;
+                    )+
+                    Transport {
+                        primary: owned.into_shared().inner.offset as u64,
+                        secondary: $n,
+                    }
+                }
+
+                fn release(self) {
+                    $(self.$i.release();)+
+                }
+            }
+
+            #[sealed::sealed]
+            impl<$($t: ForeignShareable),+> ForeignShareable for ($($t,)+) {
+                fn from_transport(t: Transport) -> Result<Self, ExitCode> {
+                    if t.secondary != $n {
+                        return Err(ExitCode::AllocationFailed);
+                    }
+                    // dropping the foreign table deallocates it, the elements are not affected
+                    let table = *Foreign::<[u64; 2 * $n]>::from_transport(t)?.get();
+                    // decode every element before failing, so the remaining ones are dropped and
+                    // release their shared memory as well
+                    let elements = ($(
+                        $t::from_transport(Transport::new(table[2 * $i], table[2 * $i + 1])),
+                    )+);
+                    Ok(($(elements.$i?,)+))
+                }
+            }
+        )*
+    };
+}
+
+impl_shareable_for_tuples!(
+    2 => (T1 0, T2 1),
+    3 => (T1 0, T2 1, T3 2),
+    4 => (T1 0, T2 1, T3 2, T4 3),
+);
+
+#[cfg(feature = "serde-transport")]
+#[sealed::sealed]
+impl<T: Send + Sync> OwnedShareable for SerdeArg<T> {
+    fn into_transport(self) -> Transport {
+        match self.inner {
+            SerdeInner::Shared(buf) => buf.into_transport(),
+            // the buffer is passed back to the peer, which is responsible for deallocation
+            SerdeInner::Foreign(buf) => buf.owned().into_shared().into_transport(),
+        }
+    }
+
+    fn release(self) {
+        // a foreign buffer is released by dropping it
+        if let SerdeInner::Shared(buf) = self.inner {
+            buf.deallocate()
+        }
+    }
+}
+
+#[cfg(feature = "serde-transport")]
+#[sealed::sealed]
+impl<T: Send + Sync> ForeignShareable for SerdeArg<T> {
+    fn from_transport(t: Transport) -> Result<Self, ExitCode> {
+        Ok(Self {
+            inner: SerdeInner::Foreign(ForeignBuf::from_transport(t)?),
+            _marker: core::marker::PhantomData,
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn wide_round_trip() {
+        let value = u64::MAX as u128 + 41;
+        let t = value.into_transport();
+        assert_eq!(t, Transport::new(40, 1));
+        assert_eq!(u128::from_transport(t).ok().map(|v| v + 1), Some(value + 1));
+
+        let value = i128::MIN + 1;
+        assert_eq!(
+            i128::from_transport(value.into_transport()).ok(),
+            Some(value)
+        );
+        assert_eq!(
+            i128::from_transport((-1i128).into_transport()).ok(),
+            Some(-1)
+        );
+    }
+
+    #[test]
+    fn invalid_bool_and_char() {
+        fn invalid<T>(r: Result<T, ExitCode>) -> bool {
+            matches!(r, Err(ExitCode::InvalidValue))
+        }
+
+        assert_eq!(bool::from_transport(Transport::new(1, 0)).ok(), Some(true));
+        assert_eq!(bool::from_transport(Transport::new(0, 0)).ok(), Some(false));
+        assert!(invalid(bool::from_transport(Transport::new(2, 0))));
+
+        assert_eq!(char::from_transport('ä'.into_transport()).ok(), Some('ä'));
+        for raw in [0xD800, 0x110000, u32::MAX as u64 + 'a' as u64] {
+            assert!(invalid(char::from_transport(Transport::new(raw, 0))));
+        }
+
+        // the register pair path validates the raw bits as well
+        assert_eq!(unsafe { scalar_from_raw::<bool>(1) }.ok(), Some(true));
+        assert!(invalid(unsafe { scalar_from_raw::<bool>(2) }));
+        assert_eq!(unsafe { scalar_from_raw::<char>(0x41) }.ok(), Some('A'));
+        assert!(invalid(unsafe { scalar_from_raw::<char>(0xD800) }));
+        assert!(invalid(unsafe { scalar_from_raw::<char>(0x110000) }));
+    }
+
+    #[test]
+    fn little_endian_encoding() {
+        let raw = scalar_to_raw(0x01020304u32);
+        assert_eq!(raw.to_le_bytes(), [4, 3, 2, 1, 0, 0, 0, 0]);
+        assert_eq!(0x01020304u32.into_transport().primary(), raw);
+    }
+
+    #[test]
+    fn inline_array() {
+        let hash = [1u8, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12];
+        let t = hash.into_transport();
+        assert_eq!(t, Transport::new(0x0807060504030201, 0x0c0b0a09));
+        assert_eq!(<[u8; 12]>::from_transport(t).ok(), Some(hash));
+        assert!(<[u8; 12] as ForeignShareable>::WIDE);
+        assert!(!<[u16; 4] as ForeignShareable>::WIDE);
+
+        let flags = [true, false];
+        assert_eq!(
+            <[bool; 2]>::from_transport(flags.into_transport()).ok(),
+            Some(flags)
+        );
+        assert!(matches!(
+            <[bool; 2]>::from_transport(Transport::new(0x0201, 0)),
+            Err(ExitCode::InvalidValue)
+        ));
+
+        // arrays exceeding the transport are passed via the shared memory
+        assert!(!<[u8; 32] as ForeignShareable>::WIDE);
+        assert!(matches!(
+            <[u8; 32]>::from_transport(Transport::new(0, 0)),
+            Err(ExitCode::AllocationFailed)
+        ));
+    }
+
+    #[test]
+    fn tuple_allocation_failed() {
+        // a zero element count signals the failed allocation of the table
+        assert!(matches!(
+            <(u64, ForeignBuf)>::from_transport(Transport::new(0, 0)),
+            Err(ExitCode::AllocationFailed)
+        ));
+        assert!(matches!(
+            <(u64, u64, u64)>::from_transport(Transport::new(8, 2)),
+            Err(ExitCode::AllocationFailed)
+        ));
+    }
+
+    #[test]
+    #[cfg(feature = "vmi-consume")]
+    fn tuple_error_releases_elements() {
+        crate::mem::setup_test_alloc();
+        let retained = crate::mem::RetainedBuf::new(8).unwrap();
+        let buf = retained.share().into_transport();
+
+        // the invalid bool fails the tuple, the buffer behind it is decoded and dropped regardless
+        let mut table = unsafe { alloc::<[u64; 4]>() }.unwrap();
+        *table.as_mut() = [2, 0, buf.primary, buf.secondary];
+        let t = Transport::new(table.into_shared().inner.offset as u64, 2);
+        assert!(!retained.is_idle());
+        assert!(matches!(
+            <(bool, ForeignBuf)>::from_transport(t),
+            Err(ExitCode::InvalidValue)
+        ));
+        assert!(retained.is_idle());
+
+        // releasing an unsent tuple drops the references of its buffers
+        let tuple = (retained.share(), 1u64, Ok::<_, HostError>(retained.share()));
+        assert!(!retained.is_idle());
+        tuple.release();
+        assert!(retained.is_idle());
+    }
+
+    #[test]
+    fn abi_selfcheck() {
+        assert_eq!(Transport::abi_selfcheck(), Ok(()));
+    }
+
+    #[test]
+    fn scalar_round_trip() {
+        assert!(is_scalar_pair::<u8, i64>());
+        assert!(!is_scalar_pair::<u64, u128>());
+
+        let raw = scalar_to_raw(-2i32);
+        assert_eq!(raw, 0xffff_fffe);
+        assert_eq!(unsafe { scalar_from_raw::<i32>(raw) }.ok(), Some(-2));
+        assert_eq!(
+            unsafe { scalar_from_raw::<f64>(scalar_to_raw(1.5f64)) }.ok(),
+            Some(1.5)
+        );
     }
 }
@@ -10,6 +10,7 @@ PHDRS
     data PT_LOAD;
     bss PT_LOAD;
     got PT_LOAD;
+    tls PT_TLS;
     note PT_NOTE;
 }
 
@@ -30,8 +31,17 @@ SECTIONS
         *(.data*)
     } :data
 
+    /* initialization image of the thread-local storage, copied to the TLS block by the host */
+    .tdata : AT(ADDR(.data) + SIZEOF(.data)) {
+        *(.tdata*)
+    } :data :tls
+
+    .tbss : {
+        *(.tbss*)
+    } :data :tls
+
     . = ALIGN(PAGE_SIZE);
-    .bss : AT(ADDR(.data) + SIZEOF(.data)) {
+    .bss : AT(ADDR(.tdata) + SIZEOF(.tdata)) {
         *(.bss*)
     } :bss
 
@@ -56,4 +66,8 @@ SECTIONS
     .bmvm.vpc.hypercall : {
         KEEP(*(.bmvm.vpc.hypercall));
     } :note
+
+    .bmvm.vpc.abi : {
+        KEEP(*(.bmvm.vpc.abi));
+    } :note
 }
\ No newline at end of file
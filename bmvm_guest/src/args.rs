@@ -0,0 +1,27 @@
+use core::sync::atomic::{AtomicPtr, AtomicUsize, Ordering};
+
+static ARGS_PTR: AtomicPtr<u8> = AtomicPtr::new(core::ptr::null_mut());
+static ARGS_LEN: AtomicUsize = AtomicUsize::new(0);
+
+/// Remember the location of the argument blob passed to the entry point. A null `ptr` means the
+/// host did not provide any arguments.
+pub(super) fn init(ptr: u64, len: u64) {
+    if ptr == 0 {
+        return;
+    }
+
+    ARGS_LEN.store(len as usize, Ordering::Relaxed);
+    ARGS_PTR.store(ptr as *mut u8, Ordering::Release);
+}
+
+/// Returns the argument blob passed by the host via `ConfigBuilder::guest_args`.
+/// The slice is empty, if the host did not provide any arguments.
+pub fn args() -> &'static [u8] {
+    let ptr = ARGS_PTR.load(Ordering::Acquire);
+    if ptr.is_null() {
+        return &[];
+    }
+
+    let len = ARGS_LEN.load(Ordering::Relaxed);
+    unsafe { core::slice::from_raw_parts(ptr, len) }
+}
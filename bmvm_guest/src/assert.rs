@@ -0,0 +1,49 @@
+use crate::{exit_with_code, serial};
+use bmvm_common::error::ExitCode;
+
+/// Report the failed assertion via the serial port and exit with the provided code.
+#[doc(hidden)]
+#[cold]
+pub fn assert_failed(msg: &str, code: ExitCode) -> ! {
+    serial::write(msg);
+    serial::write("\n");
+    exit_with_code(code)
+}
+
+/// Assert that the expression evaluates to `true`. On failure, the stringified expression is
+/// written to the serial port and the guest exits with the given `ExitCode`.
+///
+/// ```ignore
+/// guest_assert!(buf.len() == 4, ExitCode::Unmapped(100));
+/// ```
+#[macro_export]
+macro_rules! guest_assert {
+    ($cond:expr, $code:expr $(,)?) => {
+        if !$cond {
+            $crate::assert_failed(concat!("assertion failed: ", stringify!($cond)), $code)
+        }
+    };
+}
+
+/// Assert that both expressions are equal. On failure, the stringified expressions are written
+/// to the serial port and the guest exits with the given `ExitCode`.
+#[macro_export]
+macro_rules! guest_assert_eq {
+    ($left:expr, $right:expr, $code:expr $(,)?) => {
+        match (&$left, &$right) {
+            (left, right) => {
+                if !(*left == *right) {
+                    $crate::assert_failed(
+                        concat!(
+                            "assertion failed: ",
+                            stringify!($left),
+                            " == ",
+                            stringify!($right)
+                        ),
+                        $code,
+                    )
+                }
+            }
+        }
+    };
+}
@@ -0,0 +1,21 @@
+use crate::ports;
+use bmvm_common::boot::Phase;
+use core::arch::asm;
+
+/// Report the completion of a setup phase to the host, which records the time. Costs a single
+/// port write if the host enabled the markers, and nothing otherwise.
+#[inline(always)]
+pub(super) fn mark(phase: Phase) {
+    let port = ports::boot_port();
+    if port == 0 {
+        return;
+    }
+    unsafe {
+        asm!(
+            "out dx, al",
+            in("dx") port,
+            in("al") phase as u8,
+            options(nomem, nostack, preserves_flags),
+        );
+    }
+}
@@ -0,0 +1,78 @@
+//! Exit protocol towards the host: the guest writes the `ExitCode` byte to the exit port (see
+//! `exit_port`). Variants carrying a value place it in `rbx` beforehand, which the host decodes via
+//! `ExitCode::decode`. Optionally, up to `EXIT_DETAIL_MAX_LEN` bytes of detail follow the exit code
+//! byte (see `exit_with_detail`), which the host reports alongside the exit code. The host does not
+//! resume the guest after an exit unless it continues the execution on its own, e.g. by issuing
+//! the next upcall.
+
+use crate::ports::exit_port;
+use bmvm_common::error::{EXIT_DETAIL_MAX_LEN, ExitCode};
+use bmvm_common::vmi::OwnedShareable;
+use core::arch::asm;
+
+/// Trigger VM exit with the provided exit code
+pub fn exit_with_code(code: ExitCode) -> ! {
+    code.write_values();
+    unsafe {
+        asm!(
+            "out dx, al",
+            in("dx") exit_port(),
+            in("al") code.as_u8(),
+            options(nomem, nostack, preserves_flags, noreturn),
+        )
+    }
+}
+
+/// Trigger VM exit with the provided exit code and a detail value, e.g. the size of a failed
+/// allocation. The code byte and the little endian detail are written to the exit port in a single
+/// string IO operation, so the host receives both with the same exit.
+pub fn exit_with_detail(code: ExitCode, detail: u64) -> ! {
+    code.write_values();
+    let mut data = [0u8; 1 + EXIT_DETAIL_MAX_LEN];
+    data[0] = code.as_u8();
+    data[1..].copy_from_slice(&detail.to_le_bytes());
+    unsafe {
+        asm!(
+            "rep outsb",
+            in("dx") exit_port(),
+            in("rsi") data.as_ptr(),
+            in("rcx") data.len(),
+            options(readonly, nostack, preserves_flags, noreturn),
+        )
+    }
+}
+
+/// Trigger VM exit returning `value` to the host, which retrieves it via `Module::run_to_exit`.
+/// The value is transported like the result of an upcall, i.e. buffers are placed in the owned
+/// arena and the transport is passed in `r8` and `r9`. In contrast to `exit_with_code`, which
+/// reports a failure or state change as `ExitCode`, the host treats this exit as successful
+/// completion of the guest.
+pub fn exit_with_value<T: OwnedShareable>(value: T) -> ! {
+    let transport = value.into_transport();
+    unsafe {
+        asm!(
+            "out dx, al",
+            in("dx") exit_port(),
+            in("al") ExitCode::Value.as_u8(),
+            in("r8") transport.primary(),
+            in("r9") transport.secondary(),
+            options(nomem, nostack, preserves_flags, noreturn),
+        )
+    }
+}
+
+/// Shut the VM down after a successful run, reported to the host as `ExitCode::Normal`.
+pub fn exit_normal() -> ! {
+    exit_with_code(ExitCode::Normal)
+}
+
+/// Terminate the guest abnormally without a panic message, reported to the host as
+/// `ExitCode::Aborted`.
+pub fn abort() -> ! {
+    exit_with_code(ExitCode::Aborted)
+}
+
+/// Signal the completed setup to the host, which returns from `Runtime::setup`.
+pub(crate) fn ready() -> ! {
+    exit_with_code(ExitCode::Ready)
+}
@@ -0,0 +1,33 @@
+use bmvm_common::mem::{Flags, LayoutTable};
+use core::sync::atomic::{AtomicPtr, AtomicUsize, Ordering};
+
+static FILE_PTR: AtomicPtr<u8> = AtomicPtr::new(core::ptr::null_mut());
+static FILE_LEN: AtomicUsize = AtomicUsize::new(0);
+
+/// Locate the read-only mapped host file in the layout table and remember its location.
+pub(super) fn init(table: &LayoutTable) {
+    let Some(entry) = table
+        .into_iter()
+        .find(|e| e.flags().contains(Flags::MAPPED_FILE))
+    else {
+        return;
+    };
+
+    // the last page is zero padded, which is not part of the file content
+    let len = entry.size() as usize - entry.tail_padding() as usize;
+    FILE_LEN.store(len, Ordering::Relaxed);
+    FILE_PTR.store(entry.vaddr().as_mut_ptr::<u8>(), Ordering::Release);
+}
+
+/// Returns the content of the file mapped by the host via `ConfigBuilder::map_file_readonly`.
+/// The memory is shared with the host page cache without copying and can not be written.
+/// The slice is empty, if the host did not map a file.
+pub fn mapped_file() -> &'static [u8] {
+    let ptr = FILE_PTR.load(Ordering::Acquire);
+    if ptr.is_null() {
+        return &[];
+    }
+
+    let len = FILE_LEN.load(Ordering::Relaxed);
+    unsafe { core::slice::from_raw_parts(ptr, len) }
+}
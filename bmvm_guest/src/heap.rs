@@ -0,0 +1,69 @@
+use crate::pages::request_pages;
+use core::alloc::{GlobalAlloc, Layout};
+use talc::{OomHandler, Span, Talc, Talck};
+
+const PAGE_SIZE: usize = 4096;
+
+/// Minimum number of pages requested from the host once the heap is exhausted, to avoid a
+/// hypercall for every small allocation.
+const MIN_GROW_PAGES: usize = 16;
+
+/// Grow the heap by requesting additional pages from the host.
+struct RequestPages;
+
+impl OomHandler for RequestPages {
+    fn handle_oom(talc: &mut Talc<Self>, layout: Layout) -> Result<(), ()> {
+        // leave room for the allocator metadata and the alignment padding
+        let required = layout.size() + layout.align() + PAGE_SIZE;
+        let pages = required.div_ceil(PAGE_SIZE).max(MIN_GROW_PAGES);
+        let base = request_pages(pages).ok_or(())?;
+        unsafe { talc.claim(Span::from_base_size(base, pages * PAGE_SIZE)) }.map(|_| ())
+    }
+}
+
+/// Global allocator for guest private data, enabling the use of `alloc` types like `Vec`, `String`
+/// or `Box` inside the guest.
+///
+/// ```ignore
+/// extern crate alloc;
+///
+/// #[global_allocator]
+/// static HEAP: bmvm_guest::BmvmAllocator = bmvm_guest::BmvmAllocator::new();
+/// ```
+///
+/// The heap is independent of the shared arena used by `alloc`/`alloc_buf`: it starts empty and
+/// grows on demand via `request_pages`, so its memory is never visible to the host through the
+/// VMI. Values which should be passed to the host have to be copied into an `Owned`/`OwnedBuf`.
+/// If the host denies the page request, e.g. due to its memory limit or during a parallel upcall,
+/// the allocation fails and the `alloc` error handler is invoked.
+pub struct BmvmAllocator {
+    talck: Talck<spin::Mutex<()>, RequestPages>,
+}
+
+impl BmvmAllocator {
+    pub const fn new() -> Self {
+        Self {
+            talck: Talc::new(RequestPages).lock(),
+        }
+    }
+}
+
+impl Default for BmvmAllocator {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+unsafe impl GlobalAlloc for BmvmAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        unsafe { self.talck.alloc(layout) }
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        unsafe { self.talck.dealloc(ptr, layout) }
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        unsafe { self.talck.realloc(ptr, layout, new_size) }
+    }
+}
@@ -1,22 +1,50 @@
-use bmvm_common::HYPERCALL_IO_PORT;
+use crate::ports::hypercall_port;
+use bmvm_common::BMVM_HYPERCALL_MMIO;
+use bmvm_common::mem::LayoutTable;
 use bmvm_common::vmi::{Signature, Transport};
 use core::arch::asm;
+use core::sync::atomic::{AtomicPtr, Ordering};
+
+static DOORBELL: AtomicPtr<u64> = AtomicPtr::new(core::ptr::null_mut());
+
+/// Locate the MMIO doorbell in the layout table. If the host did not map one, hypercalls are
+/// triggered via port IO.
+pub(super) fn init(table: &LayoutTable) {
+    if let Some(entry) = table
+        .into_iter()
+        .find(|e| e.flags().is_system() && e.paddr() == BMVM_HYPERCALL_MMIO)
+    {
+        DOORBELL.store(entry.vaddr().as_mut_ptr::<u64>(), Ordering::Release);
+    }
+}
 
 pub unsafe fn execute(sig: Signature, transport: Transport) -> Transport {
+    let doorbell = DOORBELL.load(Ordering::Acquire);
     unsafe {
         let mut primary: u64 = transport.primary();
         let mut secondary: u64 = transport.secondary();
-        asm!(
-            // prepare for hypercall execution
-            "mov rbx, {func}",          // Move function signature to EBX
-            "out dx, al",               // Trigger VM Exit -> Hypercall Execution (we do not cate about the data in al)
-            func = in(reg) sig,
-            in("dx") HYPERCALL_IO_PORT,
-            // Post VM Exit
-            // Read return value offset ptr from EAX and construct OffsetPtr
-            inlateout("r8") primary,
-            inlateout("r9") secondary,
-        );
+        if doorbell.is_null() {
+            asm!(
+                // prepare for hypercall execution
+                "mov rbx, {func}",          // Move function signature to EBX
+                "out dx, al",               // Trigger VM Exit -> Hypercall Execution (we do not cate about the data in al)
+                func = in(reg) sig,
+                in("dx") hypercall_port(),
+                // Post VM Exit
+                // Read return value offset ptr from EAX and construct OffsetPtr
+                inlateout("r8") primary,
+                inlateout("r9") secondary,
+            );
+        } else {
+            asm!(
+                "mov rbx, {func}",          // Move function signature to EBX
+                "mov qword ptr [{bell}], rbx", // Ring the unbacked doorbell -> MMIO exit
+                func = in(reg) sig,
+                bell = in(reg) doorbell,
+                inlateout("r8") primary,
+                inlateout("r9") secondary,
+            );
+        }
 
         Transport::new(primary, secondary)
     }
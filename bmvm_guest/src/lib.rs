@@ -1,30 +1,66 @@
 #![no_std]
 #![no_main]
 
+mod args;
+mod assert;
+mod boot;
+mod exit;
+mod file;
+#[cfg(feature = "heap")]
+mod heap;
 mod hypercall;
+mod log;
+mod memops;
+pub mod metrics;
+mod pages;
 mod panic;
+mod ports;
+mod serial;
 mod setup;
+mod stream;
+mod yields;
 
 use core::arch::asm;
 
+pub use args::args;
+#[doc(hidden)]
+pub use assert::assert_failed;
+pub use exit::{abort, exit_normal, exit_with_code, exit_with_detail, exit_with_value};
+pub use file::mapped_file;
+#[cfg(feature = "heap")]
+pub use heap::BmvmAllocator;
 pub use hypercall::execute as hypercall;
-pub use panic::{exit_with_code, halt, panic, panic_with_code};
+#[doc(hidden)]
+pub use log::record as log_record;
+pub use memops::{host_memcpy, host_memset};
+pub use pages::request_pages;
+pub use panic::{halt, panic, panic_with_code};
+pub use ports::{exit_port, hypercall_port};
+pub use serial::write as serial_write;
+pub use stream::{Cancelled, cancelled as stream_cancelled, emit as stream_emit};
+pub use yields::{wait_for_host, yield_to_host};
 
 // re-export: bmvm-common
-pub use bmvm_common::error::ExitCode;
+pub use bmvm_common::error::{ExitCode, HostError};
 pub use bmvm_common::hash::SignatureHasher;
 pub use bmvm_common::mem::{
-    Foreign, ForeignBuf, OffsetPtr, Owned, OwnedBuf, RawOffsetPtr, Shared, SharedBuf, Unpackable,
-    alloc, alloc_buf, dealloc, dealloc_buf, get_foreign,
+    BufError, Foreign, ForeignBuf, ForeignCStr, OffsetPtr, Owned, OwnedBuf, OwnedCStr,
+    RawOffsetPtr, Shared, SharedBuf, Unpackable, alloc, alloc_buf, dealloc, dealloc_buf,
+    get_foreign, realloc_buf,
 };
-pub use bmvm_common::vmi::{ForeignShareable, OwnedShareable, Signature, Transport, UpcallFn};
-pub use bmvm_common::{EXIT_IO_PORT, HYPERCALL_IO_PORT, TypeSignature};
+pub use bmvm_common::vmi::{
+    ForeignShareable, HOST_MEMCPY, HOST_MEMOP_THRESHOLD, HOST_MEMSET, LogLevel, OwnedShareable,
+    Signature, Transport, UpcallFn, function_signature,
+};
+#[doc(hidden)]
+pub use bmvm_common::vmi::{is_scalar_pair, scalar_from_raw, scalar_to_raw};
+pub use bmvm_common::{EXIT_IO_PORT, HYPERCALL_IO_PORT, SERIAL_IO_PORT, TypeSignature};
 
 // re-export: bmvm-macros
-use crate::panic::ready;
+use crate::exit::ready;
 use crate::setup::setup;
 pub use bmvm_macros::TypeSignature;
-pub use bmvm_macros::{expose_guest as upcall, host as hypercall};
+pub use bmvm_macros::{bmvm_interface, expose_guest as upcall, host as hypercall};
 
 #[cfg(feature = "setup")]
 pub use bmvm_macros::setup;
@@ -33,16 +69,28 @@ unsafe extern "C" {
     fn __environment_setup();
 }
 
+/// ABI version the guest was built against, verified by the host before execution. The section
+/// name has to match `BMVM_META_SECTION_ABI`.
+#[used]
+#[unsafe(link_section = ".bmvm.vpc.abi")]
+static BMVM_ABI: [u8; 4] = bmvm_common::BMVM_ABI_VERSION.to_le_bytes();
+
+/// Entry point of the guest. The host passes the boot parameters in the argument registers of the
+/// C calling convention:
+/// * `rdi`: address of the layout table
+/// * `rsi`: address of the guest arguments blob, zero if there are none
+/// * `rdx`: length of the guest arguments blob
 #[unsafe(no_mangle)]
-pub extern "C" fn _start() -> ! {
-    if let Err(e) = setup() {
+pub extern "C" fn _start(layout_table: u64, args: u64, args_len: u64) -> ! {
+    if let Err(e) = setup(layout_table, args, args_len) {
         exit_with_code(e);
     }
 
     #[cfg(feature = "setup")]
-    unsafe {
-        __environment_setup()
-    };
+    {
+        unsafe { __environment_setup() };
+        boot::mark(bmvm_common::boot::Phase::CustomSetupDone);
+    }
 
     ready()
 }
@@ -58,3 +106,34 @@ pub fn write_buf(port: u16, buf: &[u8], len: u16) {
         );
     }
 }
+
+/// Number of bytes left in the shared memory used by `alloc` and `alloc_buf`, e.g. to check ahead
+/// of a large allocation and degrade gracefully instead of failing mid-computation. The memory is
+/// shared with the host, so its allocations reduce the figure as well. Returns zero if the host did
+/// not provide any shared memory.
+///
+/// The figure is optimistic: the allocator metadata and the fragmentation caused by deallocations
+/// are not accounted for, so an allocation of the returned size may still fail.
+pub fn arena_remaining() -> usize {
+    bmvm_common::mem::remaining().unwrap_or(0)
+}
+
+/// Compute the signature of a function in a const context, identical to the one generated by the
+/// `hypercall`/`upcall` macros and `bmvm_host::signature_of`.
+///
+/// ```ignore
+/// const SIG: Signature = signature_of!("add", (u32, u32) -> u64);
+/// ```
+#[macro_export]
+macro_rules! signature_of {
+    ($name:expr, ($($param:ty),* $(,)?) -> $ret:ty) => {
+        $crate::function_signature(
+            $name,
+            &[$(<$param as $crate::TypeSignature>::SIGNATURE),*],
+            <$ret as $crate::TypeSignature>::SIGNATURE,
+        )
+    };
+    ($name:expr, ($($param:ty),* $(,)?)) => {
+        $crate::signature_of!($name, ($($param),*) -> ())
+    };
+}
@@ -0,0 +1,35 @@
+use crate::hypercall::execute;
+use crate::panic::MessageWriter;
+use bmvm_common::vmi::{GUEST_LOG, GUEST_LOG_MAX_SIZE, LogLevel, Transport};
+use core::fmt::{Arguments, Write};
+
+/// Log a formatted message on the host at the given level. The message is tagged with the module
+/// path of the call site and truncated to `GUEST_LOG_MAX_SIZE` bytes including the tag.
+///
+/// ```ignore
+/// bmvm_guest::log!(bmvm_guest::LogLevel::Info, "processed {} of {} items", done, total);
+/// ```
+#[macro_export]
+macro_rules! log {
+    ($level:expr, $($arg:tt)+) => {
+        $crate::log_record($level, core::module_path!(), core::format_args!($($arg)+))
+    };
+}
+
+/// Format the record into a stack buffer and forward it to the host.
+#[doc(hidden)]
+pub fn record(level: LogLevel, target: &str, args: Arguments) {
+    let mut buf = [0u8; GUEST_LOG_MAX_SIZE];
+    let target_len = target.len().min(GUEST_LOG_MAX_SIZE);
+    buf[..target_len].copy_from_slice(&target.as_bytes()[..target_len]);
+
+    let mut writer = MessageWriter {
+        buf: &mut buf[target_len..],
+        len: 0,
+    };
+    let _ = writer.write_fmt(args);
+    let len = target_len + writer.len;
+
+    let packed = ((level as u64) << 56) | ((target_len as u64) << 32) | len as u64;
+    unsafe { execute(GUEST_LOG, Transport::new(buf.as_ptr() as u64, packed)) };
+}
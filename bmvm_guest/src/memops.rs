@@ -0,0 +1,34 @@
+use crate::hypercall::execute;
+use bmvm_common::vmi::{HOST_MEMCPY, HOST_MEMOP_THRESHOLD, HOST_MEMSET, Signature, Transport};
+
+/// Copy `len` bytes from `src` to `dst` like `core::ptr::copy`. Copies of at least
+/// `HOST_MEMOP_THRESHOLD` bytes are performed by the host with its optimized `memmove`, shorter
+/// ones are not worth the hypercall and are copied inline. If the host declines the request, e.g.
+/// as a range crosses a memory region, the copy is performed inline as well.
+///
+/// # Safety
+/// The same requirements as for `core::ptr::copy` apply: `src` must be valid for reads and `dst`
+/// for writes of `len` bytes. The ranges may overlap.
+pub unsafe fn host_memcpy(dst: *mut u8, src: *const u8, len: usize) {
+    if len < HOST_MEMOP_THRESHOLD || !request(HOST_MEMCPY, [dst as u64, src as u64, len as u64]) {
+        unsafe { core::ptr::copy(src, dst, len) };
+    }
+}
+
+/// Set `len` bytes starting at `dst` to `value` like `core::ptr::write_bytes`, performed by the host
+/// from `HOST_MEMOP_THRESHOLD` bytes on, see `host_memcpy`.
+///
+/// # Safety
+/// The same requirements as for `core::ptr::write_bytes` apply: `dst` must be valid for writes of
+/// `len` bytes.
+pub unsafe fn host_memset(dst: *mut u8, value: u8, len: usize) {
+    if len < HOST_MEMOP_THRESHOLD || !request(HOST_MEMSET, [dst as u64, value as u64, len as u64]) {
+        unsafe { core::ptr::write_bytes(dst, value, len) };
+    }
+}
+
+/// Pass the request to the host, returns whether the host performed it.
+fn request(sig: Signature, request: [u64; 3]) -> bool {
+    let result = unsafe { execute(sig, Transport::new(request.as_ptr() as u64, 0)) };
+    result.primary() == 0
+}
@@ -0,0 +1,115 @@
+//! Counters reported to the host without a hypercall. The guest increments named counters in a
+//! table mapped by the host at `BMVM_METRICS`, which the host reads after a call via
+//! `Module::metrics`.
+//!
+//! The table holds up to `METRICS_CAPACITY` distinct names of at most `METRIC_NAME_MAX_LEN` bytes
+//! each. Further names are rejected, their updates are dropped. The counters accumulate over the
+//! lifetime of the guest.
+//!
+//! ```ignore
+//! let iterations = bmvm_guest::metrics::counter("iterations");
+//! for _ in 0..n {
+//!     iterations.increment();
+//! }
+//! bmvm_guest::metrics::add("calls", 1);
+//! ```
+
+use bmvm_common::BMVM_METRICS;
+use bmvm_common::mem::LayoutTable;
+use bmvm_common::metrics::MetricSlot;
+pub use bmvm_common::metrics::{METRIC_NAME_MAX_LEN, METRICS_CAPACITY};
+use core::sync::atomic::{AtomicPtr, AtomicU8, AtomicU64, AtomicUsize, Ordering};
+
+static TABLE: AtomicPtr<MetricSlot> = AtomicPtr::new(core::ptr::null_mut());
+/// Number of claimed slots
+static CLAIMED: AtomicUsize = AtomicUsize::new(0);
+
+/// Locate the metrics table in the layout table.
+pub(super) fn init(table: &LayoutTable) {
+    let Some(entry) = table
+        .into_iter()
+        .find(|e| e.flags().is_system() && e.paddr() == BMVM_METRICS)
+    else {
+        return;
+    };
+
+    TABLE.store(entry.vaddr().as_mut_ptr::<MetricSlot>(), Ordering::Release);
+}
+
+/// Handle to a named counter, which updates the value without looking up the name again.
+/// Updates of a counter, whose name could not be interned, are dropped.
+#[derive(Clone, Copy)]
+pub struct Counter(Option<&'static AtomicU64>);
+
+impl Counter {
+    /// Add `n` to the counter
+    #[inline]
+    pub fn add(&self, n: u64) {
+        if let Some(value) = self.0 {
+            value.fetch_add(n, Ordering::Relaxed);
+        }
+    }
+
+    /// Add one to the counter
+    #[inline]
+    pub fn increment(&self) {
+        self.add(1)
+    }
+
+    /// Check if the name was interned, i.e. updates are visible to the host
+    pub fn is_recorded(&self) -> bool {
+        self.0.is_some()
+    }
+}
+
+/// Intern `name` in the metrics table and return its counter. Fails silently if the host did not
+/// map the table, the name is empty or too long or the table is full, see `Counter::is_recorded`.
+pub fn counter(name: &str) -> Counter {
+    Counter(intern(name))
+}
+
+/// Add `n` to the counter `name`, interning it on every call. Prefer `counter` within loops.
+pub fn add(name: &str, n: u64) {
+    counter(name).add(n)
+}
+
+fn intern(name: &str) -> Option<&'static AtomicU64> {
+    let table = TABLE.load(Ordering::Acquire);
+    if table.is_null() || name.is_empty() || name.len() > METRIC_NAME_MAX_LEN {
+        return None;
+    }
+
+    // look up the name among the claimed slots
+    let claimed = CLAIMED.load(Ordering::Acquire).min(METRICS_CAPACITY);
+    for idx in 0..claimed {
+        let slot = unsafe { table.add(idx) };
+        if unsafe { name_of(slot) } == Some(name.as_bytes()) {
+            return Some(unsafe { value_of(slot) });
+        }
+    }
+
+    // claim a new slot, a concurrent claim of the same name results in two slots, which the host
+    // merges by name
+    let idx = CLAIMED.fetch_add(1, Ordering::AcqRel);
+    if idx >= METRICS_CAPACITY {
+        return None;
+    }
+    let slot = unsafe { table.add(idx) };
+    unsafe {
+        let dst = &raw mut (*slot).name;
+        (*dst)[..name.len()].copy_from_slice(name.as_bytes());
+        AtomicU8::from_ptr(&raw mut (*slot).name_len).store(name.len() as u8, Ordering::Release);
+        Some(value_of(slot))
+    }
+}
+
+/// Name of the slot, if it is published
+unsafe fn name_of(slot: *mut MetricSlot) -> Option<&'static [u8]> {
+    let len = unsafe { AtomicU8::from_ptr(&raw mut (*slot).name_len) }.load(Ordering::Acquire);
+    let name = unsafe { &*(&raw const (*slot).name) };
+    (len > 0).then(|| &name[..len as usize])
+}
+
+unsafe fn value_of(slot: *mut MetricSlot) -> &'static AtomicU64 {
+    unsafe { AtomicU64::from_ptr(&raw mut (*slot).value) }
+}
@@ -0,0 +1,13 @@
+use crate::hypercall::execute;
+use bmvm_common::vmi::{OwnedShareable, REQUEST_PAGES};
+
+/// Request `count` additional 4KiB pages from the host. The pages are mapped contiguously above
+/// previously requested ones and appended to the layout table. Returns the virtual base address of
+/// the new memory, or `None` if the host denied the request, e.g. due to its memory limit.
+pub fn request_pages(count: usize) -> Option<*mut u8> {
+    let result = unsafe { execute(REQUEST_PAGES, (count as u64).into_transport()) };
+    match result.primary() {
+        0 => None,
+        base => Some(base as *mut u8),
+    }
+}
@@ -1,28 +1,63 @@
-use bmvm_common::EXIT_IO_PORT;
+use crate::exit::{exit_normal, exit_with_code};
+use bmvm_common::BMVM_PANIC_MESSAGE;
 use bmvm_common::error::ExitCode;
-use bmvm_common::mem::VirtAddr;
-use core::arch::asm;
+use bmvm_common::mem::{LayoutTable, VirtAddr};
+use core::fmt::Write;
 use core::panic::PanicInfo;
+use core::sync::atomic::{AtomicPtr, AtomicUsize, Ordering};
+
+static MESSAGE_PTR: AtomicPtr<u8> = AtomicPtr::new(core::ptr::null_mut());
+static MESSAGE_CAP: AtomicUsize = AtomicUsize::new(0);
+
+/// Locate the panic message region in the layout table.
+pub(super) fn init(table: &LayoutTable) {
+    let Some(entry) = table
+        .into_iter()
+        .find(|e| e.flags().is_system() && e.paddr() == BMVM_PANIC_MESSAGE)
+    else {
+        return;
+    };
+
+    MESSAGE_CAP.store(entry.size() as usize, Ordering::Relaxed);
+    MESSAGE_PTR.store(entry.vaddr().as_mut_ptr::<u8>(), Ordering::Release);
+}
+
+/// Writes into a fixed buffer, silently truncating at its end.
+pub(crate) struct MessageWriter<'a> {
+    pub(crate) buf: &'a mut [u8],
+    pub(crate) len: usize,
+}
+
+impl Write for MessageWriter<'_> {
+    fn write_str(&mut self, s: &str) -> core::fmt::Result {
+        let n = s.len().min(self.buf.len() - self.len);
+        self.buf[self.len..self.len + n].copy_from_slice(&s.as_bytes()[..n]);
+        self.len += n;
+        Ok(())
+    }
+}
+
+/// Record the panic message for post-mortem inspection by the host.
+fn record(info: &PanicInfo) {
+    let ptr = MESSAGE_PTR.load(Ordering::Acquire);
+    if ptr.is_null() {
+        return;
+    }
+
+    let cap = MESSAGE_CAP.load(Ordering::Relaxed) - size_of::<u64>();
+    let buf = unsafe { core::slice::from_raw_parts_mut(ptr.add(size_of::<u64>()), cap) };
+    let mut writer = MessageWriter { buf, len: 0 };
+    let _ = write!(writer, "{}", info);
+    unsafe { ptr.cast::<u64>().write(writer.len as u64) };
+}
 
 #[panic_handler]
 pub fn panic(info: &PanicInfo) -> ! {
+    record(info);
     let ptr = info as *const PanicInfo as u64;
     panic_with_code(ExitCode::Panic(VirtAddr::new_unchecked(ptr)))
 }
 
-/// Trigger VM exit with the provided exit code
-pub fn exit_with_code(code: ExitCode) -> ! {
-    write_additional_values(&code);
-    unsafe {
-        asm!(
-            "out dx, al",
-            in("dx") EXIT_IO_PORT,
-            in("al") code.as_u8(),
-            options(nomem, nostack, preserves_flags, noreturn),
-        )
-    }
-}
-
 /// Trigger VM exit with the provided exit code.
 pub fn panic_with_code(code: ExitCode) -> ! {
     exit_with_code(code)
@@ -30,21 +65,5 @@ pub fn panic_with_code(code: ExitCode) -> ! {
 
 /// Stop the execution
 pub fn halt() -> ! {
-    exit_with_code(ExitCode::Normal);
-}
-
-pub fn ready() -> ! {
-    exit_with_code(ExitCode::Ready);
-}
-
-/// Write additional values to registers before VM exit.
-fn write_additional_values(code: &ExitCode) {
-    unsafe {
-        match code {
-            ExitCode::UnknownUpcall(sig) => asm!("mov rbx, {}", in(reg) sig),
-            ExitCode::Unmapped(c) => asm!("mov bl, {}", in(reg_byte) *c),
-            ExitCode::Panic(addr) => asm!("mov rbx, {}", in(reg) addr.as_u64()),
-            _ => {}
-        }
-    }
+    exit_normal()
 }
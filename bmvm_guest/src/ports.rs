@@ -0,0 +1,42 @@
+use bmvm_common::mem::LayoutTable;
+use bmvm_common::{BMVM_IO_PORTS, EXIT_IO_PORT, HYPERCALL_IO_PORT};
+use core::sync::atomic::{AtomicU16, Ordering};
+
+static HYPERCALL_PORT: AtomicU16 = AtomicU16::new(HYPERCALL_IO_PORT);
+static EXIT_PORT: AtomicU16 = AtomicU16::new(EXIT_IO_PORT);
+static BOOT_PORT: AtomicU16 = AtomicU16::new(0);
+
+/// Read the IO ports configured by the host from the layout table. If the host did not map the
+/// region, the default ports are used and the boot markers are disabled.
+pub(super) fn init(table: &LayoutTable) {
+    let Some(entry) = table
+        .into_iter()
+        .find(|e| e.flags().is_system() && e.paddr() == BMVM_IO_PORTS)
+    else {
+        return;
+    };
+
+    let ptr = entry.vaddr().as_ptr::<u16>();
+    let (hypercall, exit, boot) = unsafe { (ptr.read(), ptr.add(1).read(), ptr.add(2).read()) };
+    HYPERCALL_PORT.store(hypercall, Ordering::Relaxed);
+    EXIT_PORT.store(exit, Ordering::Relaxed);
+    BOOT_PORT.store(boot, Ordering::Relaxed);
+}
+
+/// The IO port used for triggering hypercalls.
+#[inline]
+pub fn hypercall_port() -> u16 {
+    HYPERCALL_PORT.load(Ordering::Relaxed)
+}
+
+/// The IO port used for exiting to the host with an `ExitCode`.
+#[inline]
+pub fn exit_port() -> u16 {
+    EXIT_PORT.load(Ordering::Relaxed)
+}
+
+/// The IO port used for marking the setup phases, zero if the host disabled the markers.
+#[inline]
+pub(crate) fn boot_port() -> u16 {
+    BOOT_PORT.load(Ordering::Relaxed)
+}
@@ -0,0 +1,15 @@
+use bmvm_common::SERIAL_IO_PORT;
+use core::arch::asm;
+
+/// Write the message to the serial port, where it is captured by the host.
+pub fn write(msg: &str) {
+    unsafe {
+        asm!(
+            "rep outsb",
+            in("dx") SERIAL_IO_PORT,
+            inout("rsi") msg.as_ptr() => _,
+            inout("rcx") msg.len() => _,
+            options(nostack, preserves_flags, readonly),
+        );
+    }
+}
@@ -1,30 +1,56 @@
+use bmvm_common::boot::Phase;
 use bmvm_common::error::ExitCode;
 use bmvm_common::interprete::{Interpret, InterpretError};
-use bmvm_common::mem::{Align, Arena, DataAccessMode, LayoutTable, Page4KiB};
-use bmvm_common::{BMVM_MEM_LAYOUT_TABLE, mem};
+#[cfg(not(feature = "no-arena"))]
+use bmvm_common::mem::{self, Arena, DataAccessMode};
+use bmvm_common::mem::{Align, LayoutTable, Page4KiB};
 
-/// Parse the memory info structure and initialize the paging system etc.
+use crate::{args, boot, file, hypercall, metrics, panic, ports};
+
+/// Parse the memory info structure at `layout_table` and initialize the paging system etc. The
+/// arguments are passed by the host to the entry point, see `_start`.
 #[inline(always)]
-pub(super) fn setup() -> Result<(), ExitCode> {
-    let raw_ptr = BMVM_MEM_LAYOUT_TABLE.as_u64() as *const u8;
+pub(super) fn setup(layout_table: u64, args: u64, args_len: u64) -> Result<(), ExitCode> {
+    let raw_ptr = layout_table as *const u8;
     let raw = unsafe { core::slice::from_raw_parts(raw_ptr, Page4KiB::ALIGNMENT as usize) };
     let table = LayoutTable::from_bytes(raw).map_err(|interpret_err| match interpret_err {
         InterpretError::TooSmall(_, _) => ExitCode::InvalidMemoryLayoutTableTooSmall,
         InterpretError::Misaligned(_, _) => ExitCode::InvalidMemoryLayoutTableMisaligned,
     })?;
 
-    let shared = table
-        .into_iter()
-        .find(|entry| {
-            entry
-                .flags()
-                .data_access_mode()
-                .is_some_and(|m| m == DataAccessMode::Shared)
-        })
-        .map(Arena::from);
+    // use the IO ports configured by the host, which include the boot port
+    ports::init(table);
+    boot::mark(Phase::LayoutParsed);
 
     // set up the allocator for the VMI
-    mem::init(shared);
+    #[cfg(not(feature = "no-arena"))]
+    {
+        let find = |mode: DataAccessMode| {
+            table
+                .into_iter()
+                .find(|entry| entry.flags().data_access_mode() == Some(mode))
+        };
+        let shared = find(DataAccessMode::Shared).map(Arena::from);
+        // data owned by the host is read through its read-only view, so writing to it faults
+        let foreign = find(DataAccessMode::Foreign).map(|entry| Arena::from(entry).ptr);
+        mem::init(shared, foreign);
+    }
+
+    // make the host provided arguments available
+    args::init(args, args_len);
+
+    // make the host file mapping available
+    file::init(table);
+
+    // record panic messages for post-mortem inspection
+    panic::init(table);
+
+    // count metrics in the host provided table
+    metrics::init(table);
+
+    // switch to the MMIO hypercall transport, if the host provides a doorbell
+    hypercall::init(table);
+    boot::mark(Phase::ArenasInitialized);
 
     Ok(())
 }
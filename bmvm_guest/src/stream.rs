@@ -0,0 +1,32 @@
+use crate::hypercall::execute;
+use bmvm_common::vmi::{STREAM_CANCELLED, STREAM_CHUNK, Transport};
+use core::sync::atomic::{AtomicU64, Ordering};
+
+/// Control word returned by the host for the most recent chunk
+static CONTROL: AtomicU64 = AtomicU64::new(0);
+
+/// The host consumer of the streaming upcall stopped early
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Cancelled;
+
+/// Pass `chunk` to the host consumer of the current streaming upcall. The host copies the chunk
+/// before returning, so the buffer can be reused for the next one.
+///
+/// Returns `Err(Cancelled)` once the consumer dropped the stream, in which case the chunk was
+/// discarded and the guest should return from the upcall without producing further chunks. Outside
+/// a streaming upcall every chunk is rejected this way.
+pub fn emit(chunk: &[u8]) -> Result<(), Cancelled> {
+    let transport = Transport::new(chunk.as_ptr() as u64, chunk.len() as u64);
+    let control = unsafe { execute(STREAM_CHUNK, transport) }.primary();
+    CONTROL.store(control, Ordering::Relaxed);
+    match cancelled() {
+        true => Err(Cancelled),
+        false => Ok(()),
+    }
+}
+
+/// Check if the host cancelled the stream in reply to the most recent chunk, e.g. to skip an
+/// expensive computation before producing the next one.
+pub fn cancelled() -> bool {
+    CONTROL.load(Ordering::Relaxed) & STREAM_CANCELLED != 0
+}
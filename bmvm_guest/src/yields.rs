@@ -0,0 +1,31 @@
+use crate::hypercall::execute;
+use bmvm_common::HLT_WAIT_MARKER;
+use bmvm_common::vmi::{Transport, YIELD};
+use core::arch::asm;
+
+/// Suspend the current upcall and return control to the host, which continues the guest via
+/// `Module::resume`. The guest resumes right after this call: registers, stack and memory are
+/// preserved, although the host may have read or written the shared memory in the meantime.
+///
+/// Yielding outside an upcall, e.g. during the setup, a callback or a streaming upcall, returns
+/// immediately.
+pub fn yield_to_host() {
+    unsafe { execute(YIELD, Transport::new(0, 0)) };
+}
+
+/// Idle in `hlt` until the host resumes the guest via `Module::resume`, like `yield_to_host` but
+/// without a hypercall. The `hlt` is marked with `HLT_WAIT_MARKER` in `rax` to distinguish it from
+/// a final `hlt`, which terminates the guest.
+///
+/// The host has to be configured with `HltPolicy::Resumable`, otherwise it treats every `hlt` as
+/// termination. Waiting outside a plain upcall returns immediately.
+pub fn wait_for_host() {
+    unsafe {
+        asm!(
+            "hlt",
+            in("rax") HLT_WAIT_MARKER,
+            // the host may write the shared memory before resuming the guest
+            options(nostack, preserves_flags),
+        );
+    }
+}
@@ -0,0 +1,43 @@
+use bmvm_common::mem::AlignedUsize;
+use bmvm_host::{Backing, ConfigBuilder, ModuleBuilder};
+use criterion::{Criterion, criterion_group, criterion_main};
+use std::hint::black_box;
+use std::path::PathBuf;
+use std::time::Duration;
+
+// 3mm touches three large matrices, which makes it sensitive to host TLB misses
+const POLYBENCH: &str = "../bench/binaries/bmvm-polybench-3mm";
+const SHARED_MEMORY: usize = 512 * 1024 * 1024; // 512MiB
+
+pub fn bmvm_backing_polybench(c: &mut Criterion) {
+    let path = PathBuf::from(POLYBENCH);
+    let mut group = c.benchmark_group("bmvm-backing-polybench");
+    group.measurement_time(Duration::from_secs(60));
+    group.sample_size(10);
+
+    for backing in [
+        Backing::Regular,
+        Backing::Transparent,
+        Backing::HugeTlb2M,
+        Backing::HugeTlb1G,
+    ] {
+        group.bench_function(format!("{:?}", backing), |b| {
+            b.iter(|| {
+                let vm = ConfigBuilder::new()
+                    .shared_memory(AlignedUsize::new_ceil(SHARED_MEMORY))
+                    .host_backing(backing);
+                // the kernel runs during the guest setup
+                black_box(
+                    ModuleBuilder::new()
+                        .with_path(&path)
+                        .configure_vm(vm)
+                        .build()
+                        .unwrap(),
+                );
+            })
+        });
+    }
+}
+
+criterion_group!(benches, bmvm_backing_polybench);
+criterion_main!(benches);
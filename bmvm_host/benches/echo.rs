@@ -1,5 +1,8 @@
-use bmvm_common::mem::{AlignedNonZeroUsize, ForeignBuf, SharedBuf, alloc_buf};
-use bmvm_host::{ConfigBuilder, ModuleBuilder, linker};
+use bmvm_common::mem::{AlignedNonZeroUsize, ForeignBuf, SharedBuf};
+use bmvm_host::{
+    Alignment, ConfigBuilder, HostError, ModuleBuilder, TransportKind, bmvm_interface, linker,
+    register_fn,
+};
 use criterion::{Criterion, criterion_group, criterion_main};
 use std::hint::black_box;
 use std::path::PathBuf;
@@ -17,8 +20,12 @@ pub fn bmvm_echo_noop(c: &mut Criterion) {
     group.measurement_time(Duration::from_secs(10));
 
     let linker = linker::ConfigBuilder::new()
-        .register_guest_function::<(), ()>("noop")
-        .register_guest_function::<(SharedBuf,), ForeignBuf>("reverse")
+        .register_all(register_fn! {
+            noop: fn(),
+            reverse: fn(SharedBuf) -> ForeignBuf,
+            add2: fn(u64, u64) -> u64,
+            add3: fn(u64, u64, u64) -> u64,
+        })
         .build();
 
     let vm = ConfigBuilder::new().stack_size(AlignedNonZeroUsize::new_ceil(BMVM_STACK).unwrap());
@@ -44,11 +51,23 @@ pub fn bmvm_echo_noop(c: &mut Criterion) {
         })
     });
 
+    // a scalar pair bypasses the shared memory, a third parameter requires the packed struct
+    let add2 = module.get_upcall::<(u64, u64), u64>("add2").unwrap();
+    let add3 = module.get_upcall::<(u64, u64, u64), u64>("add3").unwrap();
+
+    group.bench_function("scalar-pair", |b| {
+        b.iter(|| black_box(add2.call(&mut module, (1, 2)).unwrap()))
+    });
+
+    group.bench_function("scalar-packed", |b| {
+        b.iter(|| black_box(add3.call(&mut module, (1, 2, 0)).unwrap()))
+    });
+
     group.bench_function("reverse-64", |b| {
         b.iter(|| {
             black_box({
-                let owned = unsafe { alloc_buf(64).unwrap() };
-                let _ = reverse.call(&mut module, (owned.into_shared(),)).unwrap();
+                let buf = SharedBuf::from_bytes(&[0u8; 64]).unwrap();
+                let _ = reverse.call(&mut module, (buf,)).unwrap();
             })
         })
     });
@@ -56,8 +75,8 @@ pub fn bmvm_echo_noop(c: &mut Criterion) {
     group.bench_function("reverse-256", |b| {
         b.iter(|| {
             black_box({
-                let owned = unsafe { alloc_buf(256).unwrap() };
-                let _ = reverse.call(&mut module, (owned.into_shared(),)).unwrap();
+                let buf = SharedBuf::from_bytes(&[0u8; 256]).unwrap();
+                let _ = reverse.call(&mut module, (buf,)).unwrap();
             })
         })
     });
@@ -65,13 +84,84 @@ pub fn bmvm_echo_noop(c: &mut Criterion) {
     group.bench_function("reverse-1024", |b| {
         b.iter(|| {
             black_box({
-                let owned = unsafe { alloc_buf(1024).unwrap() };
-                let _ = reverse.call(&mut module, (owned.into_shared(),)).unwrap();
+                let buf = SharedBuf::from_bytes(&[0u8; 1024]).unwrap();
+                let _ = reverse.call(&mut module, (buf,)).unwrap();
             })
         })
     });
 }
 
+include!("../../bench/interface.rs");
+
+pub fn bmvm_echo_transport(c: &mut Criterion) {
+    const HYPERCALLS: u64 = 100;
+
+    let path = PathBuf::from(BMVM);
+    let mut group = c.benchmark_group("bmvm-echo-transport");
+    group.measurement_time(Duration::from_secs(10));
+
+    for kind in [TransportKind::PortIo, TransportKind::Mmio] {
+        let linker = linker::ConfigBuilder::new()
+            .register_all(register_fn! {
+                ping: fn(u64) -> u64,
+            })
+            .build();
+
+        let vm = ConfigBuilder::new()
+            .stack_size(AlignedNonZeroUsize::new_ceil(BMVM_STACK).unwrap())
+            .transport(kind);
+
+        let mut module = ModuleBuilder::new()
+            .with_path(&path)
+            .configure_linker(linker)
+            .configure_vm(vm)
+            .build()
+            .unwrap();
+
+        let ping = module.get_upcall::<(u64,), u64>("ping").unwrap();
+        assert_eq!(ping.call(&mut module, (HYPERCALLS,)).unwrap(), HYPERCALLS);
+        // each hypercall leaves the guest, regardless of the transport
+        assert!(module.last_call_exits().total() >= HYPERCALLS);
+
+        group.bench_function(format!("ping-{}-{:?}", HYPERCALLS, kind), |b| {
+            b.iter(|| black_box(ping.call(&mut module, (HYPERCALLS,)).unwrap()))
+        });
+    }
+}
+
+/// The scalar calls with the guest entry and stacks placed at cache line and page boundaries, which
+/// shows how much of the run-to-run variance is caused by the placement.
+pub fn bmvm_echo_aligned(c: &mut Criterion) {
+    let path = PathBuf::from(BMVM);
+    let mut group = c.benchmark_group("bmvm-echo-aligned");
+    group.measurement_time(Duration::from_secs(10));
+
+    for align in [Alignment::Natural, Alignment::CacheLine, Alignment::Page] {
+        let linker = linker::ConfigBuilder::new()
+            .register_all(register_fn! {
+                add2: fn(u64, u64) -> u64,
+            })
+            .build();
+
+        let vm = ConfigBuilder::new()
+            .stack_size(AlignedNonZeroUsize::new_ceil(BMVM_STACK).unwrap())
+            .align_entry(align)
+            .align_stack(align);
+
+        let mut module = ModuleBuilder::new()
+            .with_path(&path)
+            .configure_linker(linker)
+            .configure_vm(vm)
+            .build()
+            .unwrap();
+
+        let add2 = module.get_upcall::<(u64, u64), u64>("add2").unwrap();
+        group.bench_function(format!("scalar-pair-{:?}", align), |b| {
+            b.iter(|| black_box(add2.call(&mut module, (1, 2)).unwrap()))
+        });
+    }
+}
+
 pub fn wasm_echo_noop(c: &mut Criterion) {
     let mut group = c.benchmark_group("wasm-echo");
     group.measurement_time(Duration::from_secs(5));
@@ -205,5 +295,12 @@ pub fn native_echo_noop(c: &mut Criterion) {
     });
 }
 
-criterion_group!(benches, wasm_echo_noop, native_echo_noop, bmvm_echo_noop);
+criterion_group!(
+    benches,
+    wasm_echo_noop,
+    native_echo_noop,
+    bmvm_echo_noop,
+    bmvm_echo_transport,
+    bmvm_echo_aligned
+);
 criterion_main!(benches);
@@ -0,0 +1,102 @@
+use bmvm_common::mem::{AlignedUsize, ForeignBuf, SharedBuf};
+use bmvm_common::vmi::HOST_MEMOP_THRESHOLD;
+use bmvm_host::{ConfigBuilder, Module, ModuleBuilder, Upcall, linker};
+use criterion::{Criterion, criterion_group, criterion_main};
+use std::hint::black_box;
+use std::path::PathBuf;
+use std::time::{Duration, Instant};
+
+const BMVM: &str = "../bench/binaries/bmvm-echo";
+const BMVM_SHARED: usize = 8 * 1024 * 1024; // 8MiB
+const ROUNDS: u64 = 64;
+const RUNS: usize = 16;
+const MIN_SHIFT: usize = 8;
+const MAX_SHIFT: usize = 20;
+
+type CopyRounds = Upcall<(SharedBuf, u64, bool), ForeignBuf>;
+
+/// Time per copy of `len` bytes within the guest, via the host if `host` is set. The fastest of
+/// `RUNS` calls, which excludes the outliers of the host scheduling.
+fn per_copy(module: &mut Module, copy_rounds: &CopyRounds, len: usize, host: bool) -> Duration {
+    let data = (0..len).map(|i| i as u8).collect::<Vec<_>>();
+    (0..RUNS)
+        .map(|_| {
+            let buf = SharedBuf::from_bytes(&data).unwrap();
+            let start = Instant::now();
+            let out = copy_rounds.call(module, (buf, ROUNDS, host)).unwrap();
+            let elapsed = start.elapsed();
+            assert_eq!(out.as_ref(), data.as_slice());
+            elapsed / ROUNDS as u32
+        })
+        .min()
+        .unwrap()
+}
+
+/// Copies within the guest inline and via the `HOST_MEMCPY` hypercall. Reports the smallest size
+/// from which the host is faster, which `HOST_MEMOP_THRESHOLD` should match.
+pub fn bmvm_memop_copy(c: &mut Criterion) {
+    let path = PathBuf::from(BMVM);
+    let mut group = c.benchmark_group("bmvm-memop");
+    group.measurement_time(Duration::from_secs(10));
+
+    let linker = linker::ConfigBuilder::new()
+        .register_guest_function::<(SharedBuf, u64, bool), ForeignBuf>("copy_rounds");
+    let vm = ConfigBuilder::new().shared_memory(AlignedUsize::new_ceil(BMVM_SHARED));
+
+    let mut module = ModuleBuilder::new()
+        .with_path(&path)
+        .configure_linker(linker)
+        .configure_vm(vm)
+        .build()
+        .unwrap();
+
+    let copy_rounds = module
+        .get_upcall::<(SharedBuf, u64, bool), ForeignBuf>("copy_rounds")
+        .unwrap();
+
+    let mut crossover = None;
+    for len in (MIN_SHIFT..=MAX_SHIFT).map(|shift| 1usize << shift) {
+        let inline = per_copy(&mut module, &copy_rounds, len, false);
+        let host = per_copy(&mut module, &copy_rounds, len, true);
+        println!(
+            "{:>8} bytes: inline {:>10?}, host {:>10?}",
+            len, inline, host
+        );
+        if host < inline {
+            crossover.get_or_insert(len);
+        }
+    }
+    match crossover {
+        Some(len) => println!(
+            "host copy faster from {} bytes, HOST_MEMOP_THRESHOLD is {} bytes",
+            len, HOST_MEMOP_THRESHOLD
+        ),
+        None => println!(
+            "host copy slower up to {} bytes, HOST_MEMOP_THRESHOLD is {} bytes",
+            1usize << MAX_SHIFT,
+            HOST_MEMOP_THRESHOLD
+        ),
+    }
+
+    for shift in [12, 16, 20] {
+        let data = vec![0u8; 1 << shift];
+        for host in [false, true] {
+            let name = format!(
+                "copy-{}-{}",
+                data.len(),
+                if host { "host" } else { "inline" }
+            );
+            group.bench_function(name, |b| {
+                b.iter(|| {
+                    black_box({
+                        let buf = SharedBuf::from_bytes(&data).unwrap();
+                        let _ = copy_rounds.call(&mut module, (buf, 1, host)).unwrap();
+                    })
+                })
+            });
+        }
+    }
+}
+
+criterion_group!(benches, bmvm_memop_copy);
+criterion_main!(benches);
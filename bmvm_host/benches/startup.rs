@@ -1,5 +1,6 @@
-use bmvm_host::ModuleBuilder;
-use criterion::{Criterion, criterion_group, criterion_main};
+use bmvm_host::mem::AlignedUsize;
+use bmvm_host::{ConfigBuilder, ModuleBuilder, RuntimeBuilder};
+use criterion::{BatchSize, Criterion, criterion_group, criterion_main};
 use std::hint::black_box;
 use std::path::PathBuf;
 use std::time::Duration;
@@ -21,7 +22,47 @@ pub fn bmvm_setup_noop(c: &mut Criterion) {
             black_box(builder.build().unwrap());
         })
     });
+
+    // parse, allocate and map the executable without running any guest code
+    group.bench_function("noop-prepare", |b| {
+        b.iter(|| black_box(RuntimeBuilder::new().with_path(&path).build().unwrap()))
+    });
+
+    // run the guest from `_start` until it signals readiness
+    group.bench_function("noop-guest-setup", |b| {
+        b.iter_batched(
+            || RuntimeBuilder::new().with_path(&path).build().unwrap(),
+            |runtime| black_box(runtime.setup().unwrap()),
+            BatchSize::SmallInput,
+        )
+    });
+}
+
+/// Prepare a guest with 16MiB of shared memory mapped via 4KiB pages, with and without reusing the
+/// page tables of the previous iteration.
+pub fn bmvm_setup_page_table_cache(c: &mut Criterion) {
+    let path = PathBuf::from(NOOP);
+    let mut group = c.benchmark_group("bmvm-setup-16mib");
+    group.measurement_time(Duration::from_secs(30));
+
+    for (name, cache) in [("rebuild", false), ("cached", true)] {
+        group.bench_function(name, |b| {
+            b.iter(|| {
+                let config = ConfigBuilder::new()
+                    .shared_memory(AlignedUsize::new_ceil(16 << 20))
+                    .track_access(true)
+                    .page_table_cache(cache);
+                black_box(
+                    RuntimeBuilder::new()
+                        .configure_vm(config)
+                        .with_path(&path)
+                        .build()
+                        .unwrap(),
+                )
+            })
+        });
+    }
 }
 
-criterion_group!(benches, bmvm_setup_noop);
+criterion_group!(benches, bmvm_setup_noop, bmvm_setup_page_table_cache);
 criterion_main!(benches);
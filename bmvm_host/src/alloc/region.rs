@@ -1,17 +1,26 @@
 use crate::alloc::{Accessible, Perm, ReadOnly, ReadWrite, WriteOnly};
 use bmvm_common::mem::{Align, AlignedNonZeroUsize, Arena, DefaultAlign, PhysAddr};
 use core::ffi::c_void;
-use kvm_bindings::kvm_userspace_memory_region;
+#[cfg(all(target_os = "linux", feature = "kvm"))]
+use kvm_bindings::{KVM_MEM_LOG_DIRTY_PAGES, KVM_MEM_READONLY, kvm_userspace_memory_region};
+#[cfg(all(target_os = "linux", feature = "kvm"))]
 use kvm_ioctls::VmFd;
+#[cfg(all(target_os = "linux", feature = "kvm"))]
+use nix::sys::mman::mmap;
 use nix::sys::mman::{MapFlags, ProtFlags, mmap_anonymous};
+#[cfg(target_os = "linux")]
+use nix::sys::mman::{MmapAdvise, madvise};
 use std::cmp::min;
+#[cfg(all(target_os = "linux", feature = "kvm"))]
 use std::fs::File;
+#[cfg(all(target_os = "linux", feature = "kvm"))]
 use std::io::Write;
 use std::marker::PhantomData;
 use std::ops::Range;
 use std::panic;
 use std::ptr::NonNull;
 use std::slice;
+use std::sync::atomic::{AtomicBool, Ordering};
 
 const MMAP_FLAGS: [MapFlags; 2] = [MapFlags::MAP_PRIVATE, MapFlags::MAP_ANONYMOUS];
 
@@ -19,6 +28,7 @@ type Result<T> = core::result::Result<T, Error>;
 
 #[derive(Debug, thiserror::Error)]
 pub enum Error {
+    #[cfg(all(target_os = "linux", feature = "kvm"))]
     #[error("kvm errno: {0}")]
     KvmErrno(#[from] kvm_ioctls::Error),
 
@@ -46,9 +56,11 @@ pub enum Error {
     #[error("region at {0:x} is not readable")]
     NotReadable(PhysAddr),
 
+    #[cfg(all(target_os = "linux", feature = "kvm"))]
     #[error("failed to set region as user memory ({0:#x}): {1}")]
     RegionMappingFailed(PhysAddr, kvm_ioctls::Error),
 
+    #[cfg(all(target_os = "linux", feature = "kvm"))]
     #[error("failed to remove region from user memory ({0:#x}): {1}")]
     RegionUnmappingFailed(PhysAddr, kvm_ioctls::Error),
 }
@@ -65,6 +77,7 @@ pub enum RegionEntry {
     ReadWrite(Region<ReadWrite, DefaultAlign>),
 }
 
+#[cfg(all(target_os = "linux", feature = "kvm"))]
 impl RegionEntry {
     pub fn addr(&self) -> PhysAddr {
         match self {
@@ -74,14 +87,6 @@ impl RegionEntry {
         }
     }
 
-    pub fn as_ptr(&self) -> *const u8 {
-        match self {
-            RegionEntry::ReadOnly(r) => r.as_ptr(),
-            RegionEntry::WriteOnly(r) => r.as_ptr(),
-            RegionEntry::ReadWrite(r) => r.as_ptr(),
-        }
-    }
-
     pub fn capacity(&self) -> AlignedNonZeroUsize {
         match self {
             RegionEntry::ReadOnly(r) => r.capacity,
@@ -98,6 +103,14 @@ impl RegionEntry {
         }
     }
 
+    pub fn as_mut(&mut self) -> Option<&mut [u8]> {
+        match self {
+            RegionEntry::WriteOnly(r) => Some(r.as_mut()),
+            RegionEntry::ReadWrite(r) => Some(r.as_mut()),
+            _ => None,
+        }
+    }
+
     pub fn readable(&self) -> bool {
         matches!(self, RegionEntry::ReadOnly(_) | RegionEntry::ReadWrite(_))
     }
@@ -106,11 +119,20 @@ impl RegionEntry {
         matches!(self, RegionEntry::WriteOnly(_) | RegionEntry::ReadWrite(_))
     }
 
-    pub fn set_as_guest_memory(&mut self, vm: &VmFd, slot: u32) -> Result<()> {
+    /// KVM memory slot of the region, if it is mapped into the guest
+    pub fn slot(&self) -> Option<u32> {
         match self {
-            RegionEntry::ReadOnly(r) => r.set_as_guest_memory(vm, slot),
-            RegionEntry::WriteOnly(r) => r.set_as_guest_memory(vm, slot),
-            RegionEntry::ReadWrite(r) => r.set_as_guest_memory(vm, slot),
+            RegionEntry::ReadOnly(r) => r.slot,
+            RegionEntry::WriteOnly(r) => r.slot,
+            RegionEntry::ReadWrite(r) => r.slot,
+        }
+    }
+
+    pub fn set_as_guest_memory(&mut self, vm: &VmFd, slot: u32, log_dirty: bool) -> Result<()> {
+        match self {
+            RegionEntry::ReadOnly(r) => r.set_as_guest_memory(vm, slot, log_dirty),
+            RegionEntry::WriteOnly(r) => r.set_as_guest_memory(vm, slot, log_dirty),
+            RegionEntry::ReadWrite(r) => r.set_as_guest_memory(vm, slot, log_dirty),
         }
     }
 
@@ -137,7 +159,10 @@ impl RegionCollection {
         let range = region.addr().as_usize()..(region.addr().as_usize() + region.capacity().get());
         self.inner.push((range, region.into()));
     }
+}
 
+#[cfg(all(target_os = "linux", feature = "kvm"))]
+impl RegionCollection {
     pub fn get(&self, addr: PhysAddr) -> Option<&RegionEntry> {
         self.inner
             .iter()
@@ -145,6 +170,13 @@ impl RegionCollection {
             .map(|(_, region)| region)
     }
 
+    pub fn get_mut(&mut self, addr: PhysAddr) -> Option<&mut RegionEntry> {
+        self.inner
+            .iter_mut()
+            .find(|(range, _)| range.contains(&addr.as_usize()))
+            .map(|(_, region)| region)
+    }
+
     pub fn append(&mut self, other: &mut Self) {
         self.inner.append(&mut other.inner);
     }
@@ -194,10 +226,12 @@ impl IntoIterator for RegionCollection {
     }
 }
 
+#[cfg(all(target_os = "linux", feature = "kvm"))]
 pub struct RegionCollectionIter<'a> {
     inner: slice::Iter<'a, (Range<usize>, RegionEntry)>,
 }
 
+#[cfg(all(target_os = "linux", feature = "kvm"))]
 impl<'a> RegionCollectionIter<'a> {
     fn new(collection: &'a RegionCollection) -> Self {
         Self {
@@ -206,6 +240,7 @@ impl<'a> RegionCollectionIter<'a> {
     }
 }
 
+#[cfg(all(target_os = "linux", feature = "kvm"))]
 impl<'a> Iterator for RegionCollectionIter<'a> {
     type Item = &'a RegionEntry;
     fn next(&mut self) -> Option<Self::Item> {
@@ -213,10 +248,12 @@ impl<'a> Iterator for RegionCollectionIter<'a> {
     }
 }
 
+#[cfg(all(target_os = "linux", feature = "kvm"))]
 pub struct RegionCollectionIterMut<'a> {
     inner: slice::IterMut<'a, (Range<usize>, RegionEntry)>,
 }
 
+#[cfg(all(target_os = "linux", feature = "kvm"))]
 impl<'a> RegionCollectionIterMut<'a> {
     fn new(collection: &'a mut RegionCollection) -> Self {
         Self {
@@ -225,6 +262,7 @@ impl<'a> RegionCollectionIterMut<'a> {
     }
 }
 
+#[cfg(all(target_os = "linux", feature = "kvm"))]
 impl<'a> Iterator for RegionCollectionIterMut<'a> {
     type Item = &'a mut RegionEntry;
     fn next(&mut self) -> Option<Self::Item> {
@@ -258,6 +296,7 @@ impl<P: Perm, A: Align> ProtoRegion<P, A> {
             addr,
             capacity: self.capacity,
             ptr: self.ptr,
+            #[cfg(all(target_os = "linux", feature = "kvm"))]
             slot: None,
             _perm: PhantomData,
             _align: PhantomData,
@@ -277,6 +316,7 @@ pub struct Region<P: Perm, A: Align = DefaultAlign> {
     addr: PhysAddr,
     capacity: AlignedNonZeroUsize,
     ptr: NonNull<u8>,
+    #[cfg(all(target_os = "linux", feature = "kvm"))]
     slot: Option<u32>,
     _perm: PhantomData<P>,
     _align: PhantomData<A>,
@@ -293,9 +333,21 @@ impl<P: Perm, A: Align> Region<P, A> {
         self.capacity
     }
 
-    /// Set the region as a memory region
-    pub fn set_as_guest_memory(&mut self, vm: &VmFd, slot: u32) -> Result<()> {
-        let result = unsafe { set_as_guest_memory(vm, slot, self.capacity, self.addr, self.ptr) };
+    /// Set the region as a memory region. With `log_dirty`, KVM records the pages written by the
+    /// guest, which can be retrieved via `VmFd::get_dirty_log`.
+    #[cfg(all(target_os = "linux", feature = "kvm"))]
+    pub fn set_as_guest_memory(&mut self, vm: &VmFd, slot: u32, log_dirty: bool) -> Result<()> {
+        let mut flags = if log_dirty {
+            KVM_MEM_LOG_DIRTY_PAGES
+        } else {
+            0
+        };
+        // guest writes to a read-only region exit as MMIO instead of reaching the host memory
+        if !P::prot_flags().contains(ProtFlags::PROT_WRITE) {
+            flags |= KVM_MEM_READONLY;
+        }
+        let result =
+            unsafe { set_as_guest_memory(vm, slot, flags, self.capacity, self.addr, self.ptr) };
 
         if result.is_ok() {
             self.slot = Some(slot);
@@ -304,6 +356,7 @@ impl<P: Perm, A: Align> Region<P, A> {
         result
     }
 
+    #[cfg(all(target_os = "linux", feature = "kvm"))]
     pub fn remove_from_guest_memory(&mut self, vm: &VmFd) -> Result<()> {
         unsafe {
             if self.slot.is_none() {
@@ -480,6 +533,7 @@ macro_rules! impl_write_addr {
     };
 }
 
+#[cfg(all(target_os = "linux", feature = "kvm"))]
 macro_rules! impl_as_ptr {
     ($target:ident => $($struct:ty),* $(,)?) => {
         $(
@@ -512,6 +566,7 @@ impl_as_arena!(ProtoRegion => WriteOnly, ReadWrite);
 impl_as_mut!(Region => WriteOnly, ReadWrite);
 impl_write_offset!(Region => WriteOnly, ReadWrite);
 impl_write_addr!(Region => WriteOnly, ReadWrite);
+#[cfg(all(target_os = "linux", feature = "kvm"))]
 impl_as_arena!(Region => WriteOnly, ReadWrite);
 
 impl From<Region<ReadOnly>> for RegionEntry {
@@ -532,28 +587,105 @@ impl From<Region<ReadWrite>> for RegionEntry {
     }
 }
 
+#[cfg(all(target_os = "linux", feature = "kvm"))]
 impl_as_ptr!(ProtoRegion => ReadOnly, WriteOnly, ReadWrite);
+#[cfg(all(target_os = "linux", feature = "kvm"))]
 impl_as_ptr!(Region => ReadOnly, WriteOnly, ReadWrite);
 
+/// Host pages backing the guest memory.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum Backing {
+    /// Regular 4KiB pages
+    #[default]
+    Regular,
+    /// Regular pages, which the kernel is advised to back with transparent huge pages
+    Transparent,
+    /// Explicit 2MiB pages from the hugetlb pool
+    HugeTlb2M,
+    /// Explicit 1GiB pages from the hugetlb pool
+    HugeTlb1G,
+}
+
+impl Backing {
+    /// size of the huge pages, which have to be reserved from the hugetlb pool
+    fn hugetlb_size(&self) -> Option<usize> {
+        match self {
+            Backing::HugeTlb2M => Some(1 << 21),
+            Backing::HugeTlb1G => Some(1 << 30),
+            _ => None,
+        }
+    }
+
+    #[cfg(target_os = "linux")]
+    fn hugetlb_flags(&self) -> MapFlags {
+        match self {
+            Backing::HugeTlb2M => MapFlags::MAP_HUGETLB | MapFlags::MAP_HUGE_2MB,
+            Backing::HugeTlb1G => MapFlags::MAP_HUGETLB | MapFlags::MAP_HUGE_1GB,
+            _ => MapFlags::empty(),
+        }
+    }
+
+    #[cfg(not(target_os = "linux"))]
+    fn hugetlb_flags(&self) -> MapFlags {
+        MapFlags::empty()
+    }
+}
+
 #[derive(Debug)]
 pub struct Allocator {
     m_flags: MapFlags,
+    backing: Backing,
+    /// only warn once about an unavailable backing
+    fallback_warned: AtomicBool,
+}
+
+impl Default for Allocator {
+    fn default() -> Self {
+        Self::with_backing(Backing::default())
+    }
 }
 
 impl Allocator {
-    pub fn new() -> Self {
+    pub fn with_backing(backing: Backing) -> Self {
         Self {
             m_flags: MMAP_FLAGS.iter().fold(MapFlags::empty(), |acc, x| acc | *x),
+            backing,
+            fallback_warned: AtomicBool::new(false),
         }
     }
 
+    /// Allocate a region with the configured backing. Regions, which are not a multiple of the huge
+    /// page size, as well as failed hugetlb reservations fall back to regular pages.
     pub fn alloc<P>(&self, capacity: AlignedNonZeroUsize) -> Result<ProtoRegion<P>>
     where
         P: Perm + Accessible,
     {
         let flags = P::prot_flags();
+        let len = capacity.get_non_zero();
+
+        let hugetlb = self
+            .backing
+            .hugetlb_size()
+            .filter(|size| capacity.get() % size == 0)
+            .and_then(|_| {
+                let m_flags = self.m_flags | self.backing.hugetlb_flags();
+                unsafe { mmap_anonymous(None, len, flags, m_flags) }
+                    .inspect_err(|err| self.warn_fallback(err))
+                    .ok()
+            });
+
         // mmap a region with the required size and flags
-        let mem = unsafe { mmap_anonymous(None, capacity.get_non_zero(), flags, self.m_flags) }?;
+        let mem = match hugetlb {
+            Some(mem) => mem,
+            None => unsafe { mmap_anonymous(None, len, flags, self.m_flags) }?,
+        };
+
+        #[cfg(target_os = "linux")]
+        if self.backing == Backing::Transparent {
+            if let Err(err) = unsafe { madvise(mem, len.get(), MmapAdvise::MADV_HUGEPAGE) } {
+                self.warn_fallback(&err);
+            }
+        }
 
         let region = ProtoRegion {
             capacity,
@@ -565,6 +697,44 @@ impl Allocator {
         Ok(region)
     }
 
+    /// Map `file` read-only as region of `capacity` bytes. If the file is smaller than the
+    /// capacity, the remainder of its last page reads as zero. Pages entirely behind the end of
+    /// the file must not be accessed.
+    #[cfg(all(target_os = "linux", feature = "kvm"))]
+    pub fn map_file(
+        &self,
+        file: &File,
+        capacity: AlignedNonZeroUsize,
+    ) -> Result<ProtoRegion<ReadOnly>> {
+        let mem = unsafe {
+            mmap(
+                None,
+                capacity.get_non_zero(),
+                ReadOnly::prot_flags(),
+                MapFlags::MAP_PRIVATE,
+                file,
+                0,
+            )
+        }?;
+
+        Ok(ProtoRegion {
+            capacity,
+            ptr: mem.cast::<u8>(),
+            _perm: std::marker::PhantomData,
+            _align: std::marker::PhantomData,
+        })
+    }
+
+    fn warn_fallback(&self, err: &nix::errno::Errno) {
+        if !self.fallback_warned.swap(true, Ordering::Relaxed) {
+            log::warn!(
+                "{:?} backing unavailable ({}), falling back to regular pages",
+                self.backing,
+                err
+            );
+        }
+    }
+
     /// wrap the P::prot_flags to include the guest only fallback flag
     /// if the Perm is not accessible
     fn perm_to_flags<P: Perm>(&self) -> ProtFlags {
@@ -572,16 +742,18 @@ impl Allocator {
     }
 }
 
+#[cfg(all(target_os = "linux", feature = "kvm"))]
 unsafe fn set_as_guest_memory(
     vm: &VmFd,
     slot: u32,
+    flags: u32,
     capacity: AlignedNonZeroUsize,
     addr: PhysAddr,
     mem: NonNull<u8>,
 ) -> Result<()> {
     let mapping = kvm_userspace_memory_region {
         slot,
-        flags: 0,
+        flags,
         guest_phys_addr: addr.as_u64(),
         memory_size: capacity.get() as u64,
         userspace_addr: mem.as_ptr() as u64,
@@ -593,6 +765,7 @@ unsafe fn set_as_guest_memory(
     }
 }
 
+#[cfg(all(target_os = "linux", feature = "kvm"))]
 unsafe fn remove_from_guest_memory(
     vm: &VmFd,
     slot: u32,
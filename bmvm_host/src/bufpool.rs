@@ -0,0 +1,35 @@
+use bmvm_common::mem::{Error, RetainedBuf, SharedBuf};
+
+/// Argument buffers of a module, which are reused across calls instead of allocating fresh ones
+/// in the shared memory. See `Module::pooled_buf`.
+#[derive(Default)]
+pub(crate) struct BufferPool {
+    bufs: Vec<RetainedBuf>,
+}
+
+impl BufferPool {
+    /// Share an idle buffer of `len` bytes, allocating a new one if none is available.
+    pub(crate) fn get(&mut self, len: usize) -> Result<SharedBuf, Error> {
+        if let Some(buf) = self.bufs.iter().find(|b| b.len() == len && b.is_idle()) {
+            return Ok(buf.share());
+        }
+
+        let buf = RetainedBuf::new(len)?;
+        let shared = buf.share();
+        self.bufs.push(buf);
+        Ok(shared)
+    }
+
+    /// Forget all buffers without touching the shared memory, as it was reset underneath them.
+    pub(crate) fn clear(&mut self) {
+        self.bufs.drain(..).for_each(std::mem::forget);
+    }
+}
+
+impl std::fmt::Debug for BufferPool {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("BufferPool")
+            .field("bufs", &self.bufs.len())
+            .finish()
+    }
+}
@@ -1,17 +1,21 @@
+use crate::ErrorCategory;
 use crate::alloc::*;
+use crate::vm::Alignment;
 
 use bmvm_common::mem::{
     Align, AlignedNonZeroUsize, DefaultAlign, Flags, LayoutTableEntry, MAX_REGION_SIZE, PhysAddr,
     VirtAddr, align_ceil, align_floor,
 };
-use bmvm_common::vmi::{Error as VmiError, FnCall, UpcallFn};
+use bmvm_common::vmi::{Error as VmiError, FnCall, FnPtr, Sidecar, UpcallFn};
 use bmvm_common::{
-    BMVM_META_SECTION_DEBUG, BMVM_META_SECTION_EXPOSE, BMVM_META_SECTION_EXPOSE_CALLS,
-    BMVM_META_SECTION_HOST,
+    BMVM_ABI_VERSION, BMVM_META_SECTION_ABI, BMVM_META_SECTION_DEBUG, BMVM_META_SECTION_EXPOSE,
+    BMVM_META_SECTION_EXPOSE_CALLS, BMVM_META_SECTION_HOST,
 };
 use goblin::elf;
+use goblin::elf::reloc::{R_X86_64_NONE, R_X86_64_RELATIVE, Reloc};
 use goblin::elf::{Elf, ProgramHeader};
 use goblin::elf32::header::machine_to_str;
+use rustc_hash::FxHashMap;
 use std::fmt::Debug;
 use std::fs;
 use std::path::Path;
@@ -19,6 +23,17 @@ use std::path::Path;
 #[cfg(target_arch = "x86_64")]
 const SUPPORTED_PLATFORMS: &[u16] = &[elf::header::EM_X86_64];
 
+/// OS/ABI identifications of freestanding executables: System V (no extensions) and standalone
+const SUPPORTED_OS_ABI: &[u8] = &[0, 255];
+
+/// Minimal size of an executable: the ELF header and one program header must be present
+const MIN_ELF_SIZE: usize =
+    elf::header::header64::SIZEOF_EHDR + elf::program_header::program_header64::SIZEOF_PHDR;
+
+/// Load bias of position-independent executables, matching the base address of the guest linker
+/// script
+const PIE_LOAD_BIAS: u64 = 0x400000;
+
 type Result<T> = std::result::Result<T, Error>;
 
 #[derive(Debug, thiserror::Error)]
@@ -31,10 +46,20 @@ pub enum Error {
         min: usize,
         size: usize,
     },
+    #[error("buffer is to small: required min {min} but got {size}")]
+    BufferTooSmall { min: usize, size: usize },
     #[error("Unsupported machine: {0}")]
     UnsupportedPlatform(&'static str),
+    #[error("32-bit executables are not supported, the guest runs in long mode")]
+    Unsupported32Bit,
     #[error("Missing PH_LOAD segments")]
     MissingLoadSegments,
+    #[error("No executable PH_LOAD segment")]
+    MissingExecutableSegment,
+    #[error("Executable requests the dynamic loader {0}, link it statically")]
+    DynamicInterpreter(String),
+    #[error("Unsupported OS/ABI {0:#x}, expected a freestanding executable")]
+    UnsupportedOsAbi(u8),
     #[error("unknown section at index {0}")]
     ElfUnnamedSection(usize),
     #[error("section {name} too large: got {size} but only supports up to {max}")]
@@ -43,8 +68,26 @@ pub enum Error {
     ElfNoSectionForSegment(usize),
     #[error("unsupported section {0}")]
     ElfUnsupportedSection(String),
+    #[error("LOAD segment {idx} at {vaddr:#x} with size {memsz:#x} exceeds the address space")]
+    SegmentOutOfRange { idx: usize, vaddr: u64, memsz: u64 },
     #[error("Invalid entry point: {0}")]
     InvalidEntryPoint(u64),
+    #[error("Guest entry {0:#x} is not aligned to {1} bytes")]
+    MisalignedEntry(u64, u64),
+    #[error("Entry symbol not found or not a function: {0}")]
+    SymbolNotFound(String),
+    #[error("guest was built against ABI version {guest}, but the host expects {host}")]
+    AbiMismatch { guest: u32, host: u32 },
+    #[error("guest does not embed an ABI version, rebuild it against the current bmvm-guest")]
+    MissingAbiVersion,
+    #[error("Unsupported relocation type {0}")]
+    UnsupportedRelocation(u32),
+    #[error("Relocation at {0:#x} is outside the loaded segments")]
+    RelocationOutOfRange(u64),
+    #[error("TLS segment alignment {0:#x} is not a power of two")]
+    InvalidTlsAlignment(u64),
+    #[error("Upcall pointer {0:#x} exceeds the address space once loaded")]
+    UpcallOutOfRange(u64),
     #[error("Insufficient upcall pointer: want {want} but got {got}")]
     InsufficientUpcallPointer { want: usize, got: usize },
     #[error("Unable to parse ELF: {0}")]
@@ -61,13 +104,27 @@ pub enum Error {
     IO(#[from] std::io::Error),
 }
 
+impl Error {
+    /// Origin of the error, see `ErrorCategory`. Everything but the access to the file and the
+    /// memory allocation is caused by the executable itself.
+    pub fn category(&self) -> ErrorCategory {
+        match self {
+            Error::IO(_) | Error::Alloc(_) => ErrorCategory::Environment,
+            _ => ErrorCategory::Input,
+        }
+    }
+}
+
 /// A buffer containing the ELF file.
 #[derive(Debug, Clone)]
 pub struct Buffer {
     inner: Vec<u8>,
+    /// VMI metadata used if the ELF file does not contain the metadata sections
+    sidecar: Option<Sidecar>,
 }
 
 impl Buffer {
+    /// Read the ELF file at `path`. If a sidecar file (`<path>.bmvm`) exists, it is loaded as well.
     pub fn new<P: AsRef<Path>>(path: P) -> Result<Self> {
         // early exit if minimal requirements are not met
         check_minimal_file_requirements(&path)?;
@@ -76,7 +133,98 @@ impl Buffer {
         // early exit if the platform is not supported
         check_platform_supported(&buf)?;
 
-        Ok(Self { inner: buf })
+        let sidecar_path = Sidecar::path_for(&path);
+        let sidecar = match sidecar_path.is_file() {
+            true => Some(Sidecar::read_from(&mut fs::File::open(sidecar_path)?)?),
+            false => None,
+        };
+
+        Ok(Self {
+            inner: buf,
+            sidecar,
+        })
+    }
+
+    /// Use the ELF file already in memory, e.g. embedded via `include_bytes!` or received over
+    /// the network. The same checks as for a file apply, but no sidecar is loaded.
+    pub fn from_bytes<B: Into<Vec<u8>>>(bytes: B) -> Result<Self> {
+        let buf = bytes.into();
+        if buf.len() < MIN_ELF_SIZE {
+            return Err(Error::BufferTooSmall {
+                min: MIN_ELF_SIZE,
+                size: buf.len(),
+            });
+        }
+        check_platform_supported(&buf)?;
+
+        Ok(Self {
+            inner: buf,
+            sidecar: None,
+        })
+    }
+}
+
+impl Buffer {
+    /// Parse the functions the guest expects from the host and the ones it exposes, without
+    /// loading any segment. Both vectors are sorted.
+    pub(crate) fn vmi_functions(&self) -> Result<(Vec<FnCall>, Vec<FnCall>)> {
+        let elf = Elf::parse(self.as_ref())?;
+        let vmi = VmiSections::from_elf(&elf, self.as_ref())
+            .or_else(|| self.sidecar.as_ref().map(VmiSections::from))
+            .unwrap_or_default();
+        ExecBundle::check_abi(vmi.abi)?;
+        let host = ExecBundle::parse_vmi_vec(vmi.host, BMVM_META_SECTION_HOST, vmi.debug)?;
+        let expose = ExecBundle::parse_vmi_vec(vmi.expose, BMVM_META_SECTION_EXPOSE, vmi.debug)?;
+        Ok((host, expose))
+    }
+}
+
+/// Contents of the VMI metadata sections, either from the ELF file or a sidecar.
+#[derive(Default)]
+struct VmiSections<'a> {
+    debug: bool,
+    host: Option<&'a [u8]>,
+    expose: Option<&'a [u8]>,
+    expose_calls: Option<&'a [u8]>,
+    abi: Option<&'a [u8]>,
+}
+
+impl<'a> VmiSections<'a> {
+    /// Collect the sections from the ELF file. Returns `None` if none of them are present.
+    fn from_elf(elf: &Elf, buf: &'a [u8]) -> Option<Self> {
+        let content = |name: &str| {
+            let section = &elf.section_headers[ExecBundle::find_section_header(elf, name)?];
+            buf.get(section.sh_offset as usize..(section.sh_offset + section.sh_size) as usize)
+        };
+
+        let sections = Self {
+            debug: ExecBundle::is_vmi_debug(elf),
+            host: content(BMVM_META_SECTION_HOST),
+            expose: content(BMVM_META_SECTION_EXPOSE),
+            expose_calls: content(BMVM_META_SECTION_EXPOSE_CALLS),
+            abi: content(BMVM_META_SECTION_ABI),
+        };
+        match (
+            sections.host,
+            sections.expose,
+            sections.expose_calls,
+            sections.abi,
+        ) {
+            (None, None, None, None) => None,
+            _ => Some(sections),
+        }
+    }
+}
+
+impl<'a> From<&'a Sidecar> for VmiSections<'a> {
+    fn from(sidecar: &'a Sidecar) -> Self {
+        Self {
+            debug: sidecar.debug,
+            host: Some(&sidecar.host),
+            expose: Some(&sidecar.expose),
+            expose_calls: Some(&sidecar.expose_calls),
+            abi: Some(&sidecar.abi),
+        }
     }
 }
 
@@ -86,6 +234,17 @@ impl AsRef<[u8]> for Buffer {
     }
 }
 
+/// Symbol of the guest executable, which can be read by the host after loading
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct Symbol {
+    pub(crate) addr: u64,
+    pub(crate) size: u64,
+    pub(crate) is_object: bool,
+    pub(crate) is_function: bool,
+}
+
+// without KVM support, the executable is never loaded into a guest
+#[cfg_attr(not(all(target_os = "linux", feature = "kvm")), allow(dead_code))]
 pub struct ExecBundle {
     pub(crate) entry: PhysAddr,
     pub(crate) mem_regions: RegionCollection,
@@ -97,6 +256,10 @@ pub struct ExecBundle {
     /// All function calls expected to be provided to the guest by the host.
     /// The vector is guaranteed to be sorted.
     pub(crate) host: Vec<FnCall>,
+    /// All named symbols of the ELF symbol table
+    pub(crate) symbols: FxHashMap<String, Symbol>,
+    /// Thread pointer of the guest, if the executable uses thread-local storage
+    pub(crate) tls: Option<VirtAddr>,
 }
 
 fn section_name_to_flags(name: &str) -> Result<Flags> {
@@ -106,7 +269,14 @@ fn section_name_to_flags(name: &str) -> Result<Flags> {
         _ if name.starts_with(".eh_frame") => Ok(Flags::DATA_READ), // Exception handling tables (read-only)
         _ if name.starts_with(".data") => Ok(Flags::DATA_WRITE),    // Initialized writable data
         _ if name.starts_with(".bss") => Ok(Flags::DATA_WRITE), // Uninitialized data (zero-filled)
+        _ if name.starts_with(".tdata") => Ok(Flags::DATA_WRITE), // Thread-local data (template)
+        _ if name.starts_with(".tbss") => Ok(Flags::DATA_WRITE), // Thread-local data (zero-filled)
         _ if name.starts_with(".got") => Ok(Flags::DATA_READ),
+        // dynamic linking information of position-independent executables
+        _ if name.starts_with(".dynamic") => Ok(Flags::DATA_WRITE),
+        _ if name.starts_with(".dyn") => Ok(Flags::DATA_READ),
+        _ if name.starts_with(".gnu.hash") || name.starts_with(".hash") => Ok(Flags::DATA_READ),
+        _ if name.starts_with(".rela") => Ok(Flags::DATA_READ),
         _ => Err(Error::ElfUnsupportedSection(name.to_string())),
     }
 }
@@ -119,14 +289,78 @@ struct LoadSegment {
     file_size: usize,
 }
 
+/// Placement of the thread-local storage block following the x86_64 TLS variant II: the block
+/// ends right below the thread pointer, which points to itself.
+#[derive(Debug, PartialEq, Eq)]
+struct TlsBlock {
+    /// Address of the block, where the `.tdata` image is copied to
+    start: u64,
+    /// Thread pointer, loaded into the FS base
+    tp: u64,
+    /// Number of bytes required from `base` on, including the self pointer
+    size: u64,
+}
+
+impl TlsBlock {
+    /// Place a block of `memsz` bytes with the alignment `align` at or above `base`
+    fn new(base: u64, memsz: u64, align: u64) -> Result<Self> {
+        let align = align.max(size_of::<u64>() as u64);
+        if !align.is_power_of_two() {
+            return Err(Error::InvalidTlsAlignment(align));
+        }
+
+        let len = memsz.next_multiple_of(align);
+        let tp = (base + len).next_multiple_of(align);
+        Ok(Self {
+            start: tp - len,
+            tp,
+            size: tp + size_of::<u64>() as u64 - base,
+        })
+    }
+}
+
 impl ExecBundle {
     /// Create a new `ExecBundle` from the given ELF file.
-    /// The ELF file must be a valid ELF file and contain a valid entry point.
-    pub(crate) fn from_buffer(buf: &Buffer, manager: &Allocator) -> Result<Self> {
+    /// The ELF file must be a valid ELF file and contain a valid entry point. If an entry symbol
+    /// is provided, the entry point is resolved via the symbol table instead of the ELF header.
+    ///
+    /// Position-independent executables (`ET_DYN`) are loaded at `PIE_LOAD_BIAS`. Their
+    /// `R_X86_64_RELATIVE` relocations are applied to the loaded image, while the entry point,
+    /// symbols and upcall pointers are shifted by the bias.
+    ///
+    /// If the executable contains a `PT_TLS` segment, a single TLS block is placed behind the loaded
+    /// segments and initialized from it. Its thread pointer is returned as `tls`.
+    ///
+    /// The entry point is placed at an `entry_align` boundary, see `entry_shift`.
+    pub(crate) fn from_buffer(
+        buf: &Buffer,
+        manager: &Allocator,
+        entry_symbol: Option<&str>,
+        entry_align: Alignment,
+    ) -> Result<Self> {
         let elf = Elf::parse(buf.as_ref())?;
+        Self::check_loadable(&elf)?;
+        let unbiased = match entry_symbol {
+            Some(name) => Self::find_function_symbol(&elf, name)?,
+            None => elf.entry,
+        };
 
+        let align = entry_align.bytes();
+        let bias = match elf.header.e_type {
+            elf::header::ET_DYN => {
+                let entry = unbiased.wrapping_add(PIE_LOAD_BIAS);
+                PIE_LOAD_BIAS + Self::entry_shift(&elf, entry, align)
+            }
+            _ => 0,
+        };
+        let raw_entry = unbiased
+            .checked_add(bias)
+            .ok_or(Error::InvalidEntryPoint(unbiased))?;
+        if !raw_entry.is_multiple_of(align) {
+            return Err(Error::MisalignedEntry(raw_entry, align));
+        }
         let entry =
-            PhysAddr::try_from(elf.entry).map_err(|_| Error::InvalidEntryPoint(elf.entry))?;
+            PhysAddr::try_from(raw_entry).map_err(|_| Error::InvalidEntryPoint(raw_entry))?;
         let mut layout = Vec::new();
         let mut mem_regions = RegionCollection::new();
 
@@ -142,22 +376,37 @@ impl ExecBundle {
                 continue;
             }
 
-            // calc how many pages to allocate
-            let p_start = align_floor(ph.p_vaddr);
-            let p_end = align_ceil(ph.p_vaddr + ph.p_memsz);
+            // calc how many pages to allocate, rejecting segments beyond the address space
+            let out_of_range = |vaddr| Error::SegmentOutOfRange {
+                idx,
+                vaddr,
+                memsz: ph.p_memsz,
+            };
+            let vaddr = ph
+                .p_vaddr
+                .checked_add(bias)
+                .ok_or_else(|| out_of_range(ph.p_vaddr))?;
+            let end = PhysAddr::try_from(vaddr)
+                .ok()
+                .and_then(|start: PhysAddr| start.checked_add(ph.p_memsz))
+                .ok_or_else(|| out_of_range(vaddr))?;
+            let p_start = align_floor(vaddr);
+            let p_end = align_ceil(end.as_u64());
             let to_alloc = p_end - p_start;
 
             required_capacity += to_alloc as usize;
             starting_addr = starting_addr.min(PhysAddr::new_truncate(p_start));
 
             to_allocate.push(LoadSegment {
-                region_offset: ph.p_vaddr,
+                region_offset: vaddr,
                 file_offset: ph.p_offset as usize,
                 file_size: ph.p_filesz as usize,
             });
 
             // try creating a layout entry for this segment
-            layout.push(Self::build_layout_table_entry(idx, ph, to_alloc, &elf)?);
+            layout.push(Self::build_layout_table_entry(
+                idx, ph, p_start, to_alloc, &elf,
+            )?);
         }
 
         // Error on quasi empty ELF file
@@ -165,25 +414,76 @@ impl ExecBundle {
             return Err(Error::MissingLoadSegments);
         }
 
+        // reserve the TLS block behind the loaded segments
+        let tls_segment = elf
+            .program_headers
+            .iter()
+            .enumerate()
+            .find(|(_, ph)| ph.p_type == elf::program_header::PT_TLS && ph.p_memsz > 0);
+        let tls = match tls_segment {
+            Some((idx, ph)) => {
+                let base = starting_addr.as_u64() + required_capacity as u64;
+                let block = TlsBlock::new(base, ph.p_memsz, ph.p_align)?;
+                let size = align_ceil(block.size);
+                required_capacity += size as usize;
+                layout.push(
+                    LayoutTableEntry::empty()
+                        .set_paddr(PhysAddr::new(base))
+                        .set_vaddr(VirtAddr::new_truncate(base))
+                        .set_len((size / DefaultAlign::ALIGNMENT) as u32)
+                        .set_flags(Flags::PRESENT | Flags::DATA_WRITE),
+                );
+                Some((idx, ph, block))
+            }
+            None => None,
+        };
+
         // copy the ELF segments into the memory region
         let capacity = AlignedNonZeroUsize::new_ceil(required_capacity).unwrap();
         let proto = manager.alloc::<ReadWrite>(capacity)?;
         let mut region = proto.set_guest_addr(starting_addr);
-        for segment in to_allocate {
-            let seg = &buf.as_ref()[segment.file_offset..segment.file_offset + segment.file_size];
-            region.write_addr(segment.region_offset, seg)?;
+        Self::load_segments(&mut region, buf.as_ref(), &to_allocate)?;
+        if bias != 0 {
+            Self::relocate(&mut region, elf.dynrelas.iter(), bias)?;
+            if let Some(rel) = elf.dynrels.iter().next() {
+                return Err(Error::UnsupportedRelocation(rel.r_type));
+            }
+        }
+        // initialize the TLS block from the relocated image, `.tbss` is already zeroed
+        if let Some((idx, ph, block)) = &tls {
+            let out_of_range = |vaddr| Error::SegmentOutOfRange {
+                idx: *idx,
+                vaddr,
+                memsz: ph.p_memsz,
+            };
+            let vaddr = ph
+                .p_vaddr
+                .checked_add(bias)
+                .ok_or_else(|| out_of_range(ph.p_vaddr))?;
+            let offset = vaddr.wrapping_sub(region.addr().as_u64()) as usize;
+            let image = region
+                .as_ref()
+                .get(offset..offset.saturating_add(ph.p_filesz as usize))
+                .ok_or_else(|| out_of_range(vaddr))?
+                .to_vec();
+            region.write_addr(block.start, &image)?;
+            region.write_addr(block.tp, block.tp.to_le_bytes().as_slice())?;
         }
         mem_regions.push(region);
 
-        let vmi_debug = Self::is_vmi_debug(&elf);
-        let host = Self::parse_vmi_vec(&elf, buf.as_ref(), BMVM_META_SECTION_HOST, vmi_debug)?;
-        let expose = Self::parse_vmi_vec(&elf, buf.as_ref(), BMVM_META_SECTION_EXPOSE, vmi_debug)?;
+        // fall back to the sidecar, if the metadata sections have been stripped
+        let vmi = VmiSections::from_elf(&elf, buf.as_ref())
+            .or_else(|| buf.sidecar.as_ref().map(VmiSections::from))
+            .unwrap_or_default();
+        Self::check_abi(vmi.abi)?;
+        let host = Self::parse_vmi_vec(vmi.host, BMVM_META_SECTION_HOST, vmi.debug)?;
+        let expose = Self::parse_vmi_vec(vmi.expose, BMVM_META_SECTION_EXPOSE, vmi.debug)?;
         let upcalls = if !expose.is_empty() {
             Self::parse_upcall_ptr(
-                &elf,
-                buf.as_ref(),
+                vmi.expose_calls,
                 BMVM_META_SECTION_EXPOSE_CALLS,
                 expose.len(),
+                bias,
             )?
         } else {
             Vec::new()
@@ -196,15 +496,139 @@ impl ExecBundle {
             expose,
             upcalls,
             host,
+            symbols: Self::collect_symbols(&elf, bias),
+            tls: tls.map(|(_, _, block)| VirtAddr::new_truncate(block.tp)),
         })
     }
 
+    /// Copy the file content of the segments into the region. The allocator does not guarantee
+    /// zeroed memory, but the ELF semantics require the bytes not backed by the file (`.bss` and
+    /// padding) to be zero.
+    fn load_segments(
+        region: &mut Region<ReadWrite>,
+        buf: &[u8],
+        segments: &[LoadSegment],
+    ) -> Result<()> {
+        region.as_mut().fill(0);
+        for segment in segments {
+            let seg = &buf[segment.file_offset..segment.file_offset + segment.file_size];
+            region.write_addr(segment.region_offset, seg)?;
+        }
+        Ok(())
+    }
+
+    /// Apply the dynamic relocations of a position-independent executable loaded at `bias`. Only
+    /// relative relocations are supported, as the guest is linked statically.
+    fn relocate(
+        region: &mut Region<ReadWrite>,
+        relocs: impl Iterator<Item = Reloc>,
+        bias: u64,
+    ) -> Result<()> {
+        for reloc in relocs {
+            match reloc.r_type {
+                R_X86_64_NONE => continue,
+                R_X86_64_RELATIVE => {
+                    let addr = reloc
+                        .r_offset
+                        .checked_add(bias)
+                        .ok_or(Error::RelocationOutOfRange(reloc.r_offset))?;
+                    let value = bias.wrapping_add_signed(reloc.r_addend.unwrap_or(0));
+                    let written = region
+                        .write_addr(addr, value.to_le_bytes().as_slice())
+                        .map_err(|_| Error::RelocationOutOfRange(addr))?;
+                    if written != size_of::<u64>() {
+                        return Err(Error::RelocationOutOfRange(addr));
+                    }
+                }
+                other => return Err(Error::UnsupportedRelocation(other)),
+            }
+        }
+        Ok(())
+    }
+
+    /// Reject executables built for a different environment, which would only fault once running:
+    /// a foreign OS/ABI, a requested dynamic loader or no code to execute at all.
+    fn check_loadable(elf: &Elf) -> Result<()> {
+        let os_abi = elf.header.e_ident[elf::header::EI_OSABI];
+        if !SUPPORTED_OS_ABI.contains(&os_abi) {
+            return Err(Error::UnsupportedOsAbi(os_abi));
+        }
+
+        if elf
+            .program_headers
+            .iter()
+            .any(|ph| ph.p_type == elf::program_header::PT_INTERP)
+        {
+            let interpreter = elf.interpreter.unwrap_or("<unknown>");
+            return Err(Error::DynamicInterpreter(interpreter.to_string()));
+        }
+
+        let mut loads = elf
+            .program_headers
+            .iter()
+            .filter(|ph| ph.p_type == elf::program_header::PT_LOAD)
+            .peekable();
+        if loads.peek().is_none() {
+            return Err(Error::MissingLoadSegments);
+        }
+        if !loads.any(|ph| ph.is_executable()) {
+            return Err(Error::MissingExecutableSegment);
+        }
+
+        Ok(())
+    }
+
+    /// Verify the guest was built against the same ABI version as the host
+    fn check_abi(content: Option<&[u8]>) -> Result<()> {
+        let guest = content
+            .and_then(|c| c.try_into().ok())
+            .map(u32::from_le_bytes)
+            .ok_or(Error::MissingAbiVersion)?;
+        if guest != BMVM_ABI_VERSION {
+            return Err(Error::AbiMismatch {
+                guest,
+                host: BMVM_ABI_VERSION,
+            });
+        }
+        Ok(())
+    }
+
     /// If the debug section header is included, then VMI call data includes debug information
     /// i.e. parameter and return types
     fn is_vmi_debug(elf: &Elf) -> bool {
         Self::find_section_header(elf, BMVM_META_SECTION_DEBUG).is_some()
     }
 
+    /// Resolve the address of the function symbol with `name` via the ELF symbol table
+    fn find_function_symbol(elf: &Elf, name: &str) -> Result<u64> {
+        elf.syms
+            .iter()
+            .find(|sym| sym.is_function() && elf.strtab.get_at(sym.st_name) == Some(name))
+            .map(|sym| sym.st_value)
+            .ok_or_else(|| Error::SymbolNotFound(name.to_string()))
+    }
+
+    /// Collect all named symbols from the ELF symbol table, shifting the defined ones by `bias`
+    fn collect_symbols(elf: &Elf, bias: u64) -> FxHashMap<String, Symbol> {
+        elf.syms
+            .iter()
+            .filter_map(|sym| {
+                let name = elf.strtab.get_at(sym.st_name).filter(|n| !n.is_empty())?;
+                let relocatable = sym.st_shndx != elf::section_header::SHN_UNDEF as usize
+                    && sym.st_shndx != elf::section_header::SHN_ABS as usize;
+                let symbol = Symbol {
+                    addr: sym
+                        .st_value
+                        .wrapping_add(if relocatable { bias } else { 0 }),
+                    size: sym.st_size,
+                    is_object: sym.st_type() == elf::sym::STT_OBJECT,
+                    is_function: sym.is_function(),
+                };
+                Some((name.to_string(), symbol))
+            })
+            .collect()
+    }
+
     /// Return the index to the section header if a section with `name` is found in the ELF file
     fn find_section_header(elf: &Elf, name: &str) -> Option<usize> {
         for (idx, section) in elf.section_headers.iter().enumerate() {
@@ -224,19 +648,14 @@ impl ExecBundle {
         None
     }
 
-    /// Parse a vector of VMI calls from the ELF file from the section with `name`.
+    /// Parse a vector of VMI calls from the content of the section `section_name`.
     /// On success, the returned vector is sorted via Vec::sort
     fn parse_vmi_vec(
-        elf: &Elf,
-        buf: &[u8],
+        content: Option<&[u8]>,
         section_name: &str,
         debug: bool,
     ) -> Result<Vec<FnCall>> {
-        if let Some(idx) = Self::find_section_header(elf, section_name) {
-            let section = &elf.section_headers[idx];
-            let content =
-                &buf[section.sh_offset as usize..(section.sh_offset + section.sh_size) as usize];
-
+        if let Some(content) = content {
             if content.is_empty() {
                 log::warn!("VMI section defined but empty: {}", section_name);
                 return Ok(Vec::new());
@@ -255,18 +674,16 @@ impl ExecBundle {
         Ok(Vec::new())
     }
 
+    /// Parse the upcall function pointers, shifted by the load `bias`. The VMI sections are not
+    /// loaded, so the pointers are never covered by the dynamic relocations.
     fn parse_upcall_ptr(
-        elf: &Elf,
-        buf: &[u8],
+        content: Option<&[u8]>,
         section_name: &str,
         count: usize,
+        bias: u64,
     ) -> Result<Vec<UpcallFn>> {
-        if let Some(idx) = Self::find_section_header(elf, section_name) {
-            let section = &elf.section_headers[idx];
-            let content =
-                &buf[section.sh_offset as usize..(section.sh_offset + section.sh_size) as usize];
-
-            let size = size_of::<UpcallFn>();
+        if let Some(content) = content {
+            let size = UpcallFn::RECORD_SIZE;
             if content.len() < count * size {
                 return Err(Error::InsufficientUpcallPointer {
                     want: count,
@@ -278,6 +695,13 @@ impl ExecBundle {
                 section: section_name.to_string(),
                 source: e,
             })?;
+            for call in calls.iter_mut().filter(|_| bias != 0) {
+                let func = call.func.as_u64();
+                let biased = func
+                    .checked_add(bias)
+                    .ok_or(Error::UpcallOutOfRange(func))?;
+                call.func = unsafe { FnPtr::from_u64_unchecked(biased) };
+            }
             // ensure to sort the function calls
             calls.sort();
             return Ok(calls);
@@ -286,9 +710,43 @@ impl ExecBundle {
         Ok(Vec::new())
     }
 
+    /// Additional load bias of a position-independent executable, which moves its `entry` to an
+    /// `align` boundary. As the whole image is shifted, this is only possible if the shift keeps
+    /// the alignment of every allocated section and does not move two segments into the same page.
+    /// Otherwise, the image is loaded unshifted.
+    fn entry_shift(elf: &Elf, entry: u64, align: u64) -> u64 {
+        let shift = entry.wrapping_neg() & (align - 1);
+        let aligned_sections = elf
+            .section_headers
+            .iter()
+            .filter(|sh| sh.sh_flags & elf::section_header::SHF_ALLOC as u64 != 0)
+            .all(|sh| sh.sh_addralign <= 1 || shift.is_multiple_of(sh.sh_addralign));
+
+        // segments are sorted by their address, each has to start behind the page of its predecessor
+        let mut prev_end = 0;
+        let separate_pages = elf
+            .program_headers
+            .iter()
+            .filter(|ph| ph.p_type == elf::program_header::PT_LOAD)
+            .all(|ph| {
+                let start = align_floor(ph.p_vaddr.saturating_add(shift));
+                let separate = start >= prev_end;
+                prev_end = ph.p_vaddr.saturating_add(ph.p_memsz).saturating_add(shift);
+                separate
+            });
+
+        match aligned_sections && separate_pages {
+            true => shift,
+            false => 0,
+        }
+    }
+
+    /// Layout table entry of the LOAD segment `ph`, loaded at the page `load_addr` with
+    /// `allocated_size` bytes.
     fn build_layout_table_entry(
         ph_idx: usize,
         ph: &ProgramHeader,
+        load_addr: u64,
         allocated_size: u64,
         elf: &Elf,
     ) -> Result<LayoutTableEntry> {
@@ -319,8 +777,8 @@ impl ExecBundle {
                 }
 
                 return Ok(LayoutTableEntry::empty()
-                    .set_paddr(PhysAddr::new(p_start))
-                    .set_vaddr(VirtAddr::new_truncate(p_start))
+                    .set_paddr(PhysAddr::new(load_addr))
+                    .set_vaddr(VirtAddr::new_truncate(load_addr))
                     .set_len((allocated_size / DefaultAlign::ALIGNMENT) as u32)
                     .set_flags(flags | Flags::PRESENT));
             }
@@ -337,13 +795,10 @@ fn check_minimal_file_requirements<P: AsRef<Path>>(path: P) -> Result<()> {
         return Err(Error::NotAFile(path.as_ref().to_str().unwrap().to_string()));
     }
 
-    // for 32bit systems: guest header and one program header must be at least present
-    let min_size =
-        elf::header::header32::SIZEOF_EHDR + elf::program_header::program_header32::SIZEOF_PHDR;
-    if file_meta.len() < min_size as u64 {
+    if file_meta.len() < MIN_ELF_SIZE as u64 {
         return Err(Error::FileTooSmall {
             path: path.as_ref().to_str().unwrap().to_string(),
-            min: min_size,
+            min: MIN_ELF_SIZE,
             size: file_meta.len() as usize,
         });
     }
@@ -361,5 +816,353 @@ fn check_platform_supported<B: AsRef<[u8]>>(buf: B) -> Result<()> {
         return Err(Error::UnsupportedPlatform(machine_to_str(header.e_machine)));
     }
 
+    // the guest is always entered in long mode, also rejects x32 executables
+    if header.e_ident[elf::header::EI_CLASS] != elf::header::ELFCLASS64 {
+        return Err(Error::Unsupported32Bit);
+    }
+
     Ok(())
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    /// Minimal x86_64 executable with a single LOAD segment
+    fn single_segment_elf(vaddr: u64, memsz: u64) -> Buffer {
+        single_segment_elf_of_type(elf::header::ET_EXEC, vaddr, memsz)
+    }
+
+    fn single_segment_elf_of_type(e_type: u16, vaddr: u64, memsz: u64) -> Buffer {
+        let segment = (elf::program_header::PT_LOAD, 5, vaddr, memsz);
+        Buffer {
+            inner: elf_with_segments(e_type, &[segment]),
+            sidecar: None,
+        }
+    }
+
+    /// Minimal x86_64 executable with the program headers `(p_type, p_flags, vaddr, memsz)`, whose
+    /// content is not part of the file. The entry point is the start of the first segment.
+    fn elf_with_segments(e_type: u16, segments: &[(u32, u32, u64, u64)]) -> Vec<u8> {
+        let entry = segments.first().map(|s| s.2).unwrap_or(0);
+        let mut buf = Vec::new();
+        // identification: magic, 64-bit, little endian, version 1
+        buf.extend(b"\x7fELF\x02\x01\x01");
+        buf.resize(16, 0);
+        buf.extend(e_type.to_le_bytes()); // e_type
+        buf.extend(62u16.to_le_bytes()); // e_machine: x86_64
+        buf.extend(1u32.to_le_bytes()); // e_version
+        buf.extend(entry.to_le_bytes()); // e_entry
+        buf.extend(64u64.to_le_bytes()); // e_phoff
+        buf.extend(0u64.to_le_bytes()); // e_shoff
+        buf.extend(0u32.to_le_bytes()); // e_flags
+        buf.extend(64u16.to_le_bytes()); // e_ehsize
+        buf.extend(56u16.to_le_bytes()); // e_phentsize
+        buf.extend((segments.len() as u16).to_le_bytes()); // e_phnum
+        buf.extend(64u16.to_le_bytes()); // e_shentsize
+        buf.extend(0u16.to_le_bytes()); // e_shnum
+        buf.extend(0u16.to_le_bytes()); // e_shstrndx
+
+        for &(p_type, p_flags, vaddr, memsz) in segments {
+            buf.extend(p_type.to_le_bytes());
+            buf.extend(p_flags.to_le_bytes());
+            buf.extend(0u64.to_le_bytes()); // p_offset
+            buf.extend(vaddr.to_le_bytes()); // p_vaddr
+            buf.extend(vaddr.to_le_bytes()); // p_paddr
+            buf.extend(0u64.to_le_bytes()); // p_filesz
+            buf.extend(memsz.to_le_bytes()); // p_memsz
+            buf.extend(0x1000u64.to_le_bytes()); // p_align
+        }
+        buf
+    }
+
+    #[test]
+    fn segment_overflow() {
+        let buf = single_segment_elf(0x1000, u64::MAX);
+        let result = ExecBundle::from_buffer(&buf, &Allocator::default(), None, Alignment::Natural);
+        assert!(matches!(
+            result,
+            Err(Error::SegmentOutOfRange {
+                idx: 0,
+                vaddr: 0x1000,
+                memsz: u64::MAX
+            })
+        ));
+    }
+
+    #[test]
+    fn dynamically_linked() {
+        let interp = b"/lib64/ld-linux-x86-64.so.2\0";
+        let segments = [
+            (elf::program_header::PT_LOAD, 5, 0x1000, 0x1000),
+            (elf::program_header::PT_INTERP, 4, 0, interp.len() as u64),
+        ];
+        let mut inner = elf_with_segments(elf::header::ET_EXEC, &segments);
+        // point p_offset and p_filesz of the PT_INTERP header at the appended path
+        let phdr = 64 + 56;
+        inner[phdr + 8..phdr + 16].copy_from_slice(&(inner.len() as u64).to_le_bytes());
+        inner[phdr + 32..phdr + 40].copy_from_slice(&(interp.len() as u64).to_le_bytes());
+        inner.extend(interp);
+
+        let buf = Buffer {
+            inner,
+            sidecar: None,
+        };
+        assert!(matches!(
+            ExecBundle::from_buffer(&buf, &Allocator::default(), None, Alignment::Natural),
+            Err(Error::DynamicInterpreter(path)) if path == "/lib64/ld-linux-x86-64.so.2"
+        ));
+    }
+
+    #[test]
+    fn unsupported_os_abi() {
+        let mut buf = single_segment_elf(0x1000, 0x1000);
+        buf.inner[elf::header::EI_OSABI] = 3; // Linux
+        assert!(matches!(
+            ExecBundle::from_buffer(&buf, &Allocator::default(), None, Alignment::Natural),
+            Err(Error::UnsupportedOsAbi(3))
+        ));
+    }
+
+    #[test]
+    fn missing_executable_segment() {
+        let segments = [(elf::program_header::PT_LOAD, 6, 0x1000, 0x1000)]; // R+W
+        let buf = Buffer {
+            inner: elf_with_segments(elf::header::ET_EXEC, &segments),
+            sidecar: None,
+        };
+        assert!(matches!(
+            ExecBundle::from_buffer(&buf, &Allocator::default(), None, Alignment::Natural),
+            Err(Error::MissingExecutableSegment)
+        ));
+    }
+
+    #[test]
+    fn elf32_rejected() {
+        let mut buf = single_segment_elf(0x1000, 0x1000);
+        buf.inner[elf::header::EI_CLASS] = elf::header::ELFCLASS32;
+        assert!(matches!(
+            check_platform_supported(&buf.inner),
+            Err(Error::Unsupported32Bit)
+        ));
+
+        // a 32-bit x86 executable is rejected by its machine already
+        let mut buf = single_segment_elf(0x1000, 0x1000);
+        buf.inner[18..20].copy_from_slice(&elf::header::EM_386.to_le_bytes());
+        assert!(matches!(
+            check_platform_supported(&buf.inner),
+            Err(Error::UnsupportedPlatform(_))
+        ));
+    }
+
+    #[test]
+    fn buffer_from_bytes() {
+        let bytes = single_segment_elf(0x1000, 0x1000).inner;
+        let buf = Buffer::from_bytes(bytes.as_slice()).unwrap();
+        assert_eq!(buf.inner, bytes);
+        assert!(buf.sidecar.is_none());
+
+        assert!(matches!(
+            Buffer::from_bytes(&bytes[..32]),
+            Err(Error::BufferTooSmall { min: 120, size: 32 })
+        ));
+        assert!(matches!(
+            Buffer::from_bytes(vec![0x90; 256]),
+            Err(Error::ElfParse(_))
+        ));
+    }
+
+    #[test]
+    fn headerless_blob() {
+        let buf = Buffer {
+            inner: vec![0x90; 256],
+            sidecar: None,
+        };
+        assert!(matches!(
+            ExecBundle::from_buffer(&buf, &Allocator::default(), None, Alignment::Natural),
+            Err(Error::ElfParse(_))
+        ));
+        assert!(matches!(
+            check_platform_supported(&buf.inner),
+            Err(Error::ElfParse(_))
+        ));
+    }
+
+    #[test]
+    fn pie_segment_biased() {
+        let buf = single_segment_elf_of_type(elf::header::ET_DYN, 0, u64::MAX);
+        let result = ExecBundle::from_buffer(&buf, &Allocator::default(), None, Alignment::Natural);
+        assert!(matches!(
+            result,
+            Err(Error::SegmentOutOfRange {
+                idx: 0,
+                vaddr: PIE_LOAD_BIAS,
+                ..
+            })
+        ));
+    }
+
+    #[test]
+    fn pie_bias_overflow() {
+        let high = u64::MAX - 0xfff;
+        let buf = single_segment_elf_of_type(elf::header::ET_DYN, high, 0x1000);
+        assert!(matches!(
+            ExecBundle::from_buffer(&buf, &Allocator::default(), None, Alignment::Natural),
+            Err(Error::InvalidEntryPoint(entry)) if entry == high
+        ));
+
+        let segments = [
+            (elf::program_header::PT_LOAD, 5, 0, 0x1000),
+            (elf::program_header::PT_LOAD, 6, high, 0x1000),
+        ];
+        let buf = Buffer {
+            inner: elf_with_segments(elf::header::ET_DYN, &segments),
+            sidecar: None,
+        };
+        assert!(matches!(
+            ExecBundle::from_buffer(&buf, &Allocator::default(), None, Alignment::Natural),
+            Err(Error::SegmentOutOfRange { idx: 1, vaddr, .. }) if vaddr == high
+        ));
+    }
+
+    #[test]
+    fn entry_alignment() {
+        // a position-independent image is shifted to move the entry to the boundary
+        let buf = single_segment_elf_of_type(elf::header::ET_DYN, 0x1010, 0x100);
+        let elf = Elf::parse(buf.as_ref()).unwrap();
+        let entry = PIE_LOAD_BIAS + 0x1010;
+        assert_eq!(ExecBundle::entry_shift(&elf, entry, 64), 0x30);
+        assert_eq!(ExecBundle::entry_shift(&elf, entry, 0x1000), 0xff0);
+        assert_eq!(ExecBundle::entry_shift(&elf, entry, 1), 0);
+
+        // unless a segment would move into the page of its predecessor
+        let segments = [
+            (elf::program_header::PT_LOAD, 5, 0x1010, 0xfe0),
+            (elf::program_header::PT_LOAD, 6, 0x2000, 0x100),
+        ];
+        let inner = elf_with_segments(elf::header::ET_DYN, &segments);
+        let elf = Elf::parse(&inner).unwrap();
+        assert_eq!(ExecBundle::entry_shift(&elf, entry, 64), 0);
+
+        // the placement of other executables is fixed
+        let buf = single_segment_elf(0x1010, 0x100);
+        assert!(matches!(
+            ExecBundle::from_buffer(&buf, &Allocator::default(), None, Alignment::CacheLine),
+            Err(Error::MisalignedEntry(0x1010, 64))
+        ));
+    }
+
+    #[test]
+    fn relative_relocation() {
+        let capacity = AlignedNonZeroUsize::new_ceil(0x1000).unwrap();
+        let mut region = Allocator::default()
+            .alloc::<ReadWrite>(capacity)
+            .unwrap()
+            .set_guest_addr(PhysAddr::new(PIE_LOAD_BIAS));
+        let reloc = |r_offset: u64, r_type: u32| Reloc {
+            r_offset,
+            r_addend: Some(0x120),
+            r_sym: 0,
+            r_type,
+        };
+
+        let relocs = [reloc(0x10, R_X86_64_NONE), reloc(0x18, R_X86_64_RELATIVE)];
+        ExecBundle::relocate(&mut region, relocs.into_iter(), PIE_LOAD_BIAS).unwrap();
+        let mut value = [0u8; 8];
+        region.read_offset(0x18, &mut value).unwrap();
+        assert_eq!(u64::from_le_bytes(value), PIE_LOAD_BIAS + 0x120);
+
+        assert!(matches!(
+            ExecBundle::relocate(
+                &mut region,
+                [reloc(0xffc, R_X86_64_RELATIVE)].into_iter(),
+                PIE_LOAD_BIAS
+            ),
+            Err(Error::RelocationOutOfRange(addr)) if addr == PIE_LOAD_BIAS + 0xffc
+        ));
+        assert!(matches!(
+            ExecBundle::relocate(
+                &mut region,
+                [reloc(0x20, elf::reloc::R_X86_64_64)].into_iter(),
+                PIE_LOAD_BIAS
+            ),
+            Err(Error::UnsupportedRelocation(elf::reloc::R_X86_64_64))
+        ));
+    }
+
+    #[test]
+    fn bss_zeroed() {
+        let capacity = AlignedNonZeroUsize::new_ceil(0x2000).unwrap();
+        let mut region = Allocator::default()
+            .alloc::<ReadWrite>(capacity)
+            .unwrap()
+            .set_guest_addr(PhysAddr::new(0x1000));
+        // leftovers of a previous user of the memory
+        region.as_mut().fill(0xaa);
+
+        let file = [0x11u8; 0x20];
+        let segments = [
+            LoadSegment {
+                region_offset: 0x1000,
+                file_offset: 0,
+                file_size: 0x10,
+            },
+            LoadSegment {
+                region_offset: 0x2800,
+                file_offset: 0x10,
+                file_size: 0x10,
+            },
+        ];
+        ExecBundle::load_segments(&mut region, &file, &segments).unwrap();
+
+        let mem = region.as_ref();
+        assert!(mem[..0x10].iter().all(|b| *b == 0x11));
+        assert!(mem[0x1800..0x1810].iter().all(|b| *b == 0x11));
+        // .bss behind the first segment and the padding between both segments
+        assert!(mem[0x10..0x1800].iter().all(|b| *b == 0));
+        assert!(mem[0x1810..].iter().all(|b| *b == 0));
+    }
+
+    #[test]
+    fn tls_block() {
+        let block = TlsBlock::new(0x1000, 0x14, 4).unwrap();
+        assert_eq!(
+            block,
+            TlsBlock {
+                start: 0x1000,
+                tp: 0x1018,
+                size: 0x20
+            }
+        );
+
+        // the thread pointer keeps the alignment of the segment
+        let block = TlsBlock::new(0x1000, 0x30, 0x40).unwrap();
+        assert_eq!(block.tp, 0x1040);
+        assert_eq!(block.tp - block.start, 0x40);
+
+        assert!(matches!(
+            TlsBlock::new(0x1000, 0x10, 0x18),
+            Err(Error::InvalidTlsAlignment(0x18))
+        ));
+    }
+
+    #[test]
+    fn abi_version() {
+        let current = BMVM_ABI_VERSION.to_le_bytes();
+        let next = (BMVM_ABI_VERSION + 1).to_le_bytes();
+
+        assert!(ExecBundle::check_abi(Some(&current)).is_ok());
+        assert!(matches!(
+            ExecBundle::check_abi(None),
+            Err(Error::MissingAbiVersion)
+        ));
+        assert!(matches!(
+            ExecBundle::check_abi(Some(&current[..2])),
+            Err(Error::MissingAbiVersion)
+        ));
+        assert!(matches!(
+            ExecBundle::check_abi(Some(&next)),
+            Err(Error::AbiMismatch { guest, host: BMVM_ABI_VERSION }) if guest == BMVM_ABI_VERSION + 1
+        ));
+    }
+}
@@ -1,18 +1,23 @@
 #![feature(iterator_try_collect)]
 
 mod alloc;
+mod bufpool;
 mod elf;
 pub mod linker;
+mod profile;
 mod runtime;
 mod utils;
 mod vm;
 
+#[cfg(all(target_os = "linux", feature = "kvm"))]
 use bmvm_common::mem::{AddrSpace, Align, DefaultAddrSpace, Page4KiB, PhysAddr, align_floor};
 use std::marker::PhantomData;
-use std::sync::OnceLock;
+use std::ops::Deref;
 
 // re-export bmvm-common
 pub use bmvm_common::TypeSignature;
+pub use bmvm_common::boot::Phase as BootPhase;
+pub use bmvm_common::error::HostError;
 pub use bmvm_common::hash::SignatureHasher;
 pub use bmvm_common::mem;
 pub use bmvm_common::registry;
@@ -20,14 +25,28 @@ use bmvm_common::registry::Params;
 pub use bmvm_common::vmi;
 use bmvm_common::vmi::FnPtr;
 pub use bmvm_common::vmi::{ForeignShareable, OwnedShareable, Signature, Transport};
+#[doc(hidden)]
+pub use bmvm_common::{
+    mem::Foreign,
+    vmi::{is_scalar_pair, scalar_from_raw},
+};
 // re-export bmvm-macros
-pub use bmvm_macros::{TypeSignature, expose_host as hypercall};
+pub use bmvm_macros::{TypeSignature, bmvm_interface, expose_host as hypercall};
 
+#[cfg(all(target_os = "linux", feature = "kvm"))]
 use crate::vm::{GDT_PAGE_REQUIRED, IDT_PAGE_REQUIRED};
+pub use alloc::Backing;
 pub use elf::Buffer;
+pub use linker::compute_signature as signature_of;
 pub use linker::hypercall::{CallableFunction, HypercallResult, WrapperFunc};
+pub use profile::Symbol;
 pub use runtime::*;
-pub use vm::{Config, ConfigBuilder};
+#[cfg(feature = "test-fault-injection")]
+pub use vm::FaultKind;
+pub use vm::{
+    Alignment, Config, ConfigBuilder, CpuidConfig, CpuidEntry, CpuidRegister, ExitCounts,
+    HltPolicy, HypercallContext, Registers, Snapshot, StepOutcome, TransportKind,
+};
 
 pub struct Upcall<P, R>
 where
@@ -57,35 +76,114 @@ where
     pub fn call(&self, module: &mut Module, params: P) -> Result<R, Error> {
         module.call(self, params)
     }
+
+    /// Call the upcall and keep the module borrowed for as long as the result is alive. This is
+    /// meant for results pointing into the shared memory, e.g. `ForeignBuf`, which are read in
+    /// place instead of being copied out. The view is invalidated by any subsequent call on or
+    /// reset of the module, which the borrow prevents while the `GuestRef` exists.
+    pub fn call_borrowed<'m>(
+        &self,
+        module: &'m mut Module,
+        params: P,
+    ) -> Result<GuestRef<'m, R>, Error> {
+        let value = module.call(self, params)?;
+        Ok(GuestRef {
+            value,
+            _module: PhantomData,
+        })
+    }
+}
+
+impl<P: Params> Upcall<P, ()> {
+    /// Call the upcall as streaming upcall, see `Module::call_stream`
+    pub fn stream<'m>(&self, module: &'m mut Module, params: P) -> Result<UpcallStream<'m>, Error> {
+        module.call_stream(self, params)
+    }
+}
+
+/// Result of `Upcall::call_borrowed` living in guest memory. Holds the mutable borrow of the
+/// module it was returned from.
+pub struct GuestRef<'m, R> {
+    value: R,
+    _module: PhantomData<&'m mut Module>,
+}
+
+impl<R> Deref for GuestRef<'_, R> {
+    type Target = R;
+
+    fn deref(&self) -> &Self::Target {
+        &self.value
+    }
+}
+
+impl<R: AsRef<[u8]>> AsRef<[u8]> for GuestRef<'_, R> {
+    fn as_ref(&self) -> &[u8] {
+        self.value.as_ref()
+    }
 }
 
 /// The default stack size for the guest (8MiB)
 pub(crate) const GUEST_DEFAULT_STACK_SIZE: usize = 8 * 1024 * 1024;
 /// The default shared memory size (8MiB)
 pub(crate) const DEFAULT_SHARED_MEMORY: usize = 8 * 1024 * 1024;
+/// Size of the unmapped guard page below the guest stack
+#[cfg(all(target_os = "linux", feature = "kvm"))]
+pub(crate) const GUEST_STACK_GUARD_SIZE: u64 = Page4KiB::ALIGNMENT;
+/// The default number of nested guest callbacks allowed during hypercall execution
+pub(crate) const DEFAULT_MAX_CALLBACK_DEPTH: usize = 8;
+/// The default upper bound of guest physical memory including runtime requests (1GiB)
+pub(crate) const DEFAULT_MAX_PHYSICAL_MEMORY: usize = 1024 * 1024 * 1024;
 
-static ONCE_GUEST_SYSTEM_ADDR: OnceLock<PhysAddr> = OnceLock::new();
-static ONCE_GUEST_STACK_ADDR: OnceLock<PhysAddr> = OnceLock::new();
-
-#[allow(non_snake_case)]
-#[inline]
-pub(crate) fn GUEST_SYSTEM_ADDR() -> PhysAddr {
-    *ONCE_GUEST_SYSTEM_ADDR.get_or_init(|| PhysAddr::new(1 << (DefaultAddrSpace::bits() - 1)))
+/// Guest physical addresses of the host managed regions. They are derived from the module
+/// configuration when the VM is created, rather than fixed for the process. The shared memory
+/// allocator is still set up once by the first module, see `ConfigBuilder::max_transport_bytes`.
+#[cfg(all(target_os = "linux", feature = "kvm"))]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct GuestAddrs {
+    /// Start of the system region containing GDT and IDT
+    pub(crate) system: PhysAddr,
+    /// Start of the paging structure, right after the GDT and IDT
+    pub(crate) paging: PhysAddr,
+    /// Upper end of the stack, right below the system region
+    pub(crate) stack_top: PhysAddr,
+    /// Lower end of the stack
+    pub(crate) stack: PhysAddr,
+    /// Unmapped guard page below the stack
+    pub(crate) stack_guard: PhysAddr,
 }
 
-#[allow(non_snake_case)]
-#[inline]
-pub(crate) fn GUEST_PAGING_ADDR() -> PhysAddr {
-    GUEST_SYSTEM_ADDR()
-        + (IDT_PAGE_REQUIRED * Page4KiB::ALIGNMENT as usize) as u64
-        + (GDT_PAGE_REQUIRED * Page4KiB::ALIGNMENT as usize) as u64
-}
+#[cfg(all(target_os = "linux", feature = "kvm"))]
+impl GuestAddrs {
+    pub(crate) fn new(cfg: &vm::Config) -> Self {
+        let system = PhysAddr::new(1 << (DefaultAddrSpace::bits() - 1));
+
+        let offset =
+            ((IDT_PAGE_REQUIRED + GDT_PAGE_REQUIRED) * Page4KiB::ALIGNMENT as usize) as u64;
+        let paging = system
+            .checked_add(offset)
+            .expect("system region exceeds the physical address space");
+
+        let below = system
+            .checked_sub(1)
+            .expect("system region must not start at address 0");
+        let stack_top = PhysAddr::new(align_floor(below.as_u64()));
 
-#[allow(non_snake_case)]
-#[inline]
-pub(crate) fn GUEST_STACK_ADDR() -> PhysAddr {
-    *ONCE_GUEST_STACK_ADDR
-        .get_or_init(|| PhysAddr::new(align_floor(GUEST_SYSTEM_ADDR().as_u64() - 1)))
+        let stack = stack_top
+            .checked_sub(cfg.stack_size.get() as u64)
+            .map(|addr| PhysAddr::new(align_floor(addr.as_u64())))
+            .expect("stack exceeds the physical address space");
+        let stack_guard = stack
+            .checked_sub(GUEST_STACK_GUARD_SIZE)
+            .expect("stack guard exceeds the physical address space");
+
+        Self {
+            system,
+            paging,
+            stack_top,
+            stack,
+            stack_guard,
+        }
+    }
 }
 
 #[allow(unused_imports)]
@@ -94,19 +192,199 @@ mod test {
     use bmvm_common::mem::VirtAddr;
 
     #[test]
+    #[cfg(all(target_os = "linux", feature = "kvm"))]
     fn test_addr() {
+        let addrs = GuestAddrs::new(&vm::Config::default());
         assert_eq!(
-            GUEST_SYSTEM_ADDR(),
+            addrs.system,
             PhysAddr::new(1 << (DefaultAddrSpace::bits() - 1))
         );
         assert_eq!(
-            GUEST_STACK_ADDR(),
-            PhysAddr::new(align_floor(GUEST_SYSTEM_ADDR().as_u64() - 1))
+            addrs.stack_top,
+            PhysAddr::new(align_floor(addrs.system.as_u64() - 1))
         );
 
         assert_eq!(
-            GUEST_SYSTEM_ADDR().as_virt_addr(),
+            addrs.system.as_virt_addr(),
             VirtAddr::new(0xFFFF800000000000)
         )
     }
+
+    #[test]
+    #[cfg(all(target_os = "linux", feature = "kvm"))]
+    fn test_addr_per_config() {
+        use bmvm_common::mem::AlignedNonZeroUsize;
+
+        let small = vm::ConfigBuilder::new()
+            .stack_size(AlignedNonZeroUsize::new_ceil(0x10000).unwrap())
+            .build();
+        let large = vm::ConfigBuilder::new()
+            .stack_size(AlignedNonZeroUsize::new_ceil(0x100000).unwrap())
+            .build();
+
+        let (small, large) = (GuestAddrs::new(&small), GuestAddrs::new(&large));
+        assert_eq!(small.system, large.system);
+        assert_eq!(small.stack_top, large.stack_top);
+        assert_eq!(small.stack, small.stack_top - 0x10000);
+        assert_eq!(large.stack, large.stack_top - 0x100000);
+        assert_ne!(small.stack_guard, large.stack_guard);
+    }
+
+    #[test]
+    fn test_signature_of() {
+        use bmvm_common::vmi::function_signature;
+
+        assert_eq!(
+            signature_of::<(), ()>("f"),
+            function_signature("f", &[], <()>::SIGNATURE)
+        );
+        assert_eq!(
+            signature_of::<(u32,), u64>("f"),
+            function_signature("f", &[u32::SIGNATURE], u64::SIGNATURE)
+        );
+        assert_eq!(
+            signature_of::<(u32, bool), u64>("f"),
+            function_signature("f", &[u32::SIGNATURE, bool::SIGNATURE], u64::SIGNATURE)
+        );
+    }
+
+    #[test]
+    fn test_param_signatures() {
+        use bmvm_common::vmi::function_signature;
+
+        assert_eq!(<()>::count(), 0);
+        assert_eq!(<(u32,)>::param_signatures(), [u32::SIGNATURE]);
+        assert_eq!(
+            <(u32, bool, u64)>::param_signatures(),
+            [u32::SIGNATURE, bool::SIGNATURE, u64::SIGNATURE]
+        );
+        assert_eq!(<(u32, bool, u64)>::count(), 3);
+
+        // the per-parameter signatures compose to the signature used for linking
+        assert_eq!(
+            function_signature("f", <(u32, bool)>::param_signatures(), u64::SIGNATURE),
+            signature_of::<(u32, bool), u64>("f")
+        );
+    }
+
+    #[test]
+    fn test_wide_signature() {
+        assert_ne!(
+            linker::compute_signature::<(u128,), u128>("f"),
+            linker::compute_signature::<(u64, u64), u128>("f"),
+        );
+    }
+
+    #[test]
+    fn test_signature_display() {
+        let func = linker::upcall::Function::new::<(u32,), u64>("f");
+        let mut names = linker::SignatureNames::default();
+        names.insert_func(&func.base);
+
+        let sig = signature_of::<(u32,), u64>("f");
+        assert_eq!(
+            names.display(sig).to_string(),
+            format!("f(u32) -> u64 ({:#018x})", sig)
+        );
+        assert_eq!(names.display(1).to_string(), "0x0000000000000001");
+    }
+
+    /// Define `Inner` nested twice in `Outer`, with `Inner::a` of type `$a`
+    #[cfg(test)]
+    macro_rules! nested {
+        ($module:ident, $a:ty) => {
+            mod $module {
+                use crate::TypeSignature;
+
+                #[repr(C)]
+                #[derive(TypeSignature, Debug, Clone, Copy, PartialEq)]
+                pub struct Inner {
+                    pub a: $a,
+                    pub b: bool,
+                }
+
+                #[repr(C)]
+                #[derive(TypeSignature, Debug, Clone, Copy, PartialEq)]
+                pub struct Middle {
+                    pub inner: Inner,
+                    pub c: u64,
+                }
+
+                #[repr(C)]
+                #[derive(TypeSignature, Debug, Clone, Copy, PartialEq)]
+                pub struct Outer {
+                    pub middle: Middle,
+                    pub d: u8,
+                }
+            }
+        };
+    }
+
+    #[test]
+    fn test_nested_signature() {
+        nested!(original, u32);
+        nested!(changed, u64);
+
+        assert_ne!(original::Inner::SIGNATURE, changed::Inner::SIGNATURE);
+        assert_ne!(original::Middle::SIGNATURE, changed::Middle::SIGNATURE);
+        assert_ne!(original::Outer::SIGNATURE, changed::Outer::SIGNATURE);
+        assert_ne!(original::Outer::SIGNATURE, original::Middle::SIGNATURE);
+    }
+
+    /// Initialize the shared memory allocator once with a leaked arena, like the VM does with the
+    /// guest memory
+    fn setup_arena() {
+        const ARENA_SIZE: usize = 1 << 16;
+        static INIT: std::sync::Once = std::sync::Once::new();
+        INIT.call_once(|| {
+            let layout = std::alloc::Layout::from_size_align(ARENA_SIZE, 4096).unwrap();
+            let ptr = std::ptr::NonNull::new(unsafe { std::alloc::alloc_zeroed(layout) }).unwrap();
+            let capacity = mem::AlignedNonZeroUsize::new_aligned(ARENA_SIZE).unwrap();
+            mem::init(Some(mem::Arena::new(ptr, capacity)), usize::MAX);
+        });
+    }
+
+    #[test]
+    fn test_nested_transport() {
+        nested!(types, u32);
+        use types::*;
+
+        setup_arena();
+        let value = Outer {
+            middle: Middle {
+                inner: Inner { a: 7, b: true },
+                c: u64::MAX,
+            },
+            d: 3,
+        };
+
+        // the peer receives the value through the shared memory
+        let mut owned = unsafe { mem::alloc::<Outer>() }.unwrap();
+        *owned.as_mut() = value;
+        let t = owned.into_shared().into_transport();
+        let foreign = mem::Foreign::<Outer>::from_transport(t).unwrap();
+        assert_eq!(*foreign.get(), value);
+
+        // an invalid value of the innermost field rejects the whole struct
+        let mut owned = unsafe { mem::alloc::<Outer>() }.unwrap();
+        *owned.as_mut() = value;
+        let b = core::mem::offset_of!(Outer, middle.inner.b);
+        unsafe { (owned.as_mut() as *mut Outer).cast::<u8>().add(b).write(2) };
+        let t = owned.into_shared().into_transport();
+        assert!(matches!(
+            mem::Foreign::<Outer>::from_transport(t),
+            Err(bmvm_common::error::ExitCode::InvalidValue)
+        ));
+    }
+
+    #[test]
+    fn test_closest_match() {
+        let names = ["echo", "sum", "bss_nonzero"];
+        assert_eq!(utils::closest_match("ecno", names), Some("echo"));
+        assert_eq!(
+            utils::closest_match("bss_nonzer", names),
+            Some("bss_nonzero")
+        );
+        assert_eq!(utils::closest_match("product", names), None);
+    }
 }
@@ -1,15 +1,79 @@
-use crate::linker::upcall;
+use crate::linker::hypercall::WrapperFunc;
+use crate::linker::{hypercall, upcall};
+use bmvm_common::error::ExitCode;
 use bmvm_common::registry::Params;
-use bmvm_common::vmi::ForeignShareable;
+use bmvm_common::vmi::{ForeignShareable, Signature};
 
 const ERR_ON_UNUSED_HOST: bool = false;
 const ERR_ON_UNUSED_GUEST: bool = false;
 
+/// Reaction to a hypercall, whose signature is not implemented by the host.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MissingAction {
+    /// Stop the guest execution with the given exit code.
+    Abort(ExitCode),
+    /// Resume the guest as if the hypercall returned a zeroed transport.
+    ReturnZero,
+}
+
+/// Hook invoked for every unresolved hypercall
+pub type MissingHypercallHook = fn(Signature) -> MissingAction;
+
+/// Guest function to register via `ConfigBuilder::register_all`. Lists of specs are best declared
+/// with the `register_fn!` macro.
+#[derive(Debug, Clone)]
+pub struct FunctionSpec(upcall::Function);
+
+impl FunctionSpec {
+    /// Function the guest is required to expose
+    pub fn new<P, R>(name: &'static str) -> Self
+    where
+        P: Params,
+        R: ForeignShareable,
+    {
+        Self(upcall::Function::new::<P, R>(name))
+    }
+
+    /// Function the guest may be missing, see `ConfigBuilder::register_guest_function_optional`
+    pub fn optional<P, R>(name: &'static str) -> Self
+    where
+        P: Params,
+        R: ForeignShareable,
+    {
+        Self(upcall::Function::new_optional::<P, R>(name))
+    }
+}
+
+/// Declare guest functions as `name: fn(P1, P2, ..) -> R`, expanding into an array of
+/// `FunctionSpec` for `ConfigBuilder::register_all`. The return type may be omitted for `()`.
+///
+/// ```ignore
+/// let linker = linker::ConfigBuilder::new().register_all(register_fn! {
+///     noop: fn(),
+///     reverse: fn(SharedBuf) -> ForeignBuf,
+/// });
+/// ```
+#[macro_export]
+macro_rules! register_fn {
+    (@ret) => { () };
+    (@ret $ret:ty) => { $ret };
+    ($($name:ident : fn($($param:ty),* $(,)?) $(-> $ret:ty)?),* $(,)?) => {
+        [$(
+            $crate::linker::FunctionSpec::new::<
+                ($($param,)*),
+                $crate::register_fn!(@ret $($ret)?),
+            >(stringify!($name))
+        ),*]
+    };
+}
+
 #[derive(Debug)]
 pub struct Config {
     pub(super) error_unused_host: bool,
     pub(super) error_unused_guest: bool,
     pub(super) upcalls: Vec<upcall::Function>,
+    pub(super) raw_hypercalls: Vec<hypercall::Function>,
+    pub(super) on_missing_hypercall: Option<MissingHypercallHook>,
 }
 
 impl From<ConfigBuilder> for Config {
@@ -42,6 +106,8 @@ impl ConfigBuilder {
                 error_unused_host: ERR_ON_UNUSED_HOST,
                 error_unused_guest: ERR_ON_UNUSED_GUEST,
                 upcalls: Vec::new(),
+                raw_hypercalls: Vec::new(),
+                on_missing_hypercall: None,
             },
         }
     }
@@ -56,7 +122,8 @@ impl ConfigBuilder {
         self
     }
 
-    /// Register a function on the guest, which will be called by the host.
+    /// Register a function on the guest, which will be called by the host. Linking fails if the
+    /// guest does not expose it.
     pub fn register_guest_function<P, R>(mut self, name: &'static str) -> Self
     where
         P: Params,
@@ -67,8 +134,156 @@ impl ConfigBuilder {
         self
     }
 
+    /// Register a function on the guest, which may be missing. If the guest does not expose it,
+    /// linking succeeds and the function is reported as unknown when requesting the upcall.
+    pub fn register_guest_function_optional<P, R>(mut self, name: &'static str) -> Self
+    where
+        P: Params,
+        R: ForeignShareable,
+    {
+        let func = upcall::Function::new_optional::<P, R>(name);
+        self.config.upcalls.push(func);
+        self
+    }
+
+    /// Register all given guest functions, see `register_fn!`.
+    pub fn register_all(mut self, specs: impl IntoIterator<Item = FunctionSpec>) -> Self {
+        self.config
+            .upcalls
+            .extend(specs.into_iter().map(|spec| spec.0));
+        self
+    }
+
+    /// Register a host function, which decodes its arguments from the raw `Transport` itself
+    /// instead of relying on the wrapper generated by `#[hypercall]`. It is linked against the guest
+    /// declaration called `name` like any other host function, but dispatched by `sig` alone, so
+    /// it also serves calls issued via `bmvm_guest::hypercall` with a hand-picked signature.
+    ///
+    /// The implementation has to honor the transport ABI (see `ffi.md`): a shared value in the
+    /// transport is owned by the host and has to be received exactly once (e.g. via
+    /// `ForeignBuf::from_transport`), and the returned transport has to be decodable as the return
+    /// type the guest expects. Returning an `ExitCode` aborts the guest with it.
+    pub fn register_raw_hypercall(
+        mut self,
+        name: &'static str,
+        sig: Signature,
+        func: WrapperFunc,
+    ) -> Self {
+        let func = hypercall::Function::raw(name, sig, func);
+        self.config.raw_hypercalls.push(func);
+        self
+    }
+
+    /// Handle hypercalls without a host implementation at runtime instead of failing to link.
+    /// Without a hook, missing hypercalls are a linking error. A host function matching the name
+    /// but not the signature of a hypercall is a linking error regardless of the hook.
+    pub fn on_missing_hypercall(mut self, hook: MissingHypercallHook) -> Self {
+        self.config.on_missing_hypercall = Some(hook);
+        self
+    }
+
     /// Build the final configuration.
     pub fn build(self) -> Config {
         self.config
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::linker::Error;
+    use crate::linker::hypercall::HypercallResult;
+    use crate::linker::{Linker, compute_signature};
+    use bmvm_common::mem::{ForeignBuf, SharedBuf};
+    use bmvm_common::vmi::{FnCall, Transport};
+    use std::ffi::CString;
+
+    #[test]
+    fn register_all() {
+        let single = ConfigBuilder::new()
+            .register_guest_function::<(), ()>("noop")
+            .register_guest_function::<(SharedBuf,), ForeignBuf>("reverse")
+            .register_guest_function::<(u64, u32), u64>("add")
+            .register_guest_function_optional::<(u8,), ()>("maybe")
+            .build();
+        let all = ConfigBuilder::new()
+            .register_all(crate::register_fn! {
+                noop: fn(),
+                reverse: fn(SharedBuf) -> ForeignBuf,
+                add: fn(u64, u32) -> u64,
+            })
+            .register_all([FunctionSpec::optional::<(u8,), ()>("maybe")])
+            .build();
+
+        assert_eq!(single.upcalls.len(), all.upcalls.len());
+        for (a, b) in single.upcalls.iter().zip(all.upcalls.iter()) {
+            assert_eq!(a.base.sig, b.base.sig);
+            assert_eq!(a.base.name, b.base.name);
+            assert_eq!(a.is_optional(), b.is_optional());
+        }
+    }
+
+    #[test]
+    fn register_raw_hypercall() {
+        fn dispatch(transport: Transport) -> HypercallResult {
+            match transport.primary() {
+                0 => Ok(Transport::new(transport.secondary(), 0)),
+                _ => Err(ExitCode::InvalidValue),
+            }
+        }
+
+        let sig = compute_signature::<(u64, u64), u64>("dispatch");
+        let cfg = ConfigBuilder::new()
+            .register_raw_hypercall("dispatch", sig, dispatch)
+            .build();
+        let hypercalls = Linker::host_hypercalls(&cfg).unwrap();
+        let raw = hypercalls.iter().find(|f| f.func.sig == sig).unwrap();
+
+        assert_eq!(raw.func.name, "dispatch");
+        assert_eq!((raw.call)(Transport::new(0, 7)), Ok(Transport::new(7, 0)));
+        assert_eq!(
+            (raw.call)(Transport::new(1, 7)),
+            Err(ExitCode::InvalidValue)
+        );
+    }
+
+    #[test]
+    fn missing_hypercall_hook() {
+        fn dispatch(transport: Transport) -> HypercallResult {
+            Ok(transport)
+        }
+        fn call(name: &str, sig: u64) -> FnCall {
+            FnCall {
+                sig,
+                name: CString::new(name).unwrap(),
+                debug_param_types: Vec::new(),
+                debug_return_type: None,
+            }
+        }
+
+        let sig = compute_signature::<(u64,), u64>("dispatch");
+        let cfg = |hook: Option<MissingHypercallHook>| {
+            let builder = ConfigBuilder::new().register_raw_hypercall("dispatch", sig, dispatch);
+            match hook {
+                Some(hook) => builder.on_missing_hypercall(hook),
+                None => builder,
+            }
+            .build()
+        };
+        let hook: MissingHypercallHook = |_| MissingAction::ReturnZero;
+
+        // unimplemented hypercalls are deferred to the hook
+        let missing = [call("dispatch", sig), call("absent", 1)];
+        assert_eq!(Linker::check(&cfg(None), &missing, &[]).len(), 1);
+        assert!(Linker::check(&cfg(Some(hook)), &missing, &[]).is_empty());
+
+        // mismatching signatures are rejected, even with a hook
+        let mismatch = [call("dispatch", sig ^ 1)];
+        let errors = Linker::check(&cfg(Some(hook)), &mismatch, &[]);
+        assert!(
+            matches!(errors.as_slice(), [Error::SignatureMismatch { .. }]),
+            "{:?}",
+            errors
+        );
+    }
+}
@@ -1,7 +1,7 @@
 use crate::linker::Func;
 use bmvm_common::error::ExitCode;
 use bmvm_common::vmi;
-use bmvm_common::vmi::{FnCall, Transport};
+use bmvm_common::vmi::{FnCall, Signature, Transport};
 use std::cmp::Ordering;
 use std::ffi::IntoStringError;
 use std::fmt::{Display, Formatter};
@@ -25,6 +25,21 @@ pub struct Function {
     pub call: WrapperFunc,
 }
 
+impl Function {
+    /// Host function handed the raw transport, see `ConfigBuilder::register_raw_hypercall`
+    pub(crate) fn raw(name: &'static str, sig: Signature, call: WrapperFunc) -> Self {
+        Self {
+            func: Func {
+                name: name.to_string(),
+                sig,
+                params: vec!["Transport".to_string()],
+                output: Some("Transport".to_string()),
+            },
+            call,
+        }
+    }
+}
+
 impl Display for Function {
     fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
         write!(f, "{}", self.func)
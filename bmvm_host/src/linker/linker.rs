@@ -1,7 +1,7 @@
 use crate::elf::ExecBundle;
-use crate::linker::config::Config;
+use crate::linker::config::{Config, MissingHypercallHook};
 use crate::linker::hypercall::{CallableFunction, ConversionError};
-use crate::linker::{CallDirection, Func, hypercall, upcall};
+use crate::linker::{CallDirection, Func, SignatureNames, hypercall, upcall};
 use bmvm_common::vmi::{FnCall, FnPtr, Signature};
 use rustc_hash::{FxBuildHasher, FxHashMap as HashMap, FxHashSet as HashSet};
 use std::ffi::{CStr, CString};
@@ -62,6 +62,9 @@ pub enum Error {
     /// Error when the guest expects a hypercall that is not implemented by the host.
     #[error("Missing implementation for hypercall: '{func}'")]
     MissingHypercallImpl { func: FnCall },
+    /// Error when a function registered as required by the host is not exposed by the guest.
+    #[error("Missing guest export: '{0}'")]
+    MissingExport(String),
     /// Error when the host expects an upcall that is not implemented by the guest.
     #[error("Missing implementation for upcall: '{func}'")]
     MissingUpcallImpl { func: Func },
@@ -100,6 +103,9 @@ pub enum Error {
     /// Error if parsing the function metadata for a host-exposed function
     #[error("Unable to parse function metadata: {0}")]
     ParseError(#[from] ConversionError),
+    /// Error if the guest executable could not be read for validation
+    #[error("Unable to read guest executable: {0}")]
+    Executable(#[from] crate::elf::Error),
     /// A collection of multiple linking errors.
     #[error("Multiple linking errors occurred: {0:?}")]
     Joined(Errors<Error>),
@@ -165,6 +171,16 @@ impl Error {
     }
 }
 
+impl Error {
+    /// Split joined errors into the individual ones
+    pub fn flatten(self) -> Vec<Error> {
+        match self {
+            Error::Joined(errors) => errors.into_inner(),
+            err => vec![err],
+        }
+    }
+}
+
 impl From<Vec<Error>> for Error {
     fn from(errors: Vec<Error>) -> Self {
         if errors.len() == 1 {
@@ -178,6 +194,7 @@ impl From<Vec<Error>> for Error {
 pub struct Linker {
     cfg: Config,
     hypercalls: Vec<hypercall::Function>,
+    names: SignatureNames,
 }
 
 impl Linker {
@@ -185,6 +202,7 @@ impl Linker {
         Ok(Self {
             cfg,
             hypercalls: Vec::new(),
+            names: SignatureNames::default(),
         })
     }
 
@@ -201,9 +219,23 @@ impl Linker {
     /// * `Err(Error)` containing a detailed list of all linking
     ///   errors encountered if any validation fails.
     pub(crate) fn link(&mut self, bundle: &ExecBundle) -> Result<()> {
-        self.hypercalls = inventory::iter::<CallableFunction>()
-            .map(hypercall::Function::try_from)
-            .try_collect::<Vec<hypercall::Function>>()?;
+        self.hypercalls = Self::host_hypercalls(&self.cfg)?;
+
+        self.hypercalls
+            .iter()
+            .for_each(|f| self.names.insert_func(&f.func));
+        self.cfg
+            .upcalls
+            .iter()
+            .for_each(|f| self.names.insert_func(&f.base));
+        bundle
+            .host
+            .iter()
+            .for_each(|f| self.names.insert_fn_call(f));
+        bundle
+            .expose
+            .iter()
+            .for_each(|f| self.names.insert_fn_call(f));
 
         self.link_hypercall(&bundle.host)?;
         self.link_upcall(bundle)?;
@@ -216,7 +248,7 @@ impl Linker {
     /// This function checks for:
     /// 1. Is there a signature collision between any guest upcalls?
     /// 2. Is there a signature collision between any host upcalls?
-    /// 3. Are all expected upcalls implemented by the guest?
+    /// 3. Are all required upcalls implemented by the guest? Optional ones are skipped.
     /// 4. Does the guest provide any upcalls that are not used by the host?
     ///
     /// # Arguments
@@ -228,8 +260,11 @@ impl Linker {
     /// - `Err(Error)` if a single error occurred
     /// - `Err(Error::Joined)` if multiple errors occurred
     fn link_upcall(&mut self, bundle: &ExecBundle) -> Result<()> {
-        let result = ValidationResults::new(&self.cfg.upcalls, &bundle.expose, |f| &f.base);
-        result.into_error((), CallDirection::HostToGuest, self.cfg.error_unused_guest)?;
+        Self::upcall_results(&self.cfg, &bundle.expose).into_error(
+            (),
+            CallDirection::HostToGuest,
+            self.cfg.error_unused_guest,
+        )?;
 
         // TODO: include in first pass
 
@@ -240,6 +275,7 @@ impl Linker {
         for upcall in &mut self.cfg.upcalls {
             match hashed_upcalls.get(&upcall.base.sig) {
                 Some(ptr) => upcall.link(*ptr),
+                None if upcall.is_optional() => {}
                 None => errs.push(Error::MissingUpcallImpl {
                     func: upcall.base.clone(),
                 }),
@@ -249,10 +285,29 @@ impl Linker {
         Error::with_errors((), errs)
     }
 
+    /// All hypercalls implemented by the host, including the raw ones registered with `cfg`
+    pub(crate) fn host_hypercalls(cfg: &Config) -> Result<Vec<hypercall::Function>> {
+        let mut hypercalls = inventory::iter::<CallableFunction>()
+            .map(hypercall::Function::try_from)
+            .try_collect::<Vec<hypercall::Function>>()?;
+        hypercalls.extend(cfg.raw_hypercalls.iter().cloned());
+        Ok(hypercalls)
+    }
+
     pub(crate) fn into_calls(self) -> (Vec<upcall::Function>, Vec<hypercall::Function>) {
         (self.cfg.upcalls, self.hypercalls)
     }
 
+    /// Names of all functions known to either host or guest, keyed by their signature
+    pub(crate) fn signature_names(&self) -> SignatureNames {
+        self.names.clone()
+    }
+
+    /// The hook responsible for hypercalls unresolved during linking
+    pub(crate) fn missing_hypercall_hook(&self) -> Option<MissingHypercallHook> {
+        self.cfg.on_missing_hypercall
+    }
+
     /// Link the expected hypercalls by the guest actually provided implementations by the host.
     ///
     /// This function checks for:
@@ -270,8 +325,83 @@ impl Linker {
     /// - `Err(Error)` if a single error occurred
     /// - `Err(Error::Joined)` if multiple errors occurred
     fn link_hypercall(&self, guest: &[FnCall]) -> Result<()> {
-        let result = ValidationResults::new(&self.hypercalls, guest, |f| &f.func);
-        result.into_error((), CallDirection::GuestToHost, self.cfg.error_unused_host)
+        Self::hypercall_results(&self.cfg, &self.hypercalls, guest).into_error(
+            (),
+            CallDirection::GuestToHost,
+            self.cfg.error_unused_host,
+        )
+    }
+
+    /// Run the checks of both linking directions against the VMI functions of the guest, without
+    /// resolving any function pointers. Unlike `link`, the errors of both directions are reported.
+    pub(crate) fn check(cfg: &Config, host: &[FnCall], expose: &[FnCall]) -> Vec<Error> {
+        let hypercalls = match Self::host_hypercalls(cfg) {
+            Ok(hypercalls) => hypercalls,
+            Err(err) => return vec![err],
+        };
+
+        let mut errors = Vec::new();
+        let hypercall = Self::hypercall_results(cfg, &hypercalls, host).into_error(
+            (),
+            CallDirection::GuestToHost,
+            cfg.error_unused_host,
+        );
+        let upcall = Self::upcall_results(cfg, expose).into_error(
+            (),
+            CallDirection::HostToGuest,
+            cfg.error_unused_guest,
+        );
+        for result in [hypercall, upcall] {
+            if let Err(err) = result {
+                errors.extend(err.flatten());
+            }
+        }
+        errors
+    }
+
+    /// Match the host implementations against the hypercalls expected by the guest
+    fn hypercall_results<'a>(
+        cfg: &Config,
+        hypercalls: &'a [hypercall::Function],
+        guest: &'a [FnCall],
+    ) -> ValidationResults<'a> {
+        let mut result = ValidationResults::new(hypercalls, guest, |f| &f.func);
+
+        // unresolved hypercalls are handled at runtime by the configured hook, while a host
+        // function of the same name with a different signature remains an error
+        if cfg.on_missing_hypercall.is_some() {
+            for f in result.unmatched_guest.drain(..) {
+                log::warn!(
+                    "Hypercall '{}' ({:#018x}) is not implemented by host.",
+                    f,
+                    f.sig
+                );
+            }
+        }
+
+        result
+    }
+
+    /// Match the upcalls registered by the host against the functions exposed by the guest
+    fn upcall_results<'a>(cfg: &'a Config, expose: &'a [FnCall]) -> ValidationResults<'a> {
+        let mut result = ValidationResults::new(&cfg.upcalls, expose, |f| &f.base);
+
+        // optional upcalls may be missing, they are not linked and reported unknown at runtime
+        let optional = cfg
+            .upcalls
+            .iter()
+            .filter(|f| f.is_optional())
+            .map(|f| f.base.name.as_str())
+            .collect::<HashSet<_>>();
+        result.unmatched_host.retain(|f| {
+            let required = !optional.contains(f.name.as_str());
+            if !required {
+                log::debug!("Optional upcall '{}' is not exposed by guest.", f);
+            }
+            required
+        });
+
+        result
     }
 }
 
@@ -285,7 +415,11 @@ pub struct ValidationResults<'a> {
 }
 
 impl<'a> ValidationResults<'a> {
-    fn new<T>(host: &'a [T], guest: &'a [FnCall], extract: fn(&'a T) -> &'a Func) -> Self {
+    pub(super) fn new<T>(
+        host: &'a [T],
+        guest: &'a [FnCall],
+        extract: fn(&'a T) -> &'a Func,
+    ) -> Self {
         // pre-alloc collections with estimated capacities
         let mut this = ValidationResults {
             guest_sig_collisions: HashMap::with_capacity_and_hasher(guest.len() / 2, FxBuildHasher),
@@ -360,13 +494,10 @@ impl<'a> ValidationResults<'a> {
 
         match direction {
             CallDirection::HostToGuest => {
-                // map unused host functions to errors
+                // map required host functions not exposed by the guest to errors
                 if !&self.unmatched_host.is_empty() {
                     for f in self.unmatched_host.iter() {
-                        let err = Error::MissingUpcallImpl {
-                            func: f.to_owned().clone(),
-                        };
-                        errors.push(err);
+                        errors.push(Error::MissingExport(f.name.clone()));
                     }
                 }
 
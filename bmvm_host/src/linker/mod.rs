@@ -1,18 +1,23 @@
 mod config;
 pub mod hypercall;
 mod linker;
+mod report;
 pub mod upcall;
 
 use bmvm_common::TypeSignature;
 use bmvm_common::hash::SignatureHasher;
 use bmvm_common::registry::Params;
-use bmvm_common::vmi::{ForeignShareable, Signature};
+use bmvm_common::vmi::{FnCall, ForeignShareable, Signature};
 pub use config::*;
 pub use linker::*;
+pub use report::*;
+use rustc_hash::FxHashMap;
 use std::cmp::Ordering;
 use std::fmt::{Display, Formatter};
 
-pub const fn compute_signature<P, R>(func: &'static str) -> u64
+/// Compute the signature of the function `func` with parameters `P` and return type `R` as used
+/// for linking. Also exported as `bmvm_host::signature_of`.
+pub const fn compute_signature<P, R>(func: &'static str) -> Signature
 where
     P: Params,
     R: ForeignShareable,
@@ -65,6 +70,87 @@ impl Display for Func {
     }
 }
 
+impl From<&FnCall> for Func {
+    fn from(call: &FnCall) -> Self {
+        Self {
+            sig: call.sig,
+            name: call.name.to_string_lossy().into_owned(),
+            params: call
+                .debug_param_types
+                .iter()
+                .map(|p| p.to_string_lossy().into_owned())
+                .collect(),
+            output: call
+                .debug_return_type
+                .as_ref()
+                .map(|r| r.to_string_lossy().into_owned()),
+        }
+    }
+}
+
+/// Lookup table rendering signatures as the function they belong to. Populated from the host
+/// function tables and the function lists parsed from the guest executable.
+#[derive(Debug, Clone, Default)]
+pub struct SignatureNames(FxHashMap<Signature, String>);
+
+impl SignatureNames {
+    /// Register the host description of a function, which takes precedence over the guest one.
+    pub fn insert_func(&mut self, func: &Func) {
+        self.0.insert(func.sig, func.to_string());
+    }
+
+    /// Register the guest description of a function, unless the host already described it.
+    pub fn insert_fn_call(&mut self, call: &FnCall) {
+        self.0.entry(call.sig).or_insert_with(|| call.to_string());
+    }
+
+    /// Resolve the signature into a displayable value.
+    pub fn display(&self, sig: Signature) -> SignatureDisplay {
+        SignatureDisplay {
+            sig,
+            func: self.0.get(&sig).cloned(),
+        }
+    }
+}
+
+/// A signature rendered as `name(params) -> ret (0x...)` if the function is known, or as its hex
+/// value otherwise.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SignatureDisplay {
+    sig: Signature,
+    func: Option<String>,
+}
+
+impl SignatureDisplay {
+    pub fn sig(&self) -> Signature {
+        self.sig
+    }
+}
+
+impl From<Signature> for SignatureDisplay {
+    fn from(sig: Signature) -> Self {
+        Self { sig, func: None }
+    }
+}
+
+impl From<&Func> for SignatureDisplay {
+    fn from(func: &Func) -> Self {
+        Self {
+            sig: func.sig,
+            func: Some(func.to_string()),
+        }
+    }
+}
+
+impl Display for SignatureDisplay {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match &self.func {
+            Some(func) => write!(f, "{} ({:#018x})", func, self.sig),
+            None => write!(f, "{:#018x}", self.sig),
+        }
+    }
+}
+
 #[derive(Debug, PartialEq, Eq, Hash, Copy, Clone, PartialOrd, Ord)]
 pub enum CallDirection {
     HostToGuest,
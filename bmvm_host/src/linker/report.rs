@@ -0,0 +1,103 @@
+use crate::elf::Buffer;
+use crate::linker::{Config, Func, Linker, Result, ValidationResults};
+use std::path::Path;
+
+/// Function known to both host and guest under the same name, but with different signatures.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SignatureMismatch {
+    pub guest: Func,
+    pub host: Func,
+}
+
+/// Outcome of `Config::validate_against`, describing how the registered functions match the
+/// functions of a guest executable.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ValidationReport {
+    /// Registered upcalls exposed by the guest
+    pub upcalls_present: Vec<Func>,
+    /// Registered upcalls not exposed by the guest
+    pub upcalls_missing: Vec<Func>,
+    /// Functions exposed by the guest, which are not registered
+    pub upcalls_unused: Vec<Func>,
+    /// Upcalls registered with a signature different from the guest one
+    pub upcall_mismatches: Vec<SignatureMismatch>,
+    /// Hypercalls expected by the guest without a host implementation
+    pub hypercalls_missing: Vec<Func>,
+    /// Host implementations not used by the guest
+    pub hypercalls_unused: Vec<Func>,
+    /// Hypercalls implemented with a signature different from the guest one
+    pub hypercall_mismatches: Vec<SignatureMismatch>,
+}
+
+impl ValidationReport {
+    /// Check if linking against the guest would succeed. Unused functions are ignored, as they
+    /// only fail to link if configured via `error_unused_host` or `error_unused_guest`.
+    pub fn is_ok(&self) -> bool {
+        self.upcalls_missing.is_empty()
+            && self.upcall_mismatches.is_empty()
+            && self.hypercalls_missing.is_empty()
+            && self.hypercall_mismatches.is_empty()
+    }
+
+    fn mismatches(results: &ValidationResults) -> Vec<SignatureMismatch> {
+        results
+            .sig_mismatches
+            .iter()
+            .map(|(g, h)| SignatureMismatch {
+                guest: Func::from(*g),
+                host: (*h).clone(),
+            })
+            .collect()
+    }
+}
+
+impl Config {
+    /// Validate the registered upcalls and the host hypercall implementations against the guest
+    /// executable at `path`, without loading it into a VM. Only the VMI metadata is parsed, so no
+    /// KVM resources are required, e.g. to check the registration in unit tests.
+    pub fn validate_against(&self, path: &Path) -> Result<ValidationReport> {
+        let (host, expose) = Buffer::new(path)?.vmi_functions()?;
+        let hypercalls = Linker::host_hypercalls(self)?;
+
+        let upcalls = ValidationResults::new(&self.upcalls, &expose, |f| &f.base);
+        let hypercall = ValidationResults::new(&hypercalls, &host, |f| &f.func);
+
+        let mut report = ValidationReport {
+            upcalls_present: self
+                .upcalls
+                .iter()
+                .filter(|f| expose.iter().any(|e| e.sig == f.base.sig))
+                .map(|f| f.base.clone())
+                .collect(),
+            upcalls_missing: upcalls
+                .unmatched_host
+                .iter()
+                .map(|f| (*f).clone())
+                .collect(),
+            upcalls_unused: upcalls
+                .unmatched_guest
+                .iter()
+                .map(|f| Func::from(*f))
+                .collect(),
+            upcall_mismatches: ValidationReport::mismatches(&upcalls),
+            hypercalls_missing: hypercall
+                .unmatched_guest
+                .iter()
+                .map(|f| Func::from(*f))
+                .collect(),
+            hypercalls_unused: hypercall
+                .unmatched_host
+                .iter()
+                .map(|f| (*f).clone())
+                .collect(),
+            hypercall_mismatches: ValidationReport::mismatches(&hypercall),
+        };
+
+        // the lookup tables are unordered, sort for a stable report
+        report.upcalls_missing.sort();
+        report.upcalls_unused.sort();
+        report.hypercalls_missing.sort();
+        report.hypercalls_unused.sort();
+        Ok(report)
+    }
+}
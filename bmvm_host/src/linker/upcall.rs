@@ -7,6 +7,8 @@ use bmvm_common::vmi::{FnPtr, ForeignShareable};
 pub struct Function {
     pub(crate) base: Func,
     pub(super) ptr: Option<FnPtr>,
+    /// Missing on the guest without failing to link
+    pub(super) optional: bool,
 }
 
 impl Function {
@@ -32,6 +34,19 @@ impl Function {
                 output,
             },
             ptr: None,
+            optional: false,
+        }
+    }
+
+    /// Create a function, which is not required to be exposed by the guest.
+    pub fn new_optional<P, R>(name: &'static str) -> Self
+    where
+        P: Params,
+        R: ForeignShareable,
+    {
+        Function {
+            optional: true,
+            ..Self::new::<P, R>(name)
         }
     }
 
@@ -42,4 +57,8 @@ impl Function {
     pub fn ptr(&self) -> Option<FnPtr> {
         self.ptr
     }
+
+    pub fn is_optional(&self) -> bool {
+        self.optional
+    }
 }
@@ -0,0 +1,93 @@
+use crate::elf;
+use rustc_hash::FxHashMap;
+
+/// Name used for samples outside any function symbol
+const UNKNOWN_SYMBOL: &str = "[unknown]";
+
+/// Function of the guest executable a RIP sample is attributed to.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Symbol {
+    pub name: String,
+    pub addr: u64,
+    pub size: u64,
+}
+
+impl Symbol {
+    fn unknown() -> Self {
+        Self {
+            name: UNKNOWN_SYMBOL.to_string(),
+            addr: 0,
+            size: 0,
+        }
+    }
+}
+
+/// Attribute the sampled instruction pointers to the function symbols containing them, sorted by
+/// the number of samples in descending order. Samples outside any function are reported as
+/// `[unknown]` with address and size zero.
+pub(crate) fn resolve(
+    samples: &FxHashMap<u64, u64>,
+    symbols: &FxHashMap<String, elf::Symbol>,
+) -> Vec<(Symbol, u64)> {
+    let mut functions = symbols
+        .iter()
+        .filter(|(_, sym)| sym.is_function && sym.size > 0)
+        .collect::<Vec<_>>();
+    functions.sort_by_key(|(_, sym)| sym.addr);
+
+    let mut counts = FxHashMap::<Symbol, u64>::default();
+    for (&rip, &count) in samples {
+        let idx = functions.partition_point(|(_, sym)| sym.addr <= rip);
+        let symbol = idx
+            .checked_sub(1)
+            .map(|i| functions[i])
+            .filter(|(_, sym)| rip < sym.addr + sym.size)
+            .map(|(name, sym)| Symbol {
+                name: name.clone(),
+                addr: sym.addr,
+                size: sym.size,
+            })
+            .unwrap_or_else(Symbol::unknown);
+        *counts.entry(symbol).or_default() += count;
+    }
+
+    let mut profile = counts.into_iter().collect::<Vec<_>>();
+    profile.sort_by(|(a, x), (b, y)| y.cmp(x).then(a.addr.cmp(&b.addr)));
+    profile
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn resolve_samples() {
+        let sym = |addr: u64, size: u64, is_function: bool| elf::Symbol {
+            addr,
+            size,
+            is_object: !is_function,
+            is_function,
+        };
+        let symbols = FxHashMap::from_iter([
+            ("compute".to_string(), sym(0x1000, 0x100, true)),
+            ("helper".to_string(), sym(0x1100, 0x20, true)),
+            ("TABLE".to_string(), sym(0x1120, 0x100, false)),
+        ]);
+        let samples = FxHashMap::from_iter([
+            (0x1000, 3),
+            (0x10ff, 3),
+            (0x1100, 1),
+            (0x1130, 4),
+            (0x500, 1),
+        ]);
+
+        let profile = resolve(&samples, &symbols);
+        let names = profile
+            .iter()
+            .map(|(s, n)| (s.name.as_str(), *n))
+            .collect::<Vec<_>>();
+        assert_eq!(names, [("compute", 6), ("[unknown]", 5), ("helper", 1)]);
+        assert_eq!(profile[0].0.addr, 0x1000);
+        assert_eq!(profile[0].0.size, 0x100);
+    }
+}
@@ -1,14 +1,46 @@
+use crate::alloc::Allocator;
+use crate::bufpool::BufferPool;
+use crate::elf::Symbol;
+use crate::linker::Func;
+use crate::profile;
+use crate::utils::closest_match;
 use crate::{
-    Upcall, elf,
+    BootPhase, CpuidEntry, ExitCounts, Registers, Snapshot, StepOutcome, Upcall, elf,
     elf::{Buffer, ExecBundle},
 };
 use crate::{linker, vm};
+use bmvm_common::error::ExitCode;
+use bmvm_common::mem;
+use bmvm_common::mem::{RegionStat, SharedBuf, Unpackable, alloc_buf};
 use bmvm_common::registry::Params;
-use bmvm_common::vmi::ForeignShareable;
+use bmvm_common::vmi::{FnCall, ForeignShareable, OwnedShareable, Signature, Transport, UpcallFn};
+use rustc_hash::FxHashMap;
+use std::collections::HashMap;
+use std::mem::MaybeUninit;
 use std::path::Path;
+use std::time::Duration;
 
 type Result<T> = std::result::Result<T, Error>;
 
+/// Name of the guest function executed by `Module::run_to_exit`
+const RUN_FUNCTION: &str = "run";
+
+/// Origin of an error, so a host application can decide how to react to it, e.g. to retry, abort
+/// or report the error to the user.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ErrorCategory {
+    /// Caused by the input of the host application: an invalid executable, configuration or
+    /// request, e.g. calling a function the guest does not expose. Retrying does not help.
+    Input,
+    /// Caused by the environment, e.g. missing KVM support, insufficient permissions or resources.
+    /// Retrying may succeed once the environment changed.
+    Environment,
+    /// Caused by the guest during execution, e.g. a fault, stack overflow or failed allocation.
+    Guest,
+    /// An unexpected state of the runtime itself, which should be reported as a bug.
+    Internal,
+}
+
 #[derive(Debug, thiserror::Error)]
 pub enum Error {
     #[error("No executable provided")]
@@ -18,33 +50,279 @@ pub enum Error {
     #[error("linker error: {0}")]
     Linker(#[from] linker::Error),
     #[error("vm error: {0}")]
-    Vm(#[from] vm::Error),
+    Vm(vm::Error),
+    #[error("guest stack overflow (rsp: {0:#x})")]
+    StackOverflow(u64),
     #[error("elf error: {0}")]
     Elf(#[from] elf::Error),
+    #[error("KVM support is not available in this build")]
+    Unsupported,
+    #[error("symbol not found: {0}")]
+    SymbolNotFound(String),
+    #[error(
+        "guest does not expose a function named {name}{}",
+        .suggestion.as_ref().map(|s| format!(", did you mean {s}?")).unwrap_or_default()
+    )]
+    UnknownUpcall {
+        name: String,
+        suggestion: Option<String>,
+    },
+    #[error("guest does not expose a function with signature {0:#018x}")]
+    UnknownSignature(Signature),
+    #[error("guest returned without exiting with a value")]
+    MissingExitValue,
+    #[error("guest passed a buffer exceeding the transport limit or the shared memory")]
+    TransportTooLarge,
+    #[error("unable to pass raw arguments to guest: {0}")]
+    RawArgs(mem::Error),
+    #[error("unable to allocate a pooled buffer: {0}")]
+    PooledBuf(mem::Error),
+    #[error("symbol is not a data object: {0}")]
+    SymbolNotAnObject(String),
+    #[error("symbol {name} has size {got}, but expected {want}")]
+    SymbolSizeMismatch { name: String, want: u64, got: u64 },
+    #[error("guest execution exceeded the timeout of {0:?}")]
+    Timeout(Duration),
+    #[error("guest exited with {0:?} during a hypercall")]
+    ProtocolViolation(ExitCode),
+    #[error("guest yielded, continue it via Module::resume")]
+    Yielded,
+    #[error("yielded upcall returns {expected:#018x}, but was resumed with {got:#018x}")]
+    ResumeSignature { expected: Signature, got: Signature },
+    #[error("guest accessed {addr:#x} outside the declared regions (rip: {rip:#x})")]
+    OutOfBoundsAccess { addr: u64, rip: u64 },
+    #[cfg(feature = "test-fault-injection")]
+    #[error("guest aborted by the injected fault {0:?}")]
+    GuestFault(crate::FaultKind),
 }
 
-/// A module is a loaded and initialized guest executable on which the host can call functions.
+impl Error {
+    /// Map the error to a process exit status. Errors caused by a guest exit code are forwarded via
+    /// `ExitCode::as_host_exit_code`, all others use the host runtime range `1..=63`:
+    ///
+    /// | Status | Error                                                                    |
+    /// |--------|--------------------------------------------------------------------------|
+    /// | `2`    | `MissingExecutable`                                                      |
+    /// | `3`    | `Elf`                                                                    |
+    /// | `4`    | `Linker`                                                                 |
+    /// | `5`    | `Vm`                                                                     |
+    /// | `6`    | `Upcall`, `Unknown*`, `RawArgs`, `PooledBuf`, `MissingExitValue`,        |
+    /// |        | `TransportTooLarge`, `ResumeSignature`                                   |
+    /// | `7`    | `StackOverflow`                                                          |
+    /// | `10`   | `Unsupported`                                                            |
+    /// | `11`   | `Symbol*`                                                                |
+    /// | `12`   | `Timeout`                                                                |
+    /// | `13`   | `ProtocolViolation`                                                      |
+    /// | `14`   | `Yielded`                                                                |
+    /// | `15`   | `OutOfBoundsAccess`                                                      |
+    /// | `16`   | `GuestFault`                                                             |
+    pub fn exit_code(&self) -> i32 {
+        match self {
+            Error::MissingExecutable => 2,
+            Error::Elf(_) => 3,
+            Error::Linker(_) => 4,
+            Error::Vm(err) => guest_exit_code(err).unwrap_or(5),
+            Error::Upcall(err) => guest_exit_code(err).unwrap_or(6),
+            Error::UnknownUpcall { .. }
+            | Error::UnknownSignature(_)
+            | Error::RawArgs(_)
+            | Error::PooledBuf(_)
+            | Error::MissingExitValue
+            | Error::TransportTooLarge
+            | Error::ResumeSignature { .. } => 6,
+            Error::StackOverflow(_) => 7,
+            Error::Unsupported => 10,
+            Error::SymbolNotFound(_)
+            | Error::SymbolNotAnObject(_)
+            | Error::SymbolSizeMismatch { .. } => 11,
+            Error::Timeout(_) => 12,
+            Error::ProtocolViolation(_) => 13,
+            Error::Yielded => 14,
+            Error::OutOfBoundsAccess { .. } => 15,
+            #[cfg(feature = "test-fault-injection")]
+            Error::GuestFault(_) => 16,
+        }
+    }
+
+    /// Origin of the error, e.g. to distinguish an invalid executable from missing KVM support or
+    /// a faulting guest.
+    pub fn category(&self) -> ErrorCategory {
+        match self {
+            Error::Vm(err) | Error::Upcall(err) => err.category(),
+            Error::Elf(err) => err.category(),
+            Error::MissingExecutable
+            | Error::Linker(_)
+            | Error::SymbolNotFound(_)
+            | Error::UnknownUpcall { .. }
+            | Error::UnknownSignature(_)
+            | Error::RawArgs(_)
+            | Error::PooledBuf(_)
+            | Error::ResumeSignature { .. }
+            | Error::SymbolNotAnObject(_)
+            | Error::SymbolSizeMismatch { .. } => ErrorCategory::Input,
+            Error::Unsupported => ErrorCategory::Environment,
+            Error::StackOverflow(_)
+            | Error::MissingExitValue
+            | Error::TransportTooLarge
+            | Error::Timeout(_)
+            | Error::ProtocolViolation(_)
+            | Error::Yielded
+            | Error::OutOfBoundsAccess { .. } => ErrorCategory::Guest,
+            #[cfg(feature = "test-fault-injection")]
+            Error::GuestFault(_) => ErrorCategory::Guest,
+        }
+    }
+
+    /// The detail value the guest reported alongside its exit code via
+    /// `bmvm_guest::exit_with_detail`, e.g. the size of a failed allocation.
+    pub fn exit_detail(&self) -> Option<u64> {
+        match self {
+            Error::Vm(vm::Error::UnhandledHalt(_, detail))
+            | Error::Upcall(vm::Error::UnhandledHalt(_, detail)) => *detail,
+            _ => None,
+        }
+    }
+}
+
+/// Extract the guest provided exit code, if the error originates from one.
+fn guest_exit_code(err: &vm::Error) -> Option<i32> {
+    match err {
+        vm::Error::UnhandledHalt(code, _) | vm::Error::UpcallReturn(code) => {
+            Some(code.as_host_exit_code())
+        }
+        _ => None,
+    }
+}
+
+impl From<vm::Error> for Error {
+    fn from(err: vm::Error) -> Self {
+        match err {
+            vm::Error::StackOverflow(rsp) => Error::StackOverflow(rsp),
+            #[cfg(all(target_os = "linux", feature = "kvm"))]
+            vm::Error::Timeout(timeout) => Error::Timeout(timeout),
+            #[cfg(all(target_os = "linux", feature = "kvm"))]
+            vm::Error::ProtocolViolation(code) => Error::ProtocolViolation(code),
+            #[cfg(all(target_os = "linux", feature = "kvm"))]
+            vm::Error::OutOfBoundsAccess { addr, rip } => Error::OutOfBoundsAccess { addr, rip },
+            #[cfg(all(target_os = "linux", feature = "kvm", feature = "test-fault-injection"))]
+            vm::Error::GuestFault(fault) => Error::GuestFault(fault),
+            #[cfg(not(all(target_os = "linux", feature = "kvm")))]
+            vm::Error::Unsupported => Error::Unsupported,
+            err if err.is_transport_too_large() => Error::TransportTooLarge,
+            err => Error::Vm(err),
+        }
+    }
+}
+
+impl Error {
+    /// Wrap an error of the upcall preparation or result decoding
+    pub(crate) fn upcall(err: vm::Error) -> Self {
+        match err {
+            err if err.is_transport_too_large() => Error::TransportTooLarge,
+            err => Error::Upcall(err),
+        }
+    }
+}
+
+/// A guest executable which is parsed, linked and loaded into a VM, but whose `setup` function
+/// has not been executed yet. Created via `RuntimeBuilder::build`.
 #[derive(Debug)]
-pub struct Module {
+pub struct Runtime {
     vm: vm::Vm,
+    symbols: FxHashMap<String, Symbol>,
+    exposed: Vec<Func>,
+    upcalls: Vec<UpcallFn>,
 }
 
-impl Module {
-    fn new(vm: vm::Config, linker: linker::Config, buf: &Buffer) -> Result<Module> {
+impl Runtime {
+    fn new(vm: vm::Config, linker: linker::Config, buf: &Buffer) -> Result<Runtime> {
+        let executable = Self::parse(&vm, buf)?;
+        Self::with_exec(vm, linker, executable)
+    }
+
+    /// Parse the guest executable independent of any VM, so no KVM resources are acquired
+    fn parse(vm: &vm::Config, buf: &Buffer) -> std::result::Result<ExecBundle, elf::Error> {
+        let manager = Allocator::with_backing(vm.backing);
+        ExecBundle::from_buffer(buf, &manager, vm.entry_symbol.as_deref(), vm.entry_align)
+    }
+
+    /// Create the runtime for the already parsed `executable`
+    fn with_exec(
+        vm: vm::Config,
+        linker: linker::Config,
+        mut executable: ExecBundle,
+    ) -> Result<Runtime> {
         let mut vm = vm::Vm::new(vm)?;
         let mut linker = linker::Linker::new(linker)?;
-        // parse the guest executable
-        let mut executable = ExecBundle::from_buffer(buf, vm.allocator())?;
 
         // execute linking stage
         linker.link(&executable)?;
 
         vm.load_exec(&mut executable)?;
+        let on_missing = linker.missing_hypercall_hook();
+        let names = linker.signature_names();
         let (upcalls, hypercalls) = linker.into_calls();
 
-        vm.link(hypercalls, upcalls);
-        vm.run().map_err(Error::Vm)?;
-        Ok(Self { vm })
+        vm.link(hypercalls, upcalls, on_missing, names);
+        Ok(Self {
+            vm,
+            symbols: executable.symbols,
+            exposed: executable.expose.iter().map(Func::from).collect(),
+            upcalls: executable.upcalls,
+        })
+    }
+
+    /// Run the guest from its entry point `_start` through the user provided `setup` function
+    /// until it signals readiness via `ready()`. Afterward, the host can call guest functions on
+    /// the returned module. Fails if the guest exits during setup without signaling readiness,
+    /// unless it exits with a value, see `Module::run_to_exit`.
+    pub fn setup(mut self) -> Result<Module> {
+        self.vm.boot()?;
+        Ok(Module {
+            bufs: BufferPool::default(),
+            vm: self.vm,
+            symbols: self.symbols,
+            exposed: self.exposed,
+            upcalls: self.upcalls,
+            pending_ret: None,
+        })
+    }
+}
+
+/// A module is a loaded and initialized guest executable on which the host can call functions.
+///
+/// The guest passes through the following states:
+/// 1. Created: the executable is loaded into the VM, see `Runtime`
+/// 2. Booting: `_start` and the user provided `setup` function run, see `Runtime::setup`
+/// 3. Ready: the guest signaled `ready()` and is idle, which is the state a `Module` starts in
+/// 4. Running: an upcall executes, returning to Ready once the guest function returns
+///
+/// The guest shuts down instead of returning to Ready if it exits via `exit_normal` or
+/// `exit_with_value`, see `Module::is_ready`.
+#[derive(Debug)]
+pub struct Module {
+    /// Declared before the VM, as the buffers are released into its shared memory on drop
+    bufs: BufferPool,
+    vm: vm::Vm,
+    symbols: FxHashMap<String, Symbol>,
+    exposed: Vec<Func>,
+    upcalls: Vec<UpcallFn>,
+    /// Return signature of the last typed upcall, checked by `resume` if it yielded. `None` for
+    /// raw calls, whose return type is unknown.
+    pending_ret: Option<Signature>,
+}
+
+impl Module {
+    /// Check if the guest is booted and idle, waiting for the next upcall. This is the case right
+    /// after the module is built and after every upcall the guest returned from.
+    pub fn is_ready(&self) -> bool {
+        self.vm.is_ready()
+    }
+
+    /// All functions exposed by the guest, sorted by name. Parameter and return types are only
+    /// available if the guest was built with VMI debug information.
+    pub fn exposed_functions(&self) -> &[Func] {
+        &self.exposed
     }
 
     pub fn get_upcall<P, R>(&mut self, name: &'static str) -> Result<Upcall<P, R>>
@@ -52,11 +330,407 @@ impl Module {
         P: Params,
         R: ForeignShareable,
     {
+        if !self.exposed.iter().any(|f| f.name == name) {
+            let suggestion = closest_match(name, self.exposed.iter().map(|f| f.name.as_str()));
+            return Err(Error::UnknownUpcall {
+                name: name.to_string(),
+                suggestion: suggestion.map(String::from),
+            });
+        }
         let func = self.vm.find_upcall::<P, R>(name)?;
 
         Ok(Upcall::new(name, func.ptr().unwrap()))
     }
 
+    /// Call the exposed guest function with `signature` without knowing its parameter and return
+    /// types, e.g. to fuzz the guest. Arguments of up to 16 bytes are passed directly in the
+    /// transport registers (little endian, zero padded), longer ones are copied into the shared
+    /// memory and passed as offset and length. The returned bytes are the raw transport returned
+    /// by the guest (primary followed by secondary, little endian).
+    ///
+    /// The guest metadata does not describe the parameter sizes, therefore the arguments are only
+    /// checked against the transport limit (`Error::TransportTooLarge`) and the available shared
+    /// memory (`Error::RawArgs`).
+    pub fn call_raw(&mut self, signature: Signature, args: &[u8]) -> Result<Vec<u8>> {
+        let ptr = self
+            .upcalls
+            .iter()
+            .find(|f| f.sig == signature)
+            .map(|f| f.func)
+            .ok_or(Error::UnknownSignature(signature))?;
+
+        let transport = if args.len() <= size_of::<Transport>() {
+            let mut raw = [0u8; size_of::<Transport>()];
+            raw[..args.len()].copy_from_slice(args);
+            let (primary, secondary) = raw.split_at(size_of::<u64>());
+            Transport::new(
+                u64::from_le_bytes(primary.try_into().unwrap()),
+                u64::from_le_bytes(secondary.try_into().unwrap()),
+            )
+        } else {
+            // the guest rejects buffers beyond the limit, don't occupy the shared memory for them
+            if args.len() > self.vm.max_transport_bytes() {
+                return Err(Error::TransportTooLarge);
+            }
+            if args.len() > mem::remaining().map_err(Error::RawArgs)? {
+                return Err(Error::RawArgs(mem::Error::OutOfMemory));
+            }
+            let mut buf = unsafe { alloc_buf(args.len()) }.map_err(Error::RawArgs)?;
+            buf.as_mut().copy_from_slice(args);
+            buf.into_shared().into_transport()
+        };
+
+        self.vm
+            .upcall_exec_setup_raw(ptr, transport)
+            .map_err(Error::Upcall)?;
+        self.pending_ret = None;
+        self.run()?;
+        let ret = self.vm.upcall_result_raw().map_err(Error::Upcall)?;
+
+        let mut out = Vec::with_capacity(size_of::<Transport>());
+        out.extend(ret.primary().to_le_bytes());
+        out.extend(ret.secondary().to_le_bytes());
+        Ok(out)
+    }
+
+    /// Buffer of `len` bytes in the shared memory, which is reused across calls instead of
+    /// allocating a fresh one each time, e.g. for the arguments of a hot loop. The guest drops its
+    /// reference once it is done with the buffer, after which the allocation is handed out again
+    /// by the next request of the same length. The content is not cleared in between, fill it via
+    /// `SharedBuf::try_copy_from`.
+    ///
+    /// Dropping a buffer which is never passed to the guest returns it to the pool as well.
+    /// Restoring a snapshot via `restore` empties the pool.
+    ///
+    /// ```ignore
+    /// for msg in messages {
+    ///     let mut buf = module.pooled_buf(msg.len())?;
+    ///     buf.try_copy_from(msg)?;
+    ///     upcall.call(&mut module, (buf,))?;
+    /// }
+    /// ```
+    pub fn pooled_buf(&mut self, len: usize) -> Result<SharedBuf> {
+        self.bufs.get(len).map_err(Error::PooledBuf)
+    }
+
+    /// Read the value of the static `symbol` from guest memory. The symbol is resolved via the ELF
+    /// symbol table and has to be a data object with the size of `T`. The guest should declare the
+    /// static with `#[unsafe(no_mangle)]` to keep the symbol name stable.
+    pub fn read_static<T: Unpackable<Output = T>>(&self, symbol: &str) -> Result<T> {
+        let sym = self
+            .symbols
+            .get(symbol)
+            .ok_or_else(|| Error::SymbolNotFound(symbol.to_string()))?;
+        if !sym.is_object {
+            return Err(Error::SymbolNotAnObject(symbol.to_string()));
+        }
+        if sym.size != size_of::<T>() as u64 {
+            return Err(Error::SymbolSizeMismatch {
+                name: symbol.to_string(),
+                want: size_of::<T>() as u64,
+                got: sym.size,
+            });
+        }
+
+        // copy into properly aligned storage, as the guest value may be misaligned
+        let mut value = MaybeUninit::<T>::zeroed();
+        let buf =
+            unsafe { std::slice::from_raw_parts_mut(value.as_mut_ptr().cast(), size_of::<T>()) };
+        self.vm.read_virt(sym.addr, buf).map_err(Error::Vm)?;
+        Ok(unsafe { T::unpack(value.as_ptr()) })
+    }
+
+    /// Execute the guest function `run` until the guest exits via `bmvm_guest::exit_with_value`
+    /// and return the value. In contrast to an upcall, `run` is not required to return to the host
+    /// and its return type is not part of the signature, so it does not have to be registered at
+    /// the linker. Unlike `bmvm_guest::exit_with_code`, which only reports an `ExitCode`, the exit
+    /// carries an arbitrary `OwnedShareable` value.
+    ///
+    /// An entry-only guest without a `run` upcall exits with the value from its `#[setup]`
+    /// function instead, which already ran to the exit when the module was built. In this case the
+    /// recorded value is returned without executing the guest again.
+    ///
+    /// Fails with `Error::MissingExitValue` if `run` returns without exiting with a value.
+    pub fn run_to_exit<T: ForeignShareable>(&mut self) -> Result<T> {
+        if !self.vm.is_ready()
+            && let Some(transport) = self.vm.take_exit_value()
+        {
+            return T::from_transport(transport)
+                .map_err(|e| Error::upcall(vm::Error::UpcallReturn(e)));
+        }
+
+        let sig = self
+            .exposed
+            .iter()
+            .find(|f| f.name == RUN_FUNCTION)
+            .map(|f| f.sig)
+            .ok_or_else(|| Error::UnknownUpcall {
+                name: RUN_FUNCTION.to_string(),
+                suggestion: None,
+            })?;
+        let ptr = self
+            .upcalls
+            .iter()
+            .find(|f| f.sig == sig)
+            .map(|f| f.func)
+            .ok_or(Error::UnknownSignature(sig))?;
+
+        self.vm
+            .upcall_exec_setup_raw(ptr, Transport::new(0, 0))
+            .map_err(Error::Upcall)?;
+        self.pending_ret = None;
+        self.run()?;
+        let transport = self.vm.take_exit_value().ok_or(Error::MissingExitValue)?;
+        T::from_transport(transport).map_err(|e| Error::upcall(vm::Error::UpcallReturn(e)))
+    }
+
+    /// Read `buf.len()` bytes starting at the guest virtual address `addr`.
+    pub fn read_guest(&self, addr: u64, buf: &mut [u8]) -> Result<()> {
+        self.vm.read_virt(addr, buf).map_err(Error::Vm)
+    }
+
+    /// Write `data` starting at the guest virtual address `addr`, e.g. to prepare the input of a
+    /// routine executed via `step`.
+    pub fn write_guest(&mut self, addr: u64, data: &[u8]) -> Result<()> {
+        self.vm.write_virt(addr, data).map_err(Error::Vm)
+    }
+
+    /// Number of stack bytes the guest used so far, measured against the pattern written by
+    /// `ConfigBuilder::stack_poison`. The value is the maximum over all executions since the module
+    /// was built. Only the stack of the primary vcpu is covered.
+    pub fn stack_high_water(&self) -> Result<usize> {
+        self.vm.stack_high_water().map_err(Error::Vm)
+    }
+
+    /// General purpose registers of the guest vCPU.
+    pub fn registers(&mut self) -> Result<Registers> {
+        self.vm.registers().map_err(Error::Vm)
+    }
+
+    /// Overwrite the general purpose registers of the guest vCPU, e.g. to point `rip` at the
+    /// routine to step through.
+    pub fn set_registers(&mut self, registers: &Registers) -> Result<()> {
+        self.vm.set_registers(registers).map_err(Error::Vm)
+    }
+
+    /// Execute exactly one guest instruction via KVM single stepping and return the registers
+    /// afterward, or the exit code if the instruction exits the guest. Hypercalls triggered by the
+    /// instruction are executed before returning.
+    ///
+    /// ```ignore
+    /// let mut regs = module.registers()?;
+    /// regs.rip = routine_addr;
+    /// module.set_registers(&regs)?;
+    /// while let StepOutcome::Stepped(regs) = module.step()? {
+    ///     assert!(regs.rsp <= stack_top);
+    /// }
+    /// ```
+    pub fn step(&mut self) -> Result<StepOutcome> {
+        self.vm.step().map_err(Error::Vm)
+    }
+
+    /// Capture the writable guest memory and the registers of the idle guest, which can be
+    /// restored via `restore`, e.g. to reset the guest to a known state between fuzzing iterations.
+    pub fn snapshot(&mut self) -> Result<Snapshot> {
+        self.vm.snapshot().map_err(Error::Vm)
+    }
+
+    /// Capture only the pages modified since `base`, which has to be the most recent snapshot taken
+    /// or restored on this module. Requires `ConfigBuilder::dirty_log`.
+    ///
+    /// Pages written by the guest are reported by KVM, pages written by the host are tracked by
+    /// `write_guest` and by comparing the shared memory against its content at `base`.
+    pub fn snapshot_incremental(&mut self, base: &Snapshot) -> Result<Snapshot> {
+        self.vm.snapshot_incremental(base).map_err(Error::Vm)
+    }
+
+    /// Reset the guest to the state captured by the full snapshot `base` followed by the chain of
+    /// incremental snapshots `deltas`, each based on its predecessor. The guest is idle afterward,
+    /// even if the previous execution faulted. Buffers allocated in the shared memory before must
+    /// not be used afterward. This includes the buffers of `pooled_buf`, which have to be dropped
+    /// before restoring, the pool starts empty again.
+    ///
+    /// ```ignore
+    /// let base = module.snapshot()?;
+    /// for input in inputs {
+    ///     let _ = upcall.call(&mut module, input);
+    ///     module.restore(&base, &[])?;
+    /// }
+    /// ```
+    pub fn restore(&mut self, base: &Snapshot, deltas: &[Snapshot]) -> Result<()> {
+        self.bufs.clear();
+        self.vm.restore(base, deltas).map_err(Error::Vm)
+    }
+
+    /// Write all guest memory regions to `w`, each prefixed with a header describing its physical
+    /// and virtual address, length and flags. See `bmvm_common::mem::read_dump` for the loader.
+    pub fn dump_memory<W: std::io::Write>(&self, w: &mut W) -> Result<()> {
+        self.vm.dump_memory(w).map_err(Error::Vm)
+    }
+
+    /// Registers of the CPUID leaf `function` and subleaf `index` as reported to the guest, after
+    /// applying `ConfigBuilder::cpuid_mask`. `None` if the leaf is not reported at all.
+    pub fn cpuid(&self, function: u32, index: u32) -> Result<Option<CpuidEntry>> {
+        self.vm.cpuid(function, index).map_err(Error::Vm)
+    }
+
+    /// Number of accessed and dirty pages per layout table region. Requires
+    /// `ConfigBuilder::track_access`, otherwise no statistics are collected.
+    pub fn region_stats(&self) -> Vec<RegionStat> {
+        self.vm.region_stats()
+    }
+
+    /// Number of RIP samples per guest function, sorted in descending order. Requires
+    /// `ConfigBuilder::sample_rip`, otherwise no samples are recorded. The samples accumulate over
+    /// all calls on the module, including the guest setup.
+    pub fn profile(&self) -> Vec<(profile::Symbol, u64)> {
+        profile::resolve(self.vm.rip_samples(), &self.symbols)
+    }
+
+    /// Number of VM exits per exit reason during the most recent upcall, `call_raw` or
+    /// `run_to_exit`, including hypercalls and callbacks issued by it. Compare the counts of the
+    /// port IO and MMIO transports via `ConfigBuilder::transport`. Executions on additional vCPUs
+    /// and via `step` are not counted. The counts are also logged at trace level after every call.
+    pub fn last_call_exits(&self) -> ExitCounts {
+        self.vm.exits()
+    }
+
+    /// Setup phases the guest completed during the boot, each with the time elapsed since the boot
+    /// started, measured on the host. The guest only marks its phases if enabled via
+    /// `ConfigBuilder::boot_markers`, otherwise just `BootPhase::Ready` is reported.
+    /// `BootPhase::CustomSetupDone` is only reported if the guest provides a `setup` function,
+    /// `BootPhase::Ready` is always the last phase.
+    pub fn boot_phases(&self) -> Vec<(BootPhase, Duration)> {
+        self.vm.boot_phases().to_vec()
+    }
+
+    /// Counters recorded by the guest via `bmvm_guest::metrics`, accumulated since the guest was
+    /// loaded. The table holds up to `bmvm_common::metrics::METRICS_CAPACITY` distinct names and is
+    /// only mapped if enabled via `ConfigBuilder::metrics`, otherwise no counters are reported.
+    pub fn metrics(&self) -> HashMap<String, u64> {
+        self.vm.metrics()
+    }
+
+    /// Raise `fault` in the guest during the next upcall, `call_raw` or `run_to_exit`, which then
+    /// fails with `Error::GuestFault` of the same kind. Meant for testing only: it allows
+    /// exercising the fault handling of the host without crafting a broken executable. The guest
+    /// state is undefined afterward, so the module should be dropped.
+    ///
+    /// Requires the `test-fault-injection` feature.
+    #[cfg(feature = "test-fault-injection")]
+    pub fn inject_fault(&mut self, fault: crate::FaultKind) -> Result<()> {
+        Ok(self.vm.inject_fault(fault)?)
+    }
+
+    /// Call `upcall` once per entry of `params`, distributing the calls across the vCPUs configured
+    /// via `ConfigBuilder::vcpus`. The results are returned in the order of `params`.
+    ///
+    /// The calls execute concurrently within the same guest memory, the host only serializes the
+    /// allocations within the shared memory while passing the parameters and reading the results.
+    /// The caller has to ensure that the calls are independent:
+    /// - every call operates on disjoint buffers, passing the same `ForeignBuf` twice is a data
+    ///   race within the guest
+    /// - the guest function does not modify global state without synchronization
+    /// - hypercalls issued during the calls can not use `HypercallContext::call_guest` and guest
+    ///   page requests are denied
+    ///
+    /// Additional vCPUs are not guarded against stack overflows, a fault aborts all calls with
+    /// `Error::Vm`.
+    pub fn parallel_upcall<P, R>(&mut self, upcall: &Upcall<P, R>, params: Vec<P>) -> Result<Vec<R>>
+    where
+        P: Params,
+        R: ForeignShareable,
+    {
+        let calls = params
+            .into_iter()
+            .map(|p| p.into_transport().map(|t| (upcall.ptr, t)))
+            .collect::<core::result::Result<Vec<_>, _>>()
+            .map_err(|e| Error::Upcall(vm::Error::UpcallExec(e)))?;
+
+        log::info!(
+            "Calling function '{}' {} times in parallel",
+            upcall.name,
+            calls.len()
+        );
+        self.vm
+            .parallel_upcall_raw(calls)
+            .map_err(Error::Vm)?
+            .into_iter()
+            .map(|t| R::from_transport(t).map_err(|e| Error::upcall(vm::Error::UpcallReturn(e))))
+            .collect()
+    }
+
+    /// Check if the guest yielded via `bmvm_guest::yield_to_host` or `wait_for_host` (see
+    /// `HltPolicy::Resumable`) within an upcall and waits to be resumed. No other upcall can be
+    /// issued in the meantime.
+    pub fn is_yielded(&self) -> bool {
+        self.vm.is_yielded()
+    }
+
+    /// Continue the guest from where it yielded, after the call returned `Error::Yielded`. Returns
+    /// the result of the yielded upcall once the guest returned from it, or `Error::Yielded` again
+    /// if it yielded once more. `R` has to be the return type of the yielded upcall, otherwise
+    /// `Error::ResumeSignature` is returned and the guest stays suspended.
+    ///
+    /// The guest is suspended within the yield hypercall, so its registers, stack and memory are
+    /// preserved across the yield. The host may read and write guest memory in the meantime,
+    /// e.g. to pass a cancellation flag. Restoring a snapshot discards the yielded upcall.
+    ///
+    /// ```ignore
+    /// let mut result = upcall.call(&mut module, params);
+    /// while let Err(Error::Yielded) = result {
+    ///     if cancelled() {
+    ///         break;
+    ///     }
+    ///     result = module.resume::<u64>();
+    /// }
+    /// ```
+    pub fn resume<R: ForeignShareable>(&mut self) -> Result<R> {
+        if self.vm.is_yielded()
+            && let Some(expected) = self.pending_ret
+            && expected != R::SIGNATURE
+        {
+            return Err(Error::ResumeSignature {
+                expected,
+                got: R::SIGNATURE,
+            });
+        }
+        self.vm.resume()?;
+        if self.vm.is_yielded() {
+            return Err(Error::Yielded);
+        }
+        self.vm.upcall_result::<R>().map_err(Error::upcall)
+    }
+
+    /// Call `upcall` as streaming upcall, which passes its output in chunks via
+    /// `bmvm_guest::stream_emit` instead of returning it. The guest runs whenever the next chunk
+    /// is requested from the returned stream, see `UpcallStream`.
+    pub fn call_stream<P>(&mut self, upcall: &Upcall<P, ()>, params: P) -> Result<UpcallStream<'_>>
+    where
+        P: Params,
+    {
+        let transport = params
+            .into_transport()
+            .map_err(|e| Error::Upcall(vm::Error::UpcallExec(e)))?;
+        log::info!("Streaming function '{}'", upcall.name);
+        self.vm
+            .stream_setup_raw(upcall.ptr, transport)
+            .map_err(Error::Upcall)?;
+        Ok(UpcallStream {
+            module: self,
+            done: false,
+        })
+    }
+
+    /// Run the guest, reporting a guest suspended via `bmvm_guest::yield_to_host` as `Yielded`
+    fn run(&mut self) -> Result<()> {
+        self.vm.run()?;
+        match self.vm.is_yielded() {
+            true => Err(Error::Yielded),
+            false => Ok(()),
+        }
+    }
+
     /// Try calling a function on the guest with the provided parameters.
     /// Error if the function is not found or the signatures do not match.
     pub(crate) fn call<P, R>(&mut self, upcall: &Upcall<P, R>, params: P) -> Result<R>
@@ -67,25 +741,64 @@ impl Module {
         self.vm
             .upcall_exec_setup::<P, R>(upcall, params)
             .map_err(Error::Upcall)?;
-        self.vm.run()?;
-        self.vm.upcall_result::<R>().map_err(Error::Upcall)
+        self.pending_ret = Some(R::SIGNATURE);
+        self.run()?;
+        self.vm.upcall_result::<R>().map_err(Error::upcall)
+    }
+}
+
+/// Chunks of a streaming upcall, see `Module::call_stream`. Each call to `next` runs the guest
+/// until it passes the next chunk, the stream ends once the guest returned from the upcall.
+///
+/// Dropping the stream before it ended cancels the upcall: the guest is told to stop via the
+/// stream control word and runs until it returned, so the module is ready for the next call. Chunks
+/// the guest passes in the meantime are discarded. If the guest already finished, nothing is run.
+pub struct UpcallStream<'m> {
+    module: &'m mut Module,
+    done: bool,
+}
+
+impl Iterator for UpcallStream<'_> {
+    type Item = Result<Vec<u8>>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.done {
+            return None;
+        }
+
+        let next = self.module.vm.stream_next().map_err(Error::from);
+        if !matches!(next, Ok(Some(_))) {
+            self.done = true;
+        }
+        next.transpose()
+    }
+}
+
+impl Drop for UpcallStream<'_> {
+    fn drop(&mut self) {
+        if self.done {
+            return;
+        }
+        if let Err(e) = self.module.vm.stream_cancel() {
+            log::error!("Unable to cancel the streaming upcall: {}", e);
+        }
     }
 }
 
-pub struct ModuleBuilder<'a> {
+pub struct RuntimeBuilder<'a> {
     vm: vm::Config,
     linker: linker::Config,
     path: Option<&'a Path>,
     buffer: Option<&'a Buffer>,
 }
 
-impl<'a> Default for ModuleBuilder<'a> {
+impl<'a> Default for RuntimeBuilder<'a> {
     fn default() -> Self {
         Self::new()
     }
 }
 
-impl<'a> ModuleBuilder<'a> {
+impl<'a> RuntimeBuilder<'a> {
     pub fn new() -> Self {
         Self {
             vm: vm::Config::default(),
@@ -113,7 +826,7 @@ impl<'a> ModuleBuilder<'a> {
         self
     }
 
-    /// Load the executable from a buffer.
+    /// Load the executable from a buffer, e.g. created in memory via `Buffer::from_bytes`.
     /// Note: Any previously set path will be ignored.
     pub fn with_buffer(mut self, buffer: &'a Buffer) -> Self {
         self.buffer = Some(buffer);
@@ -121,16 +834,118 @@ impl<'a> ModuleBuilder<'a> {
         self
     }
 
-    pub fn build(self) -> Result<Module> {
+    /// Prepare the guest executable without running any guest code. This covers:
+    ///
+    /// 1. Creating the KVM VM and vCPU
+    /// 2. Parsing the ELF and allocating a memory region per loadable segment
+    /// 3. Linking hypercalls and upcalls against the executable
+    /// 4. Allocating the stack, shared memory and system region, setting up the page tables and
+    ///    mapping all regions into the VM
+    ///
+    /// The guest itself is started by `Runtime::setup`.
+    pub fn build(self) -> Result<Runtime> {
         if self.path.is_none() && self.buffer.is_none() {
             return Err(Error::MissingExecutable);
         }
 
         if let Some(buf) = self.buffer {
-            Module::new(self.vm, self.linker, buf)
+            Runtime::new(self.vm, self.linker, buf)
         } else {
             let buf = Buffer::new(self.path.unwrap())?;
-            Module::new(self.vm, self.linker, &buf)
+            Runtime::new(self.vm, self.linker, &buf)
         }
     }
+
+    /// Like `build`, but reports every problem detectable up front at once instead of failing on
+    /// the first one: an invalid executable, missing or mismatching functions in both calling
+    /// directions and an invalid VM configuration. Without such problems, the runtime is built as
+    /// by `build`, whose error is then the only one returned.
+    pub fn build_checked(self) -> std::result::Result<Runtime, Vec<Error>> {
+        let loaded;
+        let buf = match (self.buffer, self.path) {
+            (Some(buf), _) => buf,
+            (None, Some(path)) => {
+                loaded = Buffer::new(path).map_err(|e| vec![e.into()])?;
+                &loaded
+            }
+            (None, None) => return Err(vec![Error::MissingExecutable]),
+        };
+
+        let mut errors = Vec::new();
+        // the executable is parsed once and reused for building the runtime
+        let executable = match Runtime::parse(&self.vm, buf) {
+            Ok(executable) => Some(executable),
+            Err(err) => {
+                errors.push(err.into());
+                None
+            }
+        };
+        let check = |host: &[FnCall], expose: &[FnCall]| {
+            linker::Linker::check(&self.linker, host, expose)
+                .into_iter()
+                .map(Error::Linker)
+        };
+        match &executable {
+            Some(exec) => errors.extend(check(&exec.host, &exec.expose)),
+            // the functions may still be readable if the segments are invalid, any other error is
+            // already reported by parsing the executable
+            None => {
+                if let Ok((host, expose)) = buf.vmi_functions() {
+                    errors.extend(check(&host, &expose));
+                }
+            }
+        }
+        errors.extend(
+            vm::Vm::check_config(&self.vm, executable.as_ref())
+                .into_iter()
+                .map(Error::from),
+        );
+
+        match executable {
+            Some(executable) if errors.is_empty() => {
+                Runtime::with_exec(self.vm, self.linker, executable).map_err(|e| vec![e])
+            }
+            _ => Err(errors),
+        }
+    }
+}
+
+/// Convenience builder combining `RuntimeBuilder::build` and `Runtime::setup`.
+#[derive(Default)]
+pub struct ModuleBuilder<'a>(RuntimeBuilder<'a>);
+
+impl<'a> ModuleBuilder<'a> {
+    pub fn new() -> Self {
+        Self(RuntimeBuilder::new())
+    }
+
+    pub fn configure_vm<C: Into<vm::Config>>(self, config: C) -> Self {
+        Self(self.0.configure_vm(config))
+    }
+
+    pub fn configure_linker<C: Into<linker::Config>>(self, config: C) -> Self {
+        Self(self.0.configure_linker(config))
+    }
+
+    /// Load the executable from a path.
+    /// Note: Any previously set buffer will be ignored.
+    pub fn with_path(self, path: &'a Path) -> Self {
+        Self(self.0.with_path(path))
+    }
+
+    /// Load the executable from a buffer, e.g. created in memory via `Buffer::from_bytes`.
+    /// Note: Any previously set path will be ignored.
+    pub fn with_buffer(self, buffer: &'a Buffer) -> Self {
+        Self(self.0.with_buffer(buffer))
+    }
+
+    pub fn build(self) -> Result<Module> {
+        self.0.build()?.setup()
+    }
+
+    /// Like `build`, but reports all problems detectable before running the guest at once, see
+    /// `RuntimeBuilder::build_checked`.
+    pub fn build_checked(self) -> std::result::Result<Module, Vec<Error>> {
+        self.0.build_checked()?.setup().map_err(|e| vec![e])
+    }
 }
@@ -1,5 +1,7 @@
+#[cfg(all(target_os = "linux", feature = "kvm"))]
 use std::fmt::Debug;
 
+#[cfg(all(target_os = "linux", feature = "kvm"))]
 #[derive(Debug)]
 pub struct Dirty<T>
 where
@@ -9,6 +11,7 @@ where
     dirty: bool,
 }
 
+#[cfg(all(target_os = "linux", feature = "kvm"))]
 impl<T: Debug> Dirty<T> {
     pub fn new(inner: T) -> Self {
         Self {
@@ -88,3 +91,38 @@ impl<T: Debug> Dirty<T> {
         }
     }
 }
+
+/// Return the candidate closest to `name` by edit distance, if it is similar enough to be a
+/// plausible misspelling.
+pub fn closest_match<'a, I>(name: &str, candidates: I) -> Option<&'a str>
+where
+    I: IntoIterator<Item = &'a str>,
+{
+    let max = (name.chars().count() / 3).max(1);
+    candidates
+        .into_iter()
+        .map(|c| (edit_distance(name, c), c))
+        .filter(|(d, _)| *d <= max)
+        .min_by_key(|(d, _)| *d)
+        .map(|(_, c)| c)
+}
+
+/// Levenshtein distance between `a` and `b`
+fn edit_distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut row: Vec<usize> = (0..=b.len()).collect();
+    for (i, ca) in a.chars().enumerate() {
+        let mut prev = row[0];
+        row[0] = i + 1;
+        for (j, cb) in b.iter().enumerate() {
+            let cur = row[j + 1];
+            row[j + 1] = if ca == *cb {
+                prev
+            } else {
+                1 + prev.min(cur).min(row[j])
+            };
+            prev = cur;
+        }
+    }
+    row[b.len()]
+}
@@ -1,11 +1,94 @@
-use crate::{DEFAULT_SHARED_MEMORY, GUEST_DEFAULT_STACK_SIZE};
-use bmvm_common::mem::{AlignedNonZeroUsize, AlignedUsize};
+use crate::alloc::Backing;
+use crate::vm::CpuidConfig;
+use crate::{
+    DEFAULT_MAX_CALLBACK_DEPTH, DEFAULT_MAX_PHYSICAL_MEMORY, DEFAULT_SHARED_MEMORY,
+    GUEST_DEFAULT_STACK_SIZE,
+};
+use bmvm_common::mem::{AlignedNonZeroUsize, AlignedUsize, PhysAddr};
+use bmvm_common::{BMVM_MEM_LAYOUT_TABLE, BOOT_IO_PORT, EXIT_IO_PORT, HYPERCALL_IO_PORT};
+use std::path::PathBuf;
+use std::time::Duration;
 
+/// Mechanism used by the guest to trigger a hypercall exit.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum TransportKind {
+    /// `out` instruction on the configured hypercall port (default: `HYPERCALL_IO_PORT`)
+    #[default]
+    PortIo,
+    /// Write to the unbacked doorbell page at `BMVM_HYPERCALL_MMIO`
+    Mmio,
+}
+
+/// Reaction to a `hlt` executed by the guest, see `ConfigBuilder::hlt_policy`.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum HltPolicy {
+    /// Every `hlt` terminates the guest like `exit_normal`. Within an upcall, the call fails as it
+    /// never returns.
+    #[default]
+    Exit,
+    /// A `hlt` marked with `HLT_WAIT_MARKER` in `rax` suspends the upcall until `Module::resume`,
+    /// see `bmvm_guest::wait_for_host`. Unmarked ones terminate the guest.
+    Resumable,
+}
+
+/// Alignment of the guest entry and stack, see `ConfigBuilder::align_entry`/`align_stack`.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum Alignment {
+    /// No additional alignment: 16 byte for the stack as required by the ABI, any for the entry
+    #[default]
+    Natural,
+    /// 64 byte cache line
+    CacheLine,
+    /// 4 KiB page
+    Page,
+}
+
+impl Alignment {
+    /// Alignment in bytes
+    pub const fn bytes(self) -> u64 {
+        match self {
+            Alignment::Natural => 1,
+            Alignment::CacheLine => 64,
+            Alignment::Page => 0x1000,
+        }
+    }
+}
+
+// without KVM support, the settings are never read
 #[derive(Debug)]
+#[cfg_attr(not(all(target_os = "linux", feature = "kvm")), allow(dead_code))]
 pub struct Config {
     pub(crate) stack_size: AlignedNonZeroUsize,
     pub(crate) shared_memory: AlignedUsize,
+    pub(crate) max_transport_bytes: Option<usize>,
     pub(crate) debug: bool,
+    pub(crate) guest_args: Vec<u8>,
+    pub(crate) entry_symbol: Option<String>,
+    pub(crate) max_callback_depth: usize,
+    pub(crate) transport: TransportKind,
+    pub(crate) hlt_policy: HltPolicy,
+    pub(crate) dump_on_fault: Option<PathBuf>,
+    pub(crate) max_physical_memory: usize,
+    pub(crate) track_access: bool,
+    pub(crate) strict_memory: bool,
+    pub(crate) dirty_log: bool,
+    pub(crate) metrics: bool,
+    pub(crate) page_table_cache: bool,
+    pub(crate) zero_memory: bool,
+    pub(crate) stack_poison: Option<u8>,
+    pub(crate) hypercall_port: u16,
+    pub(crate) exit_port: u16,
+    pub(crate) boot_markers: bool,
+    pub(crate) boot_port: u16,
+    pub(crate) backing: Backing,
+    pub(crate) cpuid: CpuidConfig,
+    pub(crate) vcpus: usize,
+    pub(crate) sample_rip: Option<Duration>,
+    pub(crate) timeout: Option<Duration>,
+    pub(crate) mapped_file: Option<(PathBuf, Option<u64>)>,
+    pub(crate) layout_table: PhysAddr,
+    pub(crate) entry_align: Alignment,
+    pub(crate) stack_align: Alignment,
 }
 
 impl Default for Config {
@@ -13,7 +96,35 @@ impl Default for Config {
         Config {
             stack_size: AlignedNonZeroUsize::new_ceil(GUEST_DEFAULT_STACK_SIZE).unwrap(),
             shared_memory: AlignedUsize::new_ceil(DEFAULT_SHARED_MEMORY),
+            max_transport_bytes: None,
             debug: false,
+            guest_args: Vec::new(),
+            entry_symbol: None,
+            max_callback_depth: DEFAULT_MAX_CALLBACK_DEPTH,
+            transport: TransportKind::default(),
+            hlt_policy: HltPolicy::default(),
+            dump_on_fault: None,
+            max_physical_memory: DEFAULT_MAX_PHYSICAL_MEMORY,
+            track_access: false,
+            strict_memory: false,
+            dirty_log: false,
+            metrics: false,
+            page_table_cache: false,
+            zero_memory: true,
+            stack_poison: None,
+            hypercall_port: HYPERCALL_IO_PORT,
+            exit_port: EXIT_IO_PORT,
+            boot_markers: false,
+            boot_port: BOOT_IO_PORT,
+            backing: Backing::default(),
+            cpuid: CpuidConfig::default(),
+            vcpus: 1,
+            sample_rip: None,
+            timeout: None,
+            mapped_file: None,
+            layout_table: BMVM_MEM_LAYOUT_TABLE,
+            entry_align: Alignment::default(),
+            stack_align: Alignment::default(),
         }
     }
 }
@@ -45,11 +156,249 @@ impl ConfigBuilder {
         self
     }
 
+    /// Reject buffers passed by the guest, which are larger than `max` bytes (default: the size of
+    /// the shared memory). Buffers reaching beyond the shared memory are always rejected. The limit
+    /// is kept by the host side allocator of the shared memory, which is set up once by the first
+    /// module of the process. Its limit applies to all later modules, whose limits are ignored.
+    pub fn max_transport_bytes(mut self, max: usize) -> Self {
+        self.config.max_transport_bytes = Some(max);
+        self
+    }
+
+    /// Single step through the guest and log the register state at every step.
     pub fn debug(mut self, debug: bool) -> Self {
         self.config.debug = debug;
         self
     }
 
+    /// Pass an arbitrary byte blob to the guest, which can be retrieved via `bmvm_guest::args()`.
+    pub fn guest_args(mut self, args: &[u8]) -> Self {
+        self.config.guest_args = args.to_vec();
+        self
+    }
+
+    /// Start the guest execution at the function with the given symbol name instead of the ELF
+    /// entry point.
+    pub fn entry_symbol(mut self, name: &str) -> Self {
+        self.config.entry_symbol = Some(name.to_string());
+        self
+    }
+
+    /// Limit the number of nested guest callbacks issued via `HypercallContext::call_guest`.
+    pub fn max_callback_depth(mut self, depth: usize) -> Self {
+        self.config.max_callback_depth = depth;
+        self
+    }
+
+    /// Select how the guest triggers hypercalls.
+    pub fn transport(mut self, kind: TransportKind) -> Self {
+        self.config.transport = kind;
+        self
+    }
+
+    /// Select how the host reacts to a `hlt` of the guest. With `HltPolicy::Resumable`, a guest may
+    /// idle via `bmvm_guest::wait_for_host` and is continued by `Module::resume`, e.g. once an event
+    /// for an event-loop style guest arrived.
+    pub fn hlt_policy(mut self, policy: HltPolicy) -> Self {
+        self.config.hlt_policy = policy;
+        self
+    }
+
+    /// Write a memory dump to the given path, if the guest faults during execution.
+    pub fn dump_on_fault(mut self, path: PathBuf) -> Self {
+        self.config.dump_on_fault = Some(path);
+        self
+    }
+
+    /// Limit the total guest physical memory in bytes. Page requests issued by the guest via
+    /// `bmvm_guest::request_pages` are denied once they would exceed the limit.
+    pub fn max_physical_memory(mut self, bytes: usize) -> Self {
+        self.config.max_physical_memory = bytes;
+        self
+    }
+
+    /// Map all guest regions via 4KiB pages, so the accessed and dirty bits set by the CPU can be
+    /// reported per page via `Module::region_stats`. This increases the size of the paging
+    /// structure.
+    pub fn track_access(mut self, track: bool) -> Self {
+        self.config.track_access = track;
+        self
+    }
+
+    /// Debug mode validating every guest memory access against the regions declared in the layout
+    /// table. The regions are mapped via 4KiB pages without any slack, and a fault on an address
+    /// outside of them is reported as `Error::OutOfBoundsAccess` with the faulting address and
+    /// instruction pointer instead of an unexpected exit. Combine it with `debug` or
+    /// `dump_on_fault` to inspect the guest state at the fault.
+    pub fn strict_memory(mut self, strict: bool) -> Self {
+        self.config.strict_memory = strict;
+        self
+    }
+
+    /// Let KVM log the pages written by the guest, which is required by
+    /// `Module::snapshot_incremental`. Writes to pages not yet written since the last snapshot
+    /// cause an additional VM exit.
+    pub fn dirty_log(mut self, log: bool) -> Self {
+        self.config.dirty_log = log;
+        self
+    }
+
+    /// Map the table the guest counts its `bmvm_guest::metrics` in, which are read via
+    /// `Module::metrics` (default: false). Without it, the guest drops its counts.
+    pub fn metrics(mut self, enable: bool) -> Self {
+        self.config.metrics = enable;
+        self
+    }
+
+    /// Reuse the page tables built for a previous VM with the same memory layout (default: false),
+    /// e.g. when building several modules from the same executable and configuration. The tables
+    /// of the most recent layout are kept per process, shared by all VMs enabling this option, and
+    /// copied instead of being rebuilt. See the `bmvm-setup-16mib` group of the startup benchmark
+    /// for the speedup over rebuilding the tables.
+    pub fn page_table_cache(mut self, cache: bool) -> Self {
+        self.config.page_table_cache = cache;
+        self
+    }
+
+    /// Zero the stack region before the guest is started (default: true). The executable image,
+    /// including `.bss` and padding between segments, is zeroed regardless of this option.
+    /// Disabling it is an optimization, which is only safe if the guest never reads uninitialized
+    /// stack memory.
+    pub fn zero_memory(mut self, zero: bool) -> Self {
+        self.config.zero_memory = zero;
+        self
+    }
+
+    /// Fill the stack region with `byte` before the guest is started instead of zeroing it. The
+    /// stack usage can then be measured via `Module::stack_high_water`, e.g. to size `stack_size`.
+    pub fn stack_poison(mut self, byte: u8) -> Self {
+        self.config.stack_poison = Some(byte);
+        self
+    }
+
+    /// Use the given IO port for port IO hypercalls instead of `HYPERCALL_IO_PORT`, e.g. if the
+    /// default collides with an emulated device.
+    pub fn hypercall_port(mut self, port: u16) -> Self {
+        self.config.hypercall_port = port;
+        self
+    }
+
+    /// Use the given IO port for guest exits instead of `EXIT_IO_PORT`.
+    pub fn exit_port(mut self, port: u16) -> Self {
+        self.config.exit_port = port;
+        self
+    }
+
+    /// Let the guest mark the completion of its setup phases, see `Module::boot_phases`
+    /// (default: false). Every marker costs a VM exit, which distorts the measured startup.
+    pub fn boot_markers(mut self, enable: bool) -> Self {
+        self.config.boot_markers = enable;
+        self
+    }
+
+    /// Use the given IO port for the boot markers instead of `BOOT_IO_PORT`. The port must not be
+    /// zero, which disables the markers in the guest.
+    pub fn boot_port(mut self, port: u16) -> Self {
+        self.config.boot_port = port;
+        self
+    }
+
+    /// Select the host pages backing the guest memory. Huge pages reduce the host TLB pressure for
+    /// large guests. If the requested pages are unavailable, regular pages are used instead.
+    pub fn host_backing(mut self, backing: Backing) -> Self {
+        self.config.backing = backing;
+        self
+    }
+
+    /// Adjust the CPU features reported to the guest via CPUID, e.g. to run the guest under a
+    /// constrained feature set independent of the host CPU.
+    pub fn cpuid_mask(mut self, cpuid: CpuidConfig) -> Self {
+        self.config.cpuid = cpuid;
+        self
+    }
+
+    /// Number of vCPUs available to `Module::parallel_upcall` (default: 1). Every additional vCPU
+    /// gets its own stack of `stack_size` bytes below the shared memory.
+    pub fn vcpus(mut self, n: usize) -> Self {
+        self.config.vcpus = n.max(1);
+        self
+    }
+
+    /// Interrupt the guest every `interval` to record its instruction pointer, which is resolved
+    /// against the ELF symbol table by `Module::profile`. A dedicated thread signals the thread
+    /// running the vCPU with `SIGUSR2`, so the host application must not use this signal otherwise.
+    ///
+    /// Every sample costs a signal delivery plus a VM exit and re-entry, typically a few
+    /// microseconds, which delays the guest without being visible in the profile. Intervals below
+    /// roughly 100µs therefore noticeably distort the measured run time. The signal is blocked on
+    /// the vCPU thread outside of `KVM_RUN`, so syscalls issued by hypercalls are never interrupted
+    /// with `EINTR`. A signal arriving while the host handles another exit stays pending and is
+    /// recorded at the position the guest resumes from. Parallel upcalls are not sampled.
+    pub fn sample_rip(mut self, interval: Duration) -> Self {
+        self.config.sample_rip = Some(interval);
+        self
+    }
+
+    /// Abort a guest execution with `Error::Timeout` once it runs longer than `timeout`, e.g. to
+    /// guard against a hanging guest. The limit applies to each upcall including the hypercalls and
+    /// callbacks issued during it, as well as to the guest setup. Like `sample_rip`, the vCPU is
+    /// interrupted via `SIGUSR2`, which is only delivered during `KVM_RUN`. A hypercall running past
+    /// the deadline is therefore not interrupted, the timeout is reported once it returned.
+    /// Parallel upcalls are not covered.
+    ///
+    /// The guest is left in the middle of its execution, so it should be reset via
+    /// `Module::restore` or rebuilt before calling it again.
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.config.timeout = Some(timeout);
+        self
+    }
+
+    /// Map the file at `path` read-only into the guest, where it can be accessed without copying
+    /// via `bmvm_guest::mapped_file()`. The file is mapped at the virtual address `vaddr_hint`, if
+    /// provided and free, otherwise next to the guest executable. The content is not copied: the
+    /// host pages of the file are passed to the guest directly and the last page is zero padded.
+    /// The memory slot is registered read-only with KVM (`KVM_CAP_READONLY_MEM`), so a guest write
+    /// fails the execution even if the guest maps the pages writable.
+    ///
+    /// The file must not be truncated while the guest is running, as accessing pages beyond its end
+    /// is a fault on the host.
+    pub fn map_file_readonly(mut self, path: PathBuf, vaddr_hint: Option<u64>) -> Self {
+        self.config.mapped_file = Some((path, vaddr_hint));
+        self
+    }
+
+    /// Place the layout table at `addr` instead of `BMVM_MEM_LAYOUT_TABLE`, e.g. if the guest
+    /// executable is linked at low addresses. The address is passed to the guest in `rdi` at entry.
+    /// It has to be page aligned and the page must not overlap any other region of the guest,
+    /// otherwise loading the guest fails with `Error::LayoutTableAddr`.
+    pub fn layout_table_addr(mut self, addr: PhysAddr) -> Self {
+        self.config.layout_table = addr;
+        self
+    }
+
+    /// Place the guest entry point at an `align` boundary. A position-independent executable is
+    /// loaded with an additional bias, which moves the entry to the boundary. The upcalls keep
+    /// their placement relative to the entry, align them when linking the guest, e.g. via
+    /// `-C llvm-args=-align-all-functions=6` for cache lines. Other executables, or ones whose
+    /// sections or segments do not allow the shift, have to be linked with an aligned entry.
+    /// Otherwise, loading fails with `Error::MisalignedEntry` instead of silently producing numbers
+    /// that depend on the placement.
+    ///
+    /// Together with `align_stack`, this is meant to reduce the run-to-run variance of benchmarks
+    /// and has no benefit otherwise.
+    pub fn align_entry(mut self, align: Alignment) -> Self {
+        self.config.entry_align = align;
+        self
+    }
+
+    /// Align the initial stack pointer of every vCPU to `align` instead of 16 bytes, so the stack
+    /// frames of the guest occupy the same cache lines in every run. Up to `align` bytes of the
+    /// stack stay unused.
+    pub fn align_stack(mut self, align: Alignment) -> Self {
+        self.config.stack_align = align;
+        self
+    }
+
     pub fn build(self) -> Config {
         self.config
     }
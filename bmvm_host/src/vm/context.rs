@@ -0,0 +1,53 @@
+use crate::runtime::Error as RuntimeError;
+use crate::vm::{Error, Vm};
+use bmvm_common::registry::Params;
+use bmvm_common::vmi::ForeignShareable;
+use std::cell::Cell;
+use std::ptr::NonNull;
+
+thread_local! {
+    /// The VM currently executing a hypercall on this thread
+    static ACTIVE: Cell<Option<NonNull<Vm>>> = const { Cell::new(None) };
+}
+
+/// Restores the previously active VM, even if the hypercall unwinds
+#[cfg(all(target_os = "linux", feature = "kvm"))]
+struct Restore(Option<NonNull<Vm>>);
+
+#[cfg(all(target_os = "linux", feature = "kvm"))]
+impl Drop for Restore {
+    fn drop(&mut self) {
+        ACTIVE.set(self.0);
+    }
+}
+
+/// Mark the VM as active for the duration of the hypercall execution `f`.
+#[cfg(all(target_os = "linux", feature = "kvm"))]
+pub(super) fn enter<T>(vm: &mut Vm, f: impl FnOnce() -> T) -> T {
+    let _restore = Restore(ACTIVE.replace(Some(NonNull::from(vm))));
+    f()
+}
+
+/// Access to the guest from within a host function, while it is handling a hypercall.
+pub struct HypercallContext;
+
+impl HypercallContext {
+    /// Re-enter the guest to execute the exposed function `name` before returning from the current
+    /// hypercall. Callbacks may issue hypercalls themselves, which in turn may call back into the
+    /// guest up to the configured maximum callback depth.
+    pub fn call_guest<P, R>(name: &'static str, params: P) -> Result<R, RuntimeError>
+    where
+        P: Params,
+        R: ForeignShareable,
+    {
+        let mut vm = ACTIVE
+            .get()
+            .ok_or(RuntimeError::Upcall(Error::NoHypercallContext))?;
+
+        // SAFETY: the pointer is only set while the VM is blocked in `Vm::hypercall_exec` on this
+        // thread, which does not access the VM until the hypercall returns.
+        let vm = unsafe { vm.as_mut() };
+        vm.callback_exec::<P, R>(name, params)
+            .map_err(RuntimeError::upcall)
+    }
+}
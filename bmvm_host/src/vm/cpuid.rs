@@ -0,0 +1,249 @@
+/// Register of a CPUID leaf.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CpuidRegister {
+    Eax,
+    Ebx,
+    Ecx,
+    Edx,
+}
+
+impl CpuidRegister {
+    fn idx(&self) -> usize {
+        match self {
+            CpuidRegister::Eax => 0,
+            CpuidRegister::Ebx => 1,
+            CpuidRegister::Ecx => 2,
+            CpuidRegister::Edx => 3,
+        }
+    }
+}
+
+/// Register values of a CPUID leaf as reported to the guest.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct CpuidEntry {
+    pub eax: u32,
+    pub ebx: u32,
+    pub ecx: u32,
+    pub edx: u32,
+}
+
+impl CpuidEntry {
+    pub fn get(&self, register: CpuidRegister) -> u32 {
+        self.as_array()[register.idx()]
+    }
+
+    /// Check if all `bits` are set in `register`.
+    pub fn has(&self, register: CpuidRegister, bits: u32) -> bool {
+        self.get(register) & bits == bits
+    }
+
+    fn as_array(&self) -> [u32; 4] {
+        [self.eax, self.ebx, self.ecx, self.edx]
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Mask {
+    function: u32,
+    /// subleaf, `None` matches all of them
+    index: Option<u32>,
+    register: CpuidRegister,
+    clear: u32,
+    set: u32,
+}
+
+// CPUID.01H:ECX
+const LEAF1_ECX_FMA: u32 = 1 << 12;
+const LEAF1_ECX_MOVBE: u32 = 1 << 22;
+const LEAF1_ECX_XSAVE: u32 = 1 << 26;
+const LEAF1_ECX_OSXSAVE: u32 = 1 << 27;
+const LEAF1_ECX_AVX: u32 = 1 << 28;
+const LEAF1_ECX_F16C: u32 = 1 << 29;
+// CPUID.(EAX=07H,ECX=0):EBX
+const LEAF7_EBX_BMI1: u32 = 1 << 3;
+const LEAF7_EBX_AVX2: u32 = 1 << 5;
+const LEAF7_EBX_BMI2: u32 = 1 << 8;
+const LEAF7_EBX_AVX512: u32 = (1 << 16) // F
+    | (1 << 17) // DQ
+    | (1 << 21) // IFMA
+    | (1 << 26) // PF
+    | (1 << 27) // ER
+    | (1 << 28) // CD
+    | (1 << 30) // BW
+    | (1 << 31); // VL
+// CPUID.(EAX=07H,ECX=0):ECX
+const LEAF7_ECX_AVX512: u32 = (1 << 1) // VBMI
+    | (1 << 6) // VBMI2
+    | (1 << 11) // VNNI
+    | (1 << 12) // BITALG
+    | (1 << 14); // VPOPCNTDQ
+// CPUID.(EAX=07H,ECX=0):EDX
+const LEAF7_EDX_AVX512: u32 = (1 << 2) // 4VNNIW
+    | (1 << 3) // 4FMAPS
+    | (1 << 8) // VP2INTERSECT
+    | (1 << 23); // FP16
+// CPUID.80000001H:ECX
+const EXT_LEAF1_ECX_LZCNT: u32 = 1 << 5;
+
+/// Adjustments of the CPUID leaves reported to the guest. By default the guest sees all features
+/// supported by KVM on the host. Features can only be hidden or set on top of that, setting bits of
+/// features the host does not support results in undefined guest behaviour.
+///
+/// ```ignore
+/// let cpuid = CpuidConfig::baseline_x86_64_v2().vendor(b"GenuineIntel");
+/// let config = ConfigBuilder::new().cpuid_mask(cpuid);
+/// ```
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct CpuidConfig {
+    masks: Vec<Mask>,
+    vendor: Option<[u8; 12]>,
+}
+
+impl CpuidConfig {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Only report features of the x86-64-v2 microarchitecture level, hiding AVX and later
+    /// extensions.
+    pub fn baseline_x86_64_v2() -> Self {
+        Self::baseline_x86_64_v3()
+            .clear(
+                0x1,
+                None,
+                CpuidRegister::Ecx,
+                LEAF1_ECX_FMA
+                    | LEAF1_ECX_MOVBE
+                    | LEAF1_ECX_XSAVE
+                    | LEAF1_ECX_OSXSAVE
+                    | LEAF1_ECX_AVX
+                    | LEAF1_ECX_F16C,
+            )
+            .clear(
+                0x7,
+                Some(0),
+                CpuidRegister::Ebx,
+                LEAF7_EBX_BMI1 | LEAF7_EBX_AVX2 | LEAF7_EBX_BMI2,
+            )
+            .clear(0x8000_0001, None, CpuidRegister::Ecx, EXT_LEAF1_ECX_LZCNT)
+    }
+
+    /// Only report features of the x86-64-v3 microarchitecture level, hiding AVX-512.
+    pub fn baseline_x86_64_v3() -> Self {
+        Self::new().without_avx512()
+    }
+
+    /// Hide all AVX-512 extensions.
+    pub fn without_avx512(self) -> Self {
+        self.clear(0x7, Some(0), CpuidRegister::Ebx, LEAF7_EBX_AVX512)
+            .clear(0x7, Some(0), CpuidRegister::Ecx, LEAF7_ECX_AVX512)
+            .clear(0x7, Some(0), CpuidRegister::Edx, LEAF7_EDX_AVX512)
+    }
+
+    /// Clear `bits` in `register` of the leaf `function`. If `index` is `None`, all subleafs are
+    /// affected.
+    pub fn clear(
+        mut self,
+        function: u32,
+        index: Option<u32>,
+        register: CpuidRegister,
+        bits: u32,
+    ) -> Self {
+        self.masks.push(Mask {
+            function,
+            index,
+            register,
+            clear: bits,
+            set: 0,
+        });
+        self
+    }
+
+    /// Set `bits` in `register` of the leaf `function`. If `index` is `None`, all subleafs are
+    /// affected.
+    pub fn set(
+        mut self,
+        function: u32,
+        index: Option<u32>,
+        register: CpuidRegister,
+        bits: u32,
+    ) -> Self {
+        self.masks.push(Mask {
+            function,
+            index,
+            register,
+            clear: 0,
+            set: bits,
+        });
+        self
+    }
+
+    /// Report the given vendor identification string, e.g. `GenuineIntel` or `AuthenticAMD`.
+    pub fn vendor(mut self, vendor: &[u8; 12]) -> Self {
+        self.vendor = Some(*vendor);
+        self
+    }
+
+    /// Apply the adjustments to the registers of the leaf `function` and subleaf `index`.
+    #[cfg(all(target_os = "linux", feature = "kvm"))]
+    pub(crate) fn apply(&self, function: u32, index: u32, entry: &mut CpuidEntry) {
+        let mut regs = entry.as_array();
+        for mask in self.masks.iter() {
+            if mask.function != function || mask.index.is_some_and(|i| i != index) {
+                continue;
+            }
+            let reg = &mut regs[mask.register.idx()];
+            *reg = (*reg & !mask.clear) | mask.set;
+        }
+
+        // the vendor string is stored in EBX, EDX, ECX
+        if let Some(vendor) = self.vendor.filter(|_| function == 0) {
+            let word = |i: usize| u32::from_le_bytes(vendor[i..i + 4].try_into().unwrap());
+            regs[1] = word(0);
+            regs[3] = word(4);
+            regs[2] = word(8);
+        }
+
+        let [eax, ebx, ecx, edx] = regs;
+        *entry = CpuidEntry { eax, ebx, ecx, edx };
+    }
+}
+
+#[cfg(all(test, target_os = "linux", feature = "kvm"))]
+mod test {
+    use super::*;
+
+    #[test]
+    fn apply_masks() {
+        let all = CpuidEntry {
+            eax: u32::MAX,
+            ebx: u32::MAX,
+            ecx: u32::MAX,
+            edx: u32::MAX,
+        };
+
+        let v2 = CpuidConfig::baseline_x86_64_v2();
+        let mut leaf7 = all;
+        v2.apply(0x7, 0, &mut leaf7);
+        assert!(!leaf7.has(CpuidRegister::Ebx, LEAF7_EBX_AVX2));
+        assert!(!leaf7.has(CpuidRegister::Ebx, 1 << 16));
+        assert_eq!(leaf7.eax, u32::MAX);
+
+        // other subleafs are not affected
+        let mut leaf7_1 = all;
+        v2.apply(0x7, 1, &mut leaf7_1);
+        assert_eq!(leaf7_1, all);
+
+        let mut leaf1 = all;
+        CpuidConfig::baseline_x86_64_v3().apply(0x1, 0, &mut leaf1);
+        assert!(leaf1.has(CpuidRegister::Ecx, LEAF1_ECX_AVX));
+
+        let mut leaf0 = CpuidEntry::default();
+        CpuidConfig::new()
+            .vendor(b"GenuineIntel")
+            .apply(0x0, 0, &mut leaf0);
+        assert_eq!(&leaf0.ebx.to_le_bytes(), b"Genu");
+        assert_eq!(&leaf0.edx.to_le_bytes(), b"ineI");
+        assert_eq!(&leaf0.ecx.to_le_bytes(), b"ntel");
+    }
+}
@@ -0,0 +1,40 @@
+/// Number of VM exits per exit reason during the most recent guest execution, see
+/// `Module::last_call_exits`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ExitCounts {
+    /// Port IO, including hypercalls via the port transport and guest logging
+    pub io: u64,
+    /// MMIO, including hypercalls via the MMIO transport
+    pub mmio: u64,
+    pub hlt: u64,
+    /// Single steps in debug mode
+    pub debug: u64,
+    /// Interruptions by the RIP sampler or the timeout watchdog
+    pub interrupted: u64,
+    /// Any other exit reason
+    pub other: u64,
+}
+
+impl ExitCounts {
+    /// Number of VM exits of all reasons
+    pub fn total(&self) -> u64 {
+        self.io + self.mmio + self.hlt + self.debug + self.interrupted + self.other
+    }
+}
+
+#[cfg(all(target_os = "linux", feature = "kvm"))]
+impl ExitCounts {
+    /// Count a single return of `KVM_RUN`
+    pub(crate) fn record(&mut self, exit: &kvm_ioctls::VcpuExit) {
+        use kvm_ioctls::VcpuExit;
+
+        let counter = match exit {
+            VcpuExit::IoIn(..) | VcpuExit::IoOut(..) => &mut self.io,
+            VcpuExit::MmioRead(..) | VcpuExit::MmioWrite(..) => &mut self.mmio,
+            VcpuExit::Hlt => &mut self.hlt,
+            VcpuExit::Debug(_) => &mut self.debug,
+            _ => &mut self.other,
+        };
+        *counter += 1;
+    }
+}
@@ -0,0 +1,27 @@
+/// Fault injected into the guest via `Module::inject_fault`. Each fault is raised by the first
+/// instruction of the next upcall, aborting it with `Error::GuestFault` of the same kind.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FaultKind {
+    /// Start the upcall at the unmapped null page, raising a page fault on instruction fetch
+    UnmappedRip,
+    /// Start the upcall at a non-canonical address, raising a general protection fault
+    NonCanonicalRip,
+    /// Mark the code segment as not present, so entering the guest fails
+    InvalidCodeSegment,
+}
+
+impl FaultKind {
+    /// All faults which can be injected
+    pub const ALL: [FaultKind; 3] = [
+        FaultKind::UnmappedRip,
+        FaultKind::NonCanonicalRip,
+        FaultKind::InvalidCodeSegment,
+    ];
+}
+
+/// Instruction pointer of `FaultKind::UnmappedRip`, the guest never maps the null page
+#[cfg(all(target_os = "linux", feature = "kvm"))]
+pub(crate) const UNMAPPED_RIP: u64 = 0;
+/// Instruction pointer of `FaultKind::NonCanonicalRip`
+#[cfg(all(target_os = "linux", feature = "kvm"))]
+pub(crate) const NON_CANONICAL_RIP: u64 = 0x8000_0000_0000_0000;
@@ -1,10 +1,35 @@
 mod config;
+mod context;
+mod cpuid;
+mod exits;
+#[cfg(feature = "test-fault-injection")]
+mod fault;
+#[cfg(all(target_os = "linux", feature = "kvm"))]
 mod paging;
+#[cfg(all(target_os = "linux", feature = "kvm"))]
 mod registry;
+#[cfg(all(target_os = "linux", feature = "kvm"))]
+mod sampler;
+#[cfg(all(target_os = "linux", feature = "kvm"))]
 mod setup;
+mod snapshot;
+mod step;
+#[cfg(all(target_os = "linux", feature = "kvm"))]
 mod vcpu;
+#[cfg(all(target_os = "linux", feature = "kvm"))]
+mod vm;
+#[cfg(not(all(target_os = "linux", feature = "kvm")))]
+#[path = "unsupported.rs"]
 mod vm;
 
 pub use config::*;
+pub use context::HypercallContext;
+pub use cpuid::*;
+pub use exits::ExitCounts;
+#[cfg(feature = "test-fault-injection")]
+pub use fault::FaultKind;
+#[cfg(all(target_os = "linux", feature = "kvm"))]
 pub use setup::{GDT_PAGE_REQUIRED, IDT_PAGE_REQUIRED};
+pub use snapshot::Snapshot;
+pub use step::{Registers, StepOutcome};
 pub use vm::*;
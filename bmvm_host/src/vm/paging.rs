@@ -6,7 +6,9 @@ use bmvm_common::mem::{
 use rustc_hash::FxHashMap;
 use std::fmt::{Debug, Display};
 use std::num::NonZeroUsize;
+use std::ptr::NonNull;
 use std::slice;
+use std::sync::{Arc, Mutex};
 
 const PAGE_FLAG_PRESENT: u64 = 1;
 const PAGE_FLAG_WRITE: u64 = 1 << 1;
@@ -34,22 +36,30 @@ pub enum Error {
     Overlapping(PhysAddr),
 }
 
-#[derive(Clone, Copy, Hash)]
-pub struct Page {
-    pub region: usize, // index in region structure
-    pub offset: usize, // offset in the region (multiple of 0x1000)
+/// State of an existing paging structure required to map additional regions later on. The table
+/// pages are referenced by their host address, which stays valid as long as the backing regions
+/// are alive.
+#[derive(Debug, Clone)]
+pub(super) struct PagingState {
+    pages: FxHashMap<PhysAddr, NonNull<u8>>,
+    next_addr: PhysAddr,
+    granular: bool,
 }
 
 pub struct PagingArena<'a> {
     allocator: &'a Allocator,
     regions: Vec<Region<ReadWrite>>, // (region, has been self mapped)
-    pages: FxHashMap<PhysAddr, Page>,
+    pages: FxHashMap<PhysAddr, NonNull<u8>>,
     current: usize,
     offset: usize,
     remaining: usize,
     on_demand: usize,
     next_addr: PhysAddr,
     mapped_region_offset: usize,
+    /// only use 4KiB leaf entries
+    granular: bool,
+    /// previous entries overwritten in the tables, if the writes may have to be reverted
+    journal: Option<Vec<(PhysAddr, usize, PageEntry)>>,
 }
 
 impl<'a> PagingArena<'a> {
@@ -58,50 +68,66 @@ impl<'a> PagingArena<'a> {
         pml4: PhysAddr,
         initial: NonZeroUsize,
         on_demand: NonZeroUsize,
+        granular: bool,
     ) -> Result<Self> {
         let capactity =
             AlignedNonZeroUsize::new_aligned(initial.get() * Page4KiB::ALIGNMENT as usize).unwrap();
         let base = allocator
             .alloc::<ReadWrite>(capactity)?
             .set_guest_addr(pml4);
-        let regions = vec![base];
         let mut pages = FxHashMap::default();
-        pages.insert(
-            pml4,
-            Page {
-                region: 0,
-                offset: 0,
-            },
-        );
+        pages.insert(pml4, NonNull::new(base.as_ptr().cast_mut()).unwrap());
+        let regions = vec![base];
 
+        // the first page of the initial region is occupied by the PML4
         Ok(Self {
             allocator,
             regions,
             pages,
             current: 0,
-            offset: 0,
-            remaining: initial.get(),
+            offset: 1,
+            remaining: initial.get() - 1,
             on_demand: on_demand.get(),
             next_addr: pml4 + Page4KiB::ALIGNMENT,
             mapped_region_offset: 0,
+            granular,
+            journal: None,
         })
     }
 
+    /// Continue with an existing paging structure. Additional tables are allocated on demand.
+    fn resume(allocator: &'a Allocator, state: PagingState, on_demand: NonZeroUsize) -> Self {
+        Self {
+            allocator,
+            regions: Vec::new(),
+            pages: state.pages,
+            current: 0,
+            offset: 0,
+            remaining: 0,
+            on_demand: on_demand.get(),
+            next_addr: state.next_addr,
+            mapped_region_offset: 0,
+            granular: state.granular,
+            journal: Some(Vec::new()),
+        }
+    }
+
     /// Try fetching the table at a given address
     fn table_at(&self, addr: PhysAddr) -> Option<&mut [u8]> {
-        // If we have a page for this address, return it
-        if let Some(page) = self.pages.get(&addr) {
-            let region = self.regions.get(page.region)?;
-            let from = page.offset * Page4KiB::ALIGNMENT as usize;
-            let to = from + Page4KiB::ALIGNMENT as usize;
-
-            let ptr = region.as_ptr().cast_mut();
-            let slice = unsafe { slice::from_raw_parts_mut(ptr, region.capacity().get()) };
-            let p = &mut slice[from..to];
-            return Some(p);
-        };
+        self.pages.get(&addr).map(|page| unsafe {
+            slice::from_raw_parts_mut(page.as_ptr(), Page4KiB::ALIGNMENT as usize)
+        })
+    }
 
-        None
+    /// Write an entry to the table at a given address.
+    fn write(&mut self, addr: PhysAddr, idx: usize, entry: PageEntry) -> Result<()> {
+        let table = self.table_at(addr).ok_or(Error::NoRegionForAddr(addr))?;
+        let previous = get_at(table, idx)?;
+        write_at(table, idx, entry)?;
+        if let Some(journal) = self.journal.as_mut() {
+            journal.push((addr, idx, previous));
+        }
+        Ok(())
     }
 
     /// Create a new child table for the parent at an index with the given flags.
@@ -134,22 +160,15 @@ impl<'a> PagingArena<'a> {
         }
 
         // update the arena allocator
+        let base = self.regions[self.current].as_ptr().cast_mut();
+        let page = unsafe { base.add(self.offset * Page4KiB::ALIGNMENT as usize) };
+        self.pages.insert(addr, NonNull::new(page).unwrap());
         self.remaining -= 1;
         self.offset += 1;
-        self.pages.insert(
-            addr,
-            Page {
-                region: self.current,
-                offset: self.offset,
-            },
-        );
 
         // write the entry to the parent table
-        let parent_table = self
-            .table_at(parent)
-            .ok_or(Error::NoRegionForAddr(parent))?;
         let entry = PageEntry::new(addr.as_u64(), false, flags);
-        write_at(parent_table, idx, entry)?;
+        self.write(parent, idx, entry)?;
 
         Ok(addr)
     }
@@ -171,8 +190,92 @@ impl<'a> PagingArena<'a> {
         layout
     }
 
-    fn into_regions(self) -> Vec<Region<ReadWrite>> {
-        self.regions
+    fn into_parts(self) -> (Vec<Region<ReadWrite>>, PagingState) {
+        let state = PagingState {
+            pages: self.pages,
+            next_addr: self.next_addr,
+            granular: self.granular,
+        };
+        (self.regions, state)
+    }
+}
+
+/// Everything the paging structure built by `setup` depends on
+#[derive(PartialEq, Eq)]
+struct TemplateKey {
+    entries: Vec<LayoutTableEntry>,
+    pml4: PhysAddr,
+    initial: NonZeroUsize,
+    on_demand: NonZeroUsize,
+    granular: bool,
+}
+
+/// Image of a paging structure built by `setup`. The table entries only contain guest addresses,
+/// so the image is valid for every VM with the same layout and merely the host addresses of the
+/// table pages have to be patched after copying.
+struct Template {
+    key: TemplateKey,
+    /// guest address and content of every table region
+    regions: Vec<(PhysAddr, Box<[u8]>)>,
+    /// guest address of every table page, the index of its region and the offset within
+    pages: Vec<(PhysAddr, usize, usize)>,
+    next_addr: PhysAddr,
+}
+
+/// Template of the most recently built layout, replaced once a different layout is built
+static TEMPLATE: Mutex<Option<Arc<Template>>> = Mutex::new(None);
+
+impl Template {
+    fn new(key: TemplateKey, regions: &[Region<ReadWrite>], state: &PagingState) -> Option<Self> {
+        let pages = state
+            .pages
+            .iter()
+            .map(|(addr, page)| {
+                regions.iter().enumerate().find_map(|(idx, region)| {
+                    let offset = (page.as_ptr() as usize).checked_sub(region.as_ptr() as usize)?;
+                    (offset < region.capacity().get()).then_some((*addr, idx, offset))
+                })
+            })
+            .collect::<Option<Vec<_>>>()?;
+
+        Some(Self {
+            key,
+            regions: regions
+                .iter()
+                .map(|region| (region.addr(), Box::from(region.as_ref())))
+                .collect(),
+            pages,
+            next_addr: state.next_addr,
+        })
+    }
+
+    /// Copy the image into freshly allocated regions
+    fn instantiate(&self, allocator: &Allocator) -> Result<(Vec<Region<ReadWrite>>, PagingState)> {
+        let mut regions = Vec::with_capacity(self.regions.len());
+        for (addr, content) in self.regions.iter() {
+            let capacity = AlignedNonZeroUsize::new_aligned(content.len()).unwrap();
+            let mut region = allocator
+                .alloc::<ReadWrite>(capacity)?
+                .set_guest_addr(*addr);
+            region.as_mut().copy_from_slice(content);
+            regions.push(region);
+        }
+
+        let pages = self
+            .pages
+            .iter()
+            .map(|(addr, idx, offset)| {
+                let base = regions[*idx].as_ptr().cast_mut();
+                (*addr, NonNull::new(unsafe { base.add(*offset) }).unwrap())
+            })
+            .collect();
+
+        let state = PagingState {
+            pages,
+            next_addr: self.next_addr,
+            granular: self.key.granular,
+        };
+        Ok((regions, state))
     }
 }
 
@@ -262,15 +365,17 @@ impl Display for PageEntry {
     }
 }
 
-/// Build the guest paging structure
+/// Build the guest paging structure. If `granular` is set, huge pages are not used, so the
+/// accessed and dirty bits can be evaluated per 4KiB page.
 pub(super) fn setup(
     allocator: &Allocator,
     entries: &[LayoutTableEntry],
     pml4: PhysAddr,
     initial: NonZeroUsize,
     on_demand: NonZeroUsize,
-) -> Result<Vec<Region<ReadWrite>>> {
-    let mut arena = PagingArena::new(allocator, pml4, initial, on_demand)?;
+    granular: bool,
+) -> Result<(Vec<Region<ReadWrite>>, PagingState)> {
+    let mut arena = PagingArena::new(allocator, pml4, initial, on_demand, granular)?;
 
     // Map the layout table
     setup_impl(&mut arena, entries, pml4)?;
@@ -282,7 +387,100 @@ pub(super) fn setup(
         arena_layout = arena.layout();
     }
 
-    Ok(arena.into_regions())
+    Ok(arena.into_parts())
+}
+
+/// Build the guest paging structure like `setup`, but copy the tables of a previous build with an
+/// identical layout instead of walking every mapped page again. Only the most recent layout is
+/// kept, so building a different layout invalidates the cached tables.
+pub(super) fn setup_cached(
+    allocator: &Allocator,
+    entries: &[LayoutTableEntry],
+    pml4: PhysAddr,
+    initial: NonZeroUsize,
+    on_demand: NonZeroUsize,
+    granular: bool,
+) -> Result<(Vec<Region<ReadWrite>>, PagingState)> {
+    let key = TemplateKey {
+        entries: entries.to_vec(),
+        pml4,
+        initial,
+        on_demand,
+        granular,
+    };
+
+    // a poisoned cache is ignored, the tables are simply rebuilt
+    let cached = TEMPLATE
+        .lock()
+        .ok()
+        .and_then(|template| template.clone())
+        .filter(|template| template.key == key);
+    if let Some(template) = cached {
+        return template.instantiate(allocator);
+    }
+
+    let (regions, state) = setup(allocator, entries, pml4, initial, on_demand, granular)?;
+    if let (Some(template), Ok(mut cache)) = (Template::new(key, &regions, &state), TEMPLATE.lock())
+    {
+        *cache = Some(Arc::new(template));
+    }
+
+    Ok((regions, state))
+}
+
+/// Map additional entries into the paging structure previously built via `setup`. Returns the
+/// newly allocated table regions, which still have to be mapped into the guest, and the means to
+/// revert the extension if that fails. On error, the paging structure is left unchanged.
+pub(super) fn extend(
+    allocator: &Allocator,
+    state: &mut PagingState,
+    entries: &[LayoutTableEntry],
+    pml4: PhysAddr,
+    on_demand: NonZeroUsize,
+) -> Result<(Vec<Region<ReadWrite>>, Revert)> {
+    let mut arena = PagingArena::resume(allocator, state.clone(), on_demand);
+    let result = setup_impl(&mut arena, entries, pml4);
+    let revert = Revert {
+        state: state.clone(),
+        journal: arena.journal.take().unwrap_or_default(),
+    };
+
+    match result {
+        Ok(()) => {
+            let (regions, extended) = arena.into_parts();
+            *state = extended;
+            Ok((regions, revert))
+        }
+        Err(err) => {
+            revert.apply(state);
+            Err(err)
+        }
+    }
+}
+
+/// Paging structure prior to an `extend`
+pub(super) struct Revert {
+    state: PagingState,
+    /// previous entries overwritten by the extension, in the order of writing
+    journal: Vec<(PhysAddr, usize, PageEntry)>,
+}
+
+impl Revert {
+    /// Restore the paging structure prior to the extension. Afterwards, the table regions allocated
+    /// by the extension are no longer referenced and may be dropped.
+    pub(super) fn apply(self, state: &mut PagingState) {
+        for (addr, idx, entry) in self.journal.into_iter().rev() {
+            // tables allocated by the extension are discarded anyway
+            if let Some(page) = self.state.pages.get(&addr) {
+                let table = unsafe {
+                    slice::from_raw_parts_mut(page.as_ptr(), Page4KiB::ALIGNMENT as usize)
+                };
+                // the index was already written to, so it is within bounds
+                let _ = write_at(table, idx, entry);
+            }
+        }
+        *state = self.state;
+    }
 }
 
 fn setup_impl(arena: &mut PagingArena, entries: &[LayoutTableEntry], pml4: PhysAddr) -> Result<()> {
@@ -293,24 +491,26 @@ fn setup_impl(arena: &mut PagingArena, entries: &[LayoutTableEntry], pml4: PhysA
         let flags = layout_entry.flags();
         while vaddr < end {
             match () {
-                _ if aligned_and_fits::<Page1GiB>(vaddr.as_u64(), end.as_u64()) => {
+                _ if !arena.granular
+                    && aligned_and_fits::<Page1GiB>(vaddr.as_u64(), end.as_u64()) =>
+                {
                     let pdpt = write_idx(arena, paddr, pml4, vaddr.p4_index(), flags)?;
 
                     // Handle leaf entry
-                    let table = arena.table_at(pdpt).ok_or(Error::NoRegionForAddr(pdpt))?;
                     let entry = PageEntry::new(paddr.as_u64(), true, flags);
-                    write_at(table, vaddr.p3_index(), entry)?;
+                    arena.write(pdpt, vaddr.p3_index(), entry)?;
                     paddr += Page1GiB::ALIGNMENT;
                     vaddr += Page1GiB::ALIGNMENT;
                 }
-                _ if aligned_and_fits::<Page2MiB>(vaddr.as_u64(), end.as_u64()) => {
+                _ if !arena.granular
+                    && aligned_and_fits::<Page2MiB>(vaddr.as_u64(), end.as_u64()) =>
+                {
                     let pdpt = write_idx(arena, paddr, pml4, vaddr.p4_index(), flags)?;
                     let pd = write_idx(arena, paddr, pdpt, vaddr.p3_index(), flags)?;
 
                     // Handle leaf entry
-                    let table = arena.table_at(pd).ok_or(Error::NoRegionForAddr(pd))?;
                     let entry = PageEntry::new(paddr.as_u64(), true, flags);
-                    write_at(table, vaddr.p2_index(), entry)?;
+                    arena.write(pd, vaddr.p2_index(), entry)?;
                     paddr += Page2MiB::ALIGNMENT;
                     vaddr += Page2MiB::ALIGNMENT;
                 }
@@ -320,9 +520,8 @@ fn setup_impl(arena: &mut PagingArena, entries: &[LayoutTableEntry], pml4: PhysA
                     let pt = write_idx(arena, paddr, pd, vaddr.p2_index(), flags)?;
 
                     // Handle leaf entry
-                    let table = arena.table_at(pt).ok_or(Error::NoRegionForAddr(pt))?;
                     let entry = PageEntry::new(paddr.as_u64(), false, flags);
-                    write_at(table, vaddr.p1_index(), entry)?;
+                    arena.write(pt, vaddr.p1_index(), entry)?;
                     paddr += Page4KiB::ALIGNMENT;
                     vaddr += Page4KiB::ALIGNMENT;
                 }
@@ -395,8 +594,99 @@ fn write_idx(
     }
 
     if modified {
-        write_at(table, idx, entry)?;
+        arena.write(addr_table, idx, entry)?;
     }
 
     Ok(PhysAddr::new(entry.addr()))
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn cached_tables() {
+        let allocator = Allocator::default();
+        let base: PhysAddr = PhysAddr::new(0x40_0000);
+        let entries = [LayoutTableEntry::empty()
+            .set_paddr(base)
+            .set_vaddr(base.as_virt_addr())
+            .set_len(0x1000)
+            .set_flags(Flags::PRESENT | Flags::DATA_WRITE)];
+        let pml4 = PhysAddr::new(0x1000_0000);
+        let (initial, on_demand) = (NonZeroUsize::new(4).unwrap(), NonZeroUsize::new(2).unwrap());
+        let content = |regions: &[Region<ReadWrite>]| {
+            regions
+                .iter()
+                .map(|r| (r.addr(), r.as_ref().to_vec()))
+                .collect::<Vec<_>>()
+        };
+
+        let (fresh, fresh_state) =
+            setup(&allocator, &entries, pml4, initial, on_demand, true).unwrap();
+        for _ in 0..2 {
+            let (cached, state) =
+                setup_cached(&allocator, &entries, pml4, initial, on_demand, true).unwrap();
+            assert_eq!(content(&cached), content(&fresh));
+            assert_eq!(state.next_addr, fresh_state.next_addr);
+
+            // every table page points into the new regions
+            for (addr, page) in state.pages.iter() {
+                let region = cached
+                    .iter()
+                    .find(|r| (r.addr()..r.addr() + r.capacity().get() as u64).contains(addr))
+                    .unwrap();
+                let offset = (*addr - region.addr()) as usize;
+                assert_eq!(page.as_ptr().cast_const(), unsafe {
+                    region.as_ptr().add(offset)
+                });
+            }
+        }
+    }
+
+    #[test]
+    fn failed_extend_keeps_state() {
+        let allocator = Allocator::default();
+        let entry = |base: u64, len: u32| {
+            LayoutTableEntry::empty()
+                .set_paddr(PhysAddr::new(base))
+                .set_vaddr(PhysAddr::new(base).as_virt_addr())
+                .set_len(len)
+                .set_flags(Flags::PRESENT | Flags::DATA_WRITE)
+        };
+        let pml4 = PhysAddr::new(0x1000_0000);
+        let (initial, on_demand) = (NonZeroUsize::new(4).unwrap(), NonZeroUsize::new(2).unwrap());
+
+        // a single 2MiB leaf entry
+        let (regions, mut state) = setup(
+            &allocator,
+            &[entry(0x40_0000, 0x200)],
+            pml4,
+            initial,
+            on_demand,
+            false,
+        )
+        .unwrap();
+        let content = |regions: &[Region<ReadWrite>]| {
+            regions
+                .iter()
+                .map(|r| r.as_ref().to_vec())
+                .collect::<Vec<_>>()
+        };
+        let before = content(&regions);
+        let (next_addr, pages) = (state.next_addr, state.pages.clone());
+
+        // the first entry is mapped into new tables, before the second overlaps the huge page
+        let entries = [entry(0x8000_0000, 1), entry(0x40_1000, 1)];
+        let err = extend(&allocator, &mut state, &entries, pml4, on_demand).unwrap_err();
+        assert!(matches!(err, Error::Overlapping(_)), "{}", err);
+        assert_eq!(content(&regions), before);
+        assert_eq!(state.next_addr, next_addr);
+        assert_eq!(state.pages, pages);
+
+        // the reverted structure can still be extended
+        let (tables, _) = extend(&allocator, &mut state, &entries[..1], pml4, on_demand).unwrap();
+        assert!(!tables.is_empty());
+        assert_ne!(content(&regions), before);
+    }
+}
@@ -1,6 +1,8 @@
-use crate::linker::compute_signature;
 use crate::linker::hypercall;
 use crate::linker::upcall;
+use crate::linker::{
+    MissingAction, MissingHypercallHook, SignatureDisplay, SignatureNames, compute_signature,
+};
 use bmvm_common::error::ExitCode;
 use bmvm_common::mem;
 use bmvm_common::registry::Params;
@@ -12,11 +14,13 @@ type Result<T> = std::result::Result<T, Error>;
 #[derive(Debug, thiserror::Error)]
 pub enum Error {
     #[error("Guest tried to call an unknown function: {0}")]
-    UnknownFunction(Signature),
+    UnknownFunction(SignatureDisplay),
     #[error("Guest tried to call an unliked upcall: {0}")]
-    UnlikedUpcall(Signature),
+    UnlikedUpcall(SignatureDisplay),
     #[error("Hypercall threw an error: {0}")]
     HypercallExec(ExitCode),
+    #[error("Guest called unresolved function {0}, aborting with: {1}")]
+    UnresolvedFunction(SignatureDisplay, ExitCode),
     #[error("Unable to pass arguments to guest: {0}")]
     UpcallParam(mem::Error),
     #[error("Upcall execution threw an error: {0}")]
@@ -26,6 +30,8 @@ pub enum Error {
 #[derive(Debug)]
 pub(super) struct Hypercalls {
     inner: Vec<hypercall::Function>,
+    on_missing: Option<MissingHypercallHook>,
+    names: SignatureNames,
 }
 
 impl Default for Hypercalls {
@@ -35,22 +41,49 @@ impl Default for Hypercalls {
 }
 
 impl Hypercalls {
-    pub fn try_execute(&self, sig: Signature, transport: Transport) -> Result<Transport> {
-        let idx = match self.inner.binary_search_by_key(&sig, |f| f.func.sig) {
-            Ok(idx) => idx,
-            Err(_) => return Err(Error::UnknownFunction(sig)),
-        };
+    pub fn with_missing_hook(mut self, hook: Option<MissingHypercallHook>) -> Self {
+        self.on_missing = hook;
+        self
+    }
+
+    pub fn with_names(mut self, names: SignatureNames) -> Self {
+        self.names = names;
+        self
+    }
 
-        let func = self.inner[idx].call;
-        let output = func(transport).map_err(Error::HypercallExec)?;
-        Ok(output)
+    /// Render the signature with the function it belongs to, if known
+    pub fn display(&self, sig: Signature) -> SignatureDisplay {
+        self.names.display(sig)
+    }
+
+    pub fn find(&self, sig: Signature) -> Result<hypercall::WrapperFunc> {
+        match self.inner.binary_search_by_key(&sig, |f| f.func.sig) {
+            Ok(idx) => Ok(self.inner[idx].call),
+            Err(_) => Err(Error::UnknownFunction(self.display(sig))),
+        }
+    }
+
+    /// Consult the configured hook on how to react to an unresolved hypercall. Returns the
+    /// transport to resume the guest with.
+    pub fn resolve_missing(&self, sig: Signature) -> Result<Transport> {
+        let display = self.display(sig);
+        log::warn!("Guest called unresolved hypercall {}", display);
+        match self.on_missing.map(|hook| hook(sig)) {
+            Some(MissingAction::ReturnZero) => Ok(Transport::new(0, 0)),
+            Some(MissingAction::Abort(code)) => Err(Error::UnresolvedFunction(display, code)),
+            None => Err(Error::UnknownFunction(display)),
+        }
     }
 }
 
 impl From<Vec<hypercall::Function>> for Hypercalls {
     fn from(mut functions: Vec<hypercall::Function>) -> Self {
         functions.sort_by_key(|f| f.func.sig);
-        Self { inner: functions }
+        Self {
+            inner: functions,
+            on_missing: None,
+            names: SignatureNames::default(),
+        }
     }
 }
 
@@ -75,9 +108,13 @@ impl Upcalls {
         let sig: u64 = compute_signature::<P, R>(name);
         let func = match self.inner.get(&sig) {
             Some(idx) => idx,
-            None => return Err(Error::UnknownFunction(sig)),
+            None => {
+                let requested = upcall::Function::new::<P, R>(name);
+                return Err(Error::UnknownFunction((&requested.base).into()));
+            }
         };
-        func.ptr().ok_or_else(|| Error::UnlikedUpcall(sig))?;
+        func.ptr()
+            .ok_or_else(|| Error::UnlikedUpcall((&func.base).into()))?;
 
         Ok(func)
     }
@@ -0,0 +1,108 @@
+use nix::errno::Errno;
+use nix::sys::pthread::{Pthread, pthread_kill, pthread_self};
+use nix::sys::signal::{SaFlags, SigAction, SigHandler, SigSet, Signal, sigaction};
+use std::sync::OnceLock;
+use std::sync::mpsc::{RecvTimeoutError, Sender, channel};
+use std::thread::JoinHandle;
+use std::time::Duration;
+
+/// Signal used to kick the vcpu out of `KVM_RUN`
+const SAMPLE_SIGNAL: Signal = Signal::SIGUSR2;
+
+static INSTALLED: OnceLock<Result<(), Errno>> = OnceLock::new();
+
+extern "C" fn interrupt(_: nix::libc::c_int) {}
+
+/// Install a no-op handler, so the signal interrupts `KVM_RUN` with `EINTR` instead of terminating
+/// the process. `SA_RESTART` does not cover every syscall, so the signal is additionally blocked
+/// outside of `KVM_RUN` via `Blocked`.
+fn install() -> Result<(), Errno> {
+    *INSTALLED.get_or_init(|| {
+        let action = SigAction::new(
+            SigHandler::Handler(interrupt),
+            SaFlags::SA_RESTART,
+            SigSet::empty(),
+        );
+        unsafe { sigaction(SAMPLE_SIGNAL, &action) }.map(|_| ())
+    })
+}
+
+/// Blocks the signal on the current thread until dropped, so it never interrupts the syscalls
+/// issued by the host or a hypercall while handling an exit. A signal sent in the meantime stays
+/// pending until the vCPU unblocks it again during `KVM_RUN`.
+pub(crate) struct Blocked {
+    unblock: bool,
+}
+
+impl Blocked {
+    /// Block the signal and return the signal mask to apply to the vCPU, which is the current one
+    /// of the thread without the signal.
+    pub(crate) fn new() -> Result<(Self, SigSet), Errno> {
+        let mut run = SigSet::thread_get_mask()?;
+        let unblock = !run.contains(SAMPLE_SIGNAL);
+        run.remove(SAMPLE_SIGNAL);
+        SigSet::from(SAMPLE_SIGNAL).thread_block()?;
+        Ok((Self { unblock }, run))
+    }
+}
+
+impl Drop for Blocked {
+    fn drop(&mut self) {
+        // a signal still pending is delivered to the no-op handler
+        if self.unblock {
+            let _ = SigSet::from(SAMPLE_SIGNAL).thread_unblock();
+        }
+    }
+}
+
+/// Periodically interrupts the thread which started it, until dropped.
+pub(crate) struct Sampler {
+    stop: Option<Sender<()>>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl Sampler {
+    pub(crate) fn start(interval: Duration) -> Result<Self, Errno> {
+        Self::spawn(interval, Some(interval))
+    }
+
+    /// Interrupt the thread once `after` elapsed. The signal stays pending while the host handles
+    /// an exit, so it interrupts the next `KVM_RUN` at the latest.
+    pub(crate) fn deadline(after: Duration) -> Result<Self, Errno> {
+        Self::spawn(after, None)
+    }
+
+    fn spawn(first: Duration, interval: Option<Duration>) -> Result<Self, Errno> {
+        install()?;
+
+        let target: Pthread = pthread_self();
+        let (stop, stopped) = channel::<()>();
+        let thread = std::thread::spawn(move || {
+            let mut wait = first;
+            while let Err(RecvTimeoutError::Timeout) = stopped.recv_timeout(wait) {
+                if pthread_kill(target, SAMPLE_SIGNAL).is_err() {
+                    return;
+                }
+                match interval {
+                    Some(interval) => wait = interval,
+                    None => return,
+                }
+            }
+        });
+
+        Ok(Self {
+            stop: Some(stop),
+            thread: Some(thread),
+        })
+    }
+}
+
+impl Drop for Sampler {
+    fn drop(&mut self) {
+        // disconnecting the channel stops the thread, join it so no signal arrives afterward
+        drop(self.stop.take());
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
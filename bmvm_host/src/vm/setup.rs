@@ -1,3 +1,4 @@
+use crate::vm::{CpuidConfig, CpuidEntry};
 use bmvm_common::mem::{AddrSpace, Align, DefaultAddrSpace, DefaultAlign, align_ceil};
 use kvm_bindings::{CpuId, KVM_MAX_CPUID_ENTRIES};
 use kvm_ioctls::Kvm;
@@ -31,7 +32,7 @@ pub(super) const GDT_FLAGS_CODE: u8 = 0b1010;
 pub(super) const GDT_ACCESS_DATA: u8 = 0x93;
 pub(super) const GDT_FLAGS_DATA: u8 = 0b1100;
 
-pub(crate) fn cpuid(kvm: &Kvm) -> Result<CpuId> {
+pub(crate) fn cpuid(kvm: &Kvm, config: &CpuidConfig) -> Result<CpuId> {
     // setup vcpu cpuid
     let mut cpuid = kvm
         .get_supported_cpuid(KVM_MAX_CPUID_ENTRIES)
@@ -73,8 +74,18 @@ pub(crate) fn cpuid(kvm: &Kvm) -> Result<CpuId> {
                 entry.ecx |= 1 << 26;
             }
 
-            _ => continue,
+            _ => {}
         }
+
+        // apply the user provided adjustments last
+        let mut regs = CpuidEntry {
+            eax: entry.eax,
+            ebx: entry.ebx,
+            ecx: entry.ecx,
+            edx: entry.edx,
+        };
+        config.apply(entry.function, entry.index, &mut regs);
+        (entry.eax, entry.ebx, entry.ecx, entry.edx) = (regs.eax, regs.ebx, regs.ecx, regs.edx);
     }
 
     Ok(cpuid)
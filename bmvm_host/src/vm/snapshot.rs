@@ -0,0 +1,174 @@
+#[cfg(all(target_os = "linux", feature = "kvm"))]
+use crate::vm::Registers;
+use bmvm_common::mem::{Align, DefaultAlign, PhysAddr};
+#[cfg(all(target_os = "linux", feature = "kvm"))]
+use std::sync::atomic::{AtomicU64, Ordering};
+
+/// Granularity of the KVM dirty log and of incremental snapshots
+pub(crate) const PAGE_SIZE: usize = DefaultAlign::ALIGNMENT as usize;
+
+/// Source of the snapshot identifiers, unique per process so snapshots of different modules can
+/// not be chained by accident
+#[cfg(all(target_os = "linux", feature = "kvm"))]
+static NEXT_ID: AtomicU64 = AtomicU64::new(0);
+
+#[cfg(all(target_os = "linux", feature = "kvm"))]
+#[derive(thiserror::Error, Debug)]
+pub enum Error {
+    #[error("Snapshots can only be taken while the guest is idle")]
+    NotIdle,
+    #[error("Snapshots can only be restored after the guest completed its setup")]
+    NotBooted,
+    #[error("Incremental snapshots require dirty page logging, see ConfigBuilder::dirty_log")]
+    DirtyLogDisabled,
+    #[error("Base is not the most recent snapshot of this module")]
+    StaleBase,
+    #[error("Restoring requires a full snapshot as base")]
+    IncrementalBase,
+    #[error("Delta {0} is not based on the preceding snapshot")]
+    BrokenChain(usize),
+    #[error("Memory layout of the guest differs from the snapshot")]
+    LayoutMismatch,
+}
+
+/// Guest memory and registers captured via `Module::snapshot` or `Module::snapshot_incremental`.
+///
+/// A full snapshot contains all writable regions, a delta only the pages modified since the
+/// snapshot it is based on. Restoring requires a full snapshot followed by an unbroken chain of
+/// deltas, see `Module::restore`.
+#[derive(Debug, Clone)]
+pub struct Snapshot {
+    #[cfg(all(target_os = "linux", feature = "kvm"))]
+    pub(crate) id: u64,
+    /// Snapshot this one is the delta of, `None` for a full snapshot
+    pub(crate) parent: Option<u64>,
+    #[cfg(all(target_os = "linux", feature = "kvm"))]
+    pub(crate) registers: Registers,
+    /// Address and capacity of every region mapped when the snapshot was taken
+    #[cfg(all(target_os = "linux", feature = "kvm"))]
+    pub(crate) layout: Vec<(PhysAddr, usize)>,
+    /// Captured memory, either whole regions or single pages
+    pub(crate) chunks: Vec<(PhysAddr, Box<[u8]>)>,
+}
+
+impl Snapshot {
+    #[cfg(all(target_os = "linux", feature = "kvm"))]
+    pub(crate) fn new(
+        parent: Option<u64>,
+        registers: Registers,
+        layout: Vec<(PhysAddr, usize)>,
+        chunks: Vec<(PhysAddr, Box<[u8]>)>,
+    ) -> Self {
+        Self {
+            id: NEXT_ID.fetch_add(1, Ordering::Relaxed),
+            parent,
+            registers,
+            layout,
+            chunks,
+        }
+    }
+
+    /// Check if the snapshot is a delta, which only contains the pages modified since its base
+    pub fn is_incremental(&self) -> bool {
+        self.parent.is_some()
+    }
+
+    /// Number of captured bytes
+    pub fn size(&self) -> usize {
+        self.chunks.iter().map(|(_, data)| data.len()).sum()
+    }
+
+    /// Number of captured 4KiB pages
+    pub fn pages(&self) -> usize {
+        self.size() / PAGE_SIZE
+    }
+
+    /// Verify `deltas` form a chain starting at the full snapshot `base`
+    #[cfg(all(target_os = "linux", feature = "kvm"))]
+    pub(crate) fn check_chain(base: &Snapshot, deltas: &[Snapshot]) -> Result<(), Error> {
+        if base.is_incremental() {
+            return Err(Error::IncrementalBase);
+        }
+
+        let mut previous = base.id;
+        for (i, delta) in deltas.iter().enumerate() {
+            if delta.parent != Some(previous) {
+                return Err(Error::BrokenChain(i));
+            }
+            previous = delta.id;
+        }
+        Ok(())
+    }
+}
+
+/// Indices of the pages marked in a KVM dirty log bitmap
+#[cfg(all(target_os = "linux", feature = "kvm"))]
+pub(crate) fn dirty_pages(bitmap: &[u64]) -> impl Iterator<Item = usize> + '_ {
+    bitmap.iter().enumerate().flat_map(|(word, bits)| {
+        (0..u64::BITS as usize)
+            .filter(move |bit| bits & (1 << bit) != 0)
+            .map(move |bit| word * u64::BITS as usize + bit)
+    })
+}
+
+/// Indices of the pages differing between `current` and `previous`
+#[cfg(all(target_os = "linux", feature = "kvm"))]
+pub(crate) fn changed_pages<'a>(
+    current: &'a [u8],
+    previous: &'a [u8],
+) -> impl Iterator<Item = usize> + 'a {
+    current
+        .chunks(PAGE_SIZE)
+        .zip(previous.chunks(PAGE_SIZE))
+        .enumerate()
+        .filter(|(_, (a, b))| a != b)
+        .map(|(i, _)| i)
+}
+
+#[cfg(all(test, target_os = "linux", feature = "kvm"))]
+mod test {
+    use super::*;
+
+    fn snapshot(parent: Option<u64>) -> Snapshot {
+        Snapshot::new(parent, Registers::default(), Vec::new(), Vec::new())
+    }
+
+    #[test]
+    fn dirty_bitmap() {
+        let pages = dirty_pages(&[0b1001, 0, 1 << 63]).collect::<Vec<_>>();
+        assert_eq!(pages, [0, 3, 191]);
+        assert_eq!(dirty_pages(&[]).count(), 0);
+    }
+
+    #[test]
+    fn changed() {
+        let previous = vec![0u8; 3 * PAGE_SIZE];
+        let mut current = previous.clone();
+        current[PAGE_SIZE + 17] = 1;
+        assert_eq!(changed_pages(&current, &previous).collect::<Vec<_>>(), [1]);
+        assert_eq!(changed_pages(&previous, &previous).count(), 0);
+    }
+
+    #[test]
+    fn chain() {
+        let base = snapshot(None);
+        let first = snapshot(Some(base.id));
+        let second = snapshot(Some(first.id));
+        let other = snapshot(Some(base.id));
+
+        assert!(Snapshot::check_chain(&base, &[]).is_ok());
+        assert!(Snapshot::check_chain(&base, &[first.clone(), second.clone()]).is_ok());
+        assert!(matches!(
+            Snapshot::check_chain(&base, &[second.clone()]),
+            Err(Error::BrokenChain(0))
+        ));
+        assert!(matches!(
+            Snapshot::check_chain(&base, &[first.clone(), other]),
+            Err(Error::BrokenChain(1))
+        ));
+        assert!(matches!(
+            Snapshot::check_chain(&first, &[second]),
+            Err(Error::IncrementalBase)
+        ));
+    }
+}
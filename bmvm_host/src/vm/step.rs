@@ -0,0 +1,85 @@
+use bmvm_common::error::ExitCode;
+
+/// General purpose registers of the guest vCPU, see `Module::registers`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Registers {
+    pub rax: u64,
+    pub rbx: u64,
+    pub rcx: u64,
+    pub rdx: u64,
+    pub rsi: u64,
+    pub rdi: u64,
+    pub rsp: u64,
+    pub rbp: u64,
+    pub r8: u64,
+    pub r9: u64,
+    pub r10: u64,
+    pub r11: u64,
+    pub r12: u64,
+    pub r13: u64,
+    pub r14: u64,
+    pub r15: u64,
+    pub rip: u64,
+    pub rflags: u64,
+}
+
+/// Result of executing a single guest instruction via `Module::step`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StepOutcome {
+    /// The instruction was executed, containing the registers afterward. Hypercalls triggered by
+    /// the instruction are executed by the host before.
+    Stepped(Registers),
+    /// The instruction wrote an exit code and optional detail to the exit port.
+    Exited(ExitCode, Option<u64>),
+}
+
+#[cfg(all(target_os = "linux", feature = "kvm"))]
+impl From<&kvm_bindings::kvm_regs> for Registers {
+    fn from(regs: &kvm_bindings::kvm_regs) -> Self {
+        Self {
+            rax: regs.rax,
+            rbx: regs.rbx,
+            rcx: regs.rcx,
+            rdx: regs.rdx,
+            rsi: regs.rsi,
+            rdi: regs.rdi,
+            rsp: regs.rsp,
+            rbp: regs.rbp,
+            r8: regs.r8,
+            r9: regs.r9,
+            r10: regs.r10,
+            r11: regs.r11,
+            r12: regs.r12,
+            r13: regs.r13,
+            r14: regs.r14,
+            r15: regs.r15,
+            rip: regs.rip,
+            rflags: regs.rflags,
+        }
+    }
+}
+
+#[cfg(all(target_os = "linux", feature = "kvm"))]
+impl Registers {
+    /// Overwrite the general purpose registers of `regs`
+    pub(crate) fn apply(&self, regs: &mut kvm_bindings::kvm_regs) {
+        regs.rax = self.rax;
+        regs.rbx = self.rbx;
+        regs.rcx = self.rcx;
+        regs.rdx = self.rdx;
+        regs.rsi = self.rsi;
+        regs.rdi = self.rdi;
+        regs.rsp = self.rsp;
+        regs.rbp = self.rbp;
+        regs.r8 = self.r8;
+        regs.r9 = self.r9;
+        regs.r10 = self.r10;
+        regs.r11 = self.r11;
+        regs.r12 = self.r12;
+        regs.r13 = self.r13;
+        regs.r14 = self.r14;
+        regs.r15 = self.r15;
+        regs.rip = self.rip;
+        regs.rflags = self.rflags;
+    }
+}
@@ -0,0 +1,244 @@
+//! Stand-in for the KVM backed VM on targets or builds without KVM support. Executables can still
+//! be parsed and linked, but every attempt to create a VM fails with `Error::Unsupported`.
+
+use crate::elf::ExecBundle;
+use crate::linker::{MissingHypercallHook, SignatureNames, hypercall, upcall};
+#[cfg(feature = "test-fault-injection")]
+use crate::vm::FaultKind;
+use crate::vm::{Config, CpuidEntry, ExitCounts, Registers, Snapshot, StepOutcome};
+use crate::{ErrorCategory, Upcall, alloc};
+use bmvm_common::boot::Phase;
+use bmvm_common::error::ExitCode;
+use bmvm_common::mem::RegionStat;
+use bmvm_common::registry::Params;
+use bmvm_common::vmi::{FnPtr, ForeignShareable, Transport};
+use rustc_hash::FxHashMap;
+use std::collections::HashMap;
+use std::io::Write;
+use std::time::Duration;
+
+type Result<T> = core::result::Result<T, Error>;
+
+#[derive(thiserror::Error, Debug)]
+pub enum Error {
+    #[error("KVM support is not available in this build")]
+    Unsupported,
+    #[error("Guest callbacks can only be issued during hypercall execution")]
+    NoHypercallContext,
+    #[error("Guest stack overflow (rsp: {0:#x})")]
+    StackOverflow(u64),
+    #[error(
+        "Guest exited with unhandled exit code: {0}{detail}",
+        detail = .1.map(|d| format!(" (detail: {d:#x})")).unwrap_or_default()
+    )]
+    UnhandledHalt(ExitCode, Option<u64>),
+    #[error("Error during upcall preparation: {0}")]
+    UpcallExec(bmvm_common::mem::Error),
+    #[error("Error during upcall return: {0}")]
+    UpcallReturn(ExitCode),
+    #[error("Allocator error: {0}")]
+    Allocator(#[from] alloc::Error),
+}
+
+impl Error {
+    pub fn category(&self) -> ErrorCategory {
+        match self {
+            Error::Unsupported | Error::Allocator(_) => ErrorCategory::Environment,
+            Error::UpcallExec(_) => ErrorCategory::Input,
+            Error::StackOverflow(_) | Error::UnhandledHalt(..) | Error::UpcallReturn(_) => {
+                ErrorCategory::Guest
+            }
+            Error::NoHypercallContext => ErrorCategory::Internal,
+        }
+    }
+
+    pub(crate) fn is_transport_too_large(&self) -> bool {
+        matches!(self, Error::UpcallReturn(ExitCode::TransportTooLarge))
+    }
+}
+
+/// Never created, as `Vm::new` always fails
+#[derive(Debug)]
+pub enum Vm {}
+
+impl Vm {
+    pub(crate) fn check_config(_cfg: &Config, _exec: Option<&ExecBundle>) -> Vec<Error> {
+        Vec::new()
+    }
+
+    pub(crate) fn new<CONFIG: Into<Config>>(_cfg: CONFIG) -> Result<Self> {
+        Err(Error::Unsupported)
+    }
+
+    pub(crate) fn load_exec(&mut self, _exec: &mut ExecBundle) -> Result<()> {
+        Err(Error::Unsupported)
+    }
+
+    pub(crate) fn link(
+        &mut self,
+        _hypercalls: Vec<hypercall::Function>,
+        _upcalls: Vec<upcall::Function>,
+        _on_missing: Option<MissingHypercallHook>,
+        _names: SignatureNames,
+    ) {
+    }
+
+    pub(crate) fn max_transport_bytes(&self) -> usize {
+        usize::MAX
+    }
+
+    pub(crate) fn run(&mut self) -> Result<()> {
+        Err(Error::Unsupported)
+    }
+
+    pub(crate) fn boot(&mut self) -> Result<()> {
+        Err(Error::Unsupported)
+    }
+
+    pub(crate) fn is_ready(&self) -> bool {
+        false
+    }
+
+    pub fn find_upcall<P, R>(&mut self, _name: &'static str) -> Result<&upcall::Function>
+    where
+        P: Params,
+        R: ForeignShareable,
+    {
+        Err(Error::Unsupported)
+    }
+
+    pub fn upcall_exec_setup<P, R>(&mut self, _upcall: &Upcall<P, R>, _params: P) -> Result<()>
+    where
+        P: Params,
+        R: ForeignShareable,
+    {
+        Err(Error::Unsupported)
+    }
+
+    pub(crate) fn upcall_exec_setup_raw(
+        &mut self,
+        _ptr: FnPtr,
+        _transport: Transport,
+    ) -> Result<()> {
+        Err(Error::Unsupported)
+    }
+
+    pub(crate) fn upcall_result_raw(&mut self) -> Result<Transport> {
+        Err(Error::Unsupported)
+    }
+
+    pub(crate) fn take_exit_value(&mut self) -> Option<Transport> {
+        None
+    }
+
+    pub fn upcall_result<R>(&mut self) -> Result<R>
+    where
+        R: ForeignShareable,
+    {
+        Err(Error::Unsupported)
+    }
+
+    pub(crate) fn is_yielded(&self) -> bool {
+        false
+    }
+
+    pub(crate) fn resume(&mut self) -> Result<()> {
+        Err(Error::Unsupported)
+    }
+
+    pub(crate) fn stream_setup_raw(&mut self, _ptr: FnPtr, _transport: Transport) -> Result<()> {
+        Err(Error::Unsupported)
+    }
+
+    pub(crate) fn stream_next(&mut self) -> Result<Option<Vec<u8>>> {
+        Err(Error::Unsupported)
+    }
+
+    pub(crate) fn stream_cancel(&mut self) -> Result<()> {
+        Err(Error::Unsupported)
+    }
+
+    pub(crate) fn parallel_upcall_raw(
+        &mut self,
+        _calls: Vec<(FnPtr, Transport)>,
+    ) -> Result<Vec<Transport>> {
+        Err(Error::Unsupported)
+    }
+
+    pub(crate) fn callback_exec<P, R>(&mut self, _name: &'static str, _params: P) -> Result<R>
+    where
+        P: Params,
+        R: ForeignShareable,
+    {
+        Err(Error::Unsupported)
+    }
+
+    pub(crate) fn read_virt(&self, _addr: u64, _buf: &mut [u8]) -> Result<()> {
+        Err(Error::Unsupported)
+    }
+
+    pub(crate) fn write_virt(&mut self, _addr: u64, _data: &[u8]) -> Result<()> {
+        Err(Error::Unsupported)
+    }
+
+    pub(crate) fn cpuid(&self, _function: u32, _index: u32) -> Result<Option<CpuidEntry>> {
+        Err(Error::Unsupported)
+    }
+
+    pub(crate) fn step(&mut self) -> Result<StepOutcome> {
+        Err(Error::Unsupported)
+    }
+
+    pub(crate) fn registers(&mut self) -> Result<Registers> {
+        Err(Error::Unsupported)
+    }
+
+    pub(crate) fn set_registers(&mut self, _registers: &Registers) -> Result<()> {
+        Err(Error::Unsupported)
+    }
+
+    pub(crate) fn stack_high_water(&self) -> Result<usize> {
+        Err(Error::Unsupported)
+    }
+
+    pub(crate) fn rip_samples(&self) -> &FxHashMap<u64, u64> {
+        match *self {}
+    }
+
+    pub(crate) fn exits(&self) -> ExitCounts {
+        ExitCounts::default()
+    }
+
+    pub(crate) fn boot_phases(&self) -> &[(Phase, Duration)] {
+        &[]
+    }
+
+    pub(crate) fn metrics(&self) -> HashMap<String, u64> {
+        HashMap::new()
+    }
+
+    #[cfg(feature = "test-fault-injection")]
+    pub(crate) fn inject_fault(&mut self, _fault: FaultKind) -> Result<()> {
+        Err(Error::Unsupported)
+    }
+
+    pub(crate) fn region_stats(&self) -> Vec<RegionStat> {
+        Vec::new()
+    }
+
+    pub(crate) fn snapshot(&mut self) -> Result<Snapshot> {
+        Err(Error::Unsupported)
+    }
+
+    pub(crate) fn snapshot_incremental(&mut self, _base: &Snapshot) -> Result<Snapshot> {
+        Err(Error::Unsupported)
+    }
+
+    pub(crate) fn restore(&mut self, _base: &Snapshot, _deltas: &[Snapshot]) -> Result<()> {
+        Err(Error::Unsupported)
+    }
+
+    pub(crate) fn dump_memory<W: Write>(&self, _w: &mut W) -> Result<()> {
+        Err(Error::Unsupported)
+    }
+}
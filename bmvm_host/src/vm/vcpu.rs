@@ -2,10 +2,12 @@ use crate::utils::Dirty;
 use crate::vm::setup::{GDT_BASE, GDT_ENTRY_SIZE, GDT_LIMIT, IDT_ENTRY_SIZE};
 use bmvm_common::mem::{PhysAddr, VirtAddr};
 use kvm_bindings::{
-    __u16, CpuId, KVM_GUESTDBG_ENABLE, KVM_GUESTDBG_SINGLESTEP, kvm_dtable, kvm_guest_debug,
-    kvm_guest_debug_arch, kvm_regs, kvm_sregs,
+    __u16, CpuId, KVM_GUESTDBG_ENABLE, KVM_GUESTDBG_SINGLESTEP, KVM_MAX_CPUID_ENTRIES, kvm_dtable,
+    kvm_guest_debug, kvm_guest_debug_arch, kvm_regs, kvm_sregs,
 };
 use kvm_ioctls::{VcpuExit, VcpuFd, VmFd};
+use nix::sys::signal::SigSet;
+use std::os::fd::AsRawFd;
 
 #[derive(Debug, thiserror::Error)]
 pub enum Error {
@@ -23,10 +25,21 @@ pub enum Error {
     SetGuestDebug(kvm_ioctls::Error),
     #[error("Failed to set cpu id: {0}")]
     SetCpuID(kvm_ioctls::Error),
+    #[error("Failed to get cpu id: {0}")]
+    GetCpuID(kvm_ioctls::Error),
+    #[error("Failed to set signal mask: {0}")]
+    SetSignalMask(kvm_ioctls::Error),
     #[error("Error during execution: {0}")]
     Run(kvm_ioctls::Error),
 }
 
+impl Error {
+    /// Check if the execution was interrupted by a signal before the guest exited
+    pub(crate) fn is_interrupted(&self) -> bool {
+        matches!(self, Error::Run(e) if e.errno() == nix::libc::EINTR)
+    }
+}
+
 type Result<T> = core::result::Result<T, Error>;
 
 /// CR0: Protection Enabled
@@ -48,6 +61,16 @@ const CR4_PAE: u64 = 0x1 << 5;
 /// CR4: Page-Global Enable
 const CR4_PGE: u64 = 0x1 << 7;
 
+/// CR2 while no page fault occurred. The address is not canonical, so a page fault never reports
+/// it, while other exceptions leave CR2 untouched.
+const CR2_NO_PAGE_FAULT: u64 = 0x8000_0000_0000_0000;
+
+/// `_IOW(KVMIO, 0x8b, struct kvm_signal_mask)`, not exposed by `kvm_ioctls`
+const KVM_SET_SIGNAL_MASK: nix::libc::c_ulong = 0x4004_ae8b;
+
+/// Size of the kernel signal set expected by `KVM_SET_SIGNAL_MASK`
+const KERNEL_SIGSET_SIZE: usize = 8;
+
 /// Long Mode Enabled
 const EFER_LME: u64 = 0x1 << 8;
 /// Long Mode Active
@@ -72,7 +95,14 @@ pub struct Setup {
     pub paging: PhysAddr,
     pub stack: VirtAddr,
     pub entry: VirtAddr,
+    /// Address of the layout table, passed to the guest entry in `rdi`
+    pub layout_table: VirtAddr,
+    /// Address and length of the guest arguments blob, passed in `rsi` and `rdx`. Both are zero if
+    /// no arguments are provided.
+    pub args: (VirtAddr, u64),
     pub cpu_id: CpuId,
+    /// Thread pointer of the guest, loaded into the FS base
+    pub tls: Option<VirtAddr>,
 }
 
 #[derive(Debug)]
@@ -109,6 +139,16 @@ impl Vcpu {
         Ok(())
     }
 
+    #[cfg(feature = "test-fault-injection")]
+    pub fn mutate_sregs<M>(&mut self, m: M) -> Result<()>
+    where
+        M: FnOnce(&mut kvm_sregs) -> bool,
+    {
+        self.refresh_regs()?;
+        self.sregs.mutate(m);
+        Ok(())
+    }
+
     pub fn set_regs(&mut self, regs: kvm_regs) {
         self.regs.set(regs)
     }
@@ -118,6 +158,13 @@ impl Vcpu {
         Ok(*self.regs.get())
     }
 
+    /// CPUID leaves as currently reported to the guest
+    pub fn get_cpuid(&self) -> Result<CpuId> {
+        self.inner
+            .get_cpuid2(KVM_MAX_CPUID_ENTRIES)
+            .map_err(Error::GetCpuID)
+    }
+
     pub fn read_regs(&mut self) -> Result<&kvm_regs> {
         self.refresh_regs()?;
         Ok(self.regs.get())
@@ -128,6 +175,23 @@ impl Vcpu {
         Ok((self.regs.get(), self.sregs.get()))
     }
 
+    /// Fault address of the page fault raised since the last `clear_page_fault`, if any
+    pub fn page_fault(&mut self) -> Result<Option<u64>> {
+        self.refresh_regs()?;
+        let cr2 = self.sregs.get().cr2;
+        Ok((cr2 != CR2_NO_PAGE_FAULT).then_some(cr2))
+    }
+
+    /// Forget the most recent page fault, see `page_fault`
+    pub fn clear_page_fault(&mut self) -> Result<()> {
+        self.refresh_regs()?;
+        self.sregs.mutate(|sregs| {
+            sregs.cr2 = CR2_NO_PAGE_FAULT;
+            true
+        });
+        Ok(())
+    }
+
     fn refresh_regs(&mut self) -> Result<()> {
         if !self.recent_exec {
             return Ok(());
@@ -178,8 +242,11 @@ impl Vcpu {
         self.setup_gdt(&setup.gdt)?;
         self.setup_idt(&setup.idt)?;
         self.setup_paging(setup.paging)?;
-        self.setup_execution(setup.stack, setup.entry)?;
-        Ok(())
+        self.setup_execution(setup)?;
+        if let Some(tp) = setup.tls {
+            self.setup_tls(tp)?;
+        }
+        self.clear_page_fault()
     }
 
     /// set up the CPUID functions supported by the vcpu in guest mode
@@ -280,8 +347,22 @@ impl Vcpu {
         Ok(())
     }
 
+    /// set up the FS segment, whose base is the thread pointer used for thread-local accesses
+    fn setup_tls(&mut self, tp: VirtAddr) -> Result<()> {
+        self.refresh_regs()?;
+
+        self.sregs.mutate(|sregs| {
+            sregs.fs = sregs.ds;
+            sregs.fs.base = tp.as_u64();
+            true
+        });
+
+        Ok(())
+    }
+
     /// set up other execution relevant registers besides the structures required for long mode
-    fn setup_execution(&mut self, stack: VirtAddr, entry: VirtAddr) -> Result<()> {
+    fn setup_execution(&mut self, setup: &Setup) -> Result<()> {
+        let (stack, entry) = (setup.stack, setup.entry);
         log::debug!(
             "Setting up execution - Stack: {:x} ({}) Entry: {:x}",
             stack,
@@ -295,6 +376,10 @@ impl Vcpu {
             regs.rflags = 1 << 1;
             regs.rip = entry.as_u64();
             regs.rsp = stack.as_u64();
+            // entry arguments, see `bmvm_common::BMVM_MEM_LAYOUT_TABLE`
+            regs.rdi = setup.layout_table.as_u64();
+            regs.rsi = setup.args.0.as_u64();
+            regs.rdx = setup.args.1;
             true
         });
 
@@ -318,11 +403,61 @@ impl Vcpu {
             .set_guest_debug(&dbg)
             .map_err(Error::SetGuestDebug)?;
 
-        self.regs.mutate(|regs| {
+        self.mutate_regs(|regs| {
             regs.rflags |= 1 << 8;
             true
-        });
+        })
+    }
+
+    /// Disable single stepping, the guest runs until the next regular exit.
+    pub fn disable_single_step(&mut self) -> Result<()> {
+        let dbg = kvm_guest_debug {
+            control: 0,
+            pad: 0,
+            arch: kvm_guest_debug_arch { debugreg: [0; 8] },
+        };
+        self.inner
+            .set_guest_debug(&dbg)
+            .map_err(Error::SetGuestDebug)?;
+
+        self.mutate_regs(|regs| {
+            regs.rflags &= !(1 << 8);
+            true
+        })
+    }
+
+    /// Replace the signal mask of the calling thread while it executes `KVM_RUN`, e.g. to deliver a
+    /// signal only while the guest runs. `None` restores the mask of the thread.
+    pub fn set_signal_mask(&mut self, mask: Option<&SigSet>) -> Result<()> {
+        #[repr(C)]
+        struct KvmSignalMask {
+            len: u32,
+            sigset: [u8; KERNEL_SIGSET_SIZE],
+        }
 
+        let arg = mask.map(|mask| {
+            let mut sigset = [0u8; KERNEL_SIGSET_SIZE];
+            // the kernel signal set is the prefix of the libc one
+            let raw: &nix::libc::sigset_t = mask.as_ref();
+            let bytes = unsafe {
+                core::slice::from_raw_parts(
+                    (raw as *const nix::libc::sigset_t).cast::<u8>(),
+                    KERNEL_SIGSET_SIZE,
+                )
+            };
+            sigset.copy_from_slice(bytes);
+            KvmSignalMask {
+                len: KERNEL_SIGSET_SIZE as u32,
+                sigset,
+            }
+        });
+        let ptr = arg
+            .as_ref()
+            .map_or(core::ptr::null(), |arg| arg as *const KvmSignalMask);
+        let ret = unsafe { nix::libc::ioctl(self.inner.as_raw_fd(), KVM_SET_SIGNAL_MASK, ptr) };
+        if ret < 0 {
+            return Err(Error::SetSignalMask(kvm_ioctls::Error::last()));
+        }
         Ok(())
     }
 
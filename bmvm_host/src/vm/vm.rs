@@ -1,26 +1,51 @@
-use crate::alloc::{Allocator, ReadWrite, Region, RegionCollection};
+use crate::alloc::{Allocator, ReadOnly, ReadWrite, Region, RegionCollection};
 use crate::elf::ExecBundle;
-use crate::linker::{hypercall, upcall};
+use crate::linker::{MissingHypercallHook, SignatureNames, hypercall, upcall};
+use crate::vm::paging::PagingState;
 use crate::vm::registry::{Hypercalls, Upcalls};
+use crate::vm::sampler::{Blocked, Sampler};
 use crate::vm::setup::{GDT_PAGE_REQUIRED, GDT_SIZE, IDT_PAGE_REQUIRED, IDT_SIZE};
+use crate::vm::snapshot::PAGE_SIZE;
 use crate::vm::vcpu::Vcpu;
-use crate::vm::{Config, paging, registry, setup, vcpu};
-use crate::{GUEST_PAGING_ADDR, GUEST_STACK_ADDR, GUEST_SYSTEM_ADDR, Upcall};
+use crate::vm::{
+    Config, CpuidEntry, ExitCounts, HltPolicy, Registers, Snapshot, StepOutcome, TransportKind,
+    context, paging, registry, setup, snapshot, vcpu,
+};
+#[cfg(feature = "test-fault-injection")]
+use crate::vm::{FaultKind, fault};
+use crate::{ErrorCategory, GUEST_STACK_GUARD_SIZE, GuestAddrs, Upcall};
+use bmvm_common::boot::Phase;
 use bmvm_common::error::ExitCode;
 use bmvm_common::interprete::Interpret;
 use bmvm_common::mem;
 use bmvm_common::mem::{
-    Align, AlignedNonZeroU64, AlignedNonZeroUsize, DefaultAddrSpace, DefaultAlign, Flags,
-    LayoutTable, LayoutTableEntry, Page1GiB, Page2MiB, Page4KiB, PhysAddr, Stack, VirtAddr,
-    align_floor, init as init_vmi_alloc,
+    Align, AlignedNonZeroU64, AlignedNonZeroUsize, DefaultAddrSpace, DefaultAlign, DumpHeader,
+    Flags, LayoutTable, LayoutTableEntry, Page1GiB, Page2MiB, Page4KiB, PhysAddr, RegionStat,
+    Stack, VirtAddr, align_floor, init as init_vmi_alloc, region_stats, write_dump_magic,
 };
+use bmvm_common::metrics::{METRIC_NAME_MAX_LEN, MetricSlot};
 use bmvm_common::registry::Params;
-use bmvm_common::vmi::{ForeignShareable, Transport};
-use bmvm_common::{BMVM_MEM_LAYOUT_TABLE, EXIT_IO_PORT, HYPERCALL_IO_PORT};
-use kvm_bindings::{KVM_API_VERSION, kvm_regs};
+use bmvm_common::vmi::{
+    FnPtr, ForeignShareable, GUEST_LOG, GUEST_LOG_MAX_SIZE, HOST_MEMCPY, HOST_MEMSET, LogLevel,
+    REQUEST_PAGES, STREAM_CANCELLED, STREAM_CHUNK, Signature, Transport, YIELD,
+};
+use bmvm_common::{
+    BMVM_GUEST_ARGS, BMVM_GUEST_ARGS_MAX_SIZE, BMVM_HYPERCALL_MMIO, BMVM_IO_PORTS, BMVM_METRICS,
+    BMVM_METRICS_SIZE, BMVM_PANIC_MESSAGE, BMVM_PANIC_MESSAGE_SIZE, EXIT_IO_PORT, HLT_WAIT_MARKER,
+    HYPERCALL_IO_PORT, SERIAL_IO_PORT,
+};
+use kvm_bindings::{KVM_API_VERSION, KVM_CPUID_FLAG_SIGNIFCANT_INDEX, kvm_regs};
 use kvm_ioctls::{Cap, Kvm, VcpuExit, VmFd};
+use rustc_hash::{FxHashMap, FxHashSet};
+use std::collections::{BTreeSet, HashMap};
+use std::fs::File;
 use std::io::Write;
+use std::mem::offset_of;
 use std::num::NonZeroUsize;
+use std::ops::Range;
+use std::path::PathBuf;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
 
 const INITIAL_PAGE_ALLOC: usize = 16;
 const ADDITIONAL_PAGE_ALLOC: usize = 4;
@@ -46,8 +71,20 @@ pub enum Error {
     VmMemoryMappingNotFound(PhysAddr),
     #[error("Memory mapping is not readable: {0:?}")]
     VmMemoryMappingNotReadable(PhysAddr),
+    #[error("Memory mapping is not writable: {0:?}")]
+    VmMemoryMappingNotWritable(PhysAddr),
     #[error("Memory request exceeds max memory: {0}")]
     VmMemoryRequestExceedsMaxMemory(u64),
+    #[error("Guest arguments too large: got {0} but only supports up to {max}", max = BMVM_GUEST_ARGS_MAX_SIZE - size_of::<u64>())]
+    GuestArgsTooLarge(usize),
+    #[error("Invalid guest log record of length {0}")]
+    InvalidLogRecord(usize),
+    #[error("Stream chunk of {0} bytes exceeds the guest memory")]
+    InvalidStreamChunk(u64),
+    #[error("IO port {0:#x} is assigned more than once")]
+    IoPortConflict(u16),
+    #[error("Layout table address {0:?} is misaligned or overlaps another region")]
+    LayoutTableAddr(PhysAddr),
     #[error("Error during hypercall execution: {0}")]
     Hypercall(registry::Error),
     #[error("Error during upcall execution: {0}")]
@@ -58,45 +95,168 @@ pub enum Error {
     UpcallReturn(ExitCode),
     #[error("Guest unexpectedly return with upcall state, without previous upcall call")]
     UnexpectedUpcallReturn,
+    #[error("Guest callbacks can only be issued during hypercall execution")]
+    NoHypercallContext,
+    #[error("Maximum guest callback depth of {0} exceeded")]
+    CallbackDepthExceeded(usize),
+    #[error("Guest did not return from callback")]
+    CallbackNotReturned,
+    #[error("Guest halted before the upcall returned")]
+    UpcallHalted,
+    #[error("Guest exited with {0:?} while a hypercall was in progress")]
+    ProtocolViolation(ExitCode),
+    #[error("Guest exited during setup without signaling readiness")]
+    NotReady,
+    #[error("Guest yielded and has to be resumed first")]
+    Yielded,
+    #[error("Guest did not yield, there is nothing to resume")]
+    NotYielded,
+    #[error("Guest stack overflow (rsp: {0:#x})")]
+    StackOverflow(u64),
+    #[error("Guest accessed {addr:#x} outside the declared regions (rip: {rip:#x})")]
+    OutOfBoundsAccess { addr: u64, rip: u64 },
+    #[error("Stack usage is only tracked with a configured stack poison")]
+    StackNotPoisoned,
     #[error("VCPU error: {0}")]
     Vcpu(#[from] vcpu::Error),
     #[error("Setup error: {0}")]
     Setup(#[from] setup::Error),
     #[error("Allocator error: {0}")]
     Allocator(#[from] crate::alloc::Error),
-    #[error("Guest exited with unhandled exit code: {0}")]
-    UnhandledHalt(ExitCode),
+    #[error(
+        "Guest exited with unhandled exit code: {0}{detail}",
+        detail = .1.map(|d| format!(" (detail: {d:#x})")).unwrap_or_default()
+    )]
+    UnhandledHalt(ExitCode, Option<u64>),
     #[error("Unexpected exit reason: See logs for details")]
     UnexpectedExit,
+    #[error("Unable to write memory dump: {0}")]
+    Dump(std::io::Error),
+    #[error("Invalid page request: {0}")]
+    InvalidPageRequest(u64),
+    #[error("Layout table has no free entry left")]
+    LayoutTableFull,
+    #[error("Virtual address is not mapped: {0:#x}")]
+    VirtAddrNotMapped(u64),
+    #[error("Parallel upcall worker panicked")]
+    WorkerPanicked,
+    #[error("Unable to start the RIP sampler or timeout watchdog: {0}")]
+    Sampler(nix::errno::Errno),
+    #[error("Unable to map file {path}: {1}", path = .0.display())]
+    MapFile(PathBuf, std::io::Error),
+    #[error("Mapped file of {0} bytes exceeds the available guest memory")]
+    MappedFileTooLarge(u64),
+    #[error("Mapped file can not be placed at virtual address {0:#x}")]
+    MappedFileAddr(u64),
+    #[error("Guest wrote to read-only memory at {0:#x}")]
+    ReadOnlyWrite(u64),
+    #[error("Snapshot error: {0}")]
+    Snapshot(#[from] snapshot::Error),
+    #[error("Guest execution exceeded the timeout of {0:?}")]
+    Timeout(Duration),
+    #[cfg(feature = "test-fault-injection")]
+    #[error("Guest aborted by the injected fault {0:?}")]
+    GuestFault(FaultKind),
+}
+
+impl Error {
+    /// Origin of the error, see `ErrorCategory`
+    pub fn category(&self) -> ErrorCategory {
+        match self {
+            Error::Kvm(_)
+            | Error::KvmApiVersionMismatch(_)
+            | Error::KvmMissingCapability(_)
+            | Error::Vm(_)
+            | Error::Vcpu(_)
+            | Error::Allocator(_)
+            | Error::Dump(_)
+            | Error::Sampler(_)
+            | Error::MapFile(..) => ErrorCategory::Environment,
+            Error::VmMemoryRequestExceedsMaxMemory(_)
+            | Error::GuestArgsTooLarge(_)
+            | Error::IoPortConflict(_)
+            | Error::LayoutTableAddr(_)
+            | Error::UpcallInit(_)
+            | Error::UpcallExec(_)
+            | Error::StackNotPoisoned
+            | Error::Yielded
+            | Error::NotYielded
+            | Error::VirtAddrNotMapped(_)
+            | Error::MappedFileTooLarge(_)
+            | Error::MappedFileAddr(_)
+            | Error::Snapshot(_) => ErrorCategory::Input,
+            Error::InvalidLogRecord(_)
+            | Error::InvalidStreamChunk(_)
+            | Error::Hypercall(_)
+            | Error::UpcallReturn(_)
+            | Error::UnexpectedUpcallReturn
+            | Error::CallbackDepthExceeded(_)
+            | Error::CallbackNotReturned
+            | Error::UpcallHalted
+            | Error::ProtocolViolation(_)
+            | Error::NotReady
+            | Error::StackOverflow(_)
+            | Error::OutOfBoundsAccess { .. }
+            | Error::UnhandledHalt(..)
+            | Error::UnexpectedExit
+            | Error::InvalidPageRequest(_)
+            | Error::LayoutTableFull
+            | Error::ReadOnlyWrite(_)
+            | Error::Timeout(_) => ErrorCategory::Guest,
+            #[cfg(feature = "test-fault-injection")]
+            Error::GuestFault(_) => ErrorCategory::Guest,
+            Error::Paging(_)
+            | Error::VmMemoryMappingNotFound(_)
+            | Error::VmMemoryMappingNotReadable(_)
+            | Error::VmMemoryMappingNotWritable(_)
+            | Error::NoHypercallContext
+            | Error::Setup(_)
+            | Error::WorkerPanicked => ErrorCategory::Internal,
+        }
+    }
+
+    /// Check if a buffer passed by the guest was rejected, see `ConfigBuilder::max_transport_bytes`
+    pub(crate) fn is_transport_too_large(&self) -> bool {
+        matches!(
+            self,
+            Error::UpcallReturn(ExitCode::TransportTooLarge)
+                | Error::Hypercall(registry::Error::HypercallExec(ExitCode::TransportTooLarge))
+        )
+    }
 }
 
+/// Execution state of the guest:
+///
+/// ```text
+/// Created (PreSetup) -> Booting -> Ready <-> Running (UpcallExec) <-> HypercallExec
+///                                            Running (UpcallExec)  -> Shutdown
+/// ```
+///
+/// The guest boots by running `_start` and the user provided `setup` until it signals `ready()`.
+/// Afterward it is idle, every upcall runs it from the requested function until it returns, which
+/// leaves the guest idle again. Exiting via `exit_normal` or `exit_with_value` shuts it down.
 #[derive(Debug, PartialEq, Eq, Clone, Copy, Hash)]
 enum State {
     PreSetup,
+    Booting,
     Ready,
     UpcallExec,
     HypercallExec,
     Shutdown,
 }
 
+/// KVM handles required to execute a guest.
 #[derive(Debug)]
-pub struct Vm {
-    cfg: Config,
-    state: State,
+struct Handle {
     kvm: Kvm,
     vm: VmFd,
     vcpu: Vcpu,
-    manager: Allocator,
-    hypercalls: Hypercalls,
-    upcalls: Upcalls,
-    mem_mappings: RegionCollection,
-
-    paging_size: usize,
+    /// Additional vcpus for parallel upcalls, created on demand
+    workers: Vec<Vcpu>,
 }
 
-impl Vm {
-    /// create a new VM instance
-    pub(crate) fn new<CONFIG: Into<Config>>(cfg: CONFIG) -> Result<Self> {
+impl Handle {
+    fn new() -> Result<Self> {
         let kvm = Kvm::new().map_err(Error::Kvm)?;
         let version = kvm.get_api_version();
         if version != KVM_API_VERSION as i32 {
@@ -114,42 +274,220 @@ impl Vm {
         // create a vcpu
         let vcpu = Vcpu::new(&vm, 0)?;
 
-        // create a region manager
-        let manager = Allocator::new();
-
         Ok(Self {
-            cfg: cfg.into(),
-            state: State::PreSetup,
             kvm,
             vm,
             vcpu,
+            workers: Vec::new(),
+        })
+    }
+}
+
+#[derive(Debug)]
+pub struct Vm {
+    cfg: Config,
+    state: State,
+    handle: Handle,
+    manager: Allocator,
+    hypercalls: Hypercalls,
+    upcalls: Upcalls,
+    mem_mappings: RegionCollection,
+
+    paging_size: usize,
+    paging: Option<PagingState>,
+    callback_depth: usize,
+    /// Guest physical addresses derived from the config
+    addrs: GuestAddrs,
+    /// Runtime requested pages are placed contiguously from `heap_top` up to `heap_limit`
+    heap_top: PhysAddr,
+    heap_limit: PhysAddr,
+    /// Initial stack pointer of each additional vcpu
+    worker_stacks: Vec<VirtAddr>,
+    /// Number of samples per guest instruction pointer, see `ConfigBuilder::sample_rip`
+    rip_samples: FxHashMap<u64, u64>,
+    /// Value passed to `bmvm_guest::exit_with_value` during the last execution
+    exit_value: Option<Transport>,
+    /// VM exits of the most recent outermost execution, including nested callbacks
+    exits: ExitCounts,
+    /// Fault raised by the next upcall, see `Module::inject_fault`
+    #[cfg(feature = "test-fault-injection")]
+    pending_fault: Option<FaultKind>,
+    /// Fault applied to the current upcall, which any error of its execution is reported as
+    #[cfg(feature = "test-fault-injection")]
+    active_fault: Option<FaultKind>,
+    /// End of the current execution, see `ConfigBuilder::timeout`
+    deadline: Option<Instant>,
+    /// Identifier of the most recent snapshot taken or restored
+    last_snapshot: Option<u64>,
+    /// Pages written by the host since the last snapshot, which are not covered by the KVM dirty log
+    host_dirty: FxHashSet<u64>,
+    /// Streaming upcall in progress, see `Module::call_stream`
+    stream: Option<Stream>,
+    /// The guest is suspended within the yield hypercall until `Module::resume`
+    yielded: bool,
+    /// Start of the boot, as long as the guest did not signal readiness
+    boot_start: Option<Instant>,
+    /// Setup phases completed by the guest with the time elapsed since the start of the boot
+    boot_phases: Vec<(Phase, Duration)>,
+    /// Exit of the guest during a callback, which left the hypercall it was issued from unfinished
+    violation: Option<ExitCode>,
+    /// Guest physical address of the shared memory
+    shared_addr: Option<PhysAddr>,
+    /// Guest virtual addresses of the read-only view of the shared memory, see `foreign_view`
+    foreign_view: Option<Range<u64>>,
+    /// Content of the shared memory at the last snapshot. The host writes it via the allocator
+    /// without going through `write_virt`, so modified pages are found by comparison.
+    shared_shadow: Option<Box<[u8]>>,
+}
+
+impl Vm {
+    /// All problems of the configuration, which can be detected before creating the VM. The
+    /// regions of `exec` are taken into account, if the executable could be parsed.
+    pub(crate) fn check_config(cfg: &Config, exec: Option<&ExecBundle>) -> Vec<Error> {
+        let mut errors = Vec::new();
+        if !Self::guest_args_fit(cfg) {
+            errors.push(Error::GuestArgsTooLarge(cfg.guest_args.len()));
+        }
+        if let Some(port) = Self::io_port_conflict(cfg) {
+            errors.push(Error::IoPortConflict(port));
+        }
+        let memory = Self::requested_memory(cfg, exec);
+        if memory > cfg.max_physical_memory as u64 {
+            errors.push(Error::VmMemoryRequestExceedsMaxMemory(memory));
+        }
+        let layout = exec.map(|e| e.layout.as_slice()).unwrap_or_default();
+        if !Self::layout_table_addr_free(cfg, layout) {
+            errors.push(Error::LayoutTableAddr(cfg.layout_table));
+        }
+        errors
+    }
+
+    /// create a new VM instance
+    pub(crate) fn new<CONFIG: Into<Config>>(cfg: CONFIG) -> Result<Self> {
+        let handle = Handle::new()?;
+        let cfg = cfg.into();
+        let addrs = GuestAddrs::new(&cfg);
+        // create a region manager
+        let manager = Allocator::with_backing(cfg.backing);
+
+        Ok(Self {
+            cfg,
+            state: State::PreSetup,
+            handle,
             manager,
             hypercalls: Hypercalls::default(),
             upcalls: Upcalls::default(),
             mem_mappings: RegionCollection::new(),
             paging_size: 0,
+            paging: None,
+            callback_depth: 0,
+            addrs,
+            heap_top: PhysAddr::new(0),
+            heap_limit: PhysAddr::new(0),
+            worker_stacks: Vec::new(),
+            rip_samples: FxHashMap::default(),
+            exit_value: None,
+            exits: ExitCounts::default(),
+            #[cfg(feature = "test-fault-injection")]
+            pending_fault: None,
+            #[cfg(feature = "test-fault-injection")]
+            active_fault: None,
+            deadline: None,
+            last_snapshot: None,
+            host_dirty: FxHashSet::default(),
+            stream: None,
+            yielded: false,
+            boot_start: None,
+            boot_phases: Vec::new(),
+            violation: None,
+            shared_addr: None,
+            foreign_view: None,
+            shared_shadow: None,
         })
     }
 
     /// load the guest executable
     pub(crate) fn load_exec(&mut self, exec: &mut ExecBundle) -> Result<()> {
+        let memory = Self::requested_memory(&self.cfg, Some(exec));
+        if memory > self.cfg.max_physical_memory as u64 {
+            return Err(Error::VmMemoryRequestExceedsMaxMemory(memory));
+        }
+
+        // runtime requested pages are placed above the executable
+        let exec_end = exec.layout.iter().map(|e| e.paddr_raw() + e.size()).max();
+        self.heap_top = PhysAddr::new(DefaultAlign::align_ceil(exec_end.unwrap_or(0)));
+
         // allocate a stack region
-        let (stack, stack_entry) = self.alloc_stack(self.cfg.stack_size, GUEST_STACK_ADDR())?;
-        let stack_addr = stack.addr();
+        let (stack, stack_entry) = self.alloc_stack(self.cfg.stack_size, self.addrs.stack_top)?;
         self.mem_mappings.push(stack);
         exec.layout.push(stack_entry);
 
-        // Memory layout: sys | stack | shared_shared | ... | code
+        // keep the page below the stack unmapped, so an overflow faults instead of corrupting memory
+        let guard = self.addrs.stack_guard;
+
+        // Memory layout: sys | stack | guard | shared | ... | code
         // Optionally allocate shared memory managed
-        let shared = self.alloc_shared(stack_addr)?.map(|(region, layout)| {
+        self.heap_limit = guard;
+        let shared = self.alloc_shared(guard)?.map(|(region, layout)| {
             let arena = region.as_arena();
+            self.heap_limit = region.addr();
+            self.shared_addr = Some(region.addr());
             self.mem_mappings.push(region);
+            let view = Self::foreign_view(&layout);
+            self.foreign_view = Some(view.vaddr_raw()..view.vaddr_raw() + view.size());
             exec.layout.push(layout);
+            exec.layout.push(view);
             arena
         });
 
         // initialize the respective allocators
-        init_vmi_alloc(shared);
+        init_vmi_alloc(shared, self.max_transport_bytes());
+
+        // every additional vcpu gets its own stack below the shared memory, separated by a guard page
+        for _ in 1..self.cfg.vcpus {
+            let upper = self.heap_limit;
+            let (stack, entry) = self.alloc_stack(self.cfg.stack_size, upper)?;
+            self.heap_limit = stack.addr() - GUEST_STACK_GUARD_SIZE;
+            self.worker_stacks.push(self.stack_pointer(upper));
+            self.mem_mappings.push(stack);
+            exec.layout
+                .push(entry.set_flags(Flags::PRESENT | Flags::DATA_WRITE));
+        }
+
+        // optionally pass the guest arguments
+        if let Some((region, layout)) = self.alloc_guest_args()? {
+            self.mem_mappings.push(region);
+            exec.layout.push(layout);
+        }
+
+        // reserve the region for the guest panic message
+        let (region, layout) = self.alloc_panic_message()?;
+        self.mem_mappings.push(region);
+        exec.layout.push(layout);
+
+        // reserve the region for the guest metrics, if requested
+        if self.cfg.metrics {
+            let (region, layout) = self.alloc_metrics()?;
+            self.mem_mappings.push(region);
+            exec.layout.push(layout);
+        }
+
+        // pass the IO ports, if they differ from the defaults or the boot markers are enabled
+        if let Some((region, layout)) = self.alloc_io_ports()? {
+            self.mem_mappings.push(region);
+            exec.layout.push(layout);
+        }
+
+        // map the hypercall doorbell without backing memory
+        if self.cfg.transport == TransportKind::Mmio {
+            exec.layout.push(Self::hypercall_doorbell());
+        }
+
+        // pass the host file without copying
+        if let Some((region, layout)) = self.map_file(&exec.layout)? {
+            self.mem_mappings.push(region);
+            exec.layout.push(layout);
+        }
 
         // prepare the system region
         let (gdt, idt, paging) = self.setup_long_mode_env(exec)?;
@@ -158,15 +496,15 @@ impl Vm {
         self.mem_mappings.append(&mut exec.mem_regions);
 
         // setup the vcpu for execution
-        self.setup_cpu(exec.entry.as_virt_addr(), gdt, idt, paging)?;
+        self.setup_cpu(exec.entry.as_virt_addr(), exec.tls, gdt, idt, paging)?;
 
         // map all regions to the guest
         for (slot, r) in self.mem_mappings.iter_mut().enumerate() {
-            r.set_as_guest_memory(&self.vm, slot as u32)?
+            r.set_as_guest_memory(&self.handle.vm, slot as u32, self.cfg.dirty_log)?
         }
 
         if self.cfg.debug {
-            self.vcpu.enable_single_step()?;
+            self.handle.vcpu.enable_single_step()?;
         }
 
         Ok(())
@@ -177,46 +515,199 @@ impl Vm {
         &mut self,
         hypercalls: Vec<hypercall::Function>,
         upcalls: Vec<upcall::Function>,
+        on_missing: Option<MissingHypercallHook>,
+        names: SignatureNames,
     ) {
-        self.hypercalls = Hypercalls::from(hypercalls);
+        self.hypercalls = Hypercalls::from(hypercalls)
+            .with_missing_hook(on_missing)
+            .with_names(names);
         self.upcalls = Upcalls::from(upcalls);
     }
 
     /// Expose the guest memory allocator used by this VM instance
-    pub(crate) fn allocator(&self) -> &Allocator {
-        &self.manager
+    /// Maximum length of a buffer passed to the guest, see `ConfigBuilder::max_transport_bytes`
+    pub(crate) fn max_transport_bytes(&self) -> usize {
+        self.cfg.max_transport_bytes.unwrap_or(usize::MAX)
     }
 }
 
 // Implementation regarding the vm execution state
 impl Vm {
-    /// run the guest
+    /// Boot the guest: run `_start` and the user provided `setup` until the guest signals
+    /// readiness, leaving it idle for the first upcall.
+    pub(crate) fn boot(&mut self) -> Result<()> {
+        self.state = State::Booting;
+        self.boot_phases.clear();
+        self.boot_start = Some(Instant::now());
+        self.run()?;
+        // an entry-only guest runs to its exit within the setup
+        let exited = self.state == State::Shutdown && self.exit_value.is_some();
+        if self.state != State::Ready && !exited {
+            return Err(Error::NotReady);
+        }
+        Ok(())
+    }
+
+    /// Check if the guest completed its setup and is idle, waiting for the next upcall
+    pub(crate) fn is_ready(&self) -> bool {
+        self.state == State::Ready
+    }
+
+    /// run the guest and write a memory dump if it faults and a dump path is configured
     pub(crate) fn run(&mut self) -> Result<()> {
+        // nested callbacks are counted towards the exits of the outermost run
+        if self.callback_depth == 0 {
+            self.exits = ExitCounts::default();
+            self.violation = None;
+        }
+        // deliver the signals of the sampler and the watchdog only during `KVM_RUN`
+        let signaled = self.cfg.sample_rip.is_some() || self.cfg.timeout.is_some();
+        let blocked = if signaled && self.callback_depth == 0 {
+            let (blocked, run_mask) = Blocked::new().map_err(Error::Sampler)?;
+            self.handle.vcpu.set_signal_mask(Some(&run_mask))?;
+            Some(blocked)
+        } else {
+            None
+        };
+        // nested callbacks are covered by the sampler of the outermost run
+        let sampler = match self.cfg.sample_rip {
+            Some(interval) if self.callback_depth == 0 => {
+                Some(Sampler::start(interval).map_err(Error::Sampler)?)
+            }
+            _ => None,
+        };
+        let watchdog = match self.cfg.timeout {
+            Some(timeout) if self.callback_depth == 0 => {
+                self.deadline = Some(Instant::now() + timeout);
+                Some(Sampler::deadline(timeout).map_err(Error::Sampler)?)
+            }
+            _ => None,
+        };
+        let result = self.run_loop();
+        if self.callback_depth == 0 {
+            log::trace!("VM exits: {:?}", self.exits);
+        }
+        drop(sampler);
+        if watchdog.is_some() {
+            drop(watchdog);
+            self.deadline = None;
+        }
+        if blocked.is_some() {
+            if let Err(err) = self.handle.vcpu.set_signal_mask(None) {
+                log::error!("Unable to restore the vCPU signal mask: {}", err);
+            }
+            drop(blocked);
+        }
+
+        // nested callbacks propagate the fault, only dump once at the outermost level
+        if let Err(
+            Error::UnexpectedExit | Error::StackOverflow(_) | Error::OutOfBoundsAccess { .. },
+        ) = result
+            && self.callback_depth == 0
+            && let Some(path) = self.cfg.dump_on_fault.as_ref()
+        {
+            log::info!("Writing memory dump to {}", path.display());
+            let dumped = std::fs::File::create(path)
+                .map_err(Error::Dump)
+                .and_then(|mut file| self.dump_memory(&mut file));
+            if let Err(err) = dumped {
+                log::error!("Memory dump failed: {}", err);
+            }
+        }
+
+        // the injected fault is the cause of whatever aborted the guest, see `FaultKind`
+        #[cfg(feature = "test-fault-injection")]
+        if let Some(fault) = self.active_fault.take()
+            && let Err(err) = &result
+        {
+            log::info!("Injected {:?} aborted the guest: {}", fault, err);
+            return Err(Error::GuestFault(fault));
+        }
+
+        result
+    }
+
+    fn run_loop(&mut self) -> Result<()> {
         log::debug!("VM Execution");
         loop {
             // Single Step through the guest in debug mode
             if self.cfg.debug {
-                self.vcpu.enable_single_step().map_err(Error::Vcpu)?
+                self.handle.vcpu.enable_single_step().map_err(Error::Vcpu)?
             }
 
-            match self.vcpu.run()? {
+            let exit = match self.handle.vcpu.run() {
+                // interrupted by the sampler or the deadline, record the position and resume
+                Err(e) if e.is_interrupted() => {
+                    self.exits.interrupted += 1;
+                    if let (Some(timeout), Some(deadline)) = (self.cfg.timeout, self.deadline)
+                        && Instant::now() >= deadline
+                    {
+                        log::error!("Guest exceeded the timeout of {:?}", timeout);
+                        return Err(Error::Timeout(timeout));
+                    }
+                    if self.cfg.sample_rip.is_some() {
+                        self.record_rip_sample()?;
+                    }
+                    continue;
+                }
+                exit => exit?,
+            };
+            self.exits.record(&exit);
+
+            match exit {
                 // IO Out should only be triggered by the hypercall
                 // execute hypercall or log warning otherwise
                 VcpuExit::IoOut(port, data) => {
                     match port {
-                        HYPERCALL_IO_PORT => {
+                        p if p == self.cfg.hypercall_port => {
                             self.hypercall_exec()?;
                         }
-                        EXIT_IO_PORT => {
+                        SERIAL_IO_PORT => {
+                            let output = String::from_utf8_lossy(data);
+                            log::info!("Guest: {}", output.trim_end());
+                        }
+                        p if self.cfg.boot_markers && p == self.cfg.boot_port => {
+                            let phase = data.first().copied().and_then(Phase::from_u8);
+                            self.record_boot_phase(phase);
+                        }
+                        p if p == self.cfg.exit_port => {
                             // Check the exit code and react accordingly
-                            let exit_code = ExitCode::from(data[0]);
+                            let data = data.to_vec();
+                            let regs = *self.handle.vcpu.read_regs()?;
+                            let exit_code =
+                                ExitCode::decode(&data, &regs).ok_or(Error::UnexpectedExit)?;
+
+                            // a callback has to return, otherwise the hypercall it was issued
+                            // from never completes
+                            if self.callback_depth > 0
+                                && matches!(
+                                    exit_code,
+                                    ExitCode::Normal | ExitCode::Value | ExitCode::Ready
+                                )
+                            {
+                                log::error!(
+                                    "Guest exited with {:?} during a hypercall at depth {}",
+                                    exit_code,
+                                    self.callback_depth
+                                );
+                                self.violation = Some(exit_code);
+                                return Err(Error::ProtocolViolation(exit_code));
+                            }
+
                             match exit_code {
                                 ExitCode::Normal => {
                                     log::info!("Guest triggered VM shutdown");
                                     self.state = State::Shutdown;
                                 }
+                                ExitCode::Value => {
+                                    log::info!("Guest exited with a value");
+                                    self.exit_value = Some(Transport::new(regs.r8, regs.r9));
+                                    self.state = State::Shutdown;
+                                }
                                 ExitCode::Ready => {
                                     log::info!("Guest Setup done, ready to execute");
+                                    self.record_boot_phase(Some(Phase::Ready));
+                                    self.boot_start = None;
                                     self.state = State::Ready;
                                 }
                                 ExitCode::Return => {
@@ -225,26 +716,26 @@ impl Vm {
                                     }
 
                                     log::info!("Guest returned from upcall");
-                                    self.state = State::UpcallExec;
+                                    self.state = State::Ready;
                                 }
-                                ExitCode::Panic(vaddr) => unsafe {
+                                ExitCode::Panic(vaddr) => {
                                     log::error!("Panic occurred: {vaddr:X}");
 
                                     let _ = &self.print_debug_info()?;
-                                    let _ = &self.dump_region(0x1000)?;
-                                    let paddr = PhysAddr::from(vaddr);
-                                    if let Some(r) = self.mem_mappings.get(paddr) {
-                                        let offset = paddr.as_usize() - r.as_ptr() as usize;
-                                        let ptr =
-                                            r.as_ptr().add(offset).cast::<core::panic::PanicInfo>();
-                                        let reference = &*ptr;
-                                        log::error!("Panic at {}", reference);
+                                    let _ = &self.dump_region(self.cfg.layout_table.as_u64())?;
+                                    if let Some(message) = self.panic_message() {
+                                        log::error!("Panic at {}", message);
                                     }
                                     return Err(Error::UnexpectedExit);
-                                },
+                                }
                                 _ => {
-                                    log::error!("Exit Code: {:?}", exit_code);
-                                    return Err(Error::UnhandledHalt(exit_code));
+                                    let detail = ExitCode::decode_detail(&data);
+                                    log::error!(
+                                        "Exit Code: {:?} (detail: {:?})",
+                                        exit_code,
+                                        detail
+                                    );
+                                    return Err(Error::UnhandledHalt(exit_code, detail));
                                 }
                             }
                             self.react_to_exit_code(exit_code)?;
@@ -260,17 +751,66 @@ impl Vm {
                         }
                     }
                 }
+                VcpuExit::MmioWrite(addr, _) if addr == BMVM_HYPERCALL_MMIO.as_u64() => {
+                    self.hypercall_exec()?;
+                }
+                // read-only slots report writes of the guest as MMIO
+                VcpuExit::MmioWrite(addr, _)
+                    if self
+                        .mem_mappings
+                        .get(PhysAddr::new(addr))
+                        .is_some_and(|r| !r.writeable()) =>
+                {
+                    log::error!("Guest wrote to read-only memory at {:#x}", addr);
+                    return Err(Error::ReadOnlyWrite(addr));
+                }
                 VcpuExit::Debug(_debug) => {
                     self.print_debug_info()?;
                 }
+                // a marked hlt waits for the host if resumable, every other one terminates
+                VcpuExit::Hlt => {
+                    let rax = self.handle.vcpu.read_regs()?.rax;
+                    if self.cfg.hlt_policy == HltPolicy::Exit || rax != HLT_WAIT_MARKER {
+                        log::info!("Guest halted, shutting down");
+                        let in_upcall = self.state == State::UpcallExec || self.callback_depth > 0;
+                        self.state = State::Shutdown;
+                        // the result registers are only set once the upcall returns
+                        if in_upcall {
+                            return Err(Error::UpcallHalted);
+                        }
+                        return Ok(());
+                    }
+                    // only a plain upcall is suspended, otherwise the guest continues right away
+                    self.yielded = self.state == State::UpcallExec
+                        && self.callback_depth == 0
+                        && self.stream.is_none();
+                }
+                // The guest does not install exception handlers, therefore a page fault on the
+                // guard page escalates to a shutdown. Inspect the fault address to report it.
+                VcpuExit::Shutdown => {
+                    let error = self.shutdown_cause()?;
+                    // a later execution must not attribute its shutdown to this page fault
+                    self.handle.vcpu.clear_page_fault()?;
+                    return Err(error);
+                }
                 // Unexpected Exit
                 reason => {
                     log::error!("Unexpected exit reason: {:?}", reason);
                     let _ = &self.print_debug_info()?;
-                    let _ = &self.dump_region(0x1000)?;
+                    let _ = &self.dump_region(self.cfg.layout_table.as_u64())?;
                     return Err(Error::UnexpectedExit);
                 }
             }
+
+            // the guest is suspended within the chunk hypercall until the consumer took the chunk
+            if self.stream.as_ref().is_some_and(|s| s.suspended) {
+                return Ok(());
+            }
+            // the guest is suspended within the yield hypercall or a hlt until the host resumes it
+            if self.yielded {
+                log::debug!("Guest yielded");
+                return Ok(());
+            }
         }
     }
 }
@@ -294,186 +834,1181 @@ impl Vm {
         R: ForeignShareable,
     {
         let transport = params.into_transport().map_err(Error::UpcallExec)?;
+        log::info!("Calling function '{}'", upcall.name);
+        self.upcall_exec_setup_raw(upcall.ptr, transport)
+    }
 
-        self.vcpu.mutate_regs(|regs| {
+    /// Setup the guest environment to execute the function at `ptr` with an already prepared
+    /// transport
+    pub(crate) fn upcall_exec_setup_raw(&mut self, ptr: FnPtr, transport: Transport) -> Result<()> {
+        if self.yielded {
+            return Err(Error::Yielded);
+        }
+        self.handle.vcpu.mutate_regs(|regs| {
             // Set the parameters
             regs.r8 = transport.primary();
             regs.r9 = transport.secondary();
 
             // Set the function pointer
-            regs.rip = upcall.ptr.as_u64();
-            log::info!("Calling function '{}'", upcall.name);
+            regs.rip = ptr.as_u64();
             true
         })?;
+        #[cfg(feature = "test-fault-injection")]
+        if let Some(fault) = self.pending_fault.take() {
+            self.apply_fault(fault)?;
+        }
 
+        self.exit_value = None;
         self.state = State::UpcallExec;
         Ok(())
     }
 
+    /// Raise `fault` during the next upcall
+    #[cfg(feature = "test-fault-injection")]
+    pub(crate) fn inject_fault(&mut self, fault: FaultKind) -> Result<()> {
+        self.pending_fault = Some(fault);
+        Ok(())
+    }
+
+    #[cfg(feature = "test-fault-injection")]
+    fn apply_fault(&mut self, fault: FaultKind) -> Result<()> {
+        log::warn!("Injecting guest fault: {:?}", fault);
+        self.active_fault = Some(fault);
+        match fault {
+            FaultKind::UnmappedRip => self.handle.vcpu.mutate_regs(|regs| {
+                regs.rip = fault::UNMAPPED_RIP;
+                true
+            })?,
+            FaultKind::NonCanonicalRip => self.handle.vcpu.mutate_regs(|regs| {
+                regs.rip = fault::NON_CANONICAL_RIP;
+                true
+            })?,
+            FaultKind::InvalidCodeSegment => self.handle.vcpu.mutate_sregs(|sregs| {
+                sregs.cs.present = 0;
+                true
+            })?,
+        }
+        Ok(())
+    }
+
+    /// Take the value the guest passed to `bmvm_guest::exit_with_value`, if it exited that way
+    /// since the last upcall setup
+    pub(crate) fn take_exit_value(&mut self) -> Option<Transport> {
+        self.exit_value.take()
+    }
+
     /// Try reading the return value form the previously executed Upcall
     pub fn upcall_result<R>(&mut self) -> Result<R>
     where
         R: ForeignShareable,
     {
-        let regs = self.vcpu.read_regs()?;
-        let transport = Transport::new(regs.r8, regs.r9);
+        let transport = self.upcall_result_raw()?;
         R::from_transport(transport).map_err(Error::UpcallReturn)
     }
 
-    fn hypercall_exec(&mut self) -> Result<()> {
-        log::debug!("HYPERCALL TRIGGER");
-
-        // save the current state and set the hypercall state
-        let prev = self.state;
-        self.state = State::HypercallExec;
-
-        // read hypercall parameters
-        let mut regs = self.vcpu.get_regs()?;
-        let sig = regs.rbx;
-        let transport = Transport::new(regs.r8, regs.r9);
-        log::debug!("Parameter: signature={}, transport={}", sig, transport);
+    /// Check if the guest yielded via `bmvm_guest::yield_to_host` or `wait_for_host` and waits to
+    /// be resumed
+    pub(crate) fn is_yielded(&self) -> bool {
+        self.yielded
+    }
 
-        // execute the hypercall
-        let output = self
-            .hypercalls
-            .try_execute(sig, transport)
-            .map_err(Error::Hypercall)?;
+    /// Continue the guest from the yield hypercall or hlt it is suspended in, until it returns from
+    /// the upcall or yields again.
+    pub(crate) fn resume(&mut self) -> Result<()> {
+        if !self.yielded {
+            return Err(Error::NotYielded);
+        }
+        self.yielded = false;
+        self.run()
+    }
 
-        // write the result to the registers
-        regs.r8 = output.primary();
-        regs.r9 = output.secondary();
-        log::debug!("Result: transport={}", output);
-        self.vcpu.set_regs(regs);
+    /// Read the transport returned by the previously executed upcall
+    pub(crate) fn upcall_result_raw(&mut self) -> Result<Transport> {
+        let regs = self.handle.vcpu.read_regs()?;
+        Ok(Transport::new(regs.r8, regs.r9))
+    }
 
-        // restore the previous state
-        self.state = prev;
+    fn record_rip_sample(&mut self) -> Result<()> {
+        let rip = self.handle.vcpu.read_regs()?.rip;
+        *self.rip_samples.entry(rip).or_default() += 1;
         Ok(())
     }
-}
 
-// Implementation regarding initial setup
-impl Vm {
-    fn align_by_ref(value: u64, reference: u64) -> AlignedNonZeroU64 {
-        if Page1GiB::is_aligned(reference) {
-            AlignedNonZeroU64::new_aligned(Page1GiB::align_floor(value)).unwrap()
-        } else if Page2MiB::is_aligned(reference) {
-            AlignedNonZeroU64::new_aligned(Page2MiB::align_floor(value)).unwrap()
-        } else {
-            AlignedNonZeroU64::new_aligned(Page4KiB::align_floor(value)).unwrap()
-        }
+    /// Number of samples recorded per guest instruction pointer
+    pub(crate) fn rip_samples(&self) -> &FxHashMap<u64, u64> {
+        &self.rip_samples
     }
 
-    /// allocate memory for the stack
-    fn alloc_stack(
-        &mut self,
-        capacity: AlignedNonZeroUsize,
-        base: PhysAddr,
-    ) -> Result<(Region<ReadWrite>, LayoutTableEntry)> {
-        let region = self
-            .manager
-            .alloc::<ReadWrite>(capacity)
-            .map_err(Error::Allocator)?;
+    /// VM exits of the most recent execution
+    pub(crate) fn exits(&self) -> ExitCounts {
+        self.exits
+    }
 
-        // stack grows downwards -> mount address is at the top of the stack
-        let guest_addr = align_floor((base - capacity.get() as u64).as_u64());
-        let phys_addr = PhysAddr::new(guest_addr);
-        let stack = region.set_guest_addr(phys_addr);
+    /// Setup phases completed by the guest during the boot, see `Module::boot_phases`
+    pub(crate) fn boot_phases(&self) -> &[(Phase, Duration)] {
+        &self.boot_phases
+    }
 
-        let size = (capacity.get() as u64 / DefaultAlign::ALIGNMENT) as u32;
-        let entry = LayoutTableEntry::new(
-            phys_addr,
-            phys_addr.as_virt_addr(),
-            size,
-            Flags::PRESENT | Flags::DATA_WRITE | Flags::STACK,
-        );
+    /// Record the completion of a setup phase. Markers outside the boot are ignored, as the phase
+    /// port is not meant to be used by the guest afterward.
+    fn record_boot_phase(&mut self, phase: Option<Phase>) {
+        let Some(start) = self.boot_start else {
+            log::warn!("Guest marked the setup phase {:?} outside the boot", phase);
+            return;
+        };
+        match phase {
+            Some(phase) => self.boot_phases.push((phase, start.elapsed())),
+            None => log::warn!("Guest marked an unknown setup phase"),
+        }
+    }
 
-        Ok((stack, entry))
+    /// Message recorded by the guest panic handler, if any
+    fn panic_message(&self) -> Option<String> {
+        let raw = self.mem_mappings.get(BMVM_PANIC_MESSAGE)?.as_ref()?;
+        let (len, message) = raw.split_first_chunk::<{ size_of::<u64>() }>()?;
+        let len = (u64::from_ne_bytes(*len) as usize).min(message.len());
+        (len > 0).then(|| String::from_utf8_lossy(&message[..len]).into_owned())
     }
 
-    /// allocate shared memory managed
-    fn alloc_shared(
-        &mut self,
-        upper: PhysAddr,
-    ) -> Result<Option<(Region<ReadWrite>, LayoutTableEntry)>> {
-        if self.cfg.shared_memory.get() == 0 {
-            return Ok(None);
+    /// Counters recorded by the guest via `bmvm_guest::metrics`, merged by name
+    pub(crate) fn metrics(&self) -> HashMap<String, u64> {
+        let mut metrics = HashMap::new();
+        let Some(raw) = self.mem_mappings.get(BMVM_METRICS).and_then(|r| r.as_ref()) else {
+            return metrics;
+        };
+
+        let value_offset = offset_of!(MetricSlot, value);
+        for slot in raw.chunks_exact(size_of::<MetricSlot>()) {
+            let len = (slot[0] as usize).min(METRIC_NAME_MAX_LEN);
+            if len == 0 {
+                continue;
+            }
+            let name = String::from_utf8_lossy(&slot[1..1 + len]).into_owned();
+            let value = u64::from_ne_bytes(slot[value_offset..].try_into().unwrap());
+            *metrics.entry(name).or_default() += value;
         }
+        metrics
+    }
 
-        let capacity = self.cfg.shared_memory;
-        let proto = self
-            .manager
-            .alloc::<ReadWrite>(capacity.try_into().unwrap())?;
+    /// Number of bytes of the primary stack used since the guest was loaded. The stack grows
+    /// downward, so the lowest byte differing from the poison marks the deepest usage. A byte
+    /// written with the poison value itself is not detected.
+    pub(crate) fn stack_high_water(&self) -> Result<usize> {
+        let poison = self.cfg.stack_poison.ok_or(Error::StackNotPoisoned)?;
+        let stack = self
+            .mem_mappings
+            .get(self.addrs.stack)
+            .ok_or(Error::VmMemoryMappingNotFound(self.addrs.stack))?;
+        let raw = stack
+            .as_ref()
+            .ok_or(Error::VmMemoryMappingNotReadable(self.addrs.stack))?;
+        let unused = raw.iter().position(|b| *b != poison).unwrap_or(raw.len());
+        Ok(raw.len() - unused)
+    }
 
-        // ensure same address alignment as the shared memory region
-        let addr_base = Self::align_by_ref(
-            upper.as_usize() as u64 - capacity.get() as u64,
-            proto.as_ptr() as u64,
-        );
+    /// Error describing why the guest shut down. Only a page fault leaves a fault address behind,
+    /// any other exception is reported as `Error::UnexpectedExit`.
+    fn shutdown_cause(&mut self) -> Result<Error> {
+        let Some(addr) = self.handle.vcpu.page_fault()? else {
+            log::error!("Unexpected exit reason: Shutdown");
+            let _ = &self.print_debug_info()?;
+            return Ok(Error::UnexpectedExit);
+        };
 
-        // set the address of the region to the aligned address
-        let addr = PhysAddr::new(addr_base.get());
-        let region = proto.set_guest_addr(addr);
+        if self.is_stack_overflow(addr) {
+            let rsp = self.handle.vcpu.read_regs()?.rsp;
+            log::error!("Guest stack overflow: rsp={:#x}", rsp);
+            return Ok(Error::StackOverflow(rsp));
+        }
+        if self.is_foreign_write(addr) {
+            log::error!("Guest wrote to foreign data at {:#x}", addr);
+            return Ok(Error::UnhandledHalt(
+                ExitCode::ForeignWriteViolation,
+                Some(addr),
+            ));
+        }
+        if self.is_out_of_bounds(addr) {
+            log::error!("Guest accessed {:#x} outside the declared regions", addr);
+            let _ = &self.print_debug_info()?;
+            let rip = self.handle.vcpu.read_regs()?.rip;
+            return Ok(Error::OutOfBoundsAccess { addr, rip });
+        }
 
-        // construct the layout table entry
-        let host_vaddr = region.as_ptr() as u64;
-        let size = (self.cfg.shared_memory.get() as u64 / DefaultAlign::ALIGNMENT) as u32;
-        let layout = LayoutTableEntry::empty()
-            .set_paddr(addr)
-            .set_vaddr(VirtAddr::new_truncate(host_vaddr))
-            .set_len(size)
-            .set_flags(Flags::PRESENT | Flags::DATA_SHARED);
+        log::error!("Unexpected exit reason: Shutdown");
+        let _ = &self.print_debug_info()?;
+        Ok(Error::UnexpectedExit)
+    }
 
-        Ok(Some((region, layout)))
+    /// Check if the page fault at `addr` hit the guard page below the stack
+    fn is_stack_overflow(&self, addr: u64) -> bool {
+        let guard = self.addrs.stack_guard.as_virt_addr().as_u64();
+        (guard..self.addrs.stack.as_virt_addr().as_u64()).contains(&addr)
     }
 
-    // TODO: Move to GuestOnly regions (if possible, wait for kernel upgrade)
-    /// Setting up a minimal environment containing paging structure, IDT and GDT to be able to enter
-    /// long mode and start with the actual structure setup by the guest.
-    fn setup_long_mode_env(
-        &mut self,
-        exec: &mut ExecBundle,
-    ) -> Result<(PhysAddr, PhysAddr, PhysAddr)> {
-        // allocate a region for the system structures
-        let size_sys = AlignedNonZeroUsize::new_ceil((IDT_SIZE + GDT_SIZE) as usize).unwrap();
-        let mut sys_region = self
-            .manager
-            .alloc::<ReadWrite>(size_sys)?
-            .set_guest_addr(GUEST_SYSTEM_ADDR());
+    /// Check if the page fault at `addr` hit the read-only view of the shared memory. The view is
+    /// always present, so the fault was caused by a write.
+    fn is_foreign_write(&self, addr: u64) -> bool {
+        self.foreign_view
+            .as_ref()
+            .is_some_and(|v| v.contains(&addr))
+    }
 
-        // write GDT
-        sys_region.write_offset(SYS_REGION_OFFSET_GDT as usize, setup::gdt().as_ref())?;
-        // write LDT
-        sys_region.write_offset(SYS_REGION_OFFSET_IDT as usize, setup::idt().as_ref())?;
-        self.mem_mappings.push(sys_region);
-        exec.layout.push(
-            LayoutTableEntry::empty()
-                .set_paddr(GUEST_SYSTEM_ADDR())
-                .set_vaddr(GUEST_SYSTEM_ADDR().as_virt_addr())
-                .set_len((IDT_PAGE_REQUIRED + GDT_PAGE_REQUIRED) as u32)
-                .set_flags(Flags::PRESENT | Flags::DATA_WRITE),
-        );
+    /// Check if strict memory checking is enabled and the page fault at `addr` is not covered by
+    /// any region of the layout table
+    fn is_out_of_bounds(&self, addr: u64) -> bool {
+        self.cfg.strict_memory
+            && Self::translate_virt(&self.mem_mappings, self.cfg.layout_table, addr).is_err()
+    }
+
+    /// Execute a guest function while the guest is suspended within a hypercall. The register
+    /// state of the suspended hypercall is restored once the callback returned.
+    pub(crate) fn callback_exec<P, R>(&mut self, name: &'static str, params: P) -> Result<R>
+    where
+        P: Params,
+        R: ForeignShareable,
+    {
+        if self.callback_depth >= self.cfg.max_callback_depth {
+            return Err(Error::CallbackDepthExceeded(self.cfg.max_callback_depth));
+        }
+
+        let ptr = self.find_upcall::<P, R>(name)?.ptr().unwrap();
+        let transport = params.into_transport().map_err(Error::UpcallExec)?;
+
+        let saved = self.handle.vcpu.get_regs()?;
+        let prev = self.state;
+        self.handle.vcpu.mutate_regs(|regs| {
+            regs.r8 = transport.primary();
+            regs.r9 = transport.secondary();
+            regs.rip = ptr.as_u64();
+            // leave the red zone of the suspended frame untouched
+            regs.rsp = Stack::align_floor(saved.rsp - 128);
+            log::info!("Calling back function '{}'", name);
+            true
+        })?;
+
+        self.state = State::UpcallExec;
+        self.callback_depth += 1;
+        let result = self.run();
+        self.callback_depth -= 1;
+        result?;
+
+        if self.state != State::Ready {
+            return Err(Error::CallbackNotReturned);
+        }
+
+        let output = self.upcall_result::<R>();
+        self.handle.vcpu.set_regs(saved);
+        self.state = prev;
+        output
+    }
+
+    /// Map `count` additional pages contiguously above the previously requested ones and append
+    /// them to the layout table. Returns the guest virtual base address of the new region.
+    fn request_pages(&mut self, count: u64) -> Result<VirtAddr> {
+        if count == 0 || count > u32::MAX as u64 || !LayoutTableEntry::is_valid_size(count as u32) {
+            return Err(Error::InvalidPageRequest(count));
+        }
+
+        let size = count * DefaultAlign::ALIGNMENT;
+        let mapped: usize = self.mem_mappings.iter().map(|r| r.capacity().get()).sum();
+        if mapped as u64 + size > self.cfg.max_physical_memory as u64
+            || self.heap_top.as_u64() + size > self.heap_limit.as_u64()
+        {
+            return Err(Error::VmMemoryRequestExceedsMaxMemory(size));
+        }
+
+        // check for a free layout table entry upfront, so no mapping has to be undone
+        let table = self.layout_table_mut()?;
+        let idx = table.len_present();
+        if idx >= table.entries.len() {
+            return Err(Error::LayoutTableFull);
+        }
+
+        let base = self.heap_top;
+        let entry = LayoutTableEntry::empty()
+            .set_paddr(base)
+            .set_vaddr(base.as_virt_addr())
+            .set_len(count as u32)
+            .set_flags(Flags::PRESENT | Flags::DATA_WRITE);
+
+        let capacity = AlignedNonZeroUsize::new_aligned(size as usize).unwrap();
+        let region = self
+            .manager
+            .alloc::<ReadWrite>(capacity)?
+            .set_guest_addr(base);
+
+        let state = self
+            .paging
+            .as_mut()
+            .ok_or(Error::VmMemoryMappingNotFound(self.addrs.paging))?;
+        let (tables, revert) = paging::extend(
+            &self.manager,
+            state,
+            &[entry],
+            self.addrs.paging,
+            NonZeroUsize::new(ADDITIONAL_PAGE_ALLOC).unwrap(),
+        )?;
+
+        if let Err(err) = self.map_requested(tables, region) {
+            // the unmapped tables are released, so the paging structure must not reference them
+            if let Some(state) = self.paging.as_mut() {
+                revert.apply(state);
+            }
+            return Err(err);
+        }
+        self.layout_table_mut()?.entries[idx] = entry;
+
+        self.heap_top = base + size;
+        log::debug!("Mapped {} pages at {:#x} on guest request", count, base);
+        Ok(base.as_virt_addr())
+    }
+
+    /// Make the memory of a page request and its page tables available to the guest.
+    fn map_requested(
+        &mut self,
+        tables: Vec<Region<ReadWrite>>,
+        mut region: Region<ReadWrite>,
+    ) -> Result<()> {
+        let slot = |vm: &Self| vm.mem_mappings.as_vec().len() as u32;
+        for mut table in tables {
+            table.set_as_guest_memory(&self.handle.vm, slot(self), self.cfg.dirty_log)?;
+            self.paging_size += table.capacity().get();
+            self.mem_mappings.push(table);
+        }
+        region.set_as_guest_memory(&self.handle.vm, slot(self), self.cfg.dirty_log)?;
+        self.mem_mappings.push(region);
+        Ok(())
+    }
+
+    /// Access the layout table shared with the guest
+    fn layout_table_mut(&mut self) -> Result<&mut LayoutTable> {
+        self.mem_mappings
+            .get_mut(self.cfg.layout_table)
+            .and_then(|r| r.as_mut())
+            .and_then(|raw| LayoutTable::from_mut_bytes(raw).ok())
+            .ok_or(Error::VmMemoryMappingNotFound(self.cfg.layout_table))
+    }
+
+    /// Forward a guest log record to the `log` crate, using the guest module path as target.
+    fn guest_log(mappings: &RegionCollection, table: PhysAddr, transport: Transport) -> Result<()> {
+        let packed = transport.secondary();
+        let level = match LogLevel::from_u8((packed >> 56) as u8) {
+            Some(LogLevel::Error) => log::Level::Error,
+            Some(LogLevel::Warn) => log::Level::Warn,
+            Some(LogLevel::Info) => log::Level::Info,
+            Some(LogLevel::Debug) => log::Level::Debug,
+            Some(LogLevel::Trace) | None => log::Level::Trace,
+        };
+        let target_len = ((packed >> 32) & 0xFFFF) as usize;
+        let len = (packed & 0xFFFF_FFFF) as usize;
+        if len > GUEST_LOG_MAX_SIZE || target_len > len {
+            return Err(Error::InvalidLogRecord(len));
+        }
+
+        let mut buf = [0u8; GUEST_LOG_MAX_SIZE];
+        Self::read_virt_in(mappings, table, transport.primary(), &mut buf[..len])?;
+        let target = String::from_utf8_lossy(&buf[..target_len]);
+        let message = String::from_utf8_lossy(&buf[target_len..len]);
+        log::log!(target: &target, level, "{}", message);
+        Ok(())
+    }
+
+    /// Copy or fill guest memory on behalf of the guest, see `HOST_MEMCPY` and `HOST_MEMSET`.
+    fn host_memop(&mut self, sig: Signature, transport: Transport) -> Result<()> {
+        let mut raw = [0u8; 3 * size_of::<u64>()];
+        self.read_virt(transport.primary(), &mut raw)?;
+        let mut words = raw
+            .chunks_exact(size_of::<u64>())
+            .map(|w| u64::from_le_bytes(w.try_into().unwrap()));
+        let (dst, arg, len) = (
+            words.next().unwrap(),
+            words.next().unwrap(),
+            words.next().unwrap() as usize,
+        );
+
+        // the host must not bypass the page permissions of the guest, e.g. to write its code or the
+        // layout table the host relies on
+        let table = self.cfg.layout_table;
+        let (paddr, entry) = Self::lookup_virt(&self.mem_mappings, table, dst)?;
+        let flags = entry.flags();
+        if !flags.is_write() || flags.is_system() || !self.guest_range(paddr, &entry, dst, len) {
+            return Err(Error::VmMemoryMappingNotWritable(paddr));
+        }
+        let src = match sig == HOST_MEMCPY {
+            true => {
+                let (paddr, entry) = Self::lookup_virt(&self.mem_mappings, table, arg)?;
+                if !self.guest_range(paddr, &entry, arg, len) {
+                    return Err(Error::VmMemoryMappingNotReadable(paddr));
+                }
+                Some(Self::virt_slice(&self.mem_mappings, table, arg, len)?.as_ptr())
+            }
+            false => None,
+        };
+        let dst = self.virt_slice_mut(dst, len)?;
+        match src {
+            // SAFETY: both ranges are within mapped regions, which may overlap
+            Some(src) => unsafe { std::ptr::copy(src, dst.as_mut_ptr(), len) },
+            None => dst.fill(arg as u8),
+        }
+        Ok(())
+    }
+
+    fn hypercall_exec(&mut self) -> Result<()> {
+        log::debug!("HYPERCALL TRIGGER");
+
+        // save the current state and set the hypercall state
+        let prev = self.state;
+        self.state = State::HypercallExec;
+
+        // read hypercall parameters
+        let mut regs = self.handle.vcpu.get_regs()?;
+        let sig = regs.rbx;
+        let transport = Transport::new(regs.r8, regs.r9);
+        log::debug!(
+            "Parameter: signature={}, transport={}",
+            self.hypercalls.display(sig),
+            transport
+        );
+
+        // execute the hypercall, allowing the host function to call back into the guest
+        let output = match self.hypercalls.find(sig) {
+            // built-in hypercall, a zero address signals the denied request to the guest
+            _ if sig == REQUEST_PAGES => {
+                let base = self.request_pages(transport.primary()).unwrap_or_else(|e| {
+                    log::warn!("Denied guest page request: {}", e);
+                    VirtAddr::new(0)
+                });
+                Transport::new(base.as_u64(), 0)
+            }
+            // built-in hypercall, malformed records are dropped
+            _ if sig == GUEST_LOG => {
+                if let Err(e) =
+                    Self::guest_log(&self.mem_mappings, self.cfg.layout_table, transport)
+                {
+                    log::warn!("Dropped guest log record: {}", e);
+                }
+                Transport::new(0, 0)
+            }
+            // built-in hypercall, the guest performs a declined operation itself
+            _ if sig == HOST_MEMCPY || sig == HOST_MEMSET => {
+                let result = self.host_memop(sig, transport);
+                if let Err(e) = &result {
+                    log::warn!("Declined guest memory operation: {}", e);
+                }
+                Transport::new(result.is_err() as u64, 0)
+            }
+            // built-in hypercall, suspends the guest until the consumer took the chunk
+            _ if sig == STREAM_CHUNK => Transport::new(self.stream_chunk(transport)?, 0),
+            // built-in hypercall, suspends the guest within a plain upcall until it is resumed
+            _ if sig == YIELD => {
+                self.yielded =
+                    prev == State::UpcallExec && self.callback_depth == 0 && self.stream.is_none();
+                Transport::new(0, 0)
+            }
+            Ok(func) => context::enter(self, || func(transport))
+                .map_err(|e| Error::Hypercall(registry::Error::HypercallExec(e)))?,
+            Err(_) => self
+                .hypercalls
+                .resolve_missing(sig)
+                .map_err(Error::Hypercall)?,
+        };
+
+        // the host function may have handled the failed callback, report it regardless
+        if let Some(code) = self.violation {
+            return Err(Error::ProtocolViolation(code));
+        }
+
+        // write the result to the registers
+        regs.r8 = output.primary();
+        regs.r9 = output.secondary();
+        log::debug!("Result: transport={}", output);
+        self.handle.vcpu.set_regs(regs);
+
+        // restore the previous state
+        self.state = prev;
+        Ok(())
+    }
+}
+
+/// Progress of a streaming upcall
+#[derive(Debug, Default)]
+struct Stream {
+    /// Chunk passed by the guest, waiting to be taken by the consumer
+    chunk: Option<Vec<u8>>,
+    /// The guest is suspended within the chunk hypercall
+    suspended: bool,
+    /// The consumer dropped the stream, further chunks are rejected
+    cancelled: bool,
+}
+
+// Implementation regarding streaming upcalls
+impl Vm {
+    /// Setup the guest environment to execute the function at `ptr` as streaming upcall. The guest
+    /// starts running with the first call to `stream_next`.
+    pub(crate) fn stream_setup_raw(&mut self, ptr: FnPtr, transport: Transport) -> Result<()> {
+        self.upcall_exec_setup_raw(ptr, transport)?;
+        self.stream = Some(Stream::default());
+        Ok(())
+    }
+
+    /// Run the streaming upcall until the guest passes the next chunk. Returns `None` once the guest
+    /// returned from the upcall or exited.
+    pub(crate) fn stream_next(&mut self) -> Result<Option<Vec<u8>>> {
+        let Some(stream) = self.stream.as_mut() else {
+            return Ok(None);
+        };
+        if stream.suspended {
+            stream.suspended = false;
+            // answer the chunk the guest is suspended in, so it stops without producing another
+            if stream.cancelled {
+                self.handle.vcpu.mutate_regs(|regs| {
+                    regs.r8 = STREAM_CANCELLED;
+                    true
+                })?;
+            }
+        }
+
+        let result = self.run();
+        let chunk = self.stream.as_mut().and_then(|s| s.chunk.take());
+        if result.is_err() || chunk.is_none() {
+            self.stream = None;
+        }
+        result.map(|_| chunk)
+    }
+
+    /// Signal the cancellation to the guest and run it until it returned from the streaming upcall.
+    /// Does nothing if the guest already finished.
+    pub(crate) fn stream_cancel(&mut self) -> Result<()> {
+        let Some(stream) = self.stream.as_mut() else {
+            return Ok(());
+        };
+        stream.cancelled = true;
+        while self.stream_next()?.is_some() {}
+        Ok(())
+    }
+
+    /// Copy the chunk passed by the guest and return the stream control word. Chunks outside a
+    /// streaming upcall, e.g. of a nested callback, are rejected like a cancelled stream.
+    fn stream_chunk(&mut self, transport: Transport) -> Result<u64> {
+        let active = self
+            .stream
+            .as_ref()
+            .is_some_and(|s| !s.cancelled && self.callback_depth == 0);
+        if !active {
+            return Ok(STREAM_CANCELLED);
+        }
+
+        let len = transport.secondary();
+        if len > self.cfg.max_physical_memory as u64 {
+            return Err(Error::InvalidStreamChunk(len));
+        }
+        // validate the range before allocating the chunk, the length is chosen by the guest
+        let chunk = Self::virt_slice(
+            &self.mem_mappings,
+            self.cfg.layout_table,
+            transport.primary(),
+            len as usize,
+        )?
+        .to_vec();
+
+        let stream = self.stream.as_mut().unwrap();
+        stream.chunk = Some(chunk);
+        stream.suspended = true;
+        Ok(0)
+    }
+}
+
+/// State shared by the vcpu threads of a parallel upcall
+struct Parallel<'a> {
+    cfg: &'a Config,
+    hypercalls: &'a Hypercalls,
+    mappings: &'a RegionCollection,
+    queue: Mutex<std::vec::IntoIter<(usize, FnPtr, Transport)>>,
+}
+
+// SAFETY: the memory regions are only read from to forward guest log records and hypercalls are
+// plain function pointers. The guest memory itself is not protected, which is why every call has
+// to operate on disjoint buffers.
+unsafe impl Sync for Parallel<'_> {}
+
+// Implementation regarding parallel guest execution
+impl Vm {
+    /// Execute independent guest functions concurrently, each vcpu taking the next pending call
+    /// once its previous one returned. The returned transports are in the order of `calls`.
+    ///
+    /// Hypercalls issued during the calls are executed on the thread of the respective vcpu and
+    /// can not call back into the guest. Page requests of the guest are denied.
+    pub(crate) fn parallel_upcall_raw(
+        &mut self,
+        calls: Vec<(FnPtr, Transport)>,
+    ) -> Result<Vec<Transport>> {
+        let count = calls.len();
+        let queue = calls
+            .into_iter()
+            .enumerate()
+            .map(|(idx, (ptr, transport))| (idx, ptr, transport))
+            .collect::<Vec<_>>()
+            .into_iter();
+        let shared = Parallel {
+            cfg: &self.cfg,
+            hypercalls: &self.hypercalls,
+            mappings: &self.mem_mappings,
+            queue: Mutex::new(queue),
+        };
+
+        // the primary vcpu continues from its current stack and is restored afterwards
+        let handle = &mut *self.handle;
+        let saved = handle.vcpu.get_regs()?;
+        let primary = VirtAddr::new(saved.rsp);
+
+        let finished = std::thread::scope(|s| {
+            let shared = &shared;
+            let mut threads =
+                vec![s.spawn(|| Self::parallel_worker(&mut handle.vcpu, primary, shared))];
+            for (worker, stack) in handle.workers.iter_mut().zip(&self.worker_stacks) {
+                threads.push(s.spawn(move || Self::parallel_worker(worker, *stack, shared)));
+            }
+
+            threads
+                .into_iter()
+                .map(|t| t.join().unwrap_or(Err(Error::WorkerPanicked)))
+                .collect::<Vec<_>>()
+        });
+        handle.vcpu.set_regs(saved);
+
+        let mut results = vec![Transport::new(0, 0); count];
+        for returned in finished {
+            for (idx, transport) in returned? {
+                results[idx] = transport;
+            }
+        }
+        Ok(results)
+    }
+
+    /// Execute pending calls on `vcpu` until the queue is empty. Every call starts at `stack`.
+    fn parallel_worker(
+        vcpu: &mut Vcpu,
+        stack: VirtAddr,
+        shared: &Parallel,
+    ) -> Result<Vec<(usize, Transport)>> {
+        let mut returned = Vec::new();
+        loop {
+            let next = shared
+                .queue
+                .lock()
+                .map_err(|_| Error::WorkerPanicked)?
+                .next();
+            let Some((idx, ptr, transport)) = next else {
+                return Ok(returned);
+            };
+
+            vcpu.mutate_regs(|regs| {
+                regs.r8 = transport.primary();
+                regs.r9 = transport.secondary();
+                regs.rip = ptr.as_u64();
+                regs.rsp = stack.as_u64();
+                true
+            })?;
+
+            loop {
+                match vcpu.run()? {
+                    VcpuExit::IoOut(port, _) if port == shared.cfg.hypercall_port => {
+                        Self::parallel_hypercall(vcpu, shared)?;
+                    }
+                    VcpuExit::IoOut(SERIAL_IO_PORT, data) => {
+                        let output = String::from_utf8_lossy(data);
+                        log::info!("Guest: {}", output.trim_end());
+                    }
+                    VcpuExit::IoOut(port, data) if port == shared.cfg.exit_port => {
+                        let data = data.to_vec();
+                        let regs = *vcpu.read_regs()?;
+                        match ExitCode::decode(&data, &regs) {
+                            Some(ExitCode::Return) => break,
+                            None => return Err(Error::UnexpectedExit),
+                            Some(code) => {
+                                let detail = ExitCode::decode_detail(&data);
+                                log::error!(
+                                    "Exit Code during parallel upcall: {:?} (detail: {:?})",
+                                    code,
+                                    detail
+                                );
+                                return Err(Error::UnhandledHalt(code, detail));
+                            }
+                        }
+                    }
+                    VcpuExit::MmioWrite(addr, _) if addr == BMVM_HYPERCALL_MMIO.as_u64() => {
+                        Self::parallel_hypercall(vcpu, shared)?;
+                    }
+                    VcpuExit::Debug(_) => {}
+                    reason => {
+                        log::error!(
+                            "Unexpected exit reason during parallel upcall: {:?}",
+                            reason
+                        );
+                        return Err(Error::UnexpectedExit);
+                    }
+                }
+            }
+
+            let regs = vcpu.read_regs()?;
+            returned.push((idx, Transport::new(regs.r8, regs.r9)));
+        }
+    }
+
+    /// Execute a hypercall of a parallel upcall without access to the VM instance.
+    fn parallel_hypercall(vcpu: &mut Vcpu, shared: &Parallel) -> Result<()> {
+        let mut regs = vcpu.get_regs()?;
+        let sig = regs.rbx;
+        let transport = Transport::new(regs.r8, regs.r9);
+
+        let output = match shared.hypercalls.find(sig) {
+            _ if sig == REQUEST_PAGES => {
+                log::warn!("Denied guest page request during parallel upcall");
+                Transport::new(0, 0)
+            }
+            _ if sig == GUEST_LOG => {
+                if let Err(e) = Self::guest_log(shared.mappings, shared.cfg.layout_table, transport)
+                {
+                    log::warn!("Dropped guest log record: {}", e);
+                }
+                Transport::new(0, 0)
+            }
+            _ if sig == STREAM_CHUNK => Transport::new(STREAM_CANCELLED, 0),
+            _ if sig == YIELD => Transport::new(0, 0),
+            // the memory is not writable without the VM instance, the guest performs it inline
+            _ if sig == HOST_MEMCPY || sig == HOST_MEMSET => Transport::new(1, 0),
+            Ok(func) => {
+                func(transport).map_err(|e| Error::Hypercall(registry::Error::HypercallExec(e)))?
+            }
+            Err(_) => shared
+                .hypercalls
+                .resolve_missing(sig)
+                .map_err(Error::Hypercall)?,
+        };
+
+        regs.r8 = output.primary();
+        regs.r9 = output.secondary();
+        vcpu.set_regs(regs);
+        Ok(())
+    }
+}
+
+// Implementation regarding initial setup
+impl Vm {
+    fn align_by_ref(value: u64, reference: u64) -> AlignedNonZeroU64 {
+        if Page1GiB::is_aligned(reference) {
+            AlignedNonZeroU64::new_aligned(Page1GiB::align_floor(value)).unwrap()
+        } else if Page2MiB::is_aligned(reference) {
+            AlignedNonZeroU64::new_aligned(Page2MiB::align_floor(value)).unwrap()
+        } else {
+            AlignedNonZeroU64::new_aligned(Page4KiB::align_floor(value)).unwrap()
+        }
+    }
+
+    /// Initial stack pointer of a stack ending at `top`, see `ConfigBuilder::align_stack`
+    fn stack_pointer(&self, top: PhysAddr) -> VirtAddr {
+        let align = self.cfg.stack_align.bytes().max(Stack::ALIGNMENT);
+        VirtAddr::new((top.as_virt_addr().as_u64() - 1) & !(align - 1))
+    }
+
+    /// allocate memory for the stack
+    fn alloc_stack(
+        &mut self,
+        capacity: AlignedNonZeroUsize,
+        base: PhysAddr,
+    ) -> Result<(Region<ReadWrite>, LayoutTableEntry)> {
+        let region = self
+            .manager
+            .alloc::<ReadWrite>(capacity)
+            .map_err(Error::Allocator)?;
+
+        // stack grows downwards -> mount address is at the top of the stack
+        let guest_addr = align_floor((base - capacity.get() as u64).as_u64());
+        let phys_addr = PhysAddr::new(guest_addr);
+        let mut stack = region.set_guest_addr(phys_addr);
+        if let Some(byte) = self.cfg.stack_poison {
+            stack.as_mut().fill(byte);
+        } else if self.cfg.zero_memory {
+            stack.as_mut().fill(0);
+        }
+
+        let size = (capacity.get() as u64 / DefaultAlign::ALIGNMENT) as u32;
+        let entry = LayoutTableEntry::new(
+            phys_addr,
+            phys_addr.as_virt_addr(),
+            size,
+            Flags::PRESENT | Flags::DATA_WRITE | Flags::STACK,
+        );
+
+        Ok((stack, entry))
+    }
+
+    /// allocate shared memory managed
+    fn alloc_shared(
+        &mut self,
+        upper: PhysAddr,
+    ) -> Result<Option<(Region<ReadWrite>, LayoutTableEntry)>> {
+        if self.cfg.shared_memory.get() == 0 {
+            return Ok(None);
+        }
+
+        let capacity = self.cfg.shared_memory;
+        let proto = self
+            .manager
+            .alloc::<ReadWrite>(capacity.try_into().unwrap())?;
+
+        // ensure same address alignment as the shared memory region
+        let addr_base = Self::align_by_ref(
+            upper.as_usize() as u64 - capacity.get() as u64,
+            proto.as_ptr() as u64,
+        );
+
+        // set the address of the region to the aligned address
+        let addr = PhysAddr::new(addr_base.get());
+        let region = proto.set_guest_addr(addr);
+
+        // construct the layout table entry
+        let host_vaddr = region.as_ptr() as u64;
+        let size = (self.cfg.shared_memory.get() as u64 / DefaultAlign::ALIGNMENT) as u32;
+        let layout = LayoutTableEntry::empty()
+            .set_paddr(addr)
+            .set_vaddr(VirtAddr::new_truncate(host_vaddr))
+            .set_len(size)
+            .set_flags(Flags::PRESENT | Flags::DATA_SHARED);
+
+        Ok(Some((region, layout)))
+    }
+
+    /// Read-only view of the shared memory placed right behind it, through which the guest reads
+    /// data owned by the host. Writing to it faults, see `ExitCode::ForeignWriteViolation`.
+    fn foreign_view(shared: &LayoutTableEntry) -> LayoutTableEntry {
+        shared
+            .set_vaddr(VirtAddr::new_truncate(shared.vaddr_raw() + shared.size()))
+            .set_flags(Flags::PRESENT | Flags::DATA_FOREIGN)
+    }
+
+    /// layout entry of the MMIO hypercall doorbell. No region is allocated for it, so guest writes
+    /// exit with `VcpuExit::MmioWrite`.
+    fn hypercall_doorbell() -> LayoutTableEntry {
+        LayoutTableEntry::empty()
+            .set_paddr(BMVM_HYPERCALL_MMIO)
+            .set_vaddr(BMVM_HYPERCALL_MMIO.as_virt_addr())
+            .set_len(1)
+            .set_flags(Flags::PRESENT | Flags::SYSTEM | Flags::DATA_WRITE)
+    }
+
+    /// Address and length of the guest arguments blob behind its length prefix, passed to the
+    /// guest entry. Both are zero if no arguments are configured.
+    fn guest_args_entry(&self) -> (VirtAddr, u64) {
+        match self.cfg.guest_args.len() {
+            0 => (VirtAddr::new(0), 0),
+            len => (
+                (BMVM_GUEST_ARGS + size_of::<u64>() as u64).as_virt_addr(),
+                len as u64,
+            ),
+        }
+    }
+
+    /// allocate the region containing the length-prefixed guest arguments
+    fn alloc_guest_args(&mut self) -> Result<Option<(Region<ReadWrite>, LayoutTableEntry)>> {
+        if self.cfg.guest_args.is_empty() {
+            return Ok(None);
+        }
+
+        let args = self.cfg.guest_args.as_slice();
+        let required = size_of::<u64>() + args.len();
+        if !Self::guest_args_fit(&self.cfg) {
+            return Err(Error::GuestArgsTooLarge(args.len()));
+        }
+
+        let capacity = AlignedNonZeroUsize::new_ceil(required).unwrap();
+        let mut region = self
+            .manager
+            .alloc::<ReadWrite>(capacity)?
+            .set_guest_addr(BMVM_GUEST_ARGS);
+        region.write_offset(0, &(args.len() as u64).to_ne_bytes())?;
+        region.write_offset(size_of::<u64>(), args)?;
+
+        let size = (capacity.get() as u64 / DefaultAlign::ALIGNMENT) as u32;
+        let layout = LayoutTableEntry::empty()
+            .set_paddr(BMVM_GUEST_ARGS)
+            .set_vaddr(BMVM_GUEST_ARGS.as_virt_addr())
+            .set_len(size)
+            .set_flags(Flags::PRESENT | Flags::SYSTEM | Flags::DATA_READ);
+
+        Ok(Some((region, layout)))
+    }
+
+    /// allocate the region the guest panic handler writes the length-prefixed message to
+    fn alloc_panic_message(&mut self) -> Result<(Region<ReadWrite>, LayoutTableEntry)> {
+        let capacity = AlignedNonZeroUsize::new_ceil(BMVM_PANIC_MESSAGE_SIZE).unwrap();
+        let mut region = self
+            .manager
+            .alloc::<ReadWrite>(capacity)?
+            .set_guest_addr(BMVM_PANIC_MESSAGE);
+        // no message recorded yet
+        region.write_offset(0, &0u64.to_ne_bytes())?;
+
+        let size = (capacity.get() as u64 / DefaultAlign::ALIGNMENT) as u32;
+        let layout = LayoutTableEntry::empty()
+            .set_paddr(BMVM_PANIC_MESSAGE)
+            .set_vaddr(BMVM_PANIC_MESSAGE.as_virt_addr())
+            .set_len(size)
+            .set_flags(Flags::PRESENT | Flags::SYSTEM | Flags::DATA_WRITE);
+
+        Ok((region, layout))
+    }
+
+    /// allocate the zeroed table the guest counts its metrics in
+    fn alloc_metrics(&mut self) -> Result<(Region<ReadWrite>, LayoutTableEntry)> {
+        let capacity = AlignedNonZeroUsize::new_ceil(BMVM_METRICS_SIZE).unwrap();
+        let mut region = self
+            .manager
+            .alloc::<ReadWrite>(capacity)?
+            .set_guest_addr(BMVM_METRICS);
+        // no slot claimed yet, even if the memory is not zeroed on allocation
+        region.write_offset(0, &[0; BMVM_METRICS_SIZE])?;
+
+        let size = (capacity.get() as u64 / DefaultAlign::ALIGNMENT) as u32;
+        let layout = LayoutTableEntry::empty()
+            .set_paddr(BMVM_METRICS)
+            .set_vaddr(BMVM_METRICS.as_virt_addr())
+            .set_len(size)
+            .set_flags(Flags::PRESENT | Flags::SYSTEM | Flags::DATA_WRITE);
+
+        Ok((region, layout))
+    }
+
+    /// Check if the guest arguments fit into their region, including the length prefix
+    fn guest_args_fit(cfg: &Config) -> bool {
+        size_of::<u64>() + cfg.guest_args.len() <= BMVM_GUEST_ARGS_MAX_SIZE
+    }
+
+    /// The port assigned more than once, if any. Port zero is reserved, as it disables the boot
+    /// markers in the guest.
+    fn io_port_conflict(cfg: &Config) -> Option<u16> {
+        let boot = cfg.boot_markers.then_some(cfg.boot_port);
+        let mut assigned = vec![0, SERIAL_IO_PORT];
+        for port in [Some(cfg.hypercall_port), Some(cfg.exit_port), boot]
+            .into_iter()
+            .flatten()
+        {
+            if assigned.contains(&port) {
+                return Some(port);
+            }
+            assigned.push(port);
+        }
+        None
+    }
+
+    /// allocate the region containing the configured hypercall, exit and boot port. The guest
+    /// falls back to the default ports without boot markers if the region is not mapped.
+    fn alloc_io_ports(&mut self) -> Result<Option<(Region<ReadWrite>, LayoutTableEntry)>> {
+        let (hypercall, exit) = (self.cfg.hypercall_port, self.cfg.exit_port);
+        if let Some(port) = Self::io_port_conflict(&self.cfg) {
+            return Err(Error::IoPortConflict(port));
+        }
+        if hypercall == HYPERCALL_IO_PORT && exit == EXIT_IO_PORT && !self.cfg.boot_markers {
+            return Ok(None);
+        }
+
+        let boot = match self.cfg.boot_markers {
+            true => self.cfg.boot_port,
+            false => 0,
+        };
+        let capacity = AlignedNonZeroUsize::new_ceil(3 * size_of::<u16>()).unwrap();
+        let mut region = self
+            .manager
+            .alloc::<ReadWrite>(capacity)?
+            .set_guest_addr(BMVM_IO_PORTS);
+        region.write_offset(0, &hypercall.to_ne_bytes())?;
+        region.write_offset(size_of::<u16>(), &exit.to_ne_bytes())?;
+        region.write_offset(2 * size_of::<u16>(), &boot.to_ne_bytes())?;
+
+        let size = (capacity.get() as u64 / DefaultAlign::ALIGNMENT) as u32;
+        let layout = LayoutTableEntry::empty()
+            .set_paddr(BMVM_IO_PORTS)
+            .set_vaddr(BMVM_IO_PORTS.as_virt_addr())
+            .set_len(size)
+            .set_flags(Flags::PRESENT | Flags::SYSTEM | Flags::DATA_READ);
+
+        Ok(Some((region, layout)))
+    }
+
+    /// map the configured host file read-only next to the executable or at the requested virtual
+    /// address, if it does not collide with any region in `layout`
+    fn map_file(
+        &mut self,
+        layout: &[LayoutTableEntry],
+    ) -> Result<Option<(Region<ReadOnly>, LayoutTableEntry)>> {
+        let Some((path, hint)) = self.cfg.mapped_file.clone() else {
+            return Ok(None);
+        };
+
+        let file = File::open(&path).map_err(|e| Error::MapFile(path.clone(), e))?;
+        let len = file
+            .metadata()
+            .map_err(|e| Error::MapFile(path.clone(), e))?
+            .len();
+        let Some(capacity) = AlignedNonZeroUsize::new_ceil(len as usize) else {
+            log::warn!("Mapped file {} is empty, skipping", path.display());
+            return Ok(None);
+        };
+        let size = capacity.get() as u64;
+        let pages = (size / DefaultAlign::ALIGNMENT) as u32;
+        let end = self.heap_top.as_u64().checked_add(size);
+        if !LayoutTableEntry::is_valid_size(pages)
+            || end.is_none_or(|end| end > self.heap_limit.as_u64())
+        {
+            return Err(Error::MappedFileTooLarge(len));
+        }
+        // writes of the guest must not reach the file, even if it maps the pages writable
+        if !self.handle.kvm.check_extension(Cap::ReadonlyMem) {
+            return Err(Error::KvmMissingCapability(Cap::ReadonlyMem));
+        }
+
+        // fall back to the identity mapping, if the requested address is taken
+        let paddr = self.heap_top;
+        let vaddr = hint
+            .filter(|hint| self.file_vaddr_free(*hint, size, layout))
+            .unwrap_or(paddr.as_u64());
+        if let Some(hint) = hint.filter(|hint| *hint != vaddr) {
+            log::warn!(
+                "Virtual address {:#x} for {} is taken, mapping it at {:#x}",
+                hint,
+                path.display(),
+                vaddr
+            );
+        }
+        if !self.file_vaddr_free(vaddr, size, layout) {
+            return Err(Error::MappedFileAddr(vaddr));
+        }
+
+        let region = self
+            .manager
+            .map_file(&file, capacity)?
+            .set_guest_addr(paddr);
+        self.heap_top = paddr + size;
+
+        let layout = LayoutTableEntry::empty()
+            .set_paddr(paddr)
+            .set_vaddr(VirtAddr::new(vaddr))
+            .set_len(pages)
+            .set_flags(Flags::PRESENT | Flags::DATA_READ | Flags::MAPPED_FILE)
+            .set_tail_padding((size - len) as u16);
+        log::debug!("Mapped {} at {:#x}", path.display(), vaddr);
+
+        Ok(Some((region, layout)))
+    }
+
+    /// Check that `size` bytes at `vaddr` are page aligned, within the lower canonical half below
+    /// the system region and neither collide with the layout table nor with a region in `layout`
+    fn file_vaddr_free(&self, vaddr: u64, size: u64, layout: &[LayoutTableEntry]) -> bool {
+        let Some(end) = vaddr.checked_add(size) else {
+            return false;
+        };
+        let reserved =
+            |e: &LayoutTableEntry| vaddr < e.vaddr_raw() + e.size() && e.vaddr_raw() < end;
+
+        DefaultAlign::is_aligned(vaddr)
+            && end <= self.addrs.system.as_u64()
+            && !(vaddr..end).contains(&self.cfg.layout_table.as_u64())
+            && !layout.iter().any(reserved)
+    }
+
+    /// Memory of the executable, if already parsed, the stacks of all vCPUs and the shared memory.
+    /// Runtime requests of the guest are limited to the remainder of `max_physical_memory`.
+    fn requested_memory(cfg: &Config, exec: Option<&ExecBundle>) -> u64 {
+        let exec = exec.map_or(0, |e| e.layout.iter().map(|e| e.size()).sum());
+        (cfg.stack_size.get() as u64)
+            .saturating_mul(cfg.vcpus as u64)
+            .saturating_add(cfg.shared_memory.get() as u64)
+            .saturating_add(exec)
+    }
+
+    /// Check that the configured layout table page is aligned, not null, below the system region
+    /// and does not collide with a region in `layout`.
+    fn layout_table_addr_free(cfg: &Config, layout: &[LayoutTableEntry]) -> bool {
+        let addr = cfg.layout_table.as_u64();
+        let end = addr.saturating_add(Page4KiB::ALIGNMENT);
+        let overlaps = |start: u64, size: u64| start < end && addr < start + size;
+
+        addr != 0
+            && Page4KiB::is_aligned(addr)
+            && end <= GuestAddrs::new(cfg).system.as_u64()
+            && !layout
+                .iter()
+                .any(|e| overlaps(e.paddr_raw(), e.size()) || overlaps(e.vaddr_raw(), e.size()))
+    }
+
+    /// Like `layout_table_addr_free`, but the page must not collide with the memory reserved for
+    /// runtime requests either.
+    fn layout_table_addr_valid(&self, layout: &[LayoutTableEntry]) -> bool {
+        let addr = self.cfg.layout_table.as_u64();
+        let end = addr.saturating_add(Page4KiB::ALIGNMENT);
+        let heap = self.heap_top.as_u64()..self.heap_limit.as_u64();
+
+        Self::layout_table_addr_free(&self.cfg, layout)
+            && (heap.is_empty() || heap.start >= end || addr >= heap.end)
+    }
+
+    // TODO: Move to GuestOnly regions (if possible, wait for kernel upgrade)
+    /// Setting up a minimal environment containing paging structure, IDT and GDT to be able to enter
+    /// long mode and start with the actual structure setup by the guest.
+    fn setup_long_mode_env(
+        &mut self,
+        exec: &mut ExecBundle,
+    ) -> Result<(PhysAddr, PhysAddr, PhysAddr)> {
+        // allocate a region for the system structures
+        let size_sys = AlignedNonZeroUsize::new_ceil((IDT_SIZE + GDT_SIZE) as usize).unwrap();
+        let mut sys_region = self
+            .manager
+            .alloc::<ReadWrite>(size_sys)?
+            .set_guest_addr(self.addrs.system);
+
+        // write GDT
+        sys_region.write_offset(SYS_REGION_OFFSET_GDT as usize, setup::gdt().as_ref())?;
+        // write LDT
+        sys_region.write_offset(SYS_REGION_OFFSET_IDT as usize, setup::idt().as_ref())?;
+        self.mem_mappings.push(sys_region);
+        exec.layout.push(
+            LayoutTableEntry::empty()
+                .set_paddr(self.addrs.system)
+                .set_vaddr(self.addrs.system.as_virt_addr())
+                .set_len((IDT_PAGE_REQUIRED + GDT_PAGE_REQUIRED) as u32)
+                .set_flags(Flags::PRESENT | Flags::DATA_WRITE),
+        );
 
         // Empty init the layout region
+        let table = self.cfg.layout_table;
+        if !self.layout_table_addr_valid(&exec.layout) {
+            return Err(Error::LayoutTableAddr(table));
+        }
         let layout = AlignedNonZeroUsize::new_aligned(Page4KiB::ALIGNMENT as usize).unwrap();
         let mut layout_region = self
             .manager
             .alloc::<ReadWrite>(layout)?
-            .set_guest_addr(BMVM_MEM_LAYOUT_TABLE);
+            .set_guest_addr(table);
         exec.layout.push(
             LayoutTableEntry::empty()
-                .set_paddr(BMVM_MEM_LAYOUT_TABLE)
-                .set_vaddr(BMVM_MEM_LAYOUT_TABLE.as_virt_addr())
+                .set_paddr(table)
+                .set_vaddr(table.as_virt_addr())
                 .set_len(1)
                 .set_flags(Flags::PRESENT | Flags::DATA_READ),
         );
 
         // setup the paging structure
-        let regions = paging::setup(
+        let setup = match self.cfg.page_table_cache {
+            true => paging::setup_cached,
+            false => paging::setup,
+        };
+        let (regions, paging) = setup(
             &self.manager,
             exec.layout.as_slice(),
-            GUEST_PAGING_ADDR(),
+            self.addrs.paging,
             NonZeroUsize::new(INITIAL_PAGE_ALLOC).unwrap(),
             NonZeroUsize::new(ADDITIONAL_PAGE_ALLOC).unwrap(),
+            self.cfg.track_access || self.cfg.strict_memory,
         )?;
 
         // fill the layout table with the allocated regions
@@ -489,10 +2024,11 @@ impl Vm {
             self.mem_mappings.push(r);
         }
         self.paging_size = paging_size;
+        self.paging = Some(paging);
 
-        let gdt = GUEST_SYSTEM_ADDR() + SYS_REGION_OFFSET_GDT;
-        let idt = GUEST_SYSTEM_ADDR() + SYS_REGION_OFFSET_IDT;
-        let paging = GUEST_PAGING_ADDR();
+        let gdt = self.addrs.system + SYS_REGION_OFFSET_GDT;
+        let idt = self.addrs.system + SYS_REGION_OFFSET_IDT;
+        let paging = self.addrs.paging;
 
         Ok((gdt, idt, paging))
     }
@@ -501,11 +2037,12 @@ impl Vm {
     fn setup_cpu(
         &mut self,
         entry_point: VirtAddr,
+        tls: Option<VirtAddr>,
         gdt: PhysAddr,
         idt: PhysAddr,
         paging: PhysAddr,
     ) -> Result<()> {
-        let setup = vcpu::Setup {
+        let mut setup = vcpu::Setup {
             gdt: vcpu::Gdt {
                 addr: gdt,
                 entries: 3,
@@ -517,17 +2054,267 @@ impl Vm {
                 entries: 0,
             },
             paging,
-            stack: (GUEST_STACK_ADDR().as_virt_addr() - 1).align_floor::<Stack>(),
+            stack: self.stack_pointer(self.addrs.stack_top),
             entry: entry_point,
-            cpu_id: setup::cpuid(&self.kvm)?,
+            layout_table: self.cfg.layout_table.as_virt_addr(),
+            args: self.guest_args_entry(),
+            cpu_id: setup::cpuid(&self.handle.kvm, &self.cfg.cpuid)?,
+            tls,
         };
 
-        self.vcpu.setup(&setup).map_err(Error::Vcpu)
+        self.handle.vcpu.setup(&setup).map_err(Error::Vcpu)?;
+
+        // additional vcpus share the system structures, but start on their own stack. The single TLS
+        // block belongs to the primary vcpu.
+        setup.tls = None;
+        while self.handle.workers.len() < self.worker_stacks.len() {
+            let id = self.handle.workers.len() as u64 + 1;
+            let worker = Vcpu::new(&self.handle.vm, id)?;
+            self.handle.workers.push(worker);
+        }
+        for (worker, stack) in self.handle.workers.iter_mut().zip(&self.worker_stacks) {
+            setup.stack = *stack;
+            worker.setup(&setup)?;
+        }
+
+        Ok(())
+    }
+}
+
+// Implementation regarding vm snapshots
+impl Vm {
+    /// Copy the writable regions and registers of the idle guest
+    pub(crate) fn snapshot(&mut self) -> Result<Snapshot> {
+        if self.state != State::Ready {
+            return Err(snapshot::Error::NotIdle.into());
+        }
+
+        let chunks = self
+            .mem_mappings
+            .iter()
+            .filter(|r| r.writeable())
+            .filter_map(|r| Some((r.addr(), Box::from(r.as_ref()?))))
+            .collect();
+        self.reset_dirty_tracking()?;
+
+        let snapshot = Snapshot::new(None, self.registers()?, self.snapshot_layout(), chunks);
+        self.last_snapshot = Some(snapshot.id);
+        Ok(snapshot)
+    }
+
+    /// Copy the pages modified since `base`, which has to be the most recent snapshot. Pages written
+    /// by the guest are taken from the KVM dirty log, those written by the host are tracked
+    /// separately.
+    pub(crate) fn snapshot_incremental(&mut self, base: &Snapshot) -> Result<Snapshot> {
+        if !self.cfg.dirty_log {
+            return Err(snapshot::Error::DirtyLogDisabled.into());
+        }
+        if self.state != State::Ready {
+            return Err(snapshot::Error::NotIdle.into());
+        }
+        if self.last_snapshot != Some(base.id) {
+            return Err(snapshot::Error::StaleBase.into());
+        }
+        if self.snapshot_layout() != base.layout {
+            return Err(snapshot::Error::LayoutMismatch.into());
+        }
+
+        let mut pages = BTreeSet::new();
+        for region in self.mem_mappings.iter().filter(|r| r.writeable()) {
+            let Some(slot) = region.slot() else {
+                continue;
+            };
+            let bitmap = self
+                .handle
+                .vm
+                .get_dirty_log(slot, region.capacity().get())
+                .map_err(Error::Vm)?;
+            let addr = region.addr().as_u64();
+            pages.extend(snapshot::dirty_pages(&bitmap).map(|i| addr + (i * PAGE_SIZE) as u64));
+        }
+        pages.extend(self.host_dirty.drain());
+
+        if let (Some(addr), Some(shadow)) = (self.shared_addr, self.shared_shadow.as_mut()) {
+            let current = self
+                .mem_mappings
+                .get(addr)
+                .and_then(|r| r.as_ref())
+                .ok_or(Error::VmMemoryMappingNotReadable(addr))?;
+            let addr = addr.as_u64();
+            pages.extend(
+                snapshot::changed_pages(current, shadow).map(|i| addr + (i * PAGE_SIZE) as u64),
+            );
+            shadow.copy_from_slice(current);
+        }
+
+        let chunks = pages
+            .into_iter()
+            .map(|addr| {
+                let addr = PhysAddr::new(addr);
+                let region = self
+                    .mem_mappings
+                    .get(addr)
+                    .ok_or(Error::VmMemoryMappingNotFound(addr))?;
+                let offset = (addr - region.addr()) as usize;
+                let page = region
+                    .as_ref()
+                    .and_then(|raw| raw.get(offset..offset + PAGE_SIZE))
+                    .ok_or(Error::VmMemoryMappingNotReadable(addr))?;
+                Ok((addr, Box::from(page)))
+            })
+            .collect::<Result<Vec<_>>>()?;
+
+        let snapshot = Snapshot::new(
+            Some(base.id),
+            self.registers()?,
+            base.layout.clone(),
+            chunks,
+        );
+        self.last_snapshot = Some(snapshot.id);
+        Ok(snapshot)
+    }
+
+    /// Write back the memory and registers of `base` followed by each of `deltas`, leaving the
+    /// guest idle.
+    pub(crate) fn restore(&mut self, base: &Snapshot, deltas: &[Snapshot]) -> Result<()> {
+        if matches!(self.state, State::PreSetup | State::Booting) {
+            return Err(snapshot::Error::NotBooted.into());
+        }
+        Snapshot::check_chain(base, deltas)?;
+        if self.snapshot_layout() != base.layout {
+            return Err(snapshot::Error::LayoutMismatch.into());
+        }
+
+        let chunks = base
+            .chunks
+            .iter()
+            .chain(deltas.iter().flat_map(|d| &d.chunks));
+        for (addr, data) in chunks {
+            let region = self
+                .mem_mappings
+                .get_mut(*addr)
+                .ok_or(Error::VmMemoryMappingNotFound(*addr))?;
+            let offset = (*addr - region.addr()) as usize;
+            region
+                .as_mut()
+                .and_then(|raw| raw.get_mut(offset..offset + data.len()))
+                .ok_or(Error::VmMemoryMappingNotWritable(*addr))?
+                .copy_from_slice(data);
+        }
+
+        let last = deltas.last().unwrap_or(base);
+        self.set_registers(&last.registers)?;
+        self.state = State::Ready;
+        self.exit_value = None;
+        self.yielded = false;
+        self.reset_dirty_tracking()?;
+        self.last_snapshot = Some(last.id);
+        Ok(())
+    }
+
+    /// Address and capacity of all mapped regions, which have to match for snapshots to apply
+    fn snapshot_layout(&self) -> Vec<(PhysAddr, usize)> {
+        self.mem_mappings
+            .iter()
+            .map(|r| (r.addr(), r.capacity().get()))
+            .collect()
+    }
+
+    /// Start tracking modified pages anew: clear the KVM dirty log and the host written pages and
+    /// record the current content of the shared memory.
+    fn reset_dirty_tracking(&mut self) -> Result<()> {
+        if !self.cfg.dirty_log {
+            return Ok(());
+        }
+
+        for region in self.mem_mappings.iter().filter(|r| r.writeable()) {
+            if let Some(slot) = region.slot() {
+                self.handle
+                    .vm
+                    .get_dirty_log(slot, region.capacity().get())
+                    .map_err(Error::Vm)?;
+            }
+        }
+        self.host_dirty.clear();
+        self.shared_shadow = self
+            .shared_addr
+            .and_then(|addr| self.mem_mappings.get(addr))
+            .and_then(|r| r.as_ref())
+            .map(Box::from);
+        Ok(())
     }
 }
 
 // Implementation regarding vm debugging
 impl Vm {
+    /// Execute exactly one guest instruction. Hypercalls triggered by the instruction are executed
+    /// as usual, guest output on the serial port is logged.
+    pub(crate) fn step(&mut self) -> Result<StepOutcome> {
+        self.handle.vcpu.enable_single_step()?;
+        let outcome = self.step_exec();
+        if !self.cfg.debug {
+            self.handle.vcpu.disable_single_step()?;
+        }
+        outcome
+    }
+
+    fn step_exec(&mut self) -> Result<StepOutcome> {
+        loop {
+            let exit = match self.handle.vcpu.run() {
+                Err(e) if e.is_interrupted() => continue,
+                exit => exit?,
+            };
+
+            match exit {
+                VcpuExit::Debug(_) => {
+                    let regs = self.handle.vcpu.read_regs()?;
+                    return Ok(StepOutcome::Stepped(Registers::from(regs)));
+                }
+                // the instruction completes once the vcpu resumes, which raises the debug exit
+                VcpuExit::IoOut(port, _) if port == self.cfg.hypercall_port => {
+                    self.hypercall_exec()?;
+                }
+                VcpuExit::MmioWrite(addr, _) if addr == BMVM_HYPERCALL_MMIO.as_u64() => {
+                    self.hypercall_exec()?;
+                }
+                VcpuExit::IoOut(SERIAL_IO_PORT, data) => {
+                    let output = String::from_utf8_lossy(data);
+                    log::info!("Guest: {}", output.trim_end());
+                }
+                VcpuExit::IoOut(port, data)
+                    if self.cfg.boot_markers && port == self.cfg.boot_port =>
+                {
+                    let phase = data.first().copied().and_then(Phase::from_u8);
+                    self.record_boot_phase(phase);
+                }
+                VcpuExit::IoOut(port, data) if port == self.cfg.exit_port => {
+                    let data = data.to_vec();
+                    let regs = *self.handle.vcpu.read_regs()?;
+                    let code = ExitCode::decode(&data, &regs).ok_or(Error::UnexpectedExit)?;
+                    return Ok(StepOutcome::Exited(code, ExitCode::decode_detail(&data)));
+                }
+                reason => {
+                    log::error!("Unexpected exit reason during single step: {:?}", reason);
+                    return Err(Error::UnexpectedExit);
+                }
+            }
+        }
+    }
+
+    /// General purpose registers of the vcpu
+    pub(crate) fn registers(&mut self) -> Result<Registers> {
+        Ok(Registers::from(self.handle.vcpu.read_regs()?))
+    }
+
+    /// Overwrite the general purpose registers of the vcpu, applied with the next execution
+    pub(crate) fn set_registers(&mut self, registers: &Registers) -> Result<()> {
+        self.handle.vcpu.mutate_regs(|regs| {
+            registers.apply(regs);
+            true
+        })?;
+        Ok(())
+    }
+
     /// dump specific region based on exit code
     fn react_to_exit_code(&mut self, code: ExitCode) -> Result<()> {
         match code {
@@ -540,14 +2327,12 @@ impl Vm {
 
     /// print the basic debug information: registers and optionally the page fault region
     fn print_debug_info(&mut self) -> Result<()> {
-        let (regs, sregs) = self.vcpu.read_all_regs()?;
-        // Store the relevant register values to avoid holding the mutable borrow
-
+        let regs = self.handle.vcpu.read_regs()?;
         // Print registers before getting memory to avoid borrow conflict
         log::debug!("Register: {}", Self::print_kvm_regs(regs));
 
-        if sregs.cr2 != 0 {
-            log::error!("PAGE FAULT at -> {:#x}", sregs.cr2);
+        if let Some(addr) = self.handle.vcpu.page_fault()? {
+            log::error!("PAGE FAULT at -> {:#x}", addr);
             self.dump_paging()?;
         }
 
@@ -561,17 +2346,211 @@ impl Vm {
 
     fn dump_paging(&self) -> Result<()> {
         let mut file =
-            std::fs::File::create(format!("dump_layout_{:x}.bin", BMVM_MEM_LAYOUT_TABLE)).unwrap();
+            std::fs::File::create(format!("dump_layout_{:x}.bin", self.cfg.layout_table)).unwrap();
         self.mem_mappings.dump(
-            BMVM_MEM_LAYOUT_TABLE,
+            self.cfg.layout_table,
             Page4KiB::ALIGNMENT as usize,
             &mut file,
         )?;
 
         let mut file =
-            std::fs::File::create(format!("dump_paging_{:x}.bin", GUEST_PAGING_ADDR())).unwrap();
+            std::fs::File::create(format!("dump_paging_{:x}.bin", self.addrs.paging)).unwrap();
         self.mem_mappings
-            .dump(GUEST_PAGING_ADDR(), self.paging_size, &mut file)?;
+            .dump(self.addrs.paging, self.paging_size, &mut file)?;
+        Ok(())
+    }
+
+    /// Read `buf.len()` bytes starting at the guest virtual address `addr`. The address is
+    /// translated via the layout table and the read must not cross region boundaries.
+    pub(crate) fn read_virt(&self, addr: u64, buf: &mut [u8]) -> Result<()> {
+        Self::read_virt_in(&self.mem_mappings, self.cfg.layout_table, addr, buf)
+    }
+
+    fn read_virt_in(
+        mappings: &RegionCollection,
+        table: PhysAddr,
+        addr: u64,
+        buf: &mut [u8],
+    ) -> Result<()> {
+        let src = Self::virt_slice(mappings, table, addr, buf.len())?;
+        buf.copy_from_slice(src);
+        Ok(())
+    }
+
+    /// Host memory of the `len` bytes at the guest virtual address `addr`, which must not cross
+    /// region boundaries.
+    fn virt_slice(
+        mappings: &RegionCollection,
+        table: PhysAddr,
+        addr: u64,
+        len: usize,
+    ) -> Result<&[u8]> {
+        let paddr = Self::translate_virt(mappings, table, addr)?;
+        let region = mappings
+            .get(paddr)
+            .ok_or(Error::VmMemoryMappingNotFound(paddr))?;
+        let raw = region
+            .as_ref()
+            .ok_or(Error::VmMemoryMappingNotReadable(paddr))?;
+        let offset = (paddr - region.addr()) as usize;
+        offset
+            .checked_add(len)
+            .and_then(|end| raw.get(offset..end))
+            .ok_or(Error::VirtAddrNotMapped(addr.saturating_add(len as u64)))
+    }
+
+    /// Write `data` starting at the guest virtual address `addr`. The address is translated via the
+    /// layout table and the write must not cross region boundaries.
+    pub(crate) fn write_virt(&mut self, addr: u64, data: &[u8]) -> Result<()> {
+        self.virt_slice_mut(addr, data.len())?.copy_from_slice(data);
+        Ok(())
+    }
+
+    /// Writable host memory of the `len` bytes at the guest virtual address `addr`, like
+    /// `virt_slice`. The pages are recorded as written by the host for the dirty log.
+    fn virt_slice_mut(&mut self, addr: u64, len: usize) -> Result<&mut [u8]> {
+        let paddr = Self::translate_virt(&self.mem_mappings, self.cfg.layout_table, addr)?;
+        let region = self
+            .mem_mappings
+            .get_mut(paddr)
+            .ok_or(Error::VmMemoryMappingNotFound(paddr))?;
+        let offset = (paddr - region.addr()) as usize;
+        let raw = region
+            .as_mut()
+            .ok_or(Error::VmMemoryMappingNotWritable(paddr))?;
+        let slice = offset
+            .checked_add(len)
+            .and_then(|end| raw.get_mut(offset..end))
+            .ok_or(Error::VirtAddrNotMapped(addr.saturating_add(len as u64)))?;
+
+        // only a range within the region is written
+        if self.cfg.dirty_log && len > 0 {
+            let first = align_floor(paddr.as_u64());
+            let end = paddr.as_u64() + len as u64;
+            self.host_dirty.extend((first..end).step_by(PAGE_SIZE));
+        }
+        Ok(slice)
+    }
+
+    /// Check that the `len` bytes at the guest virtual address `addr`, translated to `paddr` via
+    /// `entry`, lie within the entry and outside the system structures, which the guest does not
+    /// access directly.
+    fn guest_range(
+        &self,
+        paddr: PhysAddr,
+        entry: &LayoutTableEntry,
+        addr: u64,
+        len: usize,
+    ) -> bool {
+        let end = entry.vaddr_raw() + entry.size();
+        paddr < self.addrs.system && addr.checked_add(len as u64).is_some_and(|e| e <= end)
+    }
+
+    /// Translate the guest virtual address `addr` via the layout table at `table`.
+    fn translate_virt(mappings: &RegionCollection, table: PhysAddr, addr: u64) -> Result<PhysAddr> {
+        Self::lookup_virt(mappings, table, addr).map(|(paddr, _)| paddr)
+    }
+
+    /// Translate the guest virtual address `addr` via the layout table at `table`, returning the
+    /// physical address and the layout entry containing it.
+    fn lookup_virt(
+        mappings: &RegionCollection,
+        table: PhysAddr,
+        addr: u64,
+    ) -> Result<(PhysAddr, LayoutTableEntry)> {
+        let entry = mappings
+            .get(table)
+            .and_then(|r| r.as_ref())
+            .and_then(|raw| LayoutTable::from_bytes(raw).ok())
+            .and_then(|table| {
+                table
+                    .into_iter()
+                    .find(|e| (e.vaddr_raw()..e.vaddr_raw() + e.size()).contains(&addr))
+            })
+            .ok_or(Error::VirtAddrNotMapped(addr))?;
+
+        let paddr = PhysAddr::new(entry.paddr_raw() + (addr - entry.vaddr_raw()));
+        Ok((paddr, entry))
+    }
+
+    /// Registers of the CPUID leaf `function` and subleaf `index` as reported to the guest.
+    pub(crate) fn cpuid(&self, function: u32, index: u32) -> Result<Option<CpuidEntry>> {
+        let cpuid = self.handle.vcpu.get_cpuid()?;
+        let entry = cpuid
+            .as_slice()
+            .iter()
+            .find(|e| {
+                e.function == function
+                    && (e.index == index || e.flags & KVM_CPUID_FLAG_SIGNIFCANT_INDEX == 0)
+            })
+            .map(|e| CpuidEntry {
+                eax: e.eax,
+                ebx: e.ebx,
+                ecx: e.ecx,
+                edx: e.edx,
+            });
+        Ok(entry)
+    }
+
+    /// Evaluate the accessed and dirty bits of the pages mapping the layout table regions. Empty,
+    /// if access tracking is not enabled.
+    pub(crate) fn region_stats(&self) -> Vec<RegionStat> {
+        if !self.cfg.track_access {
+            return Vec::new();
+        }
+
+        let Some(layout) = self
+            .mem_mappings
+            .get(self.cfg.layout_table)
+            .and_then(|r| r.as_ref())
+            .and_then(|raw| LayoutTable::from_bytes(raw).ok())
+        else {
+            return Vec::new();
+        };
+
+        region_stats(layout, self.addrs.paging.as_u64(), |addr| {
+            let region = self.mem_mappings.get(PhysAddr::new(addr))?;
+            let offset = (addr - region.addr().as_u64()) as usize;
+            let raw = region.as_ref()?.get(offset..offset + size_of::<u64>())?;
+            Some(u64::from_ne_bytes(raw.try_into().unwrap()))
+        })
+    }
+
+    /// Write all mapped regions prefixed by a `DumpHeader`. Virtual address and flags are taken
+    /// from the layout table entry with the same physical address, if any.
+    pub(crate) fn dump_memory<W: Write>(&self, w: &mut W) -> Result<()> {
+        let layout = self
+            .mem_mappings
+            .get(self.cfg.layout_table)
+            .and_then(|r| r.as_ref())
+            .and_then(|raw| LayoutTable::from_bytes(raw).ok());
+
+        write_dump_magic(w).map_err(Error::Dump)?;
+        for region in self.mem_mappings.iter() {
+            let Some(data) = region.as_ref() else {
+                log::warn!(
+                    "Skipping unreadable region {:?} in memory dump",
+                    region.addr()
+                );
+                continue;
+            };
+
+            let entry = layout.and_then(|table| {
+                table
+                    .into_iter()
+                    .find(|e| e.paddr_raw() == region.addr().as_u64())
+            });
+            let header = DumpHeader {
+                paddr: region.addr().as_u64(),
+                vaddr: entry.map(|e| e.vaddr_raw()).unwrap_or(0),
+                len: data.len() as u64,
+                flags: entry.map(|e| e.flags()).unwrap_or(Flags::empty()),
+            };
+
+            header.write_to(w).map_err(Error::Dump)?;
+            w.write_all(data).map_err(Error::Dump)?;
+        }
+
         Ok(())
     }
 
@@ -620,7 +2599,7 @@ impl Vm {
 impl Drop for Vm {
     fn drop(&mut self) {
         for entry in self.mem_mappings.iter_mut() {
-            match entry.remove_from_guest_memory(&self.vm) {
+            match entry.remove_from_guest_memory(&self.handle.vm) {
                 Ok(_) => (),
                 Err(err) => log::warn!("Failed to remove from guest memory: {}", err),
             }
@@ -0,0 +1,40 @@
+use bmvm_host::linker;
+use bmvm_host::mem::SharedBuf;
+
+mod common;
+
+/// Host side of the `hash` upcall of the example guest
+fn hash(data: &[u8]) -> [u8; 32] {
+    let mut digest = [0u8; 32];
+    for (i, byte) in data.iter().enumerate() {
+        digest[i % 32] = digest[i % 32].wrapping_mul(31).wrapping_add(*byte);
+    }
+    digest
+}
+
+#[test]
+#[ignore = "requires KVM and the stack-overflow example guest"]
+fn array_returned_by_value() {
+    let linker = linker::ConfigBuilder::new()
+        .register_guest_function::<(SharedBuf,), [u8; 32]>("hash")
+        .register_guest_function::<(SharedBuf,), [u8; 16]>("hash_short");
+    let mut module = common::module(linker);
+    let hash_upcall = module.get_upcall::<(SharedBuf,), [u8; 32]>("hash").unwrap();
+    let hash_short = module
+        .get_upcall::<(SharedBuf,), [u8; 16]>("hash_short")
+        .unwrap();
+
+    let data = (0..100u8).collect::<Vec<_>>();
+    let expected = hash(&data);
+
+    // exceeds the transport, copied out of the shared memory
+    let buf = SharedBuf::from_bytes(&data).unwrap();
+    assert_eq!(hash_upcall.call(&mut module, (buf,)).unwrap(), expected);
+
+    // passed within the transport registers
+    let buf = SharedBuf::from_bytes(&data).unwrap();
+    assert_eq!(
+        hash_short.call(&mut module, (buf,)).unwrap(),
+        expected[..16]
+    );
+}
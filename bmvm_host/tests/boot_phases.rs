@@ -0,0 +1,25 @@
+use bmvm_host::{BootPhase, ConfigBuilder, linker};
+
+mod common;
+
+#[test]
+#[ignore = "requires KVM and the stack-overflow example guest"]
+fn boot_phases() {
+    // without the markers, only the ready exit is recorded
+    let module = common::module(linker::ConfigBuilder::new());
+    let names: Vec<_> = module.boot_phases().iter().map(|(p, _)| *p).collect();
+    assert_eq!(names, [BootPhase::Ready]);
+
+    let module = common::builder(linker::ConfigBuilder::new())
+        .configure_vm(ConfigBuilder::new().boot_markers(true))
+        .build()
+        .unwrap();
+    let phases = module.boot_phases();
+    let names: Vec<_> = phases.iter().map(|(phase, _)| *phase).collect();
+    assert_eq!(names.first(), Some(&BootPhase::LayoutParsed));
+    assert_eq!(names.get(1), Some(&BootPhase::ArenasInitialized));
+    assert_eq!(names.last(), Some(&BootPhase::Ready));
+
+    // the host records the markers in order of arrival
+    assert!(phases.windows(2).all(|w| w[0].1 <= w[1].1));
+}
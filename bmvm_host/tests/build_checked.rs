@@ -0,0 +1,37 @@
+use bmvm_host::mem::PhysAddr;
+use bmvm_host::{ConfigBuilder, Error, linker};
+
+mod common;
+
+#[test]
+#[ignore = "requires the stack-overflow example guest"]
+fn all_errors_reported() {
+    let linker = linker::ConfigBuilder::new()
+        .register_guest_function::<(u64,), u64>("does_not_exist")
+        .register_guest_function::<(u32, u32), ()>("yielding_sum");
+    let vm = ConfigBuilder::new()
+        .hypercall_port(0x3f8)
+        .max_physical_memory(1024 * 1024)
+        .layout_table_addr(PhysAddr::new(0x1800))
+        .build();
+
+    let errors = common::builder(linker)
+        .configure_vm(vm)
+        .build_checked()
+        .unwrap_err();
+    assert_eq!(errors.len(), 5, "{errors:?}");
+    assert!(errors.iter().any(|e| matches!(
+        e,
+        Error::Linker(linker::Error::MissingExport(name)) if name == "does_not_exist"
+    )));
+    assert!(
+        errors
+            .iter()
+            .any(|e| matches!(e, Error::Linker(linker::Error::SignatureMismatch { .. })))
+    );
+    // the IO port conflict, the oversized memory and the misaligned layout table
+    assert_eq!(
+        errors.iter().filter(|e| matches!(e, Error::Vm(_))).count(),
+        3
+    );
+}
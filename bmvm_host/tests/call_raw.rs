@@ -0,0 +1,39 @@
+use bmvm_host::mem::{self, AlignedUsize, SharedBuf};
+use bmvm_host::{ConfigBuilder, Error, linker, signature_of};
+
+mod common;
+
+const SHARED: usize = 0x100000;
+
+/// The shared memory allocator is set up by the first module of the process, so both limits are
+/// checked against a single module: the transport limit equals the size of the shared memory, which
+/// leaves buffers exceeding the remaining shared memory below the limit.
+#[test]
+#[ignore = "requires KVM and the stack-overflow example guest"]
+fn oversized_raw_args_rejected() {
+    let linker = linker::ConfigBuilder::new()
+        .register_guest_function::<(SharedBuf,), [u8; 16]>("hash_short");
+    let vm = ConfigBuilder::new()
+        .shared_memory(AlignedUsize::new_ceil(SHARED))
+        .max_transport_bytes(SHARED);
+    let mut module = common::builder(linker).configure_vm(vm).build().unwrap();
+    let sig = signature_of::<(SharedBuf,), [u8; 16]>("hash_short");
+
+    assert_eq!(module.call_raw(sig, &[1; 64]).unwrap().len(), 16);
+    let remaining = mem::remaining().unwrap();
+    assert!(matches!(
+        module.call_raw(sig, &vec![1; SHARED + 1]),
+        Err(Error::TransportTooLarge)
+    ));
+    // nothing was left behind in the shared memory
+    assert_eq!(mem::remaining().unwrap(), remaining);
+
+    // occupy part of the shared memory, so the remainder is below the transport limit
+    let _held = SharedBuf::from_bytes(&[0; 0x1000]).unwrap();
+    let args = vec![1; mem::remaining().unwrap() + 1];
+    assert!(args.len() <= SHARED);
+    assert!(matches!(
+        module.call_raw(sig, &args),
+        Err(Error::RawArgs(mem::Error::OutOfMemory))
+    ));
+}
@@ -0,0 +1,57 @@
+use bmvm_host::{ConfigBuilder, Error, ErrorCategory, HypercallContext, linker};
+
+mod common;
+
+fn countdown_linker() -> linker::ConfigBuilder {
+    linker::ConfigBuilder::new().register_guest_function::<(u64,), u64>("countdown")
+}
+
+#[test]
+#[ignore = "requires KVM and the stack-overflow example guest"]
+fn nested_callbacks() {
+    let mut module = common::module(countdown_linker());
+    let countdown = module.get_upcall::<(u64,), u64>("countdown").unwrap();
+
+    // every step re-enters the guest from within the `reenter` hypercall
+    assert_eq!(countdown.call(&mut module, (0,)).unwrap(), 0);
+    assert_eq!(countdown.call(&mut module, (5,)).unwrap(), 5);
+    assert!(module.is_ready());
+}
+
+#[test]
+#[ignore = "requires KVM and the stack-overflow example guest"]
+fn callback_depth_limited() {
+    let mut module = common::builder(countdown_linker())
+        .configure_vm(ConfigBuilder::new().max_callback_depth(2))
+        .build()
+        .unwrap();
+    let countdown = module.get_upcall::<(u64,), u64>("countdown").unwrap();
+
+    assert_eq!(countdown.call(&mut module, (2,)).unwrap(), 2);
+    // the third callback is refused, which the guest reports as `u64::MAX`
+    assert_eq!(countdown.call(&mut module, (3,)).unwrap(), u64::MAX);
+    // the depth is restored after the refused callback
+    assert_eq!(countdown.call(&mut module, (2,)).unwrap(), 2);
+}
+
+#[test]
+#[ignore = "requires KVM and the stack-overflow example guest"]
+fn exit_within_callback_violates_protocol() {
+    let linker = linker::ConfigBuilder::new()
+        .register_guest_function::<(u64,), u64>("exit_within_callback")
+        .register_guest_function::<(u64,), u64>("exit_now");
+    let mut module = common::module(linker);
+    let upcall = module
+        .get_upcall::<(u64,), u64>("exit_within_callback")
+        .unwrap();
+
+    // detected regardless of the debug mode
+    let err = upcall.call(&mut module, (7,)).unwrap_err();
+    assert!(matches!(err, Error::ProtocolViolation(_)), "{}", err);
+    assert_eq!(err.category(), ErrorCategory::Guest);
+}
+
+#[test]
+fn no_callback_outside_hypercall() {
+    assert!(HypercallContext::call_guest::<(u64,), u64>("countdown", (1,)).is_err());
+}
@@ -0,0 +1,54 @@
+//! Setup shared by the integration tests. Running a module requires KVM access and the
+//! `stack-overflow` example guest:
+//! `BMVM_TEST_GUEST=target/x86_64-unknown-none/debug/stack-overflow cargo test -- --ignored`
+#![allow(dead_code)]
+
+use bmvm_host::{
+    ForeignShareable, HostError, HypercallContext, HypercallResult, Module, ModuleBuilder,
+    OwnedShareable, Transport, linker, signature_of,
+};
+use std::path::{Path, PathBuf};
+use std::sync::LazyLock;
+
+const ENV_GUEST: &str = "BMVM_TEST_GUEST";
+
+/// Path of the example guest
+pub fn guest() -> &'static Path {
+    static GUEST: LazyLock<PathBuf> =
+        LazyLock::new(|| PathBuf::from(std::env::var(ENV_GUEST).expect("BMVM_TEST_GUEST not set")));
+    &GUEST
+}
+
+/// Builder of a module running the example guest, which is linked via `linker` and the
+/// hypercalls imported by the guest.
+pub fn builder(linker: linker::ConfigBuilder) -> ModuleBuilder<'static> {
+    let sig = signature_of::<(u64,), Result<u64, HostError>>("reenter");
+    let exit_sig = signature_of::<(u64,), Result<u64, HostError>>("reenter_exit");
+    let linker = linker
+        .register_raw_hypercall("reenter", sig, reenter)
+        .register_raw_hypercall("reenter_exit", exit_sig, reenter_exit);
+    ModuleBuilder::new()
+        .with_path(guest())
+        .configure_linker(linker)
+}
+
+/// Module running the example guest with the default VM configuration.
+pub fn module(linker: linker::ConfigBuilder) -> Module {
+    builder(linker).build().unwrap()
+}
+
+/// Host side of the `reenter` hypercall of the example guest, which calls `countdown` back.
+fn reenter(transport: Transport) -> HypercallResult {
+    let depth = u64::from_transport(transport)?;
+    let result = HypercallContext::call_guest::<(u64,), u64>("countdown", (depth,))
+        .map_err(|_| HostError::Failed);
+    Ok(result.into_transport())
+}
+
+/// Host side of the `reenter_exit` hypercall of the example guest, which calls `exit_now` back.
+fn reenter_exit(transport: Transport) -> HypercallResult {
+    let value = u64::from_transport(transport)?;
+    let result = HypercallContext::call_guest::<(u64,), u64>("exit_now", (value,))
+        .map_err(|_| HostError::Failed);
+    Ok(result.into_transport())
+}
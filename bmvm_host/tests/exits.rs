@@ -0,0 +1,21 @@
+use bmvm_host::linker;
+
+mod common;
+
+#[test]
+#[ignore = "requires KVM and the stack-overflow example guest"]
+fn exits_counted_per_call() {
+    let linker = linker::ConfigBuilder::new().register_guest_function::<(), u64>("tls_increment");
+    let mut module = common::module(linker);
+    let increment = module.get_upcall::<(), u64>("tls_increment").unwrap();
+
+    increment.call(&mut module, ()).unwrap();
+    let first = module.last_call_exits();
+    // the upcall returns via the exit port
+    assert!(first.io >= 1);
+    assert_eq!(first.mmio, 0);
+
+    // counts are reset for every call instead of accumulating
+    increment.call(&mut module, ()).unwrap();
+    assert_eq!(module.last_call_exits(), first);
+}
@@ -0,0 +1,28 @@
+//! Requires KVM access and the `stack-overflow` example guest:
+//! `BMVM_TEST_GUEST=target/x86_64-unknown-none/debug/stack-overflow cargo test --features test-fault-injection -- --ignored`
+#![cfg(feature = "test-fault-injection")]
+use bmvm_host::{ErrorCategory, FaultKind, linker};
+
+mod common;
+
+#[test]
+#[ignore = "requires KVM and the stack-overflow example guest"]
+fn injected_fault_aborts_next_call() {
+    for fault in FaultKind::ALL {
+        let linker =
+            linker::ConfigBuilder::new().register_guest_function::<(), u64>("tls_increment");
+        let mut module = common::module(linker);
+        let increment = module.get_upcall::<(), u64>("tls_increment").unwrap();
+        assert_eq!(increment.call(&mut module, ()).unwrap(), 41);
+
+        module.inject_fault(fault).unwrap();
+        let err = increment.call(&mut module, ()).unwrap_err();
+        assert!(
+            matches!(err, bmvm_host::Error::GuestFault(kind) if kind == fault),
+            "{:?}: {}",
+            fault,
+            err
+        );
+        assert_eq!(err.category(), ErrorCategory::Guest);
+    }
+}
@@ -0,0 +1,24 @@
+use bmvm_host::mem::SharedBuf;
+use bmvm_host::{ErrorCategory, linker};
+
+mod common;
+
+/// Process exit status of `ExitCode::ForeignWriteViolation`
+const FOREIGN_WRITE_VIOLATION: i32 = 64 + 20;
+
+#[test]
+#[ignore = "requires KVM and the stack-overflow example guest"]
+fn foreign_write_faults() {
+    let linker =
+        linker::ConfigBuilder::new().register_guest_function::<(SharedBuf,), u64>("write_foreign");
+    let mut module = common::module(linker);
+    let write_foreign = module
+        .get_upcall::<(SharedBuf,), u64>("write_foreign")
+        .unwrap();
+
+    let buf = SharedBuf::from_bytes(&[0u8; 32]).unwrap();
+    let err = write_foreign.call(&mut module, (buf,)).unwrap_err();
+    assert_eq!(err.exit_code(), FOREIGN_WRITE_VIOLATION, "{}", err);
+    assert_eq!(err.category(), ErrorCategory::Guest);
+    assert!(err.exit_detail().is_some_and(|addr| addr != 0));
+}
@@ -0,0 +1,29 @@
+use bmvm_host::linker;
+use bmvm_host::mem::{self, ForeignBuf, SharedBuf};
+
+mod common;
+
+#[test]
+#[ignore = "requires KVM and the stack-overflow example guest"]
+fn borrowed_result_stays_in_guest_memory() {
+    let linker = linker::ConfigBuilder::new()
+        .register_guest_function::<(SharedBuf, u64, bool), ForeignBuf>("copy_rounds");
+    let mut module = common::module(linker);
+    let copy_rounds = module
+        .get_upcall::<(SharedBuf, u64, bool), ForeignBuf>("copy_rounds")
+        .unwrap();
+
+    let data = (0..200u8).collect::<Vec<_>>();
+    let remaining = mem::remaining().unwrap();
+    let buf = SharedBuf::from_bytes(&data).unwrap();
+    let view = copy_rounds
+        .call_borrowed(&mut module, (buf, 1, false))
+        .unwrap();
+    assert_eq!(view.as_ref(), data.as_slice());
+    assert_eq!(view.len(), data.len());
+
+    // the result is not copied out, it occupies the shared memory until the view is dropped
+    assert!(mem::remaining().unwrap() < remaining);
+    drop(view);
+    assert_eq!(mem::remaining().unwrap(), remaining);
+}
@@ -0,0 +1,34 @@
+use bmvm_host::{ConfigBuilder, Error, linker};
+
+mod common;
+
+#[test]
+#[ignore = "requires KVM and the stack-overflow example guest"]
+fn heap_grows_via_page_requests() {
+    let linker = linker::ConfigBuilder::new().register_guest_function::<(u64,), u64>("heap_sum");
+    let mut module = common::module(linker);
+    let heap_sum = module.get_upcall::<(u64,), u64>("heap_sum").unwrap();
+
+    // 1MiB of values exceeds the initial grow step, so the heap is extended several times
+    let n = 128 * 1024;
+    assert_eq!(heap_sum.call(&mut module, (n,)).unwrap(), n * (n - 1) / 2);
+    // the freed memory is reused by the next call
+    assert_eq!(heap_sum.call(&mut module, (n,)).unwrap(), n * (n - 1) / 2);
+}
+
+#[test]
+#[ignore = "requires KVM and the stack-overflow example guest"]
+fn denied_page_request_fails_allocation() {
+    let linker = linker::ConfigBuilder::new().register_guest_function::<(u64,), u64>("heap_sum");
+    let mut module = common::builder(linker)
+        .configure_vm(ConfigBuilder::new().max_physical_memory(64 * 1024 * 1024))
+        .build()
+        .unwrap();
+    let heap_sum = module.get_upcall::<(u64,), u64>("heap_sum").unwrap();
+
+    // 128MiB of values cannot be backed within the memory limit, the alloc error handler panics
+    match heap_sum.call(&mut module, (16 * 1024 * 1024,)) {
+        Err(Error::UnexpectedExit) => {}
+        other => panic!("expected guest panic, got {:?}", other),
+    }
+}
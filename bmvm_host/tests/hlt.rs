@@ -0,0 +1,43 @@
+use bmvm_host::{ConfigBuilder, Error, HltPolicy, linker};
+
+mod common;
+
+fn module(policy: HltPolicy) -> bmvm_host::Module {
+    let linker = linker::ConfigBuilder::new().register_guest_function::<(u64,), u64>("waiting_sum");
+
+    common::builder(linker)
+        .configure_vm(ConfigBuilder::new().hlt_policy(policy))
+        .build()
+        .unwrap()
+}
+
+#[test]
+#[ignore = "requires KVM and the stack-overflow example guest"]
+fn resumable_hlt() {
+    let mut module = module(HltPolicy::Resumable);
+    let waiting_sum = module.get_upcall::<(u64,), u64>("waiting_sum").unwrap();
+
+    let mut result = waiting_sum.call(&mut module, (10,));
+    let mut waits = 0;
+    while let Err(Error::Yielded) = result {
+        assert!(module.is_yielded());
+        waits += 1;
+        result = module.resume::<u64>();
+    }
+    assert_eq!(result.unwrap(), 45);
+    assert_eq!(waits, 10);
+    assert!(module.is_ready());
+}
+
+#[test]
+#[ignore = "requires KVM and the stack-overflow example guest"]
+fn hlt_exits_by_default() {
+    let mut module = module(HltPolicy::Exit);
+    let waiting_sum = module.get_upcall::<(u64,), u64>("waiting_sum").unwrap();
+
+    // the first hlt terminates the guest instead of suspending the upcall, which never returns
+    let result = waiting_sum.call(&mut module, (10,));
+    assert!(result.is_err(), "{:?}", result);
+    assert!(!module.is_yielded());
+    assert!(!module.is_ready());
+}
@@ -0,0 +1,31 @@
+use bmvm_host::mem::PhysAddr;
+use bmvm_host::{ConfigBuilder, ErrorCategory, Module, linker};
+
+mod common;
+
+fn build(addr: u64) -> Result<Module, bmvm_host::Error> {
+    let linker = linker::ConfigBuilder::new().register_guest_function::<(), u64>("tls_increment");
+
+    common::builder(linker)
+        .configure_vm(ConfigBuilder::new().layout_table_addr(PhysAddr::new(addr)))
+        .build()
+}
+
+#[test]
+#[ignore = "requires KVM and the stack-overflow example guest"]
+fn relocated_layout_table() {
+    let mut module = build(0x20000).unwrap();
+    let increment = module.get_upcall::<(), u64>("tls_increment").unwrap();
+    assert_eq!(increment.call(&mut module, ()).unwrap(), 41);
+}
+
+#[test]
+#[ignore = "requires KVM and the stack-overflow example guest"]
+fn invalid_layout_table_addr() {
+    for addr in [0, 0x20010] {
+        let err = build(addr)
+            .err()
+            .expect("invalid layout table address accepted");
+        assert_eq!(err.category(), ErrorCategory::Input);
+    }
+}
@@ -0,0 +1,68 @@
+use bmvm_host::{ConfigBuilder, ErrorCategory, Module, linker};
+use std::path::PathBuf;
+
+mod common;
+
+/// Not page aligned, so the last page is zero padded
+const FILE_LEN: usize = 5000;
+
+fn file(name: &str) -> (PathBuf, u64) {
+    let path = std::env::temp_dir().join(format!("bmvm-{}-{}", name, std::process::id()));
+    let content = (0..FILE_LEN).map(|i| i as u8).collect::<Vec<_>>();
+    std::fs::write(&path, &content).unwrap();
+    (path, content.iter().map(|b| *b as u64).sum())
+}
+
+fn module(path: PathBuf, hint: Option<u64>) -> Module {
+    let linker = linker::ConfigBuilder::new()
+        .register_guest_function::<(), u64>("mapped_sum")
+        .register_guest_function::<(), u64>("mapped_addr")
+        .register_guest_function::<(), u64>("mapped_write")
+        .register_guest_function::<(), u64>("step_routine_addr");
+    common::builder(linker)
+        .configure_vm(ConfigBuilder::new().map_file_readonly(path, hint))
+        .build()
+        .unwrap()
+}
+
+fn call(module: &mut Module, name: &'static str) -> u64 {
+    let upcall = module.get_upcall::<(), u64>(name).unwrap();
+    upcall.call(module, ()).unwrap()
+}
+
+#[test]
+#[ignore = "requires KVM and the stack-overflow example guest"]
+fn taken_hint_falls_back() {
+    let (path, sum) = file("taken-hint");
+
+    let mut module = module(path.clone(), None);
+    assert_eq!(call(&mut module, "mapped_sum"), sum);
+    // the page of the guest code is taken
+    let code = call(&mut module, "step_routine_addr") & !0xfff;
+
+    for hint in [code, u64::MAX & !0xfff] {
+        let mut module = self::module(path.clone(), Some(hint));
+        assert_ne!(call(&mut module, "mapped_addr"), hint);
+        assert_eq!(call(&mut module, "mapped_sum"), sum);
+    }
+
+    let _ = std::fs::remove_file(path);
+}
+
+#[test]
+#[ignore = "requires KVM and the stack-overflow example guest"]
+fn guest_write_rejected() {
+    let (path, sum) = file("write");
+    let mut module = module(path.clone(), None);
+
+    let mapped_write = module.get_upcall::<(), u64>("mapped_write").unwrap();
+    let err = mapped_write.call(&mut module, ()).unwrap_err();
+    assert_eq!(err.category(), ErrorCategory::Guest);
+    assert!(err.to_string().contains("read-only memory"), "{}", err);
+
+    // the write reached neither the file nor the host memory
+    let content = std::fs::read(&path).unwrap();
+    assert_eq!(content.iter().map(|b| *b as u64).sum::<u64>(), sum);
+
+    let _ = std::fs::remove_file(path);
+}
@@ -0,0 +1,31 @@
+use bmvm_host::{ConfigBuilder, linker};
+
+mod common;
+
+#[test]
+#[ignore = "requires KVM and the stack-overflow example guest"]
+fn metrics_accumulate() {
+    let linker =
+        || linker::ConfigBuilder::new().register_guest_function::<(u64,), u64>("counted_sum");
+
+    // the guest drops its counts without the table
+    let mut module = common::module(linker());
+    let counted_sum = module.get_upcall::<(u64,), u64>("counted_sum").unwrap();
+    assert_eq!(counted_sum.call(&mut module, (10,)).unwrap(), 45);
+    assert!(module.metrics().is_empty());
+
+    let mut module = common::builder(linker())
+        .configure_vm(ConfigBuilder::new().metrics(true))
+        .build()
+        .unwrap();
+    assert!(module.metrics().is_empty());
+
+    let counted_sum = module.get_upcall::<(u64,), u64>("counted_sum").unwrap();
+    assert_eq!(counted_sum.call(&mut module, (10,)).unwrap(), 45);
+    assert_eq!(counted_sum.call(&mut module, (5,)).unwrap(), 10);
+
+    let metrics = module.metrics();
+    assert_eq!(metrics.len(), 2);
+    assert_eq!(metrics["iterations"], 15);
+    assert_eq!(metrics["calls"], 2);
+}
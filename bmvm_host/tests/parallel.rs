@@ -0,0 +1,42 @@
+use bmvm_host::ConfigBuilder;
+use bmvm_host::linker;
+use bmvm_host::mem::SharedBuf;
+
+mod common;
+
+#[test]
+#[ignore = "requires KVM and the stack-overflow example guest"]
+fn parallel_results_in_order() {
+    let linker = linker::ConfigBuilder::new()
+        .register_guest_function::<(SharedBuf,), [u8; 16]>("hash_short");
+    let mut module = common::builder(linker)
+        .configure_vm(ConfigBuilder::new().vcpus(4))
+        .build()
+        .unwrap();
+    let hash_short = module
+        .get_upcall::<(SharedBuf,), [u8; 16]>("hash_short")
+        .unwrap();
+
+    let inputs = (0..10u8)
+        .map(|i| (0..64).map(|b| b ^ i).collect::<Vec<u8>>())
+        .collect::<Vec<_>>();
+    let buffers = || {
+        inputs
+            .iter()
+            .map(|data| (SharedBuf::from_bytes(data).unwrap(),))
+            .collect::<Vec<_>>()
+    };
+
+    let expected = buffers()
+        .into_iter()
+        .map(|params| hash_short.call(&mut module, params).unwrap())
+        .collect::<Vec<_>>();
+    // each call gets its own buffer, so the calls are independent
+    let params = buffers();
+    let results = module.parallel_upcall(&hash_short, params).unwrap();
+    assert_eq!(results, expected);
+
+    // the module is still usable for regular calls afterwards
+    let params = (SharedBuf::from_bytes(&inputs[0]).unwrap(),);
+    assert_eq!(hash_short.call(&mut module, params).unwrap(), expected[0]);
+}
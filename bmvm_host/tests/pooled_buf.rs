@@ -0,0 +1,39 @@
+use bmvm_host::linker;
+use bmvm_host::mem::{self, SharedBuf};
+
+mod common;
+
+#[test]
+#[ignore = "requires KVM and the stack-overflow example guest"]
+fn pooled_buffers_are_reused() {
+    let linker = linker::ConfigBuilder::new()
+        .register_guest_function::<(SharedBuf,), [u8; 16]>("hash_short");
+    let mut module = common::module(linker);
+    let hash_short = module
+        .get_upcall::<(SharedBuf,), [u8; 16]>("hash_short")
+        .unwrap();
+
+    let data = (0..100u8).collect::<Vec<_>>();
+    let expected = hash_short
+        .call(&mut module, (SharedBuf::from_bytes(&data).unwrap(),))
+        .unwrap();
+
+    let mut buf = module.pooled_buf(data.len()).unwrap();
+    buf.try_copy_from(&data).unwrap();
+    assert_eq!(hash_short.call(&mut module, (buf,)).unwrap(), expected);
+
+    // the guest only dropped its reference, the allocation is handed out again
+    let remaining = mem::remaining().unwrap();
+    for _ in 0..10 {
+        let mut buf = module.pooled_buf(data.len()).unwrap();
+        buf.try_copy_from(&data).unwrap();
+        assert_eq!(hash_short.call(&mut module, (buf,)).unwrap(), expected);
+    }
+    assert_eq!(mem::remaining().unwrap(), remaining);
+
+    // a buffer still held by the host is not handed out twice
+    let held = module.pooled_buf(data.len()).unwrap();
+    let _other = module.pooled_buf(data.len()).unwrap();
+    assert!(mem::remaining().unwrap() < remaining);
+    held.deallocate();
+}
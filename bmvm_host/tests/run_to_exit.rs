@@ -0,0 +1,16 @@
+use bmvm_host::{ConfigBuilder, linker};
+
+mod common;
+
+#[test]
+#[ignore = "requires KVM and the stack-overflow example guest"]
+fn entry_only_guest_exits_with_value() {
+    let mut module = common::builder(linker::ConfigBuilder::new())
+        .configure_vm(ConfigBuilder::new().guest_args(b"entry-only"))
+        .build()
+        .unwrap();
+
+    // the guest exited from its setup instead of waiting for upcalls
+    assert!(!module.is_ready());
+    assert_eq!(module.run_to_exit::<u64>().unwrap(), 42);
+}
@@ -0,0 +1,81 @@
+use bmvm_host::{ConfigBuilder, Module, linker};
+
+mod common;
+
+fn module() -> Module {
+    let linker = linker::ConfigBuilder::new().register_guest_function::<(), u64>("tls_increment");
+    common::builder(linker)
+        .configure_vm(ConfigBuilder::new().dirty_log(true))
+        .build()
+        .unwrap()
+}
+
+/// Address within the unused part of the guest stack
+fn scratch(module: &mut Module) -> u64 {
+    (module.registers().unwrap().rsp - 0x2000) & !0xfff
+}
+
+#[test]
+#[ignore = "requires KVM and the stack-overflow example guest"]
+fn delta_contains_modified_page() {
+    let mut module = module();
+    let addr = scratch(&mut module);
+
+    let base = module.snapshot().unwrap();
+    assert!(!base.is_incremental());
+
+    module.write_guest(addr + 8, &[0xAB; 16]).unwrap();
+    let delta = module.snapshot_incremental(&base).unwrap();
+    assert!(delta.is_incremental());
+    assert_eq!(delta.pages(), 1);
+
+    // nothing changed since the delta
+    let empty = module.snapshot_incremental(&delta).unwrap();
+    assert_eq!(empty.pages(), 0);
+}
+
+#[test]
+#[ignore = "requires KVM and the stack-overflow example guest"]
+fn restore_chain() {
+    let mut module = module();
+    let addr = scratch(&mut module);
+
+    let base = module.snapshot().unwrap();
+    module.write_guest(addr, &[1; 8]).unwrap();
+    let first = module.snapshot_incremental(&base).unwrap();
+    module.write_guest(addr + 8, &[2; 8]).unwrap();
+    let second = module.snapshot_incremental(&first).unwrap();
+    module.write_guest(addr, &[3; 16]).unwrap();
+
+    let mut buf = [0u8; 16];
+    module.restore(&base, &[]).unwrap();
+    module.read_guest(addr, &mut buf).unwrap();
+    assert_eq!(buf, [0; 16]);
+
+    module.restore(&base, &[first.clone(), second]).unwrap();
+    module.read_guest(addr, &mut buf).unwrap();
+    assert_eq!(buf[..8], [1; 8]);
+    assert_eq!(buf[8..], [2; 8]);
+
+    // the chain has to be complete
+    assert!(module.restore(&first, &[]).is_err());
+}
+
+#[test]
+#[ignore = "requires KVM and the stack-overflow example guest"]
+fn delta_contains_guest_writes() {
+    let mut module = module();
+    let increment = module.get_upcall::<(), u64>("tls_increment").unwrap();
+
+    // the guest writes the counter itself, only the KVM dirty log sees the page change
+    let base = module.snapshot().unwrap();
+    assert_eq!(increment.call(&mut module, ()).unwrap(), 41);
+    let delta = module.snapshot_incremental(&base).unwrap();
+    assert!(delta.pages() > 0);
+    assert_eq!(increment.call(&mut module, ()).unwrap(), 42);
+
+    module.restore(&base, &[]).unwrap();
+    assert_eq!(increment.call(&mut module, ()).unwrap(), 41);
+    module.restore(&base, &[delta]).unwrap();
+    assert_eq!(increment.call(&mut module, ()).unwrap(), 42);
+}
@@ -0,0 +1,19 @@
+use bmvm_host::mem::AlignedNonZeroUsize;
+use bmvm_host::{ConfigBuilder, Error, linker};
+
+mod common;
+
+#[test]
+#[ignore = "requires KVM and the stack-overflow example guest"]
+fn stack_overflow_faults_on_guard_page() {
+    let linker = linker::ConfigBuilder::new().register_guest_function::<(u64,), u64>("overflow");
+    let vm = ConfigBuilder::new().stack_size(AlignedNonZeroUsize::new_ceil(64 * 1024).unwrap());
+
+    let mut module = common::builder(linker).configure_vm(vm).build().unwrap();
+
+    let overflow = module.get_upcall::<(u64,), u64>("overflow").unwrap();
+    match overflow.call(&mut module, (0,)) {
+        Err(Error::StackOverflow(rsp)) => assert_ne!(rsp, 0),
+        other => panic!("expected stack overflow, got {:?}", other),
+    }
+}
@@ -0,0 +1,32 @@
+use bmvm_host::{ConfigBuilder, linker};
+
+mod common;
+
+/// Size of the buffer kept alive by every recursion level of the guest
+const FRAME_SIZE: usize = 64 * size_of::<u64>();
+
+#[test]
+#[ignore = "requires KVM and the stack-overflow example guest"]
+fn stack_high_water_follows_recursion_depth() {
+    let linker =
+        linker::ConfigBuilder::new().register_guest_function::<(u64,), u64>("recurse_bounded");
+
+    let mut module = common::builder(linker)
+        .configure_vm(ConfigBuilder::new().stack_poison(0xa5))
+        .build()
+        .unwrap();
+
+    let recurse = module.get_upcall::<(u64,), u64>("recurse_bounded").unwrap();
+    recurse.call(&mut module, (100,)).unwrap();
+    let shallow = module.stack_high_water().unwrap();
+    assert!(shallow >= 100 * FRAME_SIZE);
+
+    recurse.call(&mut module, (1000,)).unwrap();
+    let deep = module.stack_high_water().unwrap();
+    assert!(deep >= 1000 * FRAME_SIZE);
+    assert!(deep > shallow);
+
+    // the mark only grows
+    recurse.call(&mut module, (10,)).unwrap();
+    assert_eq!(module.stack_high_water().unwrap(), deep);
+}
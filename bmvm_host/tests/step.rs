@@ -0,0 +1,37 @@
+use bmvm_host::{StepOutcome, linker};
+
+mod common;
+
+#[test]
+#[ignore = "requires KVM and the stack-overflow example guest"]
+fn step_executes_single_instructions() {
+    let linker =
+        linker::ConfigBuilder::new().register_guest_function::<(), u64>("step_routine_addr");
+    let mut module = common::module(linker);
+    let addr = module
+        .get_upcall::<(), u64>("step_routine_addr")
+        .unwrap()
+        .call(&mut module, ())
+        .unwrap();
+
+    let mut regs = module.registers().unwrap();
+    regs.rip = addr;
+    regs.rax = 0;
+    regs.rbx = 0;
+    module.set_registers(&regs).unwrap();
+
+    // each step advances by exactly one instruction of the routine
+    let expected = [(addr + 5, 7, 0), (addr + 8, 12, 0), (addr + 11, 12, 24)];
+    for (rip, rax, rbx) in expected {
+        match module.step().unwrap() {
+            StepOutcome::Stepped(regs) => {
+                assert_eq!((regs.rip, regs.rax, regs.rbx), (rip, rax, rbx))
+            }
+            other => panic!("expected a single step, got {:?}", other),
+        }
+    }
+
+    // the step composes with the register accessors
+    let regs = module.registers().unwrap();
+    assert_eq!((regs.rip, regs.rbx), (addr + 11, 24));
+}
@@ -0,0 +1,49 @@
+use bmvm_host::linker;
+
+mod common;
+
+#[test]
+#[ignore = "requires KVM and the stack-overflow example guest"]
+fn stream_cancellation() {
+    let linker = linker::ConfigBuilder::new()
+        .register_guest_function::<(u64,), ()>("count_stream")
+        .register_guest_function::<(), u64>("streamed");
+    let mut module = common::module(linker);
+    let count_stream = module.get_upcall::<(u64,), ()>("count_stream").unwrap();
+    let streamed = module.get_upcall::<(), u64>("streamed").unwrap();
+
+    // exhausted stream
+    let chunks = count_stream
+        .stream(&mut module, (5,))
+        .unwrap()
+        .collect::<Result<Vec<_>, _>>()
+        .unwrap();
+    let expected = (0..5u64)
+        .map(|i| i.to_le_bytes().to_vec())
+        .collect::<Vec<_>>();
+    assert_eq!(chunks, expected);
+    assert_eq!(streamed.call(&mut module, ()).unwrap(), 5);
+
+    // dropped after two chunks, the guest stops at the chunk it is suspended in
+    let first = count_stream
+        .stream(&mut module, (1000,))
+        .unwrap()
+        .take(2)
+        .collect::<Result<Vec<_>, _>>()
+        .unwrap();
+    assert_eq!(first.len(), 2);
+    assert!(module.is_ready());
+    assert_eq!(streamed.call(&mut module, ()).unwrap(), 1);
+
+    // dropped before the guest ran, it returns at the first chunk
+    drop(count_stream.stream(&mut module, (1000,)).unwrap());
+    assert!(module.is_ready());
+    assert_eq!(streamed.call(&mut module, ()).unwrap(), 0);
+
+    // dropped after the guest already returned
+    let mut stream = count_stream.stream(&mut module, (1,)).unwrap();
+    assert!(stream.next().unwrap().is_ok());
+    assert!(stream.next().is_none());
+    drop(stream);
+    assert_eq!(streamed.call(&mut module, ()).unwrap(), 1);
+}
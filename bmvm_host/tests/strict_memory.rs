@@ -0,0 +1,42 @@
+use bmvm_host::{ConfigBuilder, Error, linker};
+
+mod common;
+
+/// Far above every region of the default layout
+const UNMAPPED: u64 = 0x40_0000_0000;
+
+#[test]
+#[ignore = "requires KVM and the stack-overflow example guest"]
+fn out_of_bounds_access_reported() {
+    let linker = linker::ConfigBuilder::new().register_guest_function::<(u64,), u64>("read_at");
+
+    let mut module = common::builder(linker)
+        .configure_vm(ConfigBuilder::new().strict_memory(true))
+        .build()
+        .unwrap();
+
+    let read_at = module.get_upcall::<(u64,), u64>("read_at").unwrap();
+    match read_at.call(&mut module, (UNMAPPED,)) {
+        Err(Error::OutOfBoundsAccess { addr, rip }) => {
+            assert_eq!(addr, UNMAPPED);
+            assert_ne!(rip, 0);
+        }
+        other => panic!("expected out of bounds access, got {:?}", other),
+    }
+}
+
+#[test]
+#[ignore = "requires KVM and the stack-overflow example guest"]
+fn general_protection_fault_not_out_of_bounds() {
+    let linker = linker::ConfigBuilder::new().register_guest_function::<(u64,), u64>("read_at");
+
+    let mut module = common::builder(linker)
+        .configure_vm(ConfigBuilder::new().strict_memory(true))
+        .build()
+        .unwrap();
+
+    // a non-canonical address raises a general protection fault, which leaves no fault address
+    let read_at = module.get_upcall::<(u64,), u64>("read_at").unwrap();
+    let result = read_at.call(&mut module, (0x8000_0000_0000_0000,));
+    assert!(matches!(result, Err(Error::UnexpectedExit)), "{:?}", result);
+}
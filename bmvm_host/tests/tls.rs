@@ -0,0 +1,15 @@
+use bmvm_host::linker;
+
+mod common;
+
+#[test]
+#[ignore = "requires KVM and the stack-overflow example guest"]
+fn thread_local_counter() {
+    let linker = linker::ConfigBuilder::new().register_guest_function::<(), u64>("tls_increment");
+    let mut module = common::module(linker);
+
+    // the counter starts at its `.tdata` value and keeps its state across upcalls
+    let increment = module.get_upcall::<(), u64>("tls_increment").unwrap();
+    assert_eq!(increment.call(&mut module, ()).unwrap(), 41);
+    assert_eq!(increment.call(&mut module, ()).unwrap(), 42);
+}
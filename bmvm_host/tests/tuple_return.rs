@@ -0,0 +1,30 @@
+use bmvm_host::linker;
+use bmvm_host::mem::{ForeignBuf, SharedBuf};
+
+mod common;
+
+#[test]
+#[ignore = "requires KVM and the stack-overflow example guest"]
+fn buffers_returned_as_tuple() {
+    let linker = linker::ConfigBuilder::new()
+        .register_guest_function::<(SharedBuf,), (ForeignBuf, ForeignBuf)>("split_halves");
+    let mut module = common::module(linker);
+    let split_halves = module
+        .get_upcall::<(SharedBuf,), (ForeignBuf, ForeignBuf)>("split_halves")
+        .unwrap();
+
+    let data = (0..100u8).collect::<Vec<_>>();
+    let buf = SharedBuf::from_bytes(&data).unwrap();
+    let (head, tail) = split_halves.call(&mut module, (buf,)).unwrap();
+    assert_eq!(head.as_ref(), &data[..50]);
+    assert_eq!(tail.as_ref(), &data[50..]);
+
+    // each buffer is a separate allocation of the guest
+    let head_range = head.as_ref().as_ptr_range();
+    let tail_range = tail.as_ref().as_ptr_range();
+    assert!(head_range.end <= tail_range.start || tail_range.end <= head_range.start);
+
+    // dropping the buffers deallocates them independently
+    drop(head);
+    assert_eq!(tail.as_ref(), &data[50..]);
+}
@@ -0,0 +1,27 @@
+//! The shared memory allocator is initialized with the first VM, which this test never builds. It
+//! runs in a process of its own, as a guest built with the `no-arena` feature would.
+use bmvm_host::mem::{self, ForeignBuf, SharedBuf};
+use bmvm_host::{ForeignShareable, Transport};
+
+#[test]
+fn buffers_require_initialized_arena() {
+    assert!(matches!(
+        SharedBuf::from_bytes(b"abc"),
+        Err(mem::Error::UninitializedAllocator)
+    ));
+    assert!(matches!(
+        unsafe { mem::alloc::<u64>() },
+        Err(mem::Error::UninitializedAllocator)
+    ));
+    assert!(matches!(
+        mem::remaining(),
+        Err(mem::Error::UninitializedAllocator)
+    ));
+
+    // values received from the peer are rejected with a distinct exit code
+    let uninitialized = "Shared memory allocator is not initialized";
+    let code = ForeignBuf::from_transport(Transport::new(0x100, 8)).err();
+    assert_eq!(code.map(|c| c.to_string()).as_deref(), Some(uninitialized));
+    let code = mem::Foreign::<u64>::from_transport(Transport::new(0x100, 0)).err();
+    assert_eq!(code.map(|c| c.to_string()).as_deref(), Some(uninitialized));
+}
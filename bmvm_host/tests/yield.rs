@@ -0,0 +1,40 @@
+use bmvm_host::{Error, linker};
+
+mod common;
+
+#[test]
+#[ignore = "requires KVM and the stack-overflow example guest"]
+fn yield_and_resume() {
+    let linker =
+        linker::ConfigBuilder::new().register_guest_function::<(u64,), u64>("yielding_sum");
+    let mut module = common::module(linker);
+    let yielding_sum = module.get_upcall::<(u64,), u64>("yielding_sum").unwrap();
+
+    let mut result = yielding_sum.call(&mut module, (10,));
+    let mut yields = 0;
+    while let Err(Error::Yielded) = result {
+        assert!(module.is_yielded());
+        // no other upcall can be issued until the guest is resumed
+        assert!(yielding_sum.call(&mut module, (1,)).is_err());
+
+        // the result is decoded as the return type of the yielded upcall only
+        assert!(matches!(
+            module.resume::<u32>(),
+            Err(Error::ResumeSignature { .. })
+        ));
+        assert!(module.is_yielded());
+
+        yields += 1;
+        result = module.resume::<u64>();
+    }
+    assert_eq!(result.unwrap(), 45);
+    assert_eq!(yields, 10);
+    assert!(!module.is_yielded());
+    assert!(module.resume::<u64>().is_err());
+
+    // the module is ready for the next call afterward
+    assert!(matches!(
+        yielding_sum.call(&mut module, (3,)),
+        Err(Error::Yielded)
+    ));
+}
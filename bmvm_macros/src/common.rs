@@ -1,5 +1,5 @@
 use bmvm_common::hash::SignatureHasher;
-use bmvm_common::vmi::FnCall;
+use bmvm_common::vmi::{FnCall, RECORD_HEADER_SIZE};
 use proc_macro_crate::{FoundCrate, crate_name};
 use proc_macro2::{Ident, Span, TokenStream};
 use quote::{ToTokens, format_ident, quote};
@@ -45,9 +45,24 @@ pub enum ParamType {
         ty: Ident,
         struct_definition: TokenStream,
         packaging: ParamPackaging,
+        params: Vec<(Ident, Type)>,
     },
 }
 
+impl ParamType {
+    /// Return the parameters of a function with exactly two parameters. If both are scalars
+    /// (checked at compile time of the generated code via `is_scalar_pair`), they are passed
+    /// directly in the transport registers instead of a shared transport struct.
+    pub fn pair(&self) -> Option<(&(Ident, Type), &(Ident, Type))> {
+        match self {
+            ParamType::MultipleValues { params, .. } if params.len() == 2 => {
+                Some((&params[0], &params[1]))
+            }
+            _ => None,
+        }
+    }
+}
+
 /// get_link_name returns name specified via a `link_name` attribute if available
 pub(crate) fn get_link_name(attrs: &[Attribute]) -> Option<Ident> {
     for attr in attrs {
@@ -65,15 +80,21 @@ pub(crate) fn get_link_name(attrs: &[Attribute]) -> Option<Ident> {
 pub(crate) fn supported_type_string(ty: &Type) -> Result<String, Error> {
     match ty {
         Type::Tuple(tuple) => {
-            if tuple.elems.is_empty() {
-                Ok("()".to_string())
-            } else {
-                Err(Error::new_spanned(ty.clone(), "tuples are not supported"))
-            }
+            let elems = tuple
+                .elems
+                .iter()
+                .map(supported_type_string)
+                .collect::<Result<Vec<_>, _>>()?;
+            Ok(format!("({})", elems.join(", ")))
         }
         // Match simple types like u32, i8, etc.
         Type::Path(TypePath { path, .. }) => Ok(path.to_token_stream().to_string()),
         Type::Reference(tr) => Ok(tr.to_token_stream().to_string()),
+        Type::Array(arr) => Ok(format!(
+            "[{}; {}]",
+            supported_type_string(&arr.elem)?,
+            arr.len.to_token_stream()
+        )),
         _ => Err(Error::new_spanned(ty.clone(), "unsupported type")),
     }
 }
@@ -226,6 +247,9 @@ pub(crate) fn make_type_turbofish(ty: &Type) -> TokenStream {
                 quote! { #ty }
             }
         }
+        // `[T; N]::f` is not a valid expression path
+        Type::Array(_) => quote! { <#ty> },
+        Type::Tuple(tuple) if !tuple.elems.is_empty() => quote! { <#ty> },
         _ => quote! { #ty },
     }
 }
@@ -329,6 +353,7 @@ pub fn process_params(
             #unpack
         },
         packaging: param_packaging,
+        params: params.to_vec(),
     })
 }
 
@@ -351,10 +376,11 @@ pub fn gen_callmeta(
     let meta_name = format_ident!("{}{}", STATIC_META, fn_name.to_uppercase());
     let var_param_hash = format_ident!("param_hash");
 
-    // Get the CallMeta as bytes and prefix with the size (u16)
+    // Get the CallMeta as bytes, the record header precedes the signature
     let bytes = meta.to_bytes();
     let meta_size = bytes.len();
-    let suffix = &bytes[8..];
+    let prefix = &bytes[..RECORD_HEADER_SIZE];
+    let suffix = &bytes[RECORD_HEADER_SIZE + size_of::<u64>()..];
     let suffix_size = suffix.len();
 
     // construct fully qualified name for SignatureHasher and TypeSignature for use in the macro output
@@ -386,7 +412,7 @@ pub fn gen_callmeta(
         quote! {let #var_param_hash = <() as #ty_typesignature>::SIGNATURE;}
     };
 
-    // The FnCall signature is stored in the first 8 bytes of the FnCall data. At the moment it is
+    // The FnCall signature is stored in the 8 bytes following the record header. At the moment it is
     // only a partial signature, as the type hashes are not yet known and cannot be included on
     // macro expansion.
     // From the initial (partial) signature, create a new hasher instance and apply the remaining
@@ -403,12 +429,17 @@ pub fn gen_callmeta(
             sig_hasher.write(<#return_type as #ty_typesignature>::SIGNATURE.to_le_bytes().as_slice());
             let sig = sig_hasher.finish();
             let sig_bytes = sig.to_ne_bytes();
+            let meta_prefix = [#(#prefix),*];
             let meta_suffix = [#(#suffix),*];
 
             let mut out = [0u8; #meta_size];
             let mut i = 0;
-            while i < 8 {
-                out[i] = sig_bytes[i];
+            while i < #RECORD_HEADER_SIZE {
+                out[i] = meta_prefix[i];
+                i += 1;
+            }
+            while i < #RECORD_HEADER_SIZE + 8 {
+                out[i] = sig_bytes[i - #RECORD_HEADER_SIZE];
                 i += 1;
             }
             let mut j = 0;
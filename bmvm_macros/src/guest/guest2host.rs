@@ -171,7 +171,7 @@ fn gen_body(
 
 fn gen_transport(mother: &Ident, param: &ParamType) -> TS {
     let alloc_owned = quote! {#mother::alloc};
-    let exit_with_code = quote! {#mother::exit_with_code};
+    let exit_with_detail = quote! {#mother::exit_with_detail};
     let exit_code_alloc = quote! {#mother::ExitCode::AllocationFailed};
     let owned_shareable = quote! {#mother::OwnedShareable};
     let transport = Ident::new(VAR_NAME_TRANSPORT, Span::call_site());
@@ -195,17 +195,36 @@ fn gen_transport(mother: &Ident, param: &ParamType) -> TS {
             packaging,
             ..
         } => {
-            quote! {
+            let pack = quote! {
                 let mut owned_params = match unsafe { #alloc_owned::<#struct_ident>() } {
                     Ok(m) => m,
-                    Err(_) => #exit_with_code(#exit_code_alloc),
+                    Err(_) => #exit_with_detail(
+                        #exit_code_alloc,
+                        ::core::mem::size_of::<#struct_ident>() as u64,
+                    ),
                 };
                 let mut #params = owned_params.as_mut();
                 #(#packaging)*
 
                 let shared_params = owned_params.into_shared();
                 use #owned_shareable;
-                let #transport = shared_params.into_transport();
+                shared_params.into_transport()
+            };
+            let pack = match param.pair() {
+                Some(((a_name, a), (b_name, b))) => quote! {
+                    if #mother::is_scalar_pair::<#a, #b>() {
+                        #mother::Transport::new(
+                            #mother::scalar_to_raw::<#a>(#a_name),
+                            #mother::scalar_to_raw::<#b>(#b_name),
+                        )
+                    } else {
+                        #pack
+                    }
+                },
+                None => pack,
+            };
+            quote! {
+                let #transport = { #pack };
             }
         }
     }
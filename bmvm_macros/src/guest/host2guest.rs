@@ -103,6 +103,7 @@ pub fn expose_impl(_attr: TokenStream, item: TokenStream) -> TokenStream {
         #[allow(non_upper_case_globals)]
         #[unsafe(link_section = #BMVM_META_SECTION_EXPOSE_CALLS)]
         static #static_upcall: #mother::UpcallFn = #mother::UpcallFn {
+            header: #mother::UpcallFn::HEADER,
             sig: #upcall_sig,
             func: #wrapper_fn_name,
         };
@@ -126,7 +127,7 @@ fn gen_wrapper(
     let exit_with_code = quote! {#mother::exit_with_code};
     let var_params = Ident::new(VAR_NAME_PARAM, Span::call_site());
     let var_return = Ident::new(VAR_NAME_RETURN, Span::call_site());
-    let port_exit = quote! {#mother::EXIT_IO_PORT};
+    let port_exit = quote! {#mother::exit_port()};
 
     let func_call = match params {
         ParamType::Void => {
@@ -158,6 +159,32 @@ fn gen_wrapper(
             }
         }
         ParamType::MultipleValues { ty, packaging, .. } => {
+            let unpack = quote! {
+                let __foreign = match #foreign::<#ty>::from_transport(__input) {
+                    Ok(x) => x,
+                    Err(e) => #exit_with_code(e)
+                };
+                unsafe { __foreign.unpack() }
+            };
+            let unpack = match params.pair() {
+                Some(((_, a), (_, b))) => quote! {
+                    if #mother::is_scalar_pair::<#a, #b>() {
+                        unsafe {(
+                            match #mother::scalar_from_raw::<#a>(__primary) {
+                                Ok(x) => x,
+                                Err(e) => #exit_with_code(e)
+                            },
+                            match #mother::scalar_from_raw::<#b>(__secondary) {
+                                Ok(x) => x,
+                                Err(e) => #exit_with_code(e)
+                            },
+                        )}
+                    } else {
+                        #unpack
+                    }
+                },
+                None => unpack,
+            };
             quote! {
                     let __primary: u64;
                     let __secondary: u64;
@@ -173,12 +200,7 @@ fn gen_wrapper(
                     }
                     let __input = #ty_transport::new(__primary, __secondary);
                     use #foreign_shareable;
-                    let __foreign = match #foreign::<#ty>::from_transport(__input) {
-                        Ok(x) => x,
-                        Err(e) => #exit_with_code(e)
-                    };
-
-                    let (#(#packaging),*) = unsafe { __foreign.unpack() };
+                    let (#(#packaging),*) = { #unpack };
                     let #var_return = #fn_name(#(#packaging),*);
             }
         }
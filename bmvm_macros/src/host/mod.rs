@@ -120,10 +120,26 @@ fn gen_wrapper(mother: &Ident, fn_name: &Ident, fn_name_wrapper: &Ident, params:
             }
         }
         ParamType::MultipleValues { ty, packaging, .. } => {
+            let unpack = quote! {
+                let __foreign = #ty_foreign::<#ty>::from_transport(#var_transport)?;
+                unsafe { __foreign.unpack() }
+            };
+            let unpack = match params.pair() {
+                Some(((_, a), (_, b))) => quote! {
+                    if #mother::is_scalar_pair::<#a, #b>() {
+                        unsafe {(
+                            #mother::scalar_from_raw::<#a>(#var_transport.primary())?,
+                            #mother::scalar_from_raw::<#b>(#var_transport.secondary())?,
+                        )}
+                    } else {
+                        #unpack
+                    }
+                },
+                None => unpack,
+            };
             quote! {
                 use #foreign_shareable;
-                let __foreign = #ty_foreign::<#ty>::from_transport(#var_transport)?;
-                let (#(#packaging),*) = unsafe { __foreign.unpack() };
+                let (#(#packaging),*) = { #unpack };
                 let #var_return = #fn_name(#(#packaging),*);
             }
         }
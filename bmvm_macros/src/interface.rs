@@ -0,0 +1,189 @@
+use proc_macro::TokenStream;
+use proc_macro2::TokenStream as TS;
+use quote::quote;
+use syn::parse::{Parse, ParseStream};
+use syn::{Attribute, Block, Signature, Token, Visibility, parse_macro_input};
+
+/// Single function of a `bmvm_interface!` block. The body is only required on the host side.
+struct InterfaceFn {
+    attrs: Vec<Attribute>,
+    vis: Visibility,
+    sig: Signature,
+    #[cfg_attr(feature = "guest", allow(dead_code))]
+    body: Option<Block>,
+}
+
+impl Parse for InterfaceFn {
+    fn parse(input: ParseStream) -> syn::Result<Self> {
+        let attrs = input.call(Attribute::parse_outer)?;
+        let vis = input.parse()?;
+        let sig = input.parse()?;
+        let body = if input.peek(Token![;]) {
+            input.parse::<Token![;]>()?;
+            None
+        } else {
+            Some(input.parse()?)
+        };
+
+        Ok(Self {
+            attrs,
+            vis,
+            sig,
+            body,
+        })
+    }
+}
+
+struct Interface {
+    fns: Vec<InterfaceFn>,
+}
+
+impl Parse for Interface {
+    fn parse(input: ParseStream) -> syn::Result<Self> {
+        let mut fns = Vec::new();
+        while !input.is_empty() {
+            fns.push(input.parse()?);
+        }
+        Ok(Self { fns })
+    }
+}
+
+/// Procedural macro implementation:
+/// * Guest: drops the function bodies and passes the signatures as `extern "C"` block to `#[host]`
+/// * Host: passes each function including its body to `#[expose_host]`
+///
+/// Both sides are generated from the same signature tokens, so they cannot diverge.
+pub fn interface_impl(item: TokenStream) -> TokenStream {
+    let interface = parse_macro_input!(item as Interface);
+    match expand(interface) {
+        Ok(x) => x,
+        Err(e) => e.to_compile_error().into(),
+    }
+}
+
+#[cfg(feature = "guest")]
+fn expand(interface: Interface) -> syn::Result<TokenStream> {
+    Ok(crate::guest::host_impl(
+        TokenStream::new(),
+        guest_block(&interface).into(),
+    ))
+}
+
+/// The signatures of all functions as `extern "C"` block
+#[cfg(feature = "guest")]
+fn guest_block(interface: &Interface) -> TS {
+    let decls = interface.fns.iter().map(|f| {
+        let (attrs, vis, sig) = (&f.attrs, &f.vis, &f.sig);
+        quote! {
+            #(#attrs)*
+            #vis #sig;
+        }
+    });
+    quote! {
+        unsafe extern "C" {
+            #(#decls)*
+        }
+    }
+}
+
+#[cfg(feature = "host")]
+fn expand(interface: Interface) -> syn::Result<TokenStream> {
+    let mut out = TokenStream::new();
+    for item in host_items(&interface)? {
+        out.extend(crate::host::expose_impl(TokenStream::new(), item.into()));
+    }
+    Ok(out)
+}
+
+/// Every function including its body as standalone item
+#[cfg(feature = "host")]
+fn host_items(interface: &Interface) -> syn::Result<Vec<TS>> {
+    interface
+        .fns
+        .iter()
+        .map(|f| {
+            let (attrs, vis, sig) = (&f.attrs, &f.vis, &f.sig);
+            let Some(body) = &f.body else {
+                return Err(syn::Error::new_spanned(
+                    sig,
+                    "Host side of bmvm_interface! requires a function body",
+                ));
+            };
+            Ok(quote! {
+                #(#attrs)*
+                #vis #sig #body
+            })
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use quote::ToTokens;
+
+    const SOURCE: &str = r#"
+        /// Add two numbers
+        pub fn add(a: u64, b: u64) -> Result<u64, HostError> {
+            a.checked_add(b).ok_or(HostError::InvalidInput)
+        }
+        fn log(msg: SharedBuf);
+    "#;
+
+    fn interface() -> Interface {
+        syn::parse_str(SOURCE).unwrap()
+    }
+
+    fn signatures(interface: &Interface) -> Vec<String> {
+        interface
+            .fns
+            .iter()
+            .map(|f| f.sig.to_token_stream().to_string())
+            .collect()
+    }
+
+    #[test]
+    fn parses_functions() {
+        let interface = interface();
+        assert_eq!(interface.fns.len(), 2);
+        assert_eq!(interface.fns[0].attrs.len(), 1);
+        assert!(matches!(interface.fns[0].vis, Visibility::Public(_)));
+        assert!(interface.fns[0].body.is_some());
+        assert!(interface.fns[1].body.is_none());
+
+        assert!(syn::parse_str::<Interface>("fn add(a: u64) -> u64").is_err());
+    }
+
+    #[cfg(feature = "guest")]
+    #[test]
+    fn guest_declares_signatures() {
+        let interface = interface();
+        let block = syn::parse2::<syn::ItemForeignMod>(guest_block(&interface)).unwrap();
+        let sigs = block
+            .items
+            .iter()
+            .map(|item| match item {
+                syn::ForeignItem::Fn(f) => f.sig.to_token_stream().to_string(),
+                other => panic!("unexpected item {}", other.to_token_stream()),
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(sigs, signatures(&interface));
+    }
+
+    #[cfg(feature = "host")]
+    #[test]
+    fn host_defines_functions() {
+        let mut interface = interface();
+        interface.fns.truncate(1);
+        let items = host_items(&interface).unwrap();
+        let sigs = items
+            .into_iter()
+            .map(|item| syn::parse2::<syn::ItemFn>(item).unwrap())
+            .map(|f| f.sig.to_token_stream().to_string())
+            .collect::<Vec<_>>();
+        assert_eq!(sigs, signatures(&interface));
+
+        // a declaration without body cannot be exposed by the host
+        assert!(host_items(&self::interface()).is_err());
+    }
+}
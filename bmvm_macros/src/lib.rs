@@ -3,6 +3,7 @@
 mod common;
 mod guest;
 mod host;
+mod interface;
 mod typehash;
 
 use proc_macro::TokenStream;
@@ -47,6 +48,33 @@ pub fn expose_host(attr: TokenStream, item: TokenStream) -> TokenStream {
     host::expose_impl(attr, item)
 }
 
+/// Define the functions provided by the host once for both sides, so the guest declarations and
+/// the host implementations cannot drift apart. With the `guest` feature, the bodies are dropped
+/// and the signatures are expanded as `#[host] extern "C"` block. With the `host` feature, each
+/// function is expanded with `#[expose_host]`, therefore the bodies are required on the host side.
+///
+/// The block is usually placed in a file shared by guest and host via `include!`. Items used by
+/// the host implementations should be imported inside the bodies, as they are not compiled for
+/// the guest.
+///
+/// # Example
+/// ```ignore
+/// bmvm_macros::bmvm_interface! {
+///     fn add(a: u64, b: u64) -> Result<u64, HostError> {
+///         a.checked_add(b).ok_or(HostError::InvalidInput)
+///     }
+/// }
+/// ```
+#[proc_macro]
+pub fn bmvm_interface(item: TokenStream) -> TokenStream {
+    interface::interface_impl(item)
+}
+
+/// Derive `TypeSignature` for a `#[repr(C)]` or `#[repr(transparent)]` struct. The signature is
+/// composed of the field indexes and the signatures of the field types, so structs nest: a field
+/// of another deriving struct contributes its full signature, and changing an inner field changes
+/// the signature of every struct containing it. Values are validated field by field in the same
+/// way, while the `repr` ensures both sides agree on the layout.
 #[proc_macro_derive(TypeSignature)]
 pub fn derive_type_signature(input: TokenStream) -> TokenStream {
     typehash::derive_type_signature_impl(input)
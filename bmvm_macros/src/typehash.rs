@@ -39,6 +39,8 @@ pub fn derive_type_signature_impl(input: TokenStream) -> TokenStream {
     computable_hashes.push(quote! {
         let mut hasher = #type_djb2::new();
     });
+    // check the validity of every field, so the struct is only as valid as its fields
+    let mut field_checks = Vec::new();
     let is_primitive: proc_macro2::TokenStream;
     match &input.data {
         Data::Struct(data_struct) => {
@@ -62,10 +64,21 @@ pub fn derive_type_signature_impl(input: TokenStream) -> TokenStream {
                     computable_hashes.push(quote! {
                         hasher.write((#index as u64).to_le_bytes().as_slice());
                     });
-                    // Assuming/Enforcing non-primitive type will itself implement TypeSignature
+                    // Assuming/Enforcing non-primitive type will itself implement TypeSignature,
+                    // so nested structs contribute their composed signature
                     computable_hashes.push(quote! {
                         hasher.write(<#ty as #type_type_hash>::SIGNATURE.to_le_bytes().as_slice());
                     });
+
+                    let member = match &field.ident {
+                        Some(ident) => syn::Member::Named(ident.clone()),
+                        None => syn::Member::Unnamed(syn::Index::from(index)),
+                    };
+                    field_checks.push(quote! {
+                        && <#ty as #type_type_hash>::is_valid(
+                            ptr.add(core::mem::offset_of!(#name, #member))
+                        )
+                    });
                 })
         }
         _ => {
@@ -99,6 +112,10 @@ pub fn derive_type_signature_impl(input: TokenStream) -> TokenStream {
                 #is_primitive
             };
             #impl_name
+
+            unsafe fn is_valid(ptr: *const u8) -> bool {
+                unsafe { true #(#field_checks)* }
+            }
         }
     }
     .into()
@@ -3,13 +3,21 @@
 
 use bmvm_guest::hypercall;
 use bmvm_guest::upcall;
+use bmvm_guest::{HostError, LogLevel};
 
 #[hypercall]
 unsafe extern "C" {
-    fn add(a: u64, b: u64) -> u64;
+    fn add(a: u64, b: u64) -> Result<u64, HostError>;
 }
 
 #[upcall]
 fn hypercall_redirect() -> u64 {
-    add(10, 20)
+    match add(10, 20) {
+        Ok(sum) => sum,
+        // the host signaled a failure, fall back to zero
+        Err(e) => {
+            bmvm_guest::log!(LogLevel::Warn, "add failed: {:?}", e);
+            0
+        }
+    }
 }
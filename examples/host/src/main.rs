@@ -1,4 +1,4 @@
-use bmvm_host::mem::{AlignedNonZeroUsize, ForeignBuf, SharedBuf, alloc_buf};
+use bmvm_host::mem::{AlignedNonZeroUsize, ForeignBuf, SharedBuf};
 use bmvm_host::{ConfigBuilder, ModuleBuilder, linker};
 use clap::Parser;
 use std::hint::black_box;
@@ -40,11 +40,18 @@ fn main() -> anyhow::Result<()> {
 
     const BMVM_STACK: usize = 32 * 1024 * 1024; // 32MiB
     let path = PathBuf::from(args.guest);
-    let mut module = ModuleBuilder::new()
+    let mut module = match ModuleBuilder::new()
         .with_path(&path)
         .configure_linker(linker)
         .configure_vm(vm)
-        .build()?;
+        .build()
+    {
+        Ok(module) => module,
+        Err(err) => {
+            log::error!("{err}");
+            std::process::exit(err.exit_code());
+        }
+    };
 
     let reverse = module
         .get_upcall::<(SharedBuf,), ForeignBuf>("reverse")
@@ -52,8 +59,9 @@ fn main() -> anyhow::Result<()> {
 
     let now = std::time::Instant::now();
     for _ in 0..2_000_000 {
-        let owned = unsafe { alloc_buf(1024)? };
-        let _ = reverse.call(&mut module, (owned.into_shared(),)).unwrap();
+        let mut buf = module.pooled_buf(1024)?;
+        buf.try_copy_from(&[0u8; 1024])?;
+        let _ = reverse.call(&mut module, (buf,)).unwrap();
     }
 
     println!("DONE IN {:?}", now.elapsed());
@@ -0,0 +1,255 @@
+#![no_std]
+#![no_main]
+#![feature(thread_local)]
+
+extern crate alloc;
+
+use alloc::vec::Vec;
+use bmvm_guest::{ForeignBuf, HostError, SharedBuf, hypercall, upcall};
+use core::cell::Cell;
+use core::sync::atomic::{AtomicU64, Ordering};
+
+#[global_allocator]
+static HEAP: bmvm_guest::BmvmAllocator = bmvm_guest::BmvmAllocator::new();
+
+/// Run as entry-only guest, which exits with a value from the setup instead of waiting for
+/// upcalls, if the host passes `entry-only` as guest arguments.
+#[bmvm_guest::setup]
+fn entry() {
+    if bmvm_guest::args() == b"entry-only" {
+        bmvm_guest::exit_with_value(42u64);
+    }
+}
+
+/// Recurse without a base case until the guest stack is exhausted.
+#[upcall]
+fn overflow(depth: u64) -> u64 {
+    recurse(depth)
+}
+
+/// Recurse exactly `depth` times, each level keeping a frame sized buffer alive.
+#[upcall]
+fn recurse_bounded(depth: u64) -> u64 {
+    bounded(depth)
+}
+
+/// Counter in thread-local storage, initialized from `.tdata`
+#[thread_local]
+static COUNTER: Cell<u64> = Cell::new(40);
+
+/// Increment the thread-local counter and return its new value.
+#[upcall]
+fn tls_increment() -> u64 {
+    COUNTER.set(COUNTER.get() + 1);
+    COUNTER.get()
+}
+
+/// Fold `data` into a 32 byte digest, which exceeds the transport and is returned via the shared
+/// memory.
+#[upcall]
+fn hash(data: ForeignBuf) -> [u8; 32] {
+    let mut digest = [0u8; 32];
+    for (i, byte) in data.as_ref().iter().enumerate() {
+        digest[i % 32] = digest[i % 32].wrapping_mul(31).wrapping_add(*byte);
+    }
+    digest
+}
+
+/// First 16 bytes of `hash`, which fit into the transport.
+#[upcall]
+fn hash_short(data: ForeignBuf) -> [u8; 16] {
+    let digest = hash(data);
+    let mut short = [0u8; 16];
+    short.copy_from_slice(&digest[..16]);
+    short
+}
+
+/// Sum of `0..count`, yielding to the host after every addition.
+#[upcall]
+fn yielding_sum(count: u64) -> u64 {
+    let mut sum = 0;
+    for i in 0..count {
+        sum += i;
+        bmvm_guest::yield_to_host();
+    }
+    sum
+}
+
+/// Sum of `0..count`, waiting for the host in `hlt` after every addition.
+#[upcall]
+fn waiting_sum(count: u64) -> u64 {
+    let mut sum = 0;
+    for i in 0..count {
+        sum += i;
+        bmvm_guest::wait_for_host();
+    }
+    sum
+}
+
+/// Split `data` in halves, returned as two separate buffers.
+#[upcall]
+fn split_halves(data: ForeignBuf) -> (SharedBuf, SharedBuf) {
+    let (head, tail) = data.as_ref().split_at(data.len() / 2);
+    (
+        SharedBuf::from_bytes(head).unwrap(),
+        SharedBuf::from_bytes(tail).unwrap(),
+    )
+}
+
+/// Copy `data` into a new buffer `rounds` times, via `host_memcpy` if `host` is set or inline
+/// otherwise.
+#[upcall]
+fn copy_rounds(data: ForeignBuf, rounds: u64, host: bool) -> SharedBuf {
+    let mut out = unsafe { bmvm_guest::alloc_buf(data.len()) }.unwrap();
+    let (src, dst) = (data.as_ref().as_ptr(), out.as_mut().as_mut_ptr());
+    for _ in 0..rounds {
+        match host {
+            true => unsafe { bmvm_guest::host_memcpy(dst, src, data.len()) },
+            false => unsafe { core::ptr::copy(src, dst, data.len()) },
+        }
+    }
+    out.into_shared()
+}
+
+/// Number of chunks the most recent `count_stream` passed to the host
+static STREAMED: AtomicU64 = AtomicU64::new(0);
+
+/// Stream the numbers `0..count` as little endian chunks, stopping once the host cancels.
+#[upcall]
+fn count_stream(count: u64) {
+    STREAMED.store(0, Ordering::Relaxed);
+    for i in 0..count {
+        if bmvm_guest::stream_emit(&i.to_le_bytes()).is_err() {
+            return;
+        }
+        STREAMED.fetch_add(1, Ordering::Relaxed);
+    }
+}
+
+/// Number of chunks the most recent `count_stream` passed to the host.
+#[upcall]
+fn streamed() -> u64 {
+    STREAMED.load(Ordering::Relaxed)
+}
+
+/// Sum the numbers `0..count`, counting the iterations and calls as metrics.
+#[upcall]
+fn counted_sum(count: u64) -> u64 {
+    let iterations = bmvm_guest::metrics::counter("iterations");
+    let sum = (0..count).inspect(|_| iterations.increment()).sum();
+    bmvm_guest::metrics::add("calls", 1);
+    sum
+}
+
+#[hypercall]
+unsafe extern "C" {
+    // calls `countdown(depth)` back from within the hypercall
+    fn reenter(depth: u64) -> Result<u64, HostError>;
+}
+
+/// Count `depth` down to zero, re-entering the guest via the host for every step. Returns
+/// `u64::MAX` once the host refuses to call back.
+#[upcall]
+fn countdown(depth: u64) -> u64 {
+    match depth {
+        0 => 0,
+        depth => reenter(depth - 1).map_or(u64::MAX, |n| n.saturating_add(1)),
+    }
+}
+
+#[hypercall]
+unsafe extern "C" {
+    // calls `exit_now(value)` back from within the hypercall
+    fn reenter_exit(value: u64) -> Result<u64, HostError>;
+}
+
+/// Exit with `value` instead of returning, which breaks the protocol if called back by the host.
+#[upcall]
+fn exit_now(value: u64) -> u64 {
+    bmvm_guest::exit_with_value(value)
+}
+
+/// Issue a hypercall, whose callback exits the guest before the hypercall completes.
+#[upcall]
+fn exit_within_callback(value: u64) -> u64 {
+    reenter_exit(value).unwrap_or(u64::MAX)
+}
+
+/// Read the guest memory at `addr`, which may lie outside every mapped region.
+#[upcall]
+fn read_at(addr: u64) -> u64 {
+    unsafe { (addr as *const u64).read_volatile() }
+}
+
+/// Write to the foreign buffer through a pointer cast from its shared view, which must fault.
+#[upcall]
+fn write_foreign(data: ForeignBuf) -> u64 {
+    let ptr = data.as_ref().as_ptr().cast_mut();
+    unsafe { ptr.write_volatile(0xff) };
+    data.len() as u64
+}
+
+/// Sum `0..n` collected into a heap allocated vector, which grows the heap via page requests.
+#[upcall]
+fn heap_sum(n: u64) -> u64 {
+    let values = (0..n).collect::<Vec<_>>();
+    values.iter().sum()
+}
+
+/// Sum of the bytes of the host file mapped via `map_file_readonly`.
+#[upcall]
+fn mapped_sum() -> u64 {
+    bmvm_guest::mapped_file().iter().map(|b| *b as u64).sum()
+}
+
+/// Virtual address of the mapped host file.
+#[upcall]
+fn mapped_addr() -> u64 {
+    bmvm_guest::mapped_file().as_ptr() as u64
+}
+
+/// Write to the mapped host file with the write protection of the page tables disabled, so only
+/// the read-only memory slot of the host prevents the write.
+#[upcall]
+fn mapped_write() -> u64 {
+    let ptr = bmvm_guest::mapped_file().as_ptr().cast_mut();
+    unsafe {
+        core::arch::asm!("mov {0}, cr0", "btr {0}, 16", "mov cr0, {0}", out(reg) _);
+        ptr.write_volatile(0xff);
+    }
+    0
+}
+
+/// Address of `step_routine`, which the host steps through instruction by instruction.
+#[upcall]
+fn step_routine_addr() -> u64 {
+    step_routine as usize as u64
+}
+
+/// Fixed instruction sequence with known lengths and register effects.
+#[unsafe(naked)]
+extern "C" fn step_routine() {
+    core::arch::naked_asm!(
+        "mov eax, 7",           // 5 bytes
+        "add eax, 5",           // 3 bytes
+        "lea ebx, [rax + rax]", // 3 bytes
+        "hlt",
+    )
+}
+
+#[inline(never)]
+#[allow(unconditional_recursion)]
+fn recurse(depth: u64) -> u64 {
+    // keep a frame sized buffer alive across the call to consume stack space
+    let frame = core::hint::black_box([depth; 64]);
+    recurse(depth + 1) + frame[0]
+}
+
+#[inline(never)]
+fn bounded(depth: u64) -> u64 {
+    let frame = core::hint::black_box([depth; 64]);
+    if depth == 0 {
+        return frame[0];
+    }
+    bounded(depth - 1) + frame[0]
+}
@@ -1,10 +1,10 @@
-use crate::bench::bench;
+use crate::bench::{TIMED_OUT, bench};
 use bmvm_host::mem::AlignedUsize;
-use bmvm_host::{ConfigBuilder, Module as BmvmModule, ModuleBuilder, Upcall, linker};
+use bmvm_host::{ConfigBuilder, Module as BmvmModule, ModuleBuilder, Snapshot, Upcall, linker};
 use std::hint::black_box;
 use std::path::PathBuf;
 use std::process::Output;
-use std::time::Instant;
+use std::time::{Duration, Instant};
 use wasmtime::{Engine, Instance, Module as WasmModule, Store, TypedFunc};
 
 pub fn native(path: &PathBuf, warmup: usize, iters: usize) -> anyhow::Result<Vec<f64>> {
@@ -47,26 +47,48 @@ pub fn wasm(path: &PathBuf, warmup: usize, iters: usize) -> anyhow::Result<Vec<f
     bench(path, warmup, iters, pre, exec, post)
 }
 
-pub fn bmvm(path: &PathBuf, warmup: usize, iters: usize) -> anyhow::Result<Vec<f64>> {
-    fn pre(path: &PathBuf) -> anyhow::Result<(Upcall<(), ()>, BmvmModule)> {
+/// Guest prepared for the execution, including its initial state if a timeout is configured
+type BmvmState = (Upcall<(), ()>, BmvmModule, Option<Snapshot>);
+
+/// Call the `run` function of the guest. With a `timeout`, iterations exceeding it are recorded as
+/// `TIMED_OUT` and the guest is reset to its initial state before the next one.
+pub fn bmvm(
+    path: &PathBuf,
+    warmup: usize,
+    iters: usize,
+    timeout: Option<Duration>,
+) -> anyhow::Result<Vec<f64>> {
+    let pre = |path: &PathBuf| -> anyhow::Result<BmvmState> {
+        let mut config = ConfigBuilder::new().shared_memory(AlignedUsize::zero());
+        if let Some(timeout) = timeout {
+            config = config.timeout(timeout);
+        }
         let mut module = ModuleBuilder::new()
-            .configure_vm(ConfigBuilder::new().shared_memory(AlignedUsize::zero()))
+            .configure_vm(config)
             .configure_linker(linker::ConfigBuilder::new().register_guest_function::<(), ()>("run"))
             .with_path(path)
             .build()?;
 
         let run = module.get_upcall::<(), ()>("run")?;
-        Ok((run, module))
-    }
-    fn exec((run, guest): &mut (Upcall<(), ()>, BmvmModule)) -> anyhow::Result<f64> {
+        let initial = match timeout {
+            Some(_) => Some(module.snapshot()?),
+            None => None,
+        };
+        Ok((run, module, initial))
+    };
+    fn exec((run, guest, initial): &mut BmvmState) -> anyhow::Result<f64> {
         let now = Instant::now();
-        black_box({
-            run.call(guest, ())?;
-        });
-        let elapsed = now.elapsed();
-        Ok(elapsed.as_nanos() as f64)
+        match (black_box(run.call(guest, ())), initial) {
+            (Ok(()), _) => Ok(now.elapsed().as_nanos() as f64),
+            // the guest is stuck in the middle of the execution, reset it for the next iteration
+            (Err(bmvm_host::Error::Timeout(_)), Some(initial)) => {
+                guest.restore(initial, &[])?;
+                Ok(TIMED_OUT)
+            }
+            (Err(err), _) => Err(err.into()),
+        }
     }
-    fn post(_: &mut (Upcall<(), ()>, BmvmModule)) -> anyhow::Result<()> {
+    fn post(_: &mut BmvmState) -> anyhow::Result<()> {
         Ok(())
     }
     bench(path, warmup, iters, pre, exec, post)
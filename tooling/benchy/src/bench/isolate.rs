@@ -0,0 +1,91 @@
+use indicatif::ProgressBar;
+use std::path::PathBuf;
+use std::process::{Command, Stdio};
+
+/// Prefix of the stdout line the child process reports its sample with
+const SAMPLE_PREFIX: &str = "benchy-sample:";
+
+/// Samples collected by running every iteration in a separate process
+pub struct Outcome {
+    pub samples: Vec<f64>,
+    /// Number of iterations, which did not report a sample
+    pub crashes: usize,
+}
+
+/// Report the single sample measured by an isolated child process to the parent.
+pub fn report(samples: &[f64]) -> anyhow::Result<()> {
+    let sample = samples
+        .last()
+        .ok_or_else(|| anyhow::anyhow!("No sample measured"))?;
+    println!("{SAMPLE_PREFIX}{sample}");
+    Ok(())
+}
+
+/// Run `iters` iterations, each in a fresh child process executing the current binary with
+/// `args` (which must not contain the iteration count). The child measures a single sample after
+/// `warmup` iterations and passes it back via its stdout. Iterations, whose child crashed or
+/// exited unsuccessfully, are counted as crashes instead of aborting the whole run.
+pub fn run(args: &[String], warmup: usize, iters: usize) -> anyhow::Result<Outcome> {
+    let exe = std::env::current_exe()?;
+    let mut outcome = Outcome {
+        samples: Vec::with_capacity(iters),
+        crashes: 0,
+    };
+
+    println!("Sampling in isolated processes...");
+    let bar = ProgressBar::new(iters as u64);
+    bar.set_position(0);
+    for i in 0..iters {
+        let output = Command::new(&exe)
+            .args(args)
+            .args(["--warmup", &warmup.to_string(), "--iters", "1"])
+            .arg("--isolated-child")
+            .stdin(Stdio::null())
+            .stderr(Stdio::inherit())
+            .output()?;
+
+        let stdout = String::from_utf8_lossy(&output.stdout);
+        let sample = stdout
+            .lines()
+            .find_map(|l| l.strip_prefix(SAMPLE_PREFIX))
+            .and_then(|s| s.trim().parse::<f64>().ok());
+
+        match sample {
+            Some(sample) if output.status.success() => outcome.samples.push(sample),
+            _ => {
+                bar.println(format!("Iteration {} failed: {}", i, output.status));
+                outcome.crashes += 1;
+            }
+        }
+        bar.set_position(i as u64 + 1);
+    }
+    bar.finish();
+    println!(
+        "Execution Finished: {} samples, {} crashes.",
+        outcome.samples.len(),
+        outcome.crashes
+    );
+
+    Ok(outcome)
+}
+
+/// Command line of the child process, reproducing the benchmark selection of the parent
+pub fn child_args(
+    file: &PathBuf,
+    runtime: &str,
+    mode: &str,
+    per_iter_timeout: Option<u64>,
+) -> Vec<String> {
+    let mut args = vec![
+        String::from("--file"),
+        file.display().to_string(),
+        String::from("--runtime"),
+        runtime.to_string(),
+        String::from("--mode"),
+        mode.to_string(),
+    ];
+    if let Some(timeout) = per_iter_timeout {
+        args.extend([String::from("--per-iter-timeout"), timeout.to_string()]);
+    }
+    args
+}
@@ -2,9 +2,12 @@ use indicatif::ProgressBar;
 use std::path::PathBuf;
 
 pub mod exec;
+pub mod isolate;
 pub mod startup;
 
-type Pre<T> = fn(&PathBuf) -> anyhow::Result<T>;
+/// Sample recorded for an iteration, which exceeded the per iteration timeout
+pub const TIMED_OUT: f64 = f64::NAN;
+
 type Exec<T> = fn(&mut T) -> anyhow::Result<f64>;
 type Post<T> = fn(&mut T) -> anyhow::Result<()>;
 
@@ -12,14 +15,14 @@ fn bench<T>(
     path: &PathBuf,
     warmup: usize,
     iters: usize,
-    prep: Pre<T>,
+    prep: impl FnOnce(&PathBuf) -> anyhow::Result<T>,
     exec: Exec<T>,
     post: Post<T>,
 ) -> anyhow::Result<Vec<f64>> {
     let mut samples: Vec<f64> = Vec::with_capacity(iters);
     println!("Executable: {}", path.display());
 
-    let mut state = prep(&path)?;
+    let mut state = prep(path)?;
 
     // Executing optional warmup phase
     if warmup > 0 {
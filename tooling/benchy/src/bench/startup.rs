@@ -54,6 +54,7 @@ pub fn bmvm(path: &PathBuf, warmup: usize, iters: usize) -> anyhow::Result<Vec<f
         });
 
         let elapsed = now.elapsed();
+        anyhow::ensure!(module.is_ready(), "guest is not idle after setup");
         std::mem::drop(module);
 
         Ok(elapsed.as_nanos() as f64)
@@ -1,3 +1,4 @@
+use crate::Format;
 use serde::Serialize;
 use std::fs::File;
 use std::io::{BufWriter, Write};
@@ -5,6 +6,25 @@ use std::path::PathBuf;
 
 const FILE_RAW: &str = "raw.csv";
 const FILE_SUMMARY: &str = "summary.json";
+const FILE_RECORD: &str = "record.bin";
+
+/// Benchmark configuration, stored alongside the samples by `Format::Bincode`
+#[derive(Serialize)]
+pub struct Meta {
+    pub runtime: String,
+    pub mode: String,
+    pub file: String,
+    pub warmup: usize,
+    pub iters: usize,
+}
+
+/// Content of the binary record file. The layout must match the loader of `plotty`.
+#[derive(Serialize)]
+struct Record<'a> {
+    meta: &'a Meta,
+    summary: &'a Summary,
+    samples: &'a [f64],
+}
 
 #[derive(Serialize)]
 struct Summary {
@@ -14,6 +34,10 @@ struct Summary {
     median: f64,
     var: f64,
     std: f64,
+    /// Iterations, which crashed instead of producing a sample
+    crashes: usize,
+    /// Iterations, which exceeded the per iteration timeout
+    timeouts: usize,
 }
 
 #[repr(transparent)]
@@ -22,7 +46,7 @@ struct Samples([f64]);
 impl Samples {
     fn new(slice: &[f64]) -> &Samples {
         assert!(slice.len() > 1);
-        assert!(&&slice.iter().all(|x| !x.is_nan()));
+        assert!(slice.iter().all(|x| !x.is_nan()));
 
         unsafe { std::mem::transmute(slice) }
     }
@@ -89,7 +113,7 @@ impl Samples {
         }
     }
 
-    fn summary(&self) -> Summary {
+    fn summary(&self, crashes: usize, timeouts: usize) -> Summary {
         let min = self.min();
         let max = self.max();
         let mean = self.mean();
@@ -104,24 +128,71 @@ impl Samples {
             median,
             var,
             std,
+            crashes,
+            timeouts,
         }
     }
 }
 
-pub fn eval(directory: PathBuf, durations: &[f64]) -> anyhow::Result<()> {
+pub fn eval(
+    directory: PathBuf,
+    durations: &[f64],
+    crashes: usize,
+    format: Format,
+    meta: &Meta,
+) -> anyhow::Result<()> {
     println!("Evaluating...");
     println!("Writing results to {}", directory.display());
     std::fs::create_dir_all(&directory)?;
 
-    if durations.is_empty() {
-        return Err(anyhow::anyhow!("No values to evaluate"));
+    if crashes > 0 {
+        println!("{} iterations crashed", crashes);
+    }
+
+    // timed out iterations are recorded as NaN
+    let (timed_out, durations): (Vec<f64>, Vec<f64>) = durations.iter().partition(|d| d.is_nan());
+    if !timed_out.is_empty() {
+        println!("{} iterations timed out", timed_out.len());
+    }
+
+    if durations.len() < 2 {
+        return Err(anyhow::anyhow!(
+            "At least two values are required to evaluate, got {}",
+            durations.len()
+        ));
     }
 
-    let samples = Samples::new(durations);
-    let summary = samples.summary();
+    let samples = Samples::new(&durations);
+    let summary = samples.summary(crashes, timed_out.len());
+
+    match format {
+        Format::Text => {
+            write_raw(&directory, samples)?;
+            write_summary(&directory, &summary)?;
+        }
+        Format::Bincode => write_record(&directory, meta, &summary, samples)?,
+    }
+    Ok(())
+}
+
+/// Write the metadata, summary and raw data into a single binary file
+fn write_record(
+    path: &PathBuf,
+    meta: &Meta,
+    summary: &Summary,
+    samples: &Samples,
+) -> anyhow::Result<()> {
+    let file = File::create(path.join(FILE_RECORD))?;
+    let mut writer = BufWriter::new(file);
+
+    let record = Record {
+        meta,
+        summary,
+        samples: &samples.0,
+    };
+    bincode::serde::encode_into_std_write(&record, &mut writer, bincode::config::standard())?;
+    writer.flush()?;
 
-    write_raw(&directory, samples)?;
-    write_summary(&directory, &summary)?;
     Ok(())
 }
 
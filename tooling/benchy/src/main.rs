@@ -1,5 +1,7 @@
+use bench::isolate;
 use clap::{Parser, ValueEnum};
 use std::path::PathBuf;
+use std::time::Duration;
 
 mod bench;
 mod eval;
@@ -17,6 +19,17 @@ enum Mode {
     Exec,
 }
 
+/// Output format of the results
+#[derive(ValueEnum, Copy, Clone, Debug, Default, Eq, PartialEq)]
+pub enum Format {
+    /// Raw samples as text (`raw.csv`) and their summary as JSON (`summary.json`)
+    #[default]
+    Text,
+    /// Samples, summary and benchmark configuration in a single binary file (`record.bin`), which
+    /// is faster to write and to load for large iteration counts
+    Bincode,
+}
+
 impl Mode {
     fn dir(&self) -> String {
         match self {
@@ -27,11 +40,17 @@ impl Mode {
 }
 
 impl Runtime {
-    fn exec(&self, path: &PathBuf, warmup: usize, iters: usize) -> anyhow::Result<Vec<f64>> {
+    fn exec(
+        &self,
+        path: &PathBuf,
+        warmup: usize,
+        iters: usize,
+        timeout: Option<Duration>,
+    ) -> anyhow::Result<Vec<f64>> {
         match self {
             Runtime::Native => bench::exec::native(path, warmup, iters),
             Runtime::Wasm => bench::exec::wasm(path, warmup, iters),
-            Runtime::Bmvm => bench::exec::bmvm(path, warmup, iters),
+            Runtime::Bmvm => bench::exec::bmvm(path, warmup, iters, timeout),
         }
     }
 
@@ -61,6 +80,13 @@ fn validate(args: &Args) -> anyhow::Result<()> {
         ));
     }
 
+    if args.per_iter_timeout.is_some() && (args.runtime != Runtime::Bmvm || args.mode != Mode::Exec)
+    {
+        return Err(anyhow::anyhow!(
+            "Per iteration timeout is only supported for the bmvm runtime in exec mode"
+        ));
+    }
+
     if !args.file.is_file() {
         return Err(anyhow::anyhow!(
             "Provided path is not a file: {}",
@@ -86,6 +112,19 @@ struct Args {
     iters: usize,
     #[arg(short, long, env = "OUTPUT")]
     output: Option<String>,
+    #[arg(long, env = "FORMAT", default_value = "text")]
+    format: Format,
+    /// Run every iteration in a separate process, so a crashing guest is counted as a failed
+    /// iteration instead of aborting the benchmark
+    #[arg(long, env = "ISOLATE")]
+    isolate: bool,
+    /// Abort a bmvm iteration running longer than the given number of milliseconds, which is
+    /// reported as timed out instead of hanging the benchmark
+    #[arg(long, env = "PER_ITER_TIMEOUT")]
+    per_iter_timeout: Option<u64>,
+    /// Measure a single sample and report it to the parent process (used by `--isolate`)
+    #[arg(long, hide = true)]
+    isolated_child: bool,
 }
 
 fn main() -> anyhow::Result<()> {
@@ -105,9 +144,28 @@ fn main() -> anyhow::Result<()> {
         PathBuf::from(".")
     };
 
-    let results = match args.mode {
-        Mode::Start => args.runtime.startup(&args.file, args.warmup, args.iters)?,
-        Mode::Exec => args.runtime.exec(&args.file, args.warmup, args.iters)?,
+    let timeout = args.per_iter_timeout.map(Duration::from_millis);
+    let run = |warmup: usize, iters: usize| match args.mode {
+        Mode::Start => args.runtime.startup(&args.file, warmup, iters),
+        Mode::Exec => args.runtime.exec(&args.file, warmup, iters, timeout),
+    };
+
+    if args.isolated_child {
+        return isolate::report(&run(args.warmup, 1)?);
+    }
+
+    let (results, crashes) = if args.isolate {
+        let name = |v: clap::builder::PossibleValue| v.get_name().to_string();
+        let child = isolate::child_args(
+            &args.file,
+            &name(args.runtime.to_possible_value().unwrap()),
+            &name(args.mode.to_possible_value().unwrap()),
+            args.per_iter_timeout,
+        );
+        let outcome = isolate::run(&child, args.warmup, args.iters)?;
+        (outcome.samples, outcome.crashes)
+    } else {
+        (run(args.warmup, args.iters)?, 0)
     };
 
     output.push(args.mode.dir());
@@ -116,5 +174,12 @@ fn main() -> anyhow::Result<()> {
         output.push(args.file.file_stem().unwrap());
     }
 
-    eval::eval(output, &results)
+    let meta = eval::Meta {
+        runtime: args.runtime.dir(),
+        mode: args.mode.dir(),
+        file: args.file.display().to_string(),
+        warmup: args.warmup,
+        iters: args.iters,
+    };
+    eval::eval(output, &results, crashes, args.format, &meta)
 }
@@ -1,6 +1,6 @@
 mod plot;
 
-use crate::plot::startup;
+use crate::plot::{links, startup};
 use anyhow::Result;
 use clap::{Parser, ValueEnum};
 use plot::polybench;
@@ -10,6 +10,7 @@ use std::path::PathBuf;
 enum Benchmark {
     Polybench,
     Startup,
+    Links,
 }
 
 #[derive(Parser, Debug)]
@@ -54,5 +55,6 @@ fn main() -> Result<()> {
     match args.benchmark {
         Benchmark::Polybench => polybench::plot(args.dir.as_path(), output.as_path()),
         Benchmark::Startup => startup::plot(args.dir.as_path(), output.as_path()),
+        Benchmark::Links => links::plot(args.dir.as_path(), output.as_path()),
     }
 }
@@ -0,0 +1,152 @@
+use crate::plot::summary;
+use anyhow::{Context, Result};
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::Path;
+
+#[derive(Debug)]
+pub struct PlotData {
+    /// runtime -> (number of links, mean, stddev)
+    data: BTreeMap<String, Vec<(u64, f64, f64)>>,
+}
+
+/// Collect the call latencies from `<data_dir>/<runtime>/<links>/summary.json`, where `<links>` is
+/// the number of registered links during the measurement.
+pub fn collect_data(data_dir: &Path) -> Result<PlotData> {
+    let mut data: BTreeMap<String, Vec<(u64, f64, f64)>> = BTreeMap::new();
+
+    let dir_entries: Vec<_> = fs::read_dir(data_dir)?
+        .filter_map(Result::ok)
+        .filter(|e| e.metadata().map(|m| m.is_dir()).unwrap_or(false))
+        .collect();
+
+    for entry in dir_entries {
+        let type_name = entry.file_name().into_string().unwrap_or_default();
+        let mut points = Vec::new();
+
+        // every numeric subdirectory contains the measurement for the given number of links
+        let links: Vec<(u64, _)> = fs::read_dir(entry.path())?
+            .filter_map(Result::ok)
+            .filter(|e| e.metadata().map(|m| m.is_dir()).unwrap_or(false))
+            .filter_map(|e| {
+                let name = e.file_name().into_string().ok()?;
+                name.parse::<u64>().ok().map(|n| (n, e.path()))
+            })
+            .collect();
+
+        for (n, path) in links {
+            let summary = summary::load(&path)
+                .with_context(|| format!("Failed to load summary for {}:{}", type_name, n))?;
+
+            points.push((n, summary.mean, summary.std));
+        }
+
+        if points.is_empty() {
+            continue;
+        }
+
+        points.sort_by_key(|(n, _, _)| *n);
+        data.insert(type_name, points);
+    }
+
+    if data.is_empty() {
+        anyhow::bail!("No link measurements found in {}", data_dir.display());
+    }
+
+    Ok(PlotData { data })
+}
+
+pub fn generate_latex_plot(plot_data: &PlotData, output: &Path) -> Result<()> {
+    let mut latex = String::new();
+
+    // LaTeX document preamble
+    latex.push_str(
+        r#"
+\documentclass{standalone}
+\usepackage{pgfplots}
+\pgfplotsset{compat=1.18}
+\usepackage{textcomp}
+\usepackage{amsmath}
+
+\begin{document}
+\begin{tikzpicture}
+\begin{axis}[
+    width=12cm,
+    height=8cm,
+    xlabel={Registered Links},
+    ylabel={Call Latency},
+    ymin=0,
+    legend style={at={(0.5,-0.15)}, anchor=north, legend columns=-1},
+    grid=major,
+    grid style={dashed, gray!30},
+    "#,
+    );
+
+    // use the link counts of all runtimes as ticks
+    let mut ticks: Vec<u64> = plot_data
+        .data
+        .values()
+        .flat_map(|p| p.iter().map(|(n, _, _)| *n))
+        .collect();
+    ticks.sort();
+    ticks.dedup();
+    let ticks = ticks
+        .iter()
+        .map(|n| n.to_string())
+        .collect::<Vec<String>>()
+        .join(",");
+    latex.push_str(format!("xtick={{{}}},\n]\n", ticks).as_str());
+
+    // Add plot for each type
+    for (type_name, points) in plot_data.data.iter() {
+        latex.push_str(
+            r#"
+\addplot+[
+    error bars/.cd,
+    y dir=both,
+    y explicit
+] coordinates {
+"#,
+        );
+
+        for (n, mean, std) in points {
+            latex.push_str(&format!(
+                "({},{}) +- (0,{})\n",
+                n,
+                mean.floor(),
+                std.floor()
+            ));
+        }
+
+        latex.push_str(&format!("}};\n\\addlegendentry{{{}}};\n", type_name));
+    }
+
+    // Close the axis and document
+    latex.push_str(
+        r#"
+\end{axis}
+\end{tikzpicture}
+\end{document}
+"#,
+    );
+
+    fs::write(output, latex)?;
+    println!("LaTeX plot generated successfully at: {}", output.display());
+    Ok(())
+}
+
+pub fn plot(data_dir: &Path, output: &Path) -> Result<()> {
+    let mut o = output.to_path_buf();
+    o.push("links.tex");
+
+    println!("Collecting data from: {}", data_dir.display());
+    let plot_data = collect_data(data_dir)?;
+
+    println!("Found {} types:", plot_data.data.len());
+    for (type_name, points) in &plot_data.data {
+        println!("  - {} ({} measurements)", type_name, points.len());
+    }
+
+    println!("Generating LaTeX plot...");
+    generate_latex_plot(&plot_data, &o)
+}
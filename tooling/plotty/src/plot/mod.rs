@@ -1,2 +1,4 @@
+pub mod links;
 pub mod polybench;
 pub mod startup;
+pub mod summary;
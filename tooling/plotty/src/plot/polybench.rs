@@ -1,15 +1,10 @@
+use crate::plot::summary;
 use anyhow::{Context, Result};
-use serde::Deserialize;
 use std::collections::HashMap;
 use std::fs;
 use std::path::Path;
 use std::time::Duration;
 
-#[derive(Debug, Deserialize)]
-pub struct Summary {
-    mean: f64,
-}
-
 #[derive(Debug)]
 pub struct PlotData {
     types: Vec<String>,
@@ -52,16 +47,9 @@ pub fn collect_data(data_dir: &Path) -> Result<PlotData> {
         let mut mean_values = Vec::new();
 
         for execution in &executions {
-            let summary_path = entry.path().join(execution).join("summary.json");
-            if !summary_path.exists() {
-                anyhow::bail!("Missing summary.json for {}:{}", type_name, execution);
-            }
-
-            let summary_content = fs::read_to_string(&summary_path)
-                .with_context(|| format!("Failed to read {:?}", summary_path))?;
-
-            let summary: Summary = serde_json::from_str(&summary_content)
-                .with_context(|| format!("Failed to parse JSON in {:?}", summary_path))?;
+            let summary = summary::load(&entry.path().join(execution)).with_context(|| {
+                format!("Failed to load summary for {}:{}", type_name, execution)
+            })?;
 
             // Convert nanoseconds to microseconds for better readability
             mean_values.push(Duration::from_nanos(summary.mean.floor() as u64));
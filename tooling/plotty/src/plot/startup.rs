@@ -1,16 +1,10 @@
+use crate::plot::summary;
 use anyhow::{Context, Result};
-use serde::Deserialize;
 use std::collections::HashMap;
 use std::fs;
 use std::path::Path;
 use std::time::Duration;
 
-#[derive(Debug, Deserialize)]
-pub struct Summary {
-    mean: f64,
-    std: f64,
-}
-
 #[derive(Debug)]
 pub struct PlotData {
     data: HashMap<String, (u64, u64)>,
@@ -29,16 +23,8 @@ pub fn collect_data(data_dir: &Path) -> Result<PlotData> {
     for entry in dir_entries {
         let type_name = entry.file_name().into_string().unwrap_or_default();
 
-        let summary_path = entry.path().join("summary.json");
-        if !summary_path.exists() {
-            anyhow::bail!("Missing summary.json for {}", type_name);
-        }
-
-        let summary_content = fs::read_to_string(&summary_path)
-            .with_context(|| format!("Failed to read {:?}", summary_path))?;
-
-        let summary: Summary = serde_json::from_str(&summary_content)
-            .with_context(|| format!("Failed to parse JSON in {:?}", summary_path))?;
+        let summary = summary::load(&entry.path())
+            .with_context(|| format!("Failed to load summary for {}", type_name))?;
 
         let mean = summary.mean.floor() as u64;
         let stddev = summary.std.floor() as u64;
@@ -0,0 +1,101 @@
+use anyhow::{Context, Result};
+use serde::Deserialize;
+use std::fs::{self, File};
+use std::io::BufReader;
+use std::path::Path;
+
+const FILE_SUMMARY: &str = "summary.json";
+const FILE_RECORD: &str = "record.bin";
+
+/// Summary of a measurement as written by benchy. Only the statistics used by the plots are read.
+#[derive(Debug, Deserialize)]
+pub struct Summary {
+    pub mean: f64,
+    pub std: f64,
+}
+
+/// Layout of the binary record written by `benchy --format bincode`. It is not self-describing,
+/// so every field has to be decoded, although the plots only use the summary:
+/// - configuration: runtime, mode, file, warmup and measured iterations
+/// - summary: min, max, mean, median, variance, standard deviation, crashes and timeouts
+/// - raw samples
+type Record = (
+    (String, String, String, usize, usize),
+    (f64, f64, f64, f64, f64, f64, usize, usize),
+    Vec<f64>,
+);
+
+/// Load the summary of the measurement in `dir`, either from `summary.json` or the binary record
+pub fn load(dir: &Path) -> Result<Summary> {
+    let summary_path = dir.join(FILE_SUMMARY);
+    if summary_path.exists() {
+        let summary_content = fs::read_to_string(&summary_path)
+            .with_context(|| format!("Failed to read {:?}", summary_path))?;
+
+        return serde_json::from_str(&summary_content)
+            .with_context(|| format!("Failed to parse JSON in {:?}", summary_path));
+    }
+
+    let record_path = dir.join(FILE_RECORD);
+    if record_path.exists() {
+        return load_record(&record_path);
+    }
+
+    anyhow::bail!("Missing {} or {} in {:?}", FILE_SUMMARY, FILE_RECORD, dir)
+}
+
+/// Load the summary of the binary record at `path`
+fn load_record(path: &Path) -> Result<Summary> {
+    let file = File::open(path).with_context(|| format!("Failed to read {:?}", path))?;
+    let mut reader = BufReader::new(file);
+
+    let (_, (_, _, mean, _, _, std, _, _), _): Record =
+        bincode::serde::decode_from_std_read(&mut reader, bincode::config::standard())
+            .with_context(|| format!("Failed to decode record in {:?}", path))?;
+    Ok(Summary { mean, std })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::path::PathBuf;
+
+    /// Empty directory for the measurement files of `name`
+    fn measurement_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("plotty-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[test]
+    fn load_summary_json() {
+        let dir = measurement_dir("json");
+        let json = r#"{"min":1.0,"max":9.0,"mean":4.5,"median":4.0,"var":2.25,"std":1.5,"crashes":0,"timeouts":0}"#;
+        fs::write(dir.join(FILE_SUMMARY), json).unwrap();
+
+        let summary = load(&dir).unwrap();
+        assert_eq!((summary.mean, summary.std), (4.5, 1.5));
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn load_binary_record() {
+        let dir = measurement_dir("record");
+        assert!(load(&dir).is_err());
+
+        // encoded in the field order of benchy's record
+        let record: Record = (
+            ("bmvm".into(), "exec".into(), "gemm".into(), 2, 3),
+            (1.0, 3.0, 2.0, 2.0, 1.0, 1.0, 0, 0),
+            vec![1.0, 2.0, 3.0],
+        );
+        let mut file = File::create(dir.join(FILE_RECORD)).unwrap();
+        bincode::serde::encode_into_std_write(&record, &mut file, bincode::config::standard())
+            .unwrap();
+
+        let summary = load(&dir).unwrap();
+        assert_eq!((summary.mean, summary.std), (2.0, 1.0));
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
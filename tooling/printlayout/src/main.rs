@@ -1,10 +1,13 @@
 use bmvm_common::interprete::Interpret;
-use bmvm_common::mem::{Flags, LayoutTable};
+use bmvm_common::mem::{DumpHeader, Flags, LayoutTable, read_dump, region_stats};
+use bmvm_common::{BMVM_MEM_LAYOUT_TABLE, BMVM_PANIC_MESSAGE};
 use clap::Parser;
 use std::fs;
 use tabled::settings::Style;
 use tabled::{Table, Tabled};
 
+mod validate;
+
 #[derive(Tabled)]
 struct TableEntry {
     idx: usize,
@@ -18,6 +21,23 @@ struct TableEntry {
     present: bool,
 }
 
+#[derive(Tabled)]
+struct DumpEntry {
+    paddr: String,
+    vaddr: String,
+    len: u64,
+    flags: String,
+}
+
+#[derive(Tabled)]
+struct StatEntry {
+    paddr: String,
+    vaddr: String,
+    pages: u64,
+    accessed: u64,
+    dirty: u64,
+}
+
 #[derive(Parser, Debug)]
 #[command(version, about, long_about = None)]
 struct Args {
@@ -26,16 +46,42 @@ struct Args {
 
     #[arg(short, long, env = "OFFSET", default_value_t = 0)]
     offset: usize,
+
+    /// Check the table for overlapping or misaligned regions and exit non-zero on failure
+    #[arg(long, default_value_t = false)]
+    validate: bool,
+
+    /// Interpret the file as memory dump written by `Module::dump_memory`. The regions of the dump
+    /// are listed and the layout table is read from the contained layout region. In addition, the
+    /// number of accessed and dirty pages per region is derived from the contained paging structure.
+    #[arg(long, default_value_t = false)]
+    dump: bool,
+
+    /// Guest physical address of the layout table within the dump, if the module was configured
+    /// via `ConfigBuilder::layout_table_addr`. Requires `--dump`.
+    #[arg(long, value_parser = parse_addr, default_value_t = BMVM_MEM_LAYOUT_TABLE.as_u64(), requires = "dump")]
+    layout_table: u64,
+
+    /// Print the message recorded by the guest panic handler. Requires `--dump`.
+    #[arg(long, default_value_t = false, requires = "dump")]
+    panic: bool,
 }
 
 fn main() -> anyhow::Result<()> {
     let args = Args::parse();
-    let dump = fs::read(args.file)?;
-    let table = LayoutTable::from_bytes(&dump[args.offset..])?;
+    let mut dump = fs::read(&args.file)?;
+    let regions = match args.dump {
+        true => Some(read_dump(&mut &dump[..])?),
+        false => None,
+    };
+    if let Some(regions) = &regions {
+        dump = print_dump(regions, args.layout_table)?;
+    }
+    let layout = LayoutTable::from_bytes(&dump[args.offset..])?;
 
     let mut table_entries = Vec::new();
 
-    for (idx, entry) in table.into_iter().enumerate() {
+    for (idx, entry) in layout.into_iter().enumerate() {
         let access = match entry.flags().data_access_mode() {
             Some(a) => format!("{}", a),
             None => "N/A".to_string(),
@@ -58,5 +104,113 @@ fn main() -> anyhow::Result<()> {
     table.with(Style::modern());
     println!("{}", table);
 
+    if let Some(regions) = &regions {
+        print_stats(layout, regions);
+    }
+
+    if let Some(regions) = &regions
+        && args.panic
+    {
+        print_panic(regions);
+    }
+
+    if args.validate {
+        let issues = validate::validate(layout);
+        if !issues.is_empty() {
+            for issue in issues.iter() {
+                eprintln!("error: {}", issue);
+            }
+            anyhow::bail!(
+                "layout table validation failed with {} issue(s)",
+                issues.len()
+            );
+        }
+        println!("layout table is valid");
+    }
+
     Ok(())
 }
+
+/// List the regions contained in the memory dump and return the layout table region.
+fn print_dump(regions: &[(DumpHeader, Vec<u8>)], layout_table: u64) -> anyhow::Result<Vec<u8>> {
+    let entries = regions.iter().map(|(header, _)| DumpEntry {
+        paddr: format!("{:X}", header.paddr),
+        vaddr: format!("{:X}", header.vaddr),
+        len: header.len,
+        flags: format!("{:?}", header.flags),
+    });
+    let mut table = Table::new(entries);
+    table.with(Style::modern());
+    println!("{}", table);
+
+    regions
+        .iter()
+        .find(|(header, _)| header.paddr == layout_table)
+        .map(|(_, data)| data.clone())
+        .ok_or_else(|| anyhow::anyhow!("memory dump does not contain the layout table"))
+}
+
+/// Print the length-prefixed message of the guest panic message region.
+fn print_panic(regions: &[(DumpHeader, Vec<u8>)]) {
+    let Some((_, data)) = regions
+        .iter()
+        .find(|(header, _)| header.paddr == BMVM_PANIC_MESSAGE.as_u64())
+    else {
+        eprintln!("memory dump does not contain the panic message region");
+        return;
+    };
+
+    let (len, msg) = data.split_at(size_of::<u64>().min(data.len()));
+    let len = u64::from_ne_bytes(len.try_into().unwrap_or_default()) as usize;
+    match len {
+        0 => println!("no panic message recorded"),
+        len => println!(
+            "guest panic: {}",
+            String::from_utf8_lossy(&msg[..len.min(msg.len())])
+        ),
+    }
+}
+
+/// List the accessed and dirty pages per layout table region. The paging structures are the only
+/// regions without a layout table entry, the PML4 is located at the start of the lowest one.
+fn print_stats(layout: &LayoutTable, regions: &[(DumpHeader, Vec<u8>)]) {
+    let Some(pml4) = regions
+        .iter()
+        .filter(|(header, _)| header.vaddr == 0 && header.flags.is_empty())
+        .map(|(header, _)| header.paddr)
+        .min()
+    else {
+        eprintln!("memory dump does not contain the paging structure");
+        return;
+    };
+
+    let read = |addr: u64| {
+        let (header, data) = regions
+            .iter()
+            .find(|(h, _)| (h.paddr..h.paddr + h.len).contains(&addr))?;
+        let offset = (addr - header.paddr) as usize;
+        let raw = data.get(offset..offset + size_of::<u64>())?;
+        Some(u64::from_le_bytes(raw.try_into().unwrap()))
+    };
+
+    let entries = region_stats(layout, pml4, read)
+        .into_iter()
+        .map(|stat| StatEntry {
+            paddr: format!("{:X}", stat.paddr),
+            vaddr: format!("{:X}", stat.vaddr),
+            pages: stat.pages,
+            accessed: stat.accessed,
+            dirty: stat.dirty,
+        });
+    let mut table = Table::new(entries);
+    table.with(Style::modern());
+    println!("{}", table);
+}
+
+/// Parse an address given either in decimal or hexadecimal with `0x` prefix
+fn parse_addr(s: &str) -> Result<u64, std::num::ParseIntError> {
+    match s.strip_prefix("0x") {
+        Some(hex) => u64::from_str_radix(hex, 16),
+        None => s.parse(),
+    }
+}
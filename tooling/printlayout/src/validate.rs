@@ -0,0 +1,248 @@
+use bmvm_common::mem::{Align, DataAccessMode, DefaultAlign, Flags, LayoutTable, LayoutTableEntry};
+use std::ops::Range;
+
+/// First bit of a layout table entry behind the tail padding, which is not in use.
+const RESERVED_SHIFT: u32 = 112;
+
+/// Check the layout table for inconsistencies and return a diagnostic per finding.
+pub fn validate(table: &LayoutTable) -> Vec<String> {
+    let mut issues = Vec::new();
+    let mut present = Vec::new();
+    let mut gap = None;
+
+    for (idx, entry) in table.entries.iter().enumerate() {
+        if !entry.is_present() {
+            if entry.as_u128() != 0 {
+                issues.push(format!(
+                    "entry {}: not present but contains data ({:#x})",
+                    idx,
+                    entry.as_u128()
+                ));
+            }
+            gap.get_or_insert(idx);
+            continue;
+        }
+
+        // consumers stop at the first non-present entry, everything after is ignored
+        if let Some(gap) = gap {
+            issues.push(format!(
+                "entry {}: present after non-present entry {} and therefore unreachable",
+                idx, gap
+            ));
+        }
+
+        present.push((idx, *entry));
+    }
+
+    for (idx, entry) in present.iter() {
+        if !DefaultAlign::is_aligned(entry.paddr_raw()) {
+            issues.push(format!(
+                "entry {}: paddr {:#x} is not page aligned",
+                idx,
+                entry.paddr_raw()
+            ));
+        }
+        if !DefaultAlign::is_aligned(entry.vaddr_raw()) {
+            issues.push(format!(
+                "entry {}: vaddr {:#x} is not page aligned",
+                idx,
+                entry.vaddr_raw()
+            ));
+        }
+        // the addresses are stored as page numbers, garbage surfaces in the unused upper bits
+        if entry.as_u128() >> RESERVED_SHIFT != 0 {
+            issues.push(format!(
+                "entry {}: reserved bits are set ({:#x})",
+                idx,
+                entry.as_u128()
+            ));
+        }
+        if entry.pages() == 0 {
+            issues.push(format!("entry {}: region is empty", idx));
+        }
+    }
+
+    // the read-only view of the shared memory maps the same physical pages
+    let physical = present
+        .iter()
+        .filter(|(_, e)| e.flags().data_access_mode() != Some(DataAccessMode::Foreign))
+        .copied()
+        .collect::<Vec<_>>();
+    check_overlaps(&physical, "physical", |e| e.paddr_raw(), &mut issues);
+    check_overlaps(&present, "virtual", |e| e.vaddr_raw(), &mut issues);
+
+    let stacks = count(&present, |f| f.contains(Flags::STACK));
+    if stacks != 1 {
+        issues.push(format!(
+            "expected exactly one stack region, found {}",
+            stacks
+        ));
+    }
+
+    // the shared region is optional, as the host may be configured without shared memory
+    let shared = count(&present, |f| {
+        !f.is_code() && f.data_access_mode() == Some(DataAccessMode::Shared)
+    });
+    if shared > 1 {
+        issues.push(format!(
+            "expected at most one shared region, found {}",
+            shared
+        ));
+    }
+
+    let views = count(&present, |f| {
+        !f.is_code() && f.data_access_mode() == Some(DataAccessMode::Foreign)
+    });
+    if views != shared.min(1) {
+        issues.push(format!(
+            "expected {} read-only view(s) of the shared region, found {}",
+            shared.min(1),
+            views
+        ));
+    }
+
+    for (idx, view) in present
+        .iter()
+        .filter(|(_, e)| e.flags().data_access_mode() == Some(DataAccessMode::Foreign))
+    {
+        let aliased = present.iter().any(|(_, e)| {
+            e.flags().data_access_mode() == Some(DataAccessMode::Shared)
+                && e.paddr_raw() == view.paddr_raw()
+                && e.size() == view.size()
+        });
+        if !aliased {
+            issues.push(format!(
+                "entry {}: read-only view does not cover the shared region",
+                idx
+            ));
+        }
+    }
+
+    issues
+}
+
+fn count(entries: &[(usize, LayoutTableEntry)], pred: impl Fn(Flags) -> bool) -> usize {
+    entries.iter().filter(|(_, e)| pred(e.flags())).count()
+}
+
+fn check_overlaps(
+    entries: &[(usize, LayoutTableEntry)],
+    space: &str,
+    start: impl Fn(&LayoutTableEntry) -> u64,
+    issues: &mut Vec<String>,
+) {
+    let range = |e: &LayoutTableEntry| -> Range<u64> {
+        let start = start(e);
+        start..start.saturating_add(e.size())
+    };
+
+    for (i, (a_idx, a)) in entries.iter().enumerate() {
+        for (b_idx, b) in entries.iter().skip(i + 1) {
+            let (ra, rb) = (range(a), range(b));
+            if ra.start < rb.end && rb.start < ra.end {
+                issues.push(format!(
+                    "entry {} [{:#x}..{:#x}) overlaps entry {} [{:#x}..{:#x}) in {} memory",
+                    a_idx, ra.start, ra.end, b_idx, rb.start, rb.end, space
+                ));
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use bmvm_common::mem::{PhysAddr, VirtAddr};
+
+    fn entry(paddr: u64, vaddr: u64, pages: u32, flags: Flags) -> LayoutTableEntry {
+        LayoutTableEntry::new(
+            PhysAddr::new(paddr),
+            VirtAddr::new_truncate(vaddr),
+            pages,
+            flags | Flags::PRESENT,
+        )
+    }
+
+    fn table(entries: &[LayoutTableEntry]) -> LayoutTable {
+        LayoutTable::from_vec(entries).unwrap()
+    }
+
+    fn valid() -> Vec<LayoutTableEntry> {
+        vec![
+            entry(0x10000, 0x10000, 4, Flags::CODE),
+            entry(0x20000, 0x20000, 2, Flags::STACK | Flags::DATA_WRITE),
+            entry(0x30000, 0x7f00_0000_0000, 4, Flags::DATA_SHARED),
+            entry(0x30000, 0x7f00_0000_4000, 4, Flags::DATA_FOREIGN),
+        ]
+    }
+
+    #[test]
+    fn accepts_valid_table() {
+        assert!(validate(&table(&valid())).is_empty());
+        // without shared memory, neither the shared region nor its view are expected
+        assert!(validate(&table(&valid()[..2])).is_empty());
+    }
+
+    #[test]
+    fn rejects_overlaps() {
+        let mut entries = valid();
+        entries.push(entry(0x11000, 0x50000, 1, Flags::DATA_READ));
+        let issues = validate(&table(&entries));
+        assert_eq!(issues.len(), 1);
+        assert!(issues[0].contains("physical memory"), "{:?}", issues);
+
+        let mut entries = valid();
+        entries.push(entry(0x50000, 0x21000, 1, Flags::DATA_READ));
+        let issues = validate(&table(&entries));
+        assert_eq!(issues.len(), 1);
+        assert!(issues[0].contains("virtual memory"), "{:?}", issues);
+    }
+
+    #[test]
+    fn rejects_reserved_bits() {
+        let mut entries = valid();
+        entries[0] = LayoutTableEntry::from(entries[0].as_u128() | 1 << 120);
+        let issues = validate(&table(&entries));
+        assert_eq!(issues.len(), 1, "{:?}", issues);
+        assert!(issues[0].starts_with("entry 0: reserved bits are set"));
+    }
+
+    #[test]
+    fn rejects_unexpected_regions() {
+        // missing stack
+        let entries = valid();
+        let issues = validate(&table(&[entries[0], entries[2], entries[3]]));
+        assert_eq!(
+            issues,
+            vec!["expected exactly one stack region, found 0".to_string()]
+        );
+
+        // shared region without its read-only view
+        let issues = validate(&table(&entries[..3]));
+        assert_eq!(
+            issues,
+            vec!["expected 1 read-only view(s) of the shared region, found 0".to_string()]
+        );
+
+        // a second shared region
+        let mut entries = valid();
+        entries.push(entry(0x50000, 0x50000, 1, Flags::DATA_SHARED));
+        let issues = validate(&table(&entries));
+        assert_eq!(
+            issues,
+            vec!["expected at most one shared region, found 2".to_string()]
+        );
+    }
+
+    #[test]
+    fn rejects_unreachable_entries() {
+        let mut entries = valid();
+        entries.insert(2, LayoutTableEntry::empty());
+        let issues = validate(&table(&entries));
+        assert!(
+            issues[0].contains("entry 3: present after non-present entry 2"),
+            "{:?}",
+            issues
+        );
+    }
+}
@@ -0,0 +1,137 @@
+use bmvm_common::vmi::FnCall;
+use std::fmt::Write;
+
+/// Declarations shared by every generated header. Mirrors `bmvm_common::vmi::Transport` and the
+/// transport encoding of buffers.
+const PRELUDE: &str = r#"#include <stdbool.h>
+#include <stdint.h>
+
+/*
+ * Register pair passed between host and guest: `primary` in r8, `secondary` in r9. Scalars are
+ * encoded little endian in the low bytes of `primary`, 128-bit integers span both fields.
+ */
+typedef struct bmvm_transport {
+    uint64_t primary;
+    uint64_t secondary;
+} bmvm_transport;
+
+/*
+ * Buffer in the shared memory, used for `SharedBuf`, `ForeignBuf` and the NUL-terminated
+ * `OwnedCStr`/`ForeignCStr`. `ptr` is the offset of the buffer from the start of the shared
+ * memory, `len` its capacity in bytes including the terminator. Passed as `primary` and
+ * `secondary` of the transport.
+ */
+typedef struct bmvm_buf {
+    uint64_t ptr;
+    uint64_t len;
+} bmvm_buf;
+
+/* Offset of a single value in the shared memory, used for `Shared<T>` and `Foreign<T>` */
+typedef uint64_t bmvm_offset;
+"#;
+
+/// Generate a C header declaring the functions exposed by the guest and the hypercalls it expects,
+/// each with a constant for its signature. The prototypes describe the interface only: arguments
+/// and results are exchanged via `bmvm_transport` and not the C calling convention.
+///
+/// Parameter and return types are only known if the guest was built with VMI debug information,
+/// otherwise only the signature constants are emitted. Types without a C counterpart are declared
+/// as `bmvm_transport`, annotated with the Rust type name.
+pub fn emit(
+    name: &str,
+    abi: Option<u32>,
+    debug: bool,
+    expose: &[FnCall],
+    host: &[FnCall],
+) -> anyhow::Result<String> {
+    let guard = format!("BMVM_{}_H", ident(name).to_uppercase());
+    let mut out = String::new();
+
+    writeln!(
+        out,
+        "/* VMI interface of '{}', generated by vmi-inspect */",
+        name
+    )?;
+    writeln!(out, "#ifndef {}", guard)?;
+    writeln!(out, "#define {}\n", guard)?;
+    out.push_str(PRELUDE);
+    if let Some(abi) = abi {
+        writeln!(out, "\n#define BMVM_ABI_VERSION {}u", abi)?;
+    }
+    if !debug {
+        writeln!(
+            out,
+            "\n/* The guest was built without VMI debug information, prototypes are omitted */"
+        )?;
+    }
+
+    for (title, calls) in [("Upcalls", expose), ("Hypercalls", host)] {
+        if calls.is_empty() {
+            continue;
+        }
+        writeln!(out, "\n/* {} */", title)?;
+        for call in calls {
+            let name = call.name.to_str()?;
+            writeln!(
+                out,
+                "#define BMVM_SIG_{} UINT64_C({:#018x})",
+                ident(name).to_uppercase(),
+                call.sig
+            )?;
+            if debug {
+                writeln!(out, "{};", prototype(name, call)?)?;
+            }
+        }
+    }
+
+    writeln!(out, "\n#endif /* {} */", guard)?;
+    Ok(out)
+}
+
+fn prototype(name: &str, call: &FnCall) -> anyhow::Result<String> {
+    let params = call
+        .params()
+        .iter()
+        .enumerate()
+        .map(|(i, ty)| Ok(format!("{} arg{}", c_type(ty.to_str()?), i)))
+        .collect::<anyhow::Result<Vec<_>>>()?;
+    let params = match params.is_empty() {
+        true => "void".to_string(),
+        false => params.join(", "),
+    };
+    let output = match call.return_type() {
+        Some(ty) => c_type(ty.to_str()?),
+        None => "void".to_string(),
+    };
+    Ok(format!("{} {}({})", output, ident(name), params))
+}
+
+/// Map the Rust type name of the VMI debug information to a C type
+fn c_type(ty: &str) -> String {
+    let c = match ty {
+        "()" => "void",
+        "bool" => "bool",
+        "u8" => "uint8_t",
+        "u16" => "uint16_t",
+        "u32" => "uint32_t",
+        "u64" | "usize" | "NonZeroUsize" => "uint64_t",
+        "i8" => "int8_t",
+        "i16" => "int16_t",
+        "i32" => "int32_t",
+        "i64" | "isize" => "int64_t",
+        "f32" => "float",
+        "f64" => "double",
+        "char" => "uint32_t",
+        "SharedBuf" | "ForeignBuf" | "OwnedCStr" | "ForeignCStr" => "bmvm_buf",
+        t if t.starts_with("Shared<") || t.starts_with("Foreign<") => "bmvm_offset",
+        t => return format!("bmvm_transport /* {} */", t),
+    };
+    c.to_string()
+}
+
+/// Replace all characters not allowed in a C identifier
+fn ident(name: &str) -> String {
+    name.chars()
+        .map(|c| if c.is_ascii_alphanumeric() { c } else { '_' })
+        .collect()
+}
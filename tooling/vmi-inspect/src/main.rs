@@ -1,14 +1,18 @@
+mod header;
+mod strip;
+
 use anyhow::anyhow;
-use bmvm_common::vmi::{FnCall, FnPtr, UpcallFn};
+use bmvm_common::vmi::{FnCall, FnPtr, Sidecar, Signature, UpcallFn, record_version};
 use bmvm_common::{
-    BMVM_META_SECTION_DEBUG, BMVM_META_SECTION_EXPOSE, BMVM_META_SECTION_EXPOSE_CALLS,
-    BMVM_META_SECTION_HOST,
+    BMVM_ABI_VERSION, BMVM_META_FORMAT_VERSION, BMVM_META_SECTION_ABI, BMVM_META_SECTION_DEBUG,
+    BMVM_META_SECTION_EXPOSE, BMVM_META_SECTION_EXPOSE_CALLS, BMVM_META_SECTION_HOST,
 };
-use clap::Parser;
+use clap::{Parser, Subcommand};
 use goblin::elf::Elf;
 use std::cmp::max;
 use std::ffi::CString;
 use std::fs;
+use std::path::{Path, PathBuf};
 use tabled::builder::Builder;
 use tabled::settings::{Panel, Style};
 use tabled::{Table, Tabled};
@@ -16,6 +20,10 @@ use tabled::{Table, Tabled};
 #[derive(Debug)]
 struct VmiInfo {
     debug: bool,
+    /// ABI version the guest was built against, if embedded
+    abi: Option<u32>,
+    /// Format version of the metadata records, `None` if there are no records
+    format: Option<u8>,
     expose: Vec<FnCall>,
     upcalls: Vec<UpcallFn>,
     /// All function calls expected to be provided to the guest by the host.
@@ -24,25 +32,70 @@ struct VmiInfo {
 }
 
 impl VmiInfo {
-    fn new(buf: &[u8]) -> anyhow::Result<Self> {
-        let elf = Elf::parse(buf.as_ref())?;
-        let debug = Self::is_vmi_debug(&elf);
-        let host = Self::parse_vmi_vec(&elf, &buf, BMVM_META_SECTION_HOST, debug)?;
-        let expose = Self::parse_vmi_vec(&elf, &buf, BMVM_META_SECTION_EXPOSE, debug)?;
+    fn new(sidecar: &Sidecar) -> anyhow::Result<Self> {
+        let debug = sidecar.debug;
+        let format = [&sidecar.host, &sidecar.expose, &sidecar.expose_calls]
+            .into_iter()
+            .find_map(|content| record_version(content));
+        let host = Self::parse_vmi_vec(&sidecar.host, BMVM_META_SECTION_HOST, debug)?;
+        let expose = Self::parse_vmi_vec(&sidecar.expose, BMVM_META_SECTION_EXPOSE, debug)?;
         let upcalls = if !expose.is_empty() {
-            Self::parse_upcall_ptr(&elf, &buf, BMVM_META_SECTION_EXPOSE_CALLS, expose.len())?
+            Self::parse_upcall_ptr(
+                &sidecar.expose_calls,
+                BMVM_META_SECTION_EXPOSE_CALLS,
+                expose.len(),
+            )?
         } else {
             Vec::new()
         };
 
+        let abi = sidecar
+            .abi
+            .as_slice()
+            .try_into()
+            .ok()
+            .map(u32::from_le_bytes);
+
         Ok(Self {
             debug,
+            abi,
+            format,
             expose,
             upcalls,
             host,
         })
     }
 
+    /// Extract the raw VMI metadata sections from the ELF file. Returns `None` if the file does not
+    /// contain any of them, e.g. because they have been stripped.
+    fn extract(buf: &[u8]) -> anyhow::Result<Option<Sidecar>> {
+        let elf = Elf::parse(buf)?;
+        let content = |name: &str| -> Option<Vec<u8>> {
+            let section = &elf.section_headers[Self::find_section_header(&elf, name)?];
+            buf.get(section.sh_offset as usize..(section.sh_offset + section.sh_size) as usize)
+                .map(|c| c.to_vec())
+        };
+
+        let sections = [
+            content(BMVM_META_SECTION_HOST),
+            content(BMVM_META_SECTION_EXPOSE),
+            content(BMVM_META_SECTION_EXPOSE_CALLS),
+            content(BMVM_META_SECTION_ABI),
+        ];
+        if sections.iter().all(Option::is_none) {
+            return Ok(None);
+        }
+
+        let [host, expose, expose_calls, abi] = sections.map(Option::unwrap_or_default);
+        Ok(Some(Sidecar {
+            debug: Self::is_vmi_debug(&elf),
+            host,
+            expose,
+            expose_calls,
+            abi,
+        }))
+    }
+
     /// If the debug section header is included, then VMI call data includes debug information
     /// i.e. parameter and return types
     fn is_vmi_debug(elf: &Elf) -> bool {
@@ -68,62 +121,43 @@ impl VmiInfo {
         None
     }
 
-    /// Parse a vector of VMI calls from the ELF file from the section with `name`.
+    /// Parse a vector of VMI calls from the content of the section `section_name`.
     /// On success, the returned vector is sorted via Vec::sort
     fn parse_vmi_vec(
-        elf: &Elf,
-        buf: &[u8],
+        content: &[u8],
         section_name: &str,
         debug: bool,
     ) -> anyhow::Result<Vec<FnCall>> {
-        if let Some(idx) = Self::find_section_header(elf, section_name) {
-            let section = &elf.section_headers[idx];
-            let content =
-                &buf[section.sh_offset as usize..(section.sh_offset + section.sh_size) as usize];
-
-            if content.is_empty() {
-                log::warn!("VMI section defined but empty: {}", section_name);
-                return Ok(Vec::new());
-            }
-
-            let mut calls = FnCall::try_from_bytes_vec(content, debug)
-                .map_err(|e| anyhow!("Error parsing VMI section '{}': {}", section_name, e))?;
-            // ensure to sort the function calls
-            calls.sort();
-            return Ok(calls);
+        if content.is_empty() {
+            return Ok(Vec::new());
         }
 
-        Ok(Vec::new())
+        let mut calls = FnCall::try_from_bytes_vec(content, debug)
+            .map_err(|e| anyhow!("Error parsing VMI section '{}': {}", section_name, e))?;
+        // ensure to sort the function calls
+        calls.sort();
+        Ok(calls)
     }
 
     fn parse_upcall_ptr(
-        elf: &Elf,
-        buf: &[u8],
+        content: &[u8],
         section_name: &str,
         count: usize,
     ) -> anyhow::Result<Vec<UpcallFn>> {
-        if let Some(idx) = Self::find_section_header(elf, section_name) {
-            let section = &elf.section_headers[idx];
-            let content =
-                &buf[section.sh_offset as usize..(section.sh_offset + section.sh_size) as usize];
-
-            let size = size_of::<UpcallFn>();
-            if content.len() < count * size {
-                return Err(anyhow!(
-                    "Insufficient upcall pointers: want {} but got {}",
-                    count,
-                    content.len() / size
-                ));
-            }
-
-            let mut calls = UpcallFn::try_from_bytes_vec(content)
-                .map_err(|e| anyhow!("Error parsing VMI section '{}': {}", section_name, e))?;
-            // ensure to sort the function calls
-            calls.sort();
-            return Ok(calls);
+        let size = UpcallFn::RECORD_SIZE;
+        if content.len() < count * size {
+            return Err(anyhow!(
+                "Insufficient upcall pointers: want {} but got {}",
+                count,
+                content.len() / size
+            ));
         }
 
-        Ok(Vec::new())
+        let mut calls = UpcallFn::try_from_bytes_vec(content)
+            .map_err(|e| anyhow!("Error parsing VMI section '{}': {}", section_name, e))?;
+        // ensure to sort the function calls
+        calls.sort();
+        Ok(calls)
     }
 
     fn table_expose(&self) -> anyhow::Result<Table> {
@@ -216,19 +250,174 @@ impl VmiInfo {
 }
 
 #[derive(Parser, Debug)]
-#[command(version, about, long_about = None)]
+#[command(version, about, long_about = None, subcommand_negates_reqs = true)]
 struct Args {
-    #[arg(short, long, env = "FILE")]
-    file: String,
+    #[command(subcommand)]
+    command: Option<Command>,
+
+    #[arg(short, long, env = "FILE", required = true)]
+    file: Option<String>,
+
+    /// Verify that the binary contains a function with the given signature, e.g. `add=1234`.
+    /// Can be passed multiple times.
+    #[arg(long, value_parser = parse_expect)]
+    expect: Vec<(String, Signature)>,
+
+    /// Read the metadata from this sidecar file, if the binary does not contain the VMI sections.
+    /// Defaults to `<FILE>.bmvm`.
+    #[arg(long)]
+    sidecar: Option<PathBuf>,
+
+    /// Write the VMI sections of the binary into a sidecar file at the given path and exit. The
+    /// host reads `<FILE>.bmvm` if the sections are stripped from the binary.
+    #[arg(long)]
+    extract: Option<PathBuf>,
+
+    /// Write a C header with the prototypes and signature constants of the upcalls and hypercalls,
+    /// as well as the transport definitions, to the given path and exit. Prototypes require the
+    /// VMI debug information.
+    #[arg(long)]
+    emit_header: Option<PathBuf>,
+}
+
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Write a copy of the binary without the VMI debug information (parameter and return type
+    /// names) and report the bytes saved. The host only requires the signatures to link the guest.
+    Strip { input: PathBuf, output: PathBuf },
+}
+
+fn parse_expect(s: &str) -> Result<(String, Signature), String> {
+    let (name, sig) = s
+        .split_once('=')
+        .ok_or_else(|| format!("expected <NAME>=<SIGNATURE>, got '{}'", s))?;
+    let sig = sig
+        .parse::<Signature>()
+        .map_err(|e| format!("invalid signature '{}': {}", sig, e))?;
+    Ok((name.to_string(), sig))
+}
+
+/// Check that the stripped binary describes the same functions as the original one
+fn verify_stripped(original: Option<&Sidecar>, stripped: &[u8]) -> anyhow::Result<()> {
+    let original = original.ok_or_else(|| anyhow!("binary contains no VMI sections"))?;
+    let sidecar = VmiInfo::extract(stripped)?
+        .ok_or_else(|| anyhow!("stripped binary contains no VMI sections"))?;
+    if sidecar.debug {
+        anyhow::bail!("stripped binary still contains VMI debug information");
+    }
+
+    let (before, after) = (VmiInfo::new(original)?, VmiInfo::new(&sidecar)?);
+    let calls = |calls: &[FnCall]| {
+        calls
+            .iter()
+            .map(|c| (c.sig, c.name.clone()))
+            .collect::<Vec<_>>()
+    };
+    if calls(&before.expose) != calls(&after.expose)
+        || calls(&before.host) != calls(&after.host)
+        || before.upcalls != after.upcalls
+        || before.abi != after.abi
+    {
+        anyhow::bail!("stripped binary does not describe the same functions");
+    }
+    Ok(())
+}
+
+/// Strip the VMI debug information from `input` and write the result to `output`
+fn strip(input: &Path, output: &Path) -> anyhow::Result<()> {
+    let dump = fs::read(input)?;
+    let stripped = strip::strip(&dump)?;
+    verify_stripped(VmiInfo::extract(&dump)?.as_ref(), &stripped.data)?;
+    fs::write(output, &stripped.data)?;
+    println!(
+        "wrote stripped binary to {} ({} -> {} bytes, saved {})",
+        output.display(),
+        dump.len(),
+        stripped.data.len(),
+        stripped.saved
+    );
+    Ok(())
 }
 
 fn main() -> anyhow::Result<()> {
     let args = Args::parse();
-    let dump = fs::read(args.file)?;
+    if let Some(Command::Strip { input, output }) = &args.command {
+        return strip(input, output);
+    }
+
+    let file = args.file.ok_or_else(|| anyhow!("no input file given"))?;
+    let dump = fs::read(&file)?;
+    let extracted = VmiInfo::extract(&dump)?;
+
+    if let Some(out) = args.extract {
+        let sidecar = extracted.ok_or_else(|| anyhow!("binary contains no VMI sections"))?;
+        sidecar.write_to(&mut fs::File::create(&out)?)?;
+        println!("wrote sidecar to {}", out.display());
+        return Ok(());
+    }
+
+    let sidecar = match extracted {
+        Some(sidecar) => sidecar,
+        None => {
+            let path = args.sidecar.unwrap_or_else(|| Sidecar::path_for(&file));
+            Sidecar::read_from(&mut fs::File::open(&path)?).map_err(|e| {
+                anyhow!(
+                    "no VMI sections and unable to read {}: {}",
+                    path.display(),
+                    e
+                )
+            })?
+        }
+    };
+
+    let info = VmiInfo::new(&sidecar)?;
+    if let Some(out) = args.emit_header {
+        let name = PathBuf::from(&file)
+            .file_stem()
+            .map(|s| s.to_string_lossy().into_owned())
+            .unwrap_or_else(|| "guest".to_string());
+        let header = header::emit(&name, info.abi, info.debug, &info.expose, &info.host)?;
+        fs::write(&out, header)?;
+        println!("wrote header to {}", out.display());
+        return Ok(());
+    }
 
-    let info = VmiInfo::new(&dump)?;
+    match info.abi {
+        Some(abi) => println!("ABI version: {} (host: {})\n", abi, BMVM_ABI_VERSION),
+        None => println!("ABI version: missing (host: {})\n", BMVM_ABI_VERSION),
+    }
+    if let Some(format) = info.format {
+        println!(
+            "Metadata format version: {} (host: {})\n",
+            format, BMVM_META_FORMAT_VERSION
+        );
+    }
     println!("{}\n", info.table_expose()?);
     println!("{}", info.table_host()?);
 
+    let mut failed = 0;
+    for (name, sig) in args.expect.iter() {
+        let found = info
+            .expose
+            .iter()
+            .chain(info.host.iter())
+            .find(|f| f.name.to_bytes() == name.as_bytes());
+        match found {
+            Some(f) if f.sig == *sig => println!("ok: {} = {}", name, sig),
+            Some(f) => {
+                eprintln!("error: {} has signature {}, expected {}", name, f.sig, sig);
+                failed += 1;
+            }
+            None => {
+                eprintln!("error: {} not found", name);
+                failed += 1;
+            }
+        }
+    }
+
+    if failed > 0 {
+        anyhow::bail!("{} expected signature(s) did not match", failed);
+    }
+
     Ok(())
 }
@@ -0,0 +1,268 @@
+use anyhow::{anyhow, bail};
+use bmvm_common::vmi::{FnCall, RECORD_HEADER_SIZE, record_header};
+use bmvm_common::{BMVM_META_SECTION_DEBUG, BMVM_META_SECTION_EXPOSE, BMVM_META_SECTION_HOST};
+use goblin::elf::program_header::PT_LOAD;
+use goblin::elf::section_header::{SHT_NOBITS, SHT_NULL};
+use goblin::elf::{Elf, SectionHeader};
+use std::collections::HashMap;
+
+// field offsets within the ELF64 file, section and program headers
+const EHDR_SHOFF: usize = 0x28;
+const SHDR_SIZE: usize = 64;
+const SHDR_NAME: usize = 0x00;
+const SHDR_TYPE: usize = 0x04;
+const SHDR_OFFSET: usize = 0x18;
+const SHDR_SIZE_FIELD: usize = 0x20;
+const PHDR_OFFSET: usize = 0x08;
+const PHDR_FILESZ: usize = 0x20;
+const PHDR_MEMSZ: usize = 0x28;
+
+/// Guest executable without the VMI debug information
+pub struct Stripped {
+    pub data: Vec<u8>,
+    /// Number of bytes saved compared to the input
+    pub saved: usize,
+}
+
+/// Remove the parameter and return type names from the VMI call sections and drop the debug
+/// indicator section. The host only requires the signatures and names at runtime, so the result
+/// loads like a guest built with `vmi-no-debug`.
+///
+/// The VMI sections are placed in a non-loadable segment after the loadable ones, therefore only
+/// the file content behind them is moved and the loaded image stays untouched.
+pub fn strip(buf: &[u8]) -> anyhow::Result<Stripped> {
+    let elf = Elf::parse(buf)?;
+    if !elf.is_64 || !elf.little_endian {
+        bail!("only 64-bit little endian executables are supported");
+    }
+
+    let find = |name: &str| {
+        elf.section_headers
+            .iter()
+            .position(|s| s.sh_name != 0 && elf.shdr_strtab.get_at(s.sh_name) == Some(name))
+    };
+    let debug = find(BMVM_META_SECTION_DEBUG)
+        .ok_or_else(|| anyhow!("binary contains no VMI debug information"))?;
+
+    // re-encode the call sections without debug information
+    let mut replaced = HashMap::new();
+    for name in [BMVM_META_SECTION_HOST, BMVM_META_SECTION_EXPOSE] {
+        let Some(idx) = find(name) else {
+            continue;
+        };
+        let calls = FnCall::try_from_bytes_vec(content(buf, &elf.section_headers[idx])?, true)
+            .map_err(|e| anyhow!("Error parsing VMI section '{}': {}", name, e))?;
+        replaced.insert(
+            idx,
+            calls.iter().flat_map(encode_minimal).collect::<Vec<_>>(),
+        );
+    }
+
+    // everything from the first rewritten section onward is laid out anew
+    let start = replaced
+        .keys()
+        .map(|idx| elf.section_headers[*idx].sh_offset as usize)
+        .min()
+        .unwrap_or(buf.len());
+    if elf
+        .program_headers
+        .iter()
+        .any(|p| p.p_type == PT_LOAD && (p.p_offset + p.p_filesz) as usize > start)
+        || elf.header.e_phoff as usize >= start
+    {
+        bail!("VMI sections are not placed behind the loadable segments");
+    }
+
+    let mut order = elf
+        .section_headers
+        .iter()
+        .enumerate()
+        .filter(|(idx, s)| {
+            *idx != debug && s.sh_type != SHT_NOBITS && s.sh_offset as usize >= start
+        })
+        .collect::<Vec<_>>();
+    order.sort_by_key(|(_, s)| s.sh_offset);
+
+    let mut out = buf[..start].to_vec();
+    let mut moved = HashMap::new();
+    for (idx, section) in order {
+        let data = match replaced.get(&idx) {
+            Some(data) => data.as_slice(),
+            None => content(buf, section)?,
+        };
+        let offset = out
+            .len()
+            .next_multiple_of(section.sh_addralign.max(1) as usize);
+        out.resize(offset, 0);
+        out.extend_from_slice(data);
+        moved.insert(idx, (offset as u64, data.len() as u64));
+    }
+
+    // section header table
+    let shoff = elf.header.e_shoff as usize;
+    let shnum = elf.section_headers.len();
+    let table = buf
+        .get(shoff..shoff + shnum * SHDR_SIZE)
+        .ok_or_else(|| anyhow!("section header table out of bounds"))?;
+    let new_shoff = if shoff >= start {
+        let offset = out.len().next_multiple_of(size_of::<u64>());
+        out.resize(offset, 0);
+        out.extend_from_slice(table);
+        offset
+    } else {
+        shoff
+    };
+    write_u64(&mut out, EHDR_SHOFF, new_shoff as u64);
+
+    for (idx, (offset, size)) in moved.iter() {
+        let shdr = new_shoff + idx * SHDR_SIZE;
+        write_u64(&mut out, shdr + SHDR_OFFSET, *offset);
+        write_u64(&mut out, shdr + SHDR_SIZE_FIELD, *size);
+    }
+
+    // turn the debug indicator into an unnamed null section, keeping the section indices intact
+    let shdr = new_shoff + debug * SHDR_SIZE;
+    write_u32(&mut out, shdr + SHDR_NAME, 0);
+    write_u32(&mut out, shdr + SHDR_TYPE, SHT_NULL);
+    write_u64(&mut out, shdr + SHDR_SIZE_FIELD, 0);
+
+    // non-loadable segments covering moved sections span their new location
+    for (i, phdr) in elf.program_headers.iter().enumerate() {
+        let range = phdr.p_offset..phdr.p_offset + phdr.p_filesz;
+        let covered = moved
+            .iter()
+            .filter(|(idx, _)| range.contains(&elf.section_headers[**idx].sh_offset))
+            .map(|(_, (offset, size))| (*offset, offset + size));
+        let Some((first, end)) = covered.fold(None, |acc: Option<(u64, u64)>, (o, e)| {
+            Some(acc.map_or((o, e), |(a, b)| (a.min(o), b.max(e))))
+        }) else {
+            continue;
+        };
+
+        let base = elf.header.e_phoff as usize + i * elf.header.e_phentsize as usize;
+        write_u64(&mut out, base + PHDR_OFFSET, first);
+        write_u64(&mut out, base + PHDR_FILESZ, end - first);
+        write_u64(&mut out, base + PHDR_MEMSZ, end - first);
+    }
+
+    Ok(Stripped {
+        saved: buf.len().saturating_sub(out.len()),
+        data: out,
+    })
+}
+
+/// Encode the call like a guest built without VMI debug information
+fn encode_minimal(call: &FnCall) -> Vec<u8> {
+    let mut buf = vec![0u8; RECORD_HEADER_SIZE];
+    buf.extend(call.sig.to_ne_bytes());
+    buf.extend(call.name.as_bytes_with_nul());
+    let header = record_header(buf.len() - RECORD_HEADER_SIZE);
+    buf[..RECORD_HEADER_SIZE].copy_from_slice(&header.to_le_bytes());
+    buf
+}
+
+fn content<'a>(buf: &'a [u8], section: &SectionHeader) -> anyhow::Result<&'a [u8]> {
+    buf.get(section.sh_offset as usize..(section.sh_offset + section.sh_size) as usize)
+        .ok_or_else(|| anyhow!("section content out of bounds"))
+}
+
+fn write_u32(buf: &mut [u8], offset: usize, value: u32) {
+    buf[offset..offset + size_of::<u32>()].copy_from_slice(&value.to_le_bytes());
+}
+
+fn write_u64(buf: &mut [u8], offset: usize, value: u64) {
+    buf[offset..offset + size_of::<u64>()].copy_from_slice(&value.to_le_bytes());
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{VmiInfo, verify_stripped};
+    use goblin::elf::section_header::{SHT_PROGBITS, SHT_STRTAB};
+
+    /// Build a minimal executable containing the given sections after the ELF header, followed by
+    /// the section header table.
+    fn elf(sections: &[(&str, u32, Vec<u8>)]) -> Vec<u8> {
+        let mut strtab = vec![0u8];
+        let mut headers = vec![[0u8; SHDR_SIZE]];
+        let mut out = vec![0u8; 0x40];
+        let shstrtab = (".shstrtab", SHT_STRTAB, Vec::new());
+        for (name, kind, data) in sections.iter().chain([&shstrtab]) {
+            let name_offset = strtab.len() as u32;
+            strtab.extend(name.as_bytes());
+            strtab.push(0);
+            let data = if *kind == SHT_STRTAB { &strtab } else { data };
+
+            let mut shdr = [0u8; SHDR_SIZE];
+            write_u32(&mut shdr, SHDR_NAME, name_offset);
+            write_u32(&mut shdr, SHDR_TYPE, *kind);
+            write_u64(&mut shdr, SHDR_OFFSET, out.len() as u64);
+            write_u64(&mut shdr, SHDR_SIZE_FIELD, data.len() as u64);
+            // sh_addralign
+            write_u64(&mut shdr, 0x30, 1);
+            headers.push(shdr);
+            out.extend_from_slice(data);
+        }
+
+        let shoff = out.len().next_multiple_of(size_of::<u64>());
+        out.resize(shoff, 0);
+        out.extend(headers.iter().flatten());
+
+        out[..4].copy_from_slice(b"\x7fELF");
+        // 64-bit, little endian, version 1
+        out[4..7].copy_from_slice(&[2, 1, 1]);
+        out[0x10..0x12].copy_from_slice(&2u16.to_le_bytes());
+        out[0x12..0x14].copy_from_slice(&0x3eu16.to_le_bytes());
+        write_u32(&mut out, 0x14, 1);
+        write_u64(&mut out, EHDR_SHOFF, shoff as u64);
+        out[0x34..0x36].copy_from_slice(&0x40u16.to_le_bytes());
+        out[0x36..0x38].copy_from_slice(&56u16.to_le_bytes());
+        out[0x3a..0x3c].copy_from_slice(&(SHDR_SIZE as u16).to_le_bytes());
+        out[0x3c..0x3e].copy_from_slice(&(headers.len() as u16).to_le_bytes());
+        out[0x3e..0x40].copy_from_slice(&(headers.len() as u16 - 1).to_le_bytes());
+        out
+    }
+
+    fn calls() -> Vec<FnCall> {
+        vec![
+            FnCall::new(0x1234, "add", &["u64", "u64"], Some("u64")).unwrap(),
+            FnCall::new(0x5678, "log", &["SharedBuf"], None).unwrap(),
+        ]
+    }
+
+    fn guest() -> Vec<u8> {
+        let records = calls().iter().flat_map(FnCall::to_bytes).collect();
+        elf(&[
+            (BMVM_META_SECTION_HOST, SHT_PROGBITS, records),
+            (BMVM_META_SECTION_DEBUG, SHT_PROGBITS, vec![1]),
+        ])
+    }
+
+    #[test]
+    fn strips_debug_information() {
+        let input = guest();
+        let stripped = strip(&input).unwrap();
+        assert!(stripped.saved > 0);
+        assert_eq!(stripped.saved, input.len() - stripped.data.len());
+
+        let original = VmiInfo::extract(&input).unwrap();
+        verify_stripped(original.as_ref(), &stripped.data).unwrap();
+
+        let sidecar = VmiInfo::extract(&stripped.data).unwrap().unwrap();
+        assert!(!sidecar.debug);
+        let host = VmiInfo::new(&sidecar).unwrap().host;
+        let expected = calls();
+        assert_eq!(host.len(), expected.len());
+        for (call, expected) in host.iter().zip(expected.iter()) {
+            assert_eq!((call.sig, &call.name), (expected.sig, &expected.name));
+            assert!(call.params().is_empty());
+            assert!(call.return_type().is_none());
+        }
+    }
+
+    #[test]
+    fn rejects_stripped_binary() {
+        let stripped = strip(&guest()).unwrap();
+        assert!(strip(&stripped.data).is_err());
+    }
+}